@@ -17,12 +17,12 @@ fn main() {
                 }
                 let msg = msg_str.as_bytes();
 
-                stream.write(msg).unwrap();
+                stream.write_all(msg).unwrap();
 
-                let mut response_size_buffer = [0 as u8; 8];
+                let mut response_size_buffer = [0_u8; 8];
                 stream.read_exact(&mut response_size_buffer).unwrap();
                 let response_size: usize = usize::from_le_bytes(response_size_buffer);
-                let mut response_buffer = vec![0 as u8; response_size];
+                let mut response_buffer = vec![0_u8; response_size];
                 stream.read_exact(&mut response_buffer).unwrap();
                 println!(
                     "{}",
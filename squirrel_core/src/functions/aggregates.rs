@@ -0,0 +1,115 @@
+use std::cmp::Ordering;
+
+use crate::parser::command::{Aggregate, AggregateFunction, DataValue, ValueOperator};
+use anyhow::anyhow;
+
+// Running state of one aggregate call over a group of rows. Rows are fed
+// in with update() and the result is read back with finish().
+#[derive(Debug, Clone)]
+pub struct Accumulator {
+    function: AggregateFunction,
+    distinct: bool,
+    count: i64,
+    // Sum for SUM/AVG, current best for MIN/MAX. Integers are summed as an
+    // i128 so they can't overflow before being turned into a numeric.
+    value: DataValue,
+    int_sum: i128,
+    seen: Vec<DataValue>,
+}
+
+impl Accumulator {
+    pub fn new(aggregate: &Aggregate) -> Accumulator {
+        Accumulator {
+            function: aggregate.function,
+            distinct: aggregate.distinct,
+            count: 0,
+            value: DataValue::Null,
+            int_sum: 0,
+            seen: vec![],
+        }
+    }
+
+    // Adds a row's value. COUNT(*) should be given any non-NULL value for
+    // every row.
+    pub fn update(&mut self, value: DataValue) -> ::anyhow::Result<()> {
+        if value == DataValue::Null {
+            return Ok(());
+        }
+        if self.distinct {
+            if self.seen.contains(&value) {
+                return Ok(());
+            }
+            self.seen.push(value.clone());
+        }
+
+        self.count += 1;
+        match self.function {
+            AggregateFunction::Count => {}
+            AggregateFunction::Sum | AggregateFunction::Avg => match value {
+                DataValue::I64Value(val) if matches!(self.value, DataValue::Null) => self.int_sum += i128::from(val),
+                DataValue::F64Value(_) | DataValue::NumericValue(_, _) => {
+                    self.value = match &self.value {
+                        DataValue::Null => value,
+                        current => DataValue::arithmetic(&ValueOperator::Add, current.clone(), value)?,
+                    };
+                }
+                // A float or numeric came first, fold the integer into it
+                DataValue::I64Value(val) => {
+                    self.value = DataValue::arithmetic(&ValueOperator::Add, self.value.clone(), DataValue::I64Value(val))?;
+                }
+                _ => return Err(self.type_error(&value)),
+            },
+            AggregateFunction::Min | AggregateFunction::Max => {
+                if let DataValue::Null = self.value {
+                    self.value = value;
+                    return Ok(());
+                }
+                let (current, candidate) = DataValue::coerce(self.value.clone(), value.clone())?;
+                let wanted = if self.function == AggregateFunction::Min { Ordering::Less } else { Ordering::Greater };
+                match candidate.compare(&current) {
+                    Some(ordering) if ordering == wanted => self.value = value,
+                    Some(_) => {}
+                    None => return Err(self.type_error(&value)),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(&self) -> ::anyhow::Result<DataValue> {
+        if self.function == AggregateFunction::Count {
+            return Ok(DataValue::I64Value(self.count));
+        }
+        if self.count == 0 {
+            return Ok(DataValue::Null);
+        }
+
+        match self.function {
+            AggregateFunction::Sum | AggregateFunction::Avg => {
+                let int_sum = match i64::try_from(self.int_sum) {
+                    Ok(val) => DataValue::I64Value(val),
+                    Err(_) => DataValue::NumericValue(self.int_sum, 0),
+                };
+                let sum = match &self.value {
+                    DataValue::Null => int_sum,
+                    value if self.int_sum == 0 => value.clone(),
+                    value => DataValue::arithmetic(&ValueOperator::Add, value.clone(), int_sum)?,
+                };
+                if self.function == AggregateFunction::Sum {
+                    return Ok(sum);
+                }
+                // Like Postgres, the average of integers is a numeric
+                let sum = match sum {
+                    DataValue::I64Value(val) => DataValue::NumericValue(val.into(), 0),
+                    sum => sum,
+                };
+                DataValue::arithmetic(&ValueOperator::Divide, sum, DataValue::I64Value(self.count))
+            }
+            _ => Ok(self.value.clone()),
+        }
+    }
+
+    fn type_error(&self, value: &DataValue) -> anyhow::Error {
+        anyhow!("ERROR: function {}({}) does not exist", self.function.as_str(), value.type_name())
+    }
+}
@@ -0,0 +1,271 @@
+use std::cmp::Ordering;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::functions::signature_error;
+use crate::parser::command::{DataValue, ValueOperator};
+use crate::table::numeric;
+use anyhow::anyhow;
+
+pub struct Builtin {
+    pub name: &'static str,
+    pub min_args: usize,
+    pub max_args: Option<usize>, // None is variadic
+    // Strict functions return NULL as soon as any argument is NULL,
+    // without being called.
+    pub strict: bool,
+    pub call: fn(&str, Vec<DataValue>) -> ::anyhow::Result<DataValue>,
+}
+
+const fn builtin(
+    name: &'static str,
+    min_args: usize,
+    max_args: Option<usize>,
+    strict: bool,
+    call: fn(&str, Vec<DataValue>) -> ::anyhow::Result<DataValue>,
+) -> Builtin {
+    Builtin { name, min_args, max_args, strict, call }
+}
+
+pub const BUILTINS: &[Builtin] = &[
+    // Strings
+    builtin("UPPER", 1, Some(1), true, upper),
+    builtin("LOWER", 1, Some(1), true, lower),
+    builtin("LENGTH", 1, Some(1), true, length),
+    builtin("CHAR_LENGTH", 1, Some(1), true, length),
+    builtin("SUBSTRING", 2, Some(3), true, substring),
+    builtin("SUBSTR", 2, Some(3), true, substring),
+    builtin("TRIM", 1, Some(2), true, trim),
+    builtin("LTRIM", 1, Some(2), true, trim),
+    builtin("RTRIM", 1, Some(2), true, trim),
+    builtin("REPLACE", 3, Some(3), true, replace),
+    builtin("CONCAT", 1, None, false, concat),
+    // Numbers
+    builtin("ABS", 1, Some(1), true, abs),
+    builtin("ROUND", 1, Some(2), true, round),
+    builtin("CEIL", 1, Some(1), true, round),
+    builtin("CEILING", 1, Some(1), true, round),
+    builtin("FLOOR", 1, Some(1), true, round),
+    builtin("MOD", 2, Some(2), true, modulo),
+    builtin("POWER", 2, Some(2), true, float_math),
+    builtin("SQRT", 1, Some(1), true, float_math),
+    // Conditionals
+    builtin("COALESCE", 1, None, false, coalesce),
+    builtin("NULLIF", 2, Some(2), false, nullif),
+    builtin("GREATEST", 1, None, false, greatest),
+    builtin("LEAST", 1, None, false, greatest),
+    // Dates
+    builtin("NOW", 0, Some(0), false, now),
+];
+
+pub fn find(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|builtin| builtin.name.eq_ignore_ascii_case(name))
+}
+
+fn string_arg(name: &str, args: &[DataValue], idx: usize) -> ::anyhow::Result<String> {
+    match &args[idx] {
+        DataValue::StringValue(val) => Ok(val.clone()),
+        _ => Err(signature_error(name, args)),
+    }
+}
+
+fn int_arg(name: &str, args: &[DataValue], idx: usize) -> ::anyhow::Result<i64> {
+    match &args[idx] {
+        DataValue::I64Value(val) => Ok(*val),
+        _ => Err(signature_error(name, args)),
+    }
+}
+
+fn float_arg(name: &str, args: &[DataValue], idx: usize) -> ::anyhow::Result<f64> {
+    match &args[idx] {
+        DataValue::I64Value(val) => Ok(*val as f64),
+        DataValue::F64Value(val) => Ok(*val),
+        DataValue::NumericValue(val, scale) => Ok(numeric::to_f64(*val, *scale)),
+        _ => Err(signature_error(name, args)),
+    }
+}
+
+fn upper(name: &str, args: Vec<DataValue>) -> ::anyhow::Result<DataValue> {
+    Ok(DataValue::StringValue(string_arg(name, &args, 0)?.to_uppercase()))
+}
+
+fn lower(name: &str, args: Vec<DataValue>) -> ::anyhow::Result<DataValue> {
+    Ok(DataValue::StringValue(string_arg(name, &args, 0)?.to_lowercase()))
+}
+
+fn length(name: &str, args: Vec<DataValue>) -> ::anyhow::Result<DataValue> {
+    Ok(DataValue::I64Value(string_arg(name, &args, 0)?.chars().count() as i64))
+}
+
+// SUBSTRING(string, start [, count]), positions start at 1 and a start
+// before the string still counts towards count, like Postgres.
+fn substring(name: &str, args: Vec<DataValue>) -> ::anyhow::Result<DataValue> {
+    let string: Vec<char> = string_arg(name, &args, 0)?.chars().collect();
+    let start = int_arg(name, &args, 1)?;
+    let end = match args.get(2) {
+        Some(_) => {
+            let count = int_arg(name, &args, 2)?;
+            if count < 0 {
+                return Err(anyhow!("ERROR: negative substring length not allowed"));
+            }
+            start.saturating_add(count)
+        }
+        None => i64::MAX,
+    };
+    let clamp = |position: i64| (position.max(1) - 1).min(string.len() as i64) as usize;
+    let (start, end) = (clamp(start), clamp(end));
+    Ok(DataValue::StringValue(string[start..end.max(start)].iter().collect()))
+}
+
+// TRIM/LTRIM/RTRIM(string [, characters]), characters defaults to spaces
+fn trim(name: &str, args: Vec<DataValue>) -> ::anyhow::Result<DataValue> {
+    let string = string_arg(name, &args, 0)?;
+    let characters: Vec<char> = match args.get(1) {
+        Some(_) => string_arg(name, &args, 1)?.chars().collect(),
+        None => vec![' '],
+    };
+    let trimmed = match name.to_uppercase().as_str() {
+        "LTRIM" => string.trim_start_matches(characters.as_slice()),
+        "RTRIM" => string.trim_end_matches(characters.as_slice()),
+        _ => string.trim_matches(characters.as_slice()),
+    };
+    Ok(DataValue::StringValue(trimmed.to_string()))
+}
+
+fn replace(name: &str, args: Vec<DataValue>) -> ::anyhow::Result<DataValue> {
+    let string = string_arg(name, &args, 0)?;
+    let from = string_arg(name, &args, 1)?;
+    let to = string_arg(name, &args, 2)?;
+    if from.is_empty() {
+        return Ok(DataValue::StringValue(string));
+    }
+    Ok(DataValue::StringValue(string.replace(&from, &to)))
+}
+
+// NULLs are skipped rather than making the whole result NULL
+fn concat(_name: &str, args: Vec<DataValue>) -> ::anyhow::Result<DataValue> {
+    let string: String = args
+        .iter()
+        .filter(|arg| **arg != DataValue::Null)
+        .map(|arg| arg.to_string())
+        .collect();
+    Ok(DataValue::StringValue(string))
+}
+
+fn abs(name: &str, args: Vec<DataValue>) -> ::anyhow::Result<DataValue> {
+    match &args[0] {
+        DataValue::I64Value(val) => {
+            Ok(DataValue::I64Value(val.checked_abs().ok_or_else(|| anyhow!("ERROR: integer out of range"))?))
+        }
+        DataValue::F64Value(val) => Ok(DataValue::F64Value(val.abs())),
+        DataValue::NumericValue(val, scale) => Ok(DataValue::NumericValue(val.abs(), *scale)),
+        _ => Err(signature_error(name, &args)),
+    }
+}
+
+// ROUND(x [, places]), CEIL(x) and FLOOR(x)
+fn round(name: &str, args: Vec<DataValue>) -> ::anyhow::Result<DataValue> {
+    let places = match args.get(1) {
+        Some(_) => int_arg(name, &args, 1)?,
+        None => 0,
+    };
+    let name = name.to_uppercase();
+    match &args[0] {
+        DataValue::I64Value(val) => Ok(DataValue::I64Value(*val)),
+        DataValue::F64Value(val) => {
+            let factor = 10_f64.powi(places.clamp(-308, 308) as i32);
+            Ok(DataValue::F64Value(match name.as_str() {
+                "CEIL" | "CEILING" => val.ceil(),
+                "FLOOR" => val.floor(),
+                _ => (val * factor).round() / factor,
+            }))
+        }
+        DataValue::NumericValue(val, scale) => {
+            let overflow = || anyhow!("ERROR: value overflows numeric format");
+            let places = u32::try_from(places.max(0)).map_err(|_| overflow())?.min(numeric::MAX_PRECISION);
+            let rounded = match name.as_str() {
+                "CEIL" | "CEILING" | "FLOOR" => {
+                    let truncated = numeric::rescale(*val - *val % 10_i128.pow(*scale), *scale, 0).ok_or_else(overflow)?;
+                    let has_fraction = *val % 10_i128.pow(*scale) != 0;
+                    match (name.as_str(), has_fraction, *val > 0) {
+                        ("FLOOR", true, false) => (truncated - 1, 0),
+                        ("CEIL" | "CEILING", true, true) => (truncated + 1, 0),
+                        _ => (truncated, 0),
+                    }
+                }
+                _ => (numeric::rescale(*val, *scale, places).ok_or_else(overflow)?, places),
+            };
+            Ok(DataValue::NumericValue(rounded.0, rounded.1))
+        }
+        _ => Err(signature_error(&name, &args)),
+    }
+}
+
+fn modulo(name: &str, args: Vec<DataValue>) -> ::anyhow::Result<DataValue> {
+    let mut args = args.into_iter();
+    match (args.next(), args.next()) {
+        (Some(left), Some(right)) => DataValue::arithmetic(&ValueOperator::Modulo, left, right),
+        _ => Err(anyhow!("ERROR: function {}() expects 2 arguments", name.to_lowercase())),
+    }
+}
+
+// POWER(x, y) and SQRT(x), always computed as double precision
+fn float_math(name: &str, args: Vec<DataValue>) -> ::anyhow::Result<DataValue> {
+    let value = float_arg(name, &args, 0)?;
+    if name.eq_ignore_ascii_case("SQRT") {
+        if value < 0.0 {
+            return Err(anyhow!("ERROR: cannot take square root of a negative number"));
+        }
+        return Ok(DataValue::F64Value(value.sqrt()));
+    }
+    let result = value.powf(float_arg(name, &args, 1)?);
+    if result.is_nan() {
+        return Err(anyhow!("ERROR: a negative number raised to a non-integer power yields a complex result"));
+    }
+    Ok(DataValue::F64Value(result))
+}
+
+fn coalesce(_name: &str, args: Vec<DataValue>) -> ::anyhow::Result<DataValue> {
+    Ok(args.into_iter().find(|arg| *arg != DataValue::Null).unwrap_or(DataValue::Null))
+}
+
+// NULLIF(a, b) is NULL when a = b, otherwise a
+fn nullif(_name: &str, args: Vec<DataValue>) -> ::anyhow::Result<DataValue> {
+    let mut args = args.into_iter();
+    let (left, right) = (args.next().unwrap_or(DataValue::Null), args.next().unwrap_or(DataValue::Null));
+    if left == DataValue::Null || right == DataValue::Null {
+        return Ok(left);
+    }
+    let (coerced_left, coerced_right) = DataValue::coerce(left.clone(), right)?;
+    if coerced_left == coerced_right || coerced_left.compare(&coerced_right) == Some(Ordering::Equal) {
+        return Ok(DataValue::Null);
+    }
+    Ok(left)
+}
+
+// GREATEST(...) and LEAST(...), NULLs are ignored
+fn greatest(name: &str, args: Vec<DataValue>) -> ::anyhow::Result<DataValue> {
+    let wanted = if name.eq_ignore_ascii_case("LEAST") { Ordering::Less } else { Ordering::Greater };
+    let mut result = DataValue::Null;
+    for arg in args {
+        if arg == DataValue::Null {
+            continue;
+        }
+        if result == DataValue::Null {
+            result = arg;
+            continue;
+        }
+        let (current, candidate) = DataValue::coerce(result, arg)?;
+        result = match candidate.compare(&current) {
+            Some(ordering) if ordering == wanted => candidate,
+            Some(_) => current,
+            None if candidate == current => current,
+            None => return Err(anyhow!("ERROR: could not compare values of type {}", current.type_name())),
+        };
+    }
+    Ok(result)
+}
+
+fn now(_name: &str, _args: Vec<DataValue>) -> ::anyhow::Result<DataValue> {
+    let micros = SystemTime::now().duration_since(UNIX_EPOCH)?.as_micros();
+    Ok(DataValue::TimestampValue(i64::try_from(micros)?))
+}
@@ -0,0 +1,111 @@
+pub mod aggregates;
+pub mod builtins;
+
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, RwLock};
+
+use crate::parser::command::{AggregateFunction, DataValue};
+use crate::table::datatypes::Datatype;
+use anyhow::anyhow;
+
+pub type UserFunctionBody = dyn Fn(&[DataValue]) -> ::anyhow::Result<DataValue> + Send + Sync;
+
+// A scalar function implemented in Rust and registered at runtime. Like a
+// STRICT function in Postgres, it's never called with a NULL argument,
+// the result is just NULL.
+pub struct UserFunction {
+    pub name: String,
+    pub arg_types: Vec<Datatype>,
+    pub return_type: Datatype,
+    body: Arc<UserFunctionBody>,
+}
+
+static USER_FUNCTIONS: LazyLock<RwLock<HashMap<String, Arc<UserFunction>>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
+// Makes a function callable from any query by name, i.e.
+//
+//     register_function("DOUBLE_IT", vec![Datatype::BigInt], Datatype::BigInt, |args| match args[0] {
+//         DataValue::I64Value(val) => Ok(DataValue::I64Value(val * 2)),
+//         _ => unreachable!(),
+//     })?;
+//
+// Arguments are cast to arg_types before the body is called, and the result
+// is cast to return_type.
+pub fn register_function<F>(name: &str, arg_types: Vec<Datatype>, return_type: Datatype, body: F) -> ::anyhow::Result<()>
+where
+    F: Fn(&[DataValue]) -> ::anyhow::Result<DataValue> + Send + Sync + 'static,
+{
+    let key = name.to_uppercase();
+    if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(anyhow!("ERROR: invalid function name '{}'", name));
+    }
+    if builtins::find(&key).is_some() || AggregateFunction::from_name(&key).is_some() {
+        return Err(anyhow!("ERROR: function {} is a built-in and can't be redefined", name.to_lowercase()));
+    }
+
+    let mut user_functions = USER_FUNCTIONS.write().map_err(|_| anyhow!("Function registry is poisoned"))?;
+    if user_functions.contains_key(&key) {
+        return Err(anyhow!("ERROR: function {} already exists", name.to_lowercase()));
+    }
+    let function = UserFunction { name: key.clone(), arg_types, return_type, body: Arc::new(body) };
+    user_functions.insert(key, Arc::new(function));
+    Ok(())
+}
+
+pub fn unregister_function(name: &str) -> ::anyhow::Result<()> {
+    let mut user_functions = USER_FUNCTIONS.write().map_err(|_| anyhow!("Function registry is poisoned"))?;
+    match user_functions.remove(&name.to_uppercase()) {
+        Some(_) => Ok(()),
+        None => Err(anyhow!("ERROR: function {} does not exist", name.to_lowercase())),
+    }
+}
+
+fn find_user_function(name: &str) -> Option<Arc<UserFunction>> {
+    USER_FUNCTIONS.read().ok()?.get(&name.to_uppercase()).cloned()
+}
+
+// Calls a scalar function by (case-insensitive) name with already
+// evaluated arguments.
+pub fn call(name: &str, args: Vec<DataValue>) -> ::anyhow::Result<DataValue> {
+    if let Some(function) = find_user_function(name) {
+        return function.call(args);
+    }
+
+    let Some(builtin) = builtins::find(name) else {
+        return Err(signature_error(name, &args));
+    };
+    if args.len() < builtin.min_args || builtin.max_args.is_some_and(|max_args| args.len() > max_args) {
+        return Err(signature_error(name, &args));
+    }
+    if builtin.strict && args.contains(&DataValue::Null) {
+        return Ok(DataValue::Null);
+    }
+    (builtin.call)(name, args)
+}
+
+impl UserFunction {
+    fn call(&self, args: Vec<DataValue>) -> ::anyhow::Result<DataValue> {
+        if args.len() != self.arg_types.len() {
+            return Err(signature_error(&self.name, &args));
+        }
+        if args.contains(&DataValue::Null) {
+            return Ok(DataValue::Null);
+        }
+        let args = args
+            .into_iter()
+            .zip(&self.arg_types)
+            .map(|(arg, arg_type)| arg_type.cast_value(arg))
+            .collect::<::anyhow::Result<Vec<DataValue>>>()?;
+        let result = (self.body)(&args)
+            .map_err(|err| anyhow!("ERROR: function {} failed: {}", self.name.to_lowercase(), err))?;
+        self.return_type.cast_value(result)
+    }
+}
+
+// Postgres reports a bad call as the function with those argument types
+// not existing, i.e. function upper(integer) does not exist
+pub fn signature_error(name: &str, args: &[DataValue]) -> anyhow::Error {
+    let arg_types: Vec<&str> = args.iter().map(|arg| arg.type_name()).collect();
+    anyhow!("ERROR: function {}({}) does not exist", name.to_lowercase(), arg_types.join(", "))
+}
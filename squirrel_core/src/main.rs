@@ -1,23 +1,31 @@
+pub mod functions;
 pub mod parser;
 pub mod table;
 
 pub use crate::parser::command::Command;
-use crate::parser::command::{CreateCommand, InsertCommand, SelectCommand, DeleteCommand, LogicExpression, InsertItem, DataValue, FunctionCall, ValueExpression};
 pub use crate::table::datatypes::Datatype;
 pub use crate::table::table_definition::{ColumnDefinition, TableDefinition};
 
+#[cfg(test)]
+use crate::table::table_definition::{CheckConstraint, ForeignKey, Identity, ReferentialAction, UniqueConstraint};
+#[cfg(test)]
+use crate::table::sequence_definition::SequenceDefinition;
+
+#[cfg(test)]
+use crate::parser::command::{CreateIndexCommand, DropIndexCommand, CreateSequenceCommand, DropSequenceCommand, AlterTableCommand, AlterTableAction, InsertCommand, InsertItem, InsertSource, UpdateCommand, UpdateItem, DeleteCommand, SelectCommand, SelectColumn, Distinct, Join, JoinType, OrderByItem, DataValue, FunctionCall, ValueExpression, Aggregate, AggregateFunction, LogicExpression, Comparison, LogicalOperator, InExpression, InSource, CommonTableExpression, SetOperation, SetOperator, WindowFunction, WindowFunctionType, WindowFrame, FrameUnits, FrameBound};
+#[cfg(test)]
+use crate::functions::aggregates::Accumulator;
+#[cfg(test)]
 use anyhow::anyhow;
-use std::fs;
-use std::io::{BufRead, BufReader, Read, Write};
-use std::net::{Shutdown, TcpListener, TcpStream};
-use std::thread;
+#[cfg(test)]
 use std::collections::HashMap;
-use std::cmp;
 
+#[allow(dead_code)]
 fn main() { 
 }
 
 #[test]
+#[allow(clippy::bool_assert_comparison)]
 fn value_expression() -> anyhow::Result<()> {
     let tests = HashMap::from([
         ( 
@@ -38,12 +46,32 @@ fn value_expression() -> anyhow::Result<()> {
         ),
         (
             "55",
-            Ok(ValueExpression::DataValue(DataValue::U8Value(55)))
+            Ok(ValueExpression::DataValue(DataValue::I64Value(55)))
         ),
         (
             "\"Name\"",
             Ok(ValueExpression::DataValue(DataValue::StringValue(String::from("Name"))))
         ),
+        (
+            "-1.50",
+            Ok(ValueExpression::DataValue(DataValue::NumericValue(-150, 2)))
+        ),
+        (
+            "1e3",
+            Ok(ValueExpression::DataValue(DataValue::F64Value(1000.0)))
+        ),
+        (
+            "true",
+            Ok(ValueExpression::DataValue(DataValue::BoolValue(true)))
+        ),
+        (
+            "DATE '1970-01-02'",
+            Ok(ValueExpression::DataValue(DataValue::DateValue(1)))
+        ),
+        (
+            "DATE '1970-02-30'",
+            Err(anyhow!("Error"))
+        ),
 
     ]);
 
@@ -68,6 +96,7 @@ fn value_expression() -> anyhow::Result<()> {
 }
 
 #[test]
+#[allow(clippy::bool_assert_comparison)]
 fn logical_expression() -> anyhow::Result<()> {
     assert_eq!(Command::le_from_string(String::from("1 < 5")).unwrap().evaluate().unwrap(), true);
     assert_eq!(Command::le_from_string(String::from("1 > 5")).unwrap().evaluate().unwrap(), false);
@@ -81,10 +110,10 @@ fn logical_expression() -> anyhow::Result<()> {
 
     assert_eq!(Command::le_from_string(String::from("'Test' = 'Test'")).unwrap().evaluate().unwrap(), true);
     assert_eq!(Command::le_from_string(String::from("'Test' = 'Text'")).unwrap().evaluate().unwrap(), false);
-    assert_eq!(Command::le_from_string(String::from("'Test' <= 'Test'")).unwrap().evaluate().is_ok(), false);
-    assert_eq!(Command::le_from_string(String::from("'Test' >= 'Test'")).unwrap().evaluate().is_ok(), false);
-    assert_eq!(Command::le_from_string(String::from("'Test' < 'Test'")).unwrap().evaluate().is_ok(), false);
-    assert_eq!(Command::le_from_string(String::from("'Test' > 'Test'")).unwrap().evaluate().is_ok(), false);
+    assert_eq!(Command::le_from_string(String::from("'Test' <= 'Test'")).unwrap().evaluate().unwrap(), true);
+    assert_eq!(Command::le_from_string(String::from("'Test' >= 'Test'")).unwrap().evaluate().unwrap(), true);
+    assert_eq!(Command::le_from_string(String::from("'Test' < 'Test'")).unwrap().evaluate().unwrap(), false);
+    assert_eq!(Command::le_from_string(String::from("'Test' > 'Test'")).unwrap().evaluate().unwrap(), false);
     assert_eq!(Command::le_from_string(String::from("'Test' AND 'Test'")).unwrap().evaluate().is_ok(), false);
     assert_eq!(Command::le_from_string(String::from("'Test' OR 'Test'")).unwrap().evaluate().is_ok(), false);
 
@@ -102,6 +131,623 @@ fn logical_expression() -> anyhow::Result<()> {
 }
 
 #[test]
+fn compound_logical_expression() -> anyhow::Result<()> {
+    // AND binds tighter than OR
+    assert!(Command::le_from_string(String::from("1 = 1 OR 1 = 2 AND 1 = 2"))?.evaluate()?);
+    assert!(!Command::le_from_string(String::from("( 1 = 1 OR 1 = 2 ) AND 1 = 2"))?.evaluate()?);
+    assert!(Command::le_from_string(String::from("NOT 1 = 2 AND 2 > 1"))?.evaluate()?);
+    assert!(!Command::le_from_string(String::from("NOT (1 = 2 OR 2 > 1)"))?.evaluate()?);
+    assert!(Command::le_from_string(String::from("( ( 1 = 1 ) )"))?.evaluate()?);
+    assert_eq!(Command::le_from_string(String::from("NOT NULL = 1"))?.evaluate_nullable()?, None);
+
+    let mut expression = Command::le_from_string(String::from("id > 1 AND (name = 'Bob' OR name IS NULL)"))?;
+    let mut row = HashMap::new();
+    row.insert(String::from("id"), ValueExpression::DataValue(DataValue::I64Value(2)));
+    row.insert(String::from("name"), ValueExpression::DataValue(DataValue::Null));
+    expression.fill_values(row)?;
+    assert!(expression.evaluate()?);
+
+    assert!(Command::le_from_string(String::from("( 1 = 1")).is_err());
+    assert!(Command::le_from_string(String::from("1 = 1 AND")).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn string_operators() -> anyhow::Result<()> {
+    assert!(Command::le_from_string(String::from("'apple' < 'banana'"))?.evaluate()?);
+    assert!(Command::le_from_string(String::from("'b'>'abc'"))?.evaluate()?);
+    assert!(Command::le_from_string(String::from("'Test' <> 'Text'"))?.evaluate()?);
+    assert!(!Command::le_from_string(String::from("'Test' != 'Test'"))?.evaluate()?);
+
+    assert!(Command::le_from_string(String::from("'Squirrel' LIKE 'Sq%'"))?.evaluate()?);
+    assert!(Command::le_from_string(String::from("'Squirrel' LIKE '%rr_l'"))?.evaluate()?);
+    assert!(!Command::le_from_string(String::from("'Squirrel' LIKE 'sq%'"))?.evaluate()?);
+    assert!(Command::le_from_string(String::from("'Squirrel' ILIKE 'sq%'"))?.evaluate()?);
+    assert!(Command::le_from_string(String::from("'Squirrel' NOT LIKE 'S_'"))?.evaluate()?);
+    assert!(Command::le_from_string(String::from("'50%' LIKE '50\\%'"))?.evaluate()?);
+    assert!(!Command::le_from_string(String::from("'500' LIKE '50\\%'"))?.evaluate()?);
+    assert!(Command::le_from_string(String::from("5 LIKE '5'"))?.evaluate().is_err());
+
+    assert_eq!(
+        Command::value_expression_from_string(String::from("'Squ' || 'irrel'"))?.evaluate()?,
+        DataValue::StringValue(String::from("Squirrel"))
+    );
+    assert_eq!(
+        Command::value_expression_from_string(String::from("'v' || 2"))?.evaluate()?,
+        DataValue::StringValue(String::from("v2"))
+    );
+    assert_eq!(Command::value_expression_from_string(String::from("'v' || NULL"))?.evaluate()?, DataValue::Null);
+    assert!(Command::value_expression_from_string(String::from("1 || 2"))?.evaluate().is_err());
+    assert!(Command::le_from_string(String::from("('a' || 'b') = 'ab'"))?.evaluate()?);
+
+    Ok(())
+}
+
+#[test]
+fn arithmetic_expressions() -> anyhow::Result<()> {
+    let evaluate = |expr: &str| Command::value_expression_from_string(String::from(expr))?.evaluate();
+
+    assert_eq!(evaluate("1 + 2 * 3")?, DataValue::I64Value(7));
+    assert_eq!(evaluate("(1 + 2) * 3")?, DataValue::I64Value(9));
+    assert_eq!(evaluate("10-2-3")?, DataValue::I64Value(5));
+    assert_eq!(evaluate("7 / 2")?, DataValue::I64Value(3));
+    assert_eq!(evaluate("-7 % 3")?, DataValue::I64Value(-1));
+    assert_eq!(evaluate("2 * -3")?, DataValue::I64Value(-6));
+    assert_eq!(evaluate("1.5 + 1")?, DataValue::NumericValue(25, 1));
+    assert_eq!(evaluate("1.25 * 2.5")?, DataValue::NumericValue(3125, 3));
+    assert_eq!(evaluate("1.0 / 4")?, DataValue::NumericValue(2_500_000_000_000_000, 16));
+    assert_eq!(evaluate("1e1 / 4")?, DataValue::F64Value(2.5));
+    assert_eq!(evaluate("DATE '2024-02-28' + 2")?, DataValue::DateValue(19783));
+    assert_eq!(evaluate("DATE '2024-03-01' - DATE '2024-02-28'")?, DataValue::I64Value(2));
+    assert_eq!(evaluate("1 + NULL")?, DataValue::Null);
+    assert_eq!(evaluate("'a' || 1 + 2")?, DataValue::StringValue(String::from("a3")));
+
+    assert!(evaluate("1 / 0").is_err());
+    assert!(evaluate("1.5 % 0").is_err());
+    assert!(evaluate("9223372036854775807 + 1").is_err());
+    assert!(evaluate("'a' + 1").is_err());
+    assert!(evaluate("TRUE * 2").is_err());
+
+    // Integer results have to fit the wider of the two sides' types, where
+    // a literal is an integer if it fits one
+    assert_eq!(evaluate("2147483646 + 1")?, DataValue::I64Value(2147483647));
+    assert_eq!(evaluate("-2147483647 - 1")?, DataValue::I64Value(-2147483648));
+    assert_eq!(evaluate("2147483648 + 1")?, DataValue::I64Value(2147483649));
+    assert_eq!(evaluate("2147483647 + 1").unwrap_err().to_string(), "ERROR: integer out of range");
+    assert_eq!(evaluate("65536 * 32768").unwrap_err().to_string(), "ERROR: integer out of range");
+
+    let evaluate_column = |expr: &str, value: i64, datatype: Datatype| {
+        let mut expression = Command::value_expression_from_string(String::from(expr))?;
+        let column = ValueExpression::column_value(DataValue::I64Value(value), &datatype);
+        expression.fill_values(&HashMap::from([(String::from("a"), column)]));
+        expression.evaluate()
+    };
+    assert_eq!(evaluate_column("-a", -2147483647, Datatype::Integer)?, DataValue::I64Value(2147483647));
+    assert_eq!(evaluate_column("-a", -2147483648, Datatype::Integer).unwrap_err().to_string(), "ERROR: integer out of range");
+    assert_eq!(evaluate_column("-a", -2147483648, Datatype::BigInt)?, DataValue::I64Value(2147483648));
+    assert_eq!(evaluate_column("a * a", 181, Datatype::SmallInt)?, DataValue::I64Value(32761));
+    assert_eq!(evaluate_column("a * a", 32768 / 2, Datatype::SmallInt).unwrap_err().to_string(), "ERROR: smallint out of range");
+    assert_eq!(evaluate_column("-a", -32768, Datatype::SmallInt).unwrap_err().to_string(), "ERROR: smallint out of range");
+    assert_eq!(evaluate_column("a * 2", 32767, Datatype::SmallInt)?, DataValue::I64Value(65534));
+    assert_eq!(evaluate_column("a * 2.5", 2147483647, Datatype::Integer)?, DataValue::NumericValue(53687091175, 1));
+
+    assert!(Command::le_from_string(String::from("2 * 3 > 5"))?.evaluate()?);
+    assert!(Command::le_from_string(String::from("(1 + 1) * 2 = 4"))?.evaluate()?);
+
+    Ok(())
+}
+
+#[test]
+fn scalar_functions() -> anyhow::Result<()> {
+    let evaluate = |expr: &str| Command::value_expression_from_string(String::from(expr))?.evaluate();
+    let string = |val: &str| DataValue::StringValue(String::from(val));
+
+    assert_eq!(
+        Command::value_expression_from_string(String::from("SUBSTRING(name FROM 2 FOR 3)"))?,
+        ValueExpression::FunctionCall(FunctionCall {
+            function_name: String::from("SUBSTRING"),
+            parameters: vec![
+                ValueExpression::ColumnName(String::from("name")),
+                ValueExpression::DataValue(DataValue::I64Value(2)),
+                ValueExpression::DataValue(DataValue::I64Value(3)),
+            ],
+        })
+    );
+
+    assert_eq!(evaluate("UPPER('squirrel')")?, string("SQUIRREL"));
+    assert_eq!(evaluate("lower('SQUIRREL')")?, string("squirrel"));
+    assert_eq!(evaluate("LENGTH(TRIM('  nut  '))")?, DataValue::I64Value(3));
+    assert_eq!(evaluate("SUBSTRING('squirrel', 2, 3)")?, string("qui"));
+    assert_eq!(evaluate("SUBSTRING('squirrel', 0, 2)")?, string("s"));
+    assert_eq!(evaluate("SUBSTRING('squirrel' FROM 5)")?, string("rrel"));
+    assert_eq!(evaluate("LTRIM('xxnutxx', 'x')")?, string("nutxx"));
+    assert_eq!(evaluate("ABS(-5) + ABS(-1.5)")?, DataValue::NumericValue(65, 1));
+    assert_eq!(evaluate("ROUND(2.345, 2)")?, DataValue::NumericValue(235, 2));
+    assert_eq!(evaluate("FLOOR(-2.5)")?, DataValue::NumericValue(-3, 0));
+    assert_eq!(evaluate("COALESCE(NULL, NULL, 'nut')")?, string("nut"));
+    assert_eq!(evaluate("NULLIF(1, 1.0)")?, DataValue::Null);
+    assert_eq!(evaluate("GREATEST(1, NULL, 3, 2)")?, DataValue::I64Value(3));
+    assert_eq!(evaluate("LEAST('b', 'a')")?, string("a"));
+    assert_eq!(evaluate("CONCAT('a', NULL, 1)")?, string("a1"));
+    assert_eq!(evaluate("UPPER(NULL)")?, DataValue::Null);
+    assert!(matches!(evaluate("NOW()")?, DataValue::TimestampValue(_)));
+
+    assert!(evaluate("UPPER(1)").is_err());
+    assert!(evaluate("UPPER('a', 'b')").is_err());
+    assert!(evaluate("NOT_A_FUNCTION()").is_err());
+    assert!(evaluate("SUBSTRING('squirrel', 1, -1)").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn user_defined_functions() -> anyhow::Result<()> {
+    functions::register_function("TEST_REPEAT", vec![Datatype::CharacterVarying, Datatype::SmallInt], Datatype::CharacterVarying, |args| {
+        match (&args[0], &args[1]) {
+            (DataValue::StringValue(val), DataValue::I64Value(count)) => Ok(DataValue::StringValue(val.repeat(*count as usize))),
+            _ => Err(anyhow!("unexpected arguments")),
+        }
+    })?;
+    let evaluate = |expr: &str| Command::value_expression_from_string(String::from(expr))?.evaluate();
+
+    assert_eq!(evaluate("test_repeat('ab', 3)")?, DataValue::StringValue(String::from("ababab")));
+    assert_eq!(evaluate("TEST_REPEAT('ab', 2.4)")?, DataValue::StringValue(String::from("abab")));
+    assert_eq!(evaluate("TEST_REPEAT(NULL, 2)")?, DataValue::Null);
+    assert!(evaluate("TEST_REPEAT('ab')").is_err());
+    assert!(evaluate("TEST_REPEAT('ab', 100000)").is_err());
+    assert!(evaluate("TEST_REPEAT('ab', TRUE)").is_err());
+
+    assert!(functions::register_function("test_repeat", vec![], Datatype::Boolean, |_| Ok(DataValue::Null)).is_err());
+    assert!(functions::register_function("UPPER", vec![], Datatype::Boolean, |_| Ok(DataValue::Null)).is_err());
+
+    functions::unregister_function("TEST_REPEAT")?;
+    assert!(evaluate("TEST_REPEAT('ab', 3)").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn aggregate_functions() -> anyhow::Result<()> {
+    let count_all = ValueExpression::Aggregate(Aggregate { function: AggregateFunction::Count, argument: None, distinct: false });
+    assert_eq!(
+        Command::from_string(String::from("SELECT city, COUNT(*) FROM users GROUP BY city HAVING COUNT(*) > 1;"))?,
+        Command::Select(Box::new(SelectCommand {
+            with: vec![],
+            table_name: String::from("users"),
+            table_alias: None,
+            joins: vec![],
+            distinct: None,
+            columns: vec![
+                SelectColumn::Expression(ValueExpression::ColumnName(String::from("city")), None),
+                SelectColumn::Expression(count_all.clone(), None),
+            ],
+            logic_expression: None,
+            group_by: vec![ValueExpression::ColumnName(String::from("city"))],
+            having: Some(LogicExpression::Comparison(Comparison {
+                left_hand: count_all,
+                right_hand: ValueExpression::DataValue(DataValue::I64Value(1)),
+                operator: LogicalOperator::GreaterThan,
+            })),
+            order_by: vec![],
+            limit: None,
+            offset: 0,
+            set_operations: vec![],
+        }))
+    );
+    assert_eq!(
+        Command::value_expression_from_string(String::from("sum(DISTINCT age)"))?,
+        ValueExpression::Aggregate(Aggregate {
+            function: AggregateFunction::Sum,
+            argument: Some(Box::new(ValueExpression::ColumnName(String::from("age")))),
+            distinct: true,
+        })
+    );
+    assert!(Command::from_string(String::from("SELECT COUNT(*) FROM users HAVING COUNT(*) > 1 GROUP BY city;")).is_err());
+    assert!(Command::value_expression_from_string(String::from("SUM(*)")).is_err());
+
+    let accumulate = |expr: &str, values: Vec<DataValue>| -> anyhow::Result<DataValue> {
+        let ValueExpression::Aggregate(aggregate) = Command::value_expression_from_string(String::from(expr))? else {
+            return Err(anyhow!("not an aggregate"));
+        };
+        let mut accumulator = Accumulator::new(&aggregate);
+        for value in values {
+            accumulator.update(value)?;
+        }
+        accumulator.finish()
+    };
+    let ints = vec![DataValue::I64Value(1), DataValue::Null, DataValue::I64Value(2), DataValue::I64Value(2)];
+
+    assert_eq!(accumulate("COUNT(x)", ints.clone())?, DataValue::I64Value(3));
+    assert_eq!(accumulate("COUNT(DISTINCT x)", ints.clone())?, DataValue::I64Value(2));
+    assert_eq!(accumulate("SUM(x)", ints.clone())?, DataValue::I64Value(5));
+    assert_eq!(accumulate("MIN(x)", ints.clone())?, DataValue::I64Value(1));
+    assert_eq!(accumulate("MAX(x)", ints.clone())?, DataValue::I64Value(2));
+    assert_eq!(accumulate("AVG(x)", vec![DataValue::I64Value(1), DataValue::I64Value(2)])?, DataValue::NumericValue(15_000_000_000_000_000, 16));
+    assert_eq!(accumulate("SUM(x)", vec![DataValue::I64Value(i64::MAX), DataValue::I64Value(1)])?, DataValue::NumericValue(i128::from(i64::MAX) + 1, 0));
+    assert_eq!(accumulate("SUM(x)", vec![])?, DataValue::Null);
+    assert_eq!(accumulate("COUNT(x)", vec![])?, DataValue::I64Value(0));
+    assert!(accumulate("SUM(x)", vec![DataValue::StringValue(String::from("a"))]).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn order_by_and_limit() -> anyhow::Result<()> {
+    let Command::Select(select) = Command::from_string(String::from(
+        "SELECT name FROM users ORDER BY age DESC, name NULLS FIRST, 1 ASC NULLS LAST LIMIT 10 OFFSET 5;",
+    ))?
+    else {
+        return Err(anyhow!("not a select"));
+    };
+    assert_eq!(
+        select.order_by,
+        vec![
+            OrderByItem { expression: ValueExpression::ColumnName(String::from("age")), descending: true, nulls_first: true },
+            OrderByItem { expression: ValueExpression::ColumnName(String::from("name")), descending: false, nulls_first: true },
+            OrderByItem { expression: ValueExpression::DataValue(DataValue::I64Value(1)), descending: false, nulls_first: false },
+        ]
+    );
+    assert_eq!((select.limit, select.offset), (Some(10), 5));
+
+    let Command::Select(select) = Command::from_string(String::from("SELECT * FROM users OFFSET 2 ROWS LIMIT ALL;"))? else {
+        return Err(anyhow!("not a select"));
+    };
+    assert_eq!((select.limit, select.offset), (None, 2));
+
+    assert!(Command::from_string(String::from("SELECT * FROM users LIMIT -1;")).is_err());
+    assert!(Command::from_string(String::from("SELECT * FROM users LIMIT 'a';")).is_err());
+    assert!(Command::from_string(String::from("SELECT * FROM users LIMIT 1 ORDER BY id;")).is_err());
+    assert!(Command::from_string(String::from("SELECT * FROM users ORDER BY id NULLS;")).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn distinct_and_aliases() -> anyhow::Result<()> {
+    let Command::Select(select) = Command::from_string(String::from(
+        "SELECT DISTINCT ON (city, age) name AS who, age + 1 AS \"Next Age\" FROM users;",
+    ))?
+    else {
+        return Err(anyhow!("not a select"));
+    };
+    assert_eq!(
+        select.distinct,
+        Some(Distinct::On(vec![
+            ValueExpression::ColumnName(String::from("city")),
+            ValueExpression::ColumnName(String::from("age")),
+        ]))
+    );
+    assert_eq!(select.columns[0], SelectColumn::Expression(ValueExpression::ColumnName(String::from("name")), Some(String::from("who"))));
+    assert!(matches!(&select.columns[1], SelectColumn::Expression(_, Some(alias)) if alias == "Next Age"));
+
+    let Command::Select(select) = Command::from_string(String::from("SELECT DISTINCT city, * FROM users;"))? else {
+        return Err(anyhow!("not a select"));
+    };
+    assert_eq!(select.distinct, Some(Distinct::AllColumns));
+    assert_eq!(select.columns[1], SelectColumn::Wildcard);
+
+    assert!(Command::from_string(String::from("SELECT DISTINCT ON city name FROM users;")).is_err());
+    assert!(Command::from_string(String::from("SELECT name AS FROM users;")).is_err());
+    assert!(Command::from_string(String::from("SELECT * AS everything FROM users;")).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn joins() -> anyhow::Result<()> {
+    let Command::Select(select) = Command::from_string(String::from(
+        "SELECT u.*, o.total FROM users u LEFT OUTER JOIN orders AS o ON u.id = o.user_id CROSS JOIN cities, tags t WHERE o.total > 5;",
+    ))?
+    else {
+        return Err(anyhow!("not a select"));
+    };
+    assert_eq!(select.columns[0], SelectColumn::TableWildcard(String::from("u")));
+    assert_eq!(select.columns[1], SelectColumn::Expression(ValueExpression::ColumnName(String::from("o.total")), None));
+    assert_eq!((select.table_name.as_str(), select.table_alias.as_deref()), ("users", Some("u")));
+    assert_eq!(
+        select.joins[0],
+        Join {
+            join_type: JoinType::Left,
+            table_name: String::from("orders"),
+            table_alias: Some(String::from("o")),
+            condition: Some(LogicExpression::Comparison(Comparison {
+                left_hand: ValueExpression::ColumnName(String::from("u.id")),
+                right_hand: ValueExpression::ColumnName(String::from("o.user_id")),
+                operator: LogicalOperator::Equal,
+            })),
+        }
+    );
+    let joins: Vec<(JoinType, &str, Option<&str>)> = select.joins[1..]
+        .iter()
+        .map(|join| (join.join_type, join.table_name.as_str(), join.table_alias.as_deref()))
+        .collect();
+    assert_eq!(joins, vec![(JoinType::Cross, "cities", None), (JoinType::Cross, "tags", Some("t"))]);
+    assert!(select.logic_expression.is_some());
+
+    assert_eq!(Command::value_expression_from_string(String::from("o.total"))?.output_name(), "total");
+    assert!(Command::from_string(String::from("SELECT * FROM users u JOIN orders o;")).is_err());
+    assert!(Command::from_string(String::from("SELECT * FROM users u INNER OUTER JOIN orders o ON u.id = o.id;")).is_err());
+    assert!(Command::from_string(String::from("SELECT * FROM users AS WHERE id = 1;")).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn subqueries() -> anyhow::Result<()> {
+    assert!(Command::le_from_string(String::from("2 IN (1, 2, 3)"))?.evaluate()?);
+    assert!(Command::le_from_string(String::from("4 NOT IN (1, 2, 3)"))?.evaluate()?);
+    // x NOT IN (..., NULL) is never true
+    assert!(!Command::le_from_string(String::from("4 NOT IN (1, NULL)"))?.evaluate()?);
+
+    let Command::Select(select) = Command::from_string(String::from(
+        "SELECT name, (SELECT MAX(total) FROM orders o WHERE o.user_id = u.id) FROM users u WHERE id IN (SELECT user_id FROM orders) AND NOT EXISTS (SELECT 1 FROM bans);",
+    ))?
+    else {
+        return Err(anyhow!("not a select"));
+    };
+    let SelectColumn::Expression(ValueExpression::Subquery(scalar), None) = &select.columns[1] else {
+        return Err(anyhow!("not a scalar subquery"));
+    };
+    assert_eq!(scalar.table_alias.as_deref(), Some("o"));
+    assert_eq!(select.columns[1], SelectColumn::Expression(ValueExpression::Subquery(scalar.clone()), None));
+
+    let mut where_clause = select.logic_expression.clone().unwrap();
+    assert!(where_clause.has_subqueries());
+    let LogicExpression::And(in_expression, _) = &where_clause else {
+        return Err(anyhow!("not an AND"));
+    };
+    let LogicExpression::In(InExpression { source: InSource::Subquery(subquery), negated: false, .. }) = in_expression.as_ref() else {
+        return Err(anyhow!("not an IN subquery"));
+    };
+    assert_eq!(subquery.table_name, "orders");
+
+    // Subqueries are run by whoever evaluates the expression
+    where_clause.fill_values(HashMap::from([(String::from("id"), ValueExpression::DataValue(DataValue::I64Value(2)))]))?;
+    where_clause.resolve_subqueries(&mut |subquery| match subquery.table_name.as_str() {
+        "orders" => Ok((1, vec![vec![DataValue::I64Value(1)], vec![DataValue::I64Value(2)]])),
+        _ => Ok((1, vec![])),
+    })?;
+    assert!(where_clause.evaluate()?);
+
+    let mut scalar = Command::value_expression_from_string(String::from("(SELECT id FROM users)"))?;
+    assert!(scalar.resolve_subqueries(&mut |_| Ok((1, vec![vec![DataValue::I64Value(1)], vec![DataValue::I64Value(2)]]))).is_err());
+    assert!(Command::from_string(String::from("SELECT * FROM users WHERE id IN (SELECT id FROM users;")).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn common_table_expressions() -> anyhow::Result<()> {
+    let Command::Select(select) = Command::from_string(String::from(
+        "WITH RECURSIVE tree (id, depth) AS (SELECT id, 0 FROM emp WHERE boss IS NULL UNION ALL SELECT e.id, t.depth + 1 FROM emp e JOIN tree t ON e.boss = t.id), leaves AS (SELECT id FROM tree) SELECT * FROM leaves;",
+    ))?
+    else {
+        return Err(anyhow!("not a select"));
+    };
+    assert_eq!(select.table_name, "leaves");
+    let [tree, leaves] = &select.with[..] else {
+        return Err(anyhow!("expected two WITH queries"));
+    };
+    assert_eq!((tree.name.as_str(), tree.column_names.clone(), tree.recursive), ("tree", vec![String::from("id"), String::from("depth")], true));
+    assert_eq!(tree.query.table_name, "emp");
+    let [SetOperation { operator: SetOperator::Union, all: true, query: recursive_query }] = &tree.query.set_operations[..] else {
+        return Err(anyhow!("expected UNION ALL"));
+    };
+    assert_eq!(recursive_query.joins[0].table_name, "tree");
+    assert_eq!(
+        *leaves,
+        CommonTableExpression {
+            name: String::from("leaves"),
+            column_names: vec![],
+            query: Box::new(match Command::from_string(String::from("SELECT id FROM tree;"))? {
+                Command::Select(select) => *select,
+                _ => return Err(anyhow!("not a select")),
+            }),
+            recursive: true,
+        }
+    );
+
+    assert!(Command::from_string(String::from("WITH t AS (SELECT id FROM a), t AS (SELECT id FROM b) SELECT * FROM t;")).is_err());
+    assert!(Command::from_string(String::from("WITH t AS SELECT id FROM a SELECT * FROM t;")).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn set_operations() -> anyhow::Result<()> {
+    let Command::Select(select) = Command::from_string(String::from(
+        "SELECT id FROM a UNION ALL SELECT id FROM b INTERSECT SELECT id FROM c EXCEPT (SELECT id FROM d LIMIT 1) ORDER BY id DESC LIMIT 5;",
+    ))?
+    else {
+        return Err(anyhow!("not a select"));
+    };
+    // INTERSECT binds tighter, so it's part of the query after UNION ALL
+    let operations: Vec<(SetOperator, bool, &str)> = select
+        .set_operations
+        .iter()
+        .map(|set_operation| (set_operation.operator, set_operation.all, set_operation.query.table_name.as_str()))
+        .collect();
+    assert_eq!(operations, vec![(SetOperator::Union, true, "b"), (SetOperator::Except, false, "d")]);
+    assert_eq!(select.set_operations[0].query.set_operations[0].operator, SetOperator::Intersect);
+    assert_eq!(select.set_operations[0].query.set_operations[0].query.table_name, "c");
+
+    // The trailing ORDER BY and LIMIT are for the whole query, a
+    // parenthesized query keeps its own
+    assert_eq!((select.order_by.len(), select.limit), (1, Some(5)));
+    assert_eq!(select.set_operations[1].query.limit, Some(1));
+
+    assert!(Command::from_string(String::from("SELECT id FROM a ORDER BY id UNION SELECT id FROM b;")).is_err());
+    assert!(Command::from_string(String::from("(SELECT id FROM a LIMIT 1) UNION SELECT id FROM b;")).is_err());
+
+    assert_eq!(Datatype::Integer.common_type(&Datatype::BigInt), Some(Datatype::BigInt));
+    assert_eq!(Datatype::Integer.common_type(&Datatype::Numeric(Some((6, 2)))), Some(Datatype::Numeric(None)));
+    assert_eq!(Datatype::Date.common_type(&Datatype::Timestamp), Some(Datatype::Timestamp));
+    assert_eq!(Datatype::Integer.common_type(&Datatype::CharacterVarying), None);
+
+    Ok(())
+}
+
+#[test]
+fn window_functions() -> anyhow::Result<()> {
+    let Command::Select(select) = Command::from_string(String::from(
+        "SELECT SUM(amount) OVER (PARTITION BY region ORDER BY id ROWS BETWEEN 1 PRECEDING AND CURRENT ROW) FROM sales;",
+    ))?
+    else {
+        return Err(anyhow!("not a select"));
+    };
+    let aggregate = Aggregate {
+        function: AggregateFunction::Sum,
+        argument: Some(Box::new(ValueExpression::ColumnName(String::from("amount")))),
+        distinct: false,
+    };
+    let window = WindowFunction {
+        function: WindowFunctionType::Aggregate(aggregate),
+        arguments: vec![],
+        partition_by: vec![ValueExpression::ColumnName(String::from("region"))],
+        order_by: vec![OrderByItem {
+            expression: ValueExpression::ColumnName(String::from("id")),
+            descending: false,
+            nulls_first: false,
+        }],
+        frame: Some(WindowFrame { units: FrameUnits::Rows, start: FrameBound::Preceding(1), end: FrameBound::CurrentRow }),
+    };
+    let SelectColumn::Expression(expression, _) = &select.columns[0] else {
+        return Err(anyhow!("not an expression"));
+    };
+    assert_eq!(expression, &ValueExpression::Window(Box::new(window)));
+    // Aggregates used as window functions don't group the query
+    assert!(expression.aggregates().is_empty());
+    assert_eq!(expression.windows().len(), 1);
+
+    assert!(Command::from_string(String::from("SELECT ROW_NUMBER() FROM sales;")).is_err());
+    assert!(Command::from_string(String::from("SELECT LAG(amount) OVER (ROWS UNBOUNDED FOLLOWING) FROM sales;")).is_err());
+    assert!(Command::from_string(String::from("SELECT UPPER(region) OVER () FROM sales;")).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn integer_datatypes() -> anyhow::Result<()> {
+    assert_eq!(Datatype::SmallInt.to_bytes(String::from("-2"))?, vec![0xfe, 0xff]);
+    assert_eq!(Datatype::Integer.to_bytes(String::from("300"))?, vec![0x2c, 0x01, 0, 0]);
+    assert_eq!(Datatype::BigInt.to_bytes(String::from("1"))?, vec![1, 0, 0, 0, 0, 0, 0, 0]);
+
+    assert!(Datatype::SmallInt.to_bytes(String::from("32768")).is_err());
+    assert!(Datatype::Integer.to_bytes(String::from("2147483648")).is_err());
+    assert!(Datatype::Integer.to_bytes(String::from("abc")).is_err());
+
+    for (datatype, value) in [
+        (Datatype::SmallInt, "-32768"),
+        (Datatype::Integer, "2147483647"),
+        (Datatype::BigInt, "-9000000000"),
+    ] {
+        let bytes = datatype.to_bytes(String::from(value))?;
+        assert_eq!(bytes.len(), datatype.get_byte_size().unwrap());
+        assert_eq!(datatype.from_bytes(&bytes)?, value);
+    }
+
+    assert!(Command::le_from_string(String::from("300 > -5")).unwrap().evaluate().unwrap());
+
+    Ok(())
+}
+
+#[test]
+fn datatypes() -> anyhow::Result<()> {
+    let round_trips = [
+        (Datatype::Boolean, "yes", "TRUE"),
+        (Datatype::Real, "0.1", "0.1"),
+        (Datatype::DoublePrecision, "-2.5", "-2.5"),
+        (Datatype::Numeric(Some((5, 2))), "1.005", "1.01"),
+        (Datatype::Numeric(Some((5, 2))), "-3", "-3.00"),
+        (Datatype::Numeric(None), "-0.001", "-0.001"),
+        (Datatype::Date, "2024-02-29", "2024-02-29"),
+        (Datatype::Date, "1900-03-01", "1900-03-01"),
+        (Datatype::Timestamp, "1999-12-31T23:59:59.5", "1999-12-31 23:59:59.5"),
+        (Datatype::Timestamp, "1969-07-20", "1969-07-20 00:00:00"),
+    ];
+
+    for (datatype, input, output) in round_trips {
+        let bytes = datatype.to_bytes(String::from(input))?;
+        assert_eq!(bytes.len(), datatype.get_byte_size().unwrap());
+        assert_eq!(datatype.from_bytes(&bytes)?, output);
+    }
+
+    assert!(Datatype::Boolean.to_bytes(String::from("maybe")).is_err());
+    assert!(Datatype::Numeric(Some((5, 2))).to_bytes(String::from("1000")).is_err());
+    assert!(Datatype::Date.to_bytes(String::from("2023-02-29")).is_err());
+    assert!(Datatype::Timestamp.to_bytes(String::from("2023-01-01 24:00:00")).is_err());
+
+    assert_eq!(Datatype::parse_from_str("numeric(10,2)")?, Datatype::Numeric(Some((10, 2))));
+    assert_eq!(Datatype::parse_from_str("double precision")?, Datatype::DoublePrecision);
+    assert!(Datatype::parse_from_str("numeric(2,3)").is_err());
+
+    assert!(Command::le_from_string(String::from("1.50 = 1.5")).unwrap().evaluate().unwrap());
+    assert!(Command::le_from_string(String::from("2 > 1.5")).unwrap().evaluate().unwrap());
+    assert!(Command::le_from_string(String::from("1e3 >= 1000")).unwrap().evaluate().unwrap());
+    assert!(Command::le_from_string(String::from("DATE '2024-01-01' < '2024-01-02'")).unwrap().evaluate().unwrap());
+    assert!(Command::le_from_string(String::from("TRUE AND FALSE")).unwrap().evaluate().is_ok());
+    assert!(Command::le_from_string(String::from("TRUE = 1")).unwrap().evaluate().is_err());
+
+    let create_statement = "CREATE TABLE items (price numeric(10,2), ratio double precision, added date);";
+    if let Command::Create(create_command) = Command::from_string(String::from(create_statement))? {
+        let data_types: Vec<Datatype> = create_command.table_definition.column_defs.into_iter().map(|col_def| col_def.data_type).collect();
+        assert_eq!(data_types, vec![Datatype::Numeric(Some((10, 2))), Datatype::DoublePrecision, Datatype::Date]);
+    } else {
+        panic!("Expected CREATE command");
+    }
+
+    let create_statement = "CREATE TABLE users (id bigint PRIMARY KEY, name varchar 20);";
+    if let Command::Create(create_command) = Command::from_string(String::from(create_statement))? {
+        let primary_keys: Vec<bool> = create_command.table_definition.column_defs.iter().map(|col_def| col_def.primary_key).collect();
+        assert_eq!(primary_keys, vec![true, false]);
+    } else {
+        panic!("Expected CREATE command");
+    }
+    assert!(Command::from_string(String::from("CREATE TABLE users (id int PRIMARY KEY, code int PRIMARY KEY);")).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn null_values() -> anyhow::Result<()> {
+    assert_eq!(
+        Command::value_expression_from_string(String::from("NULL"))?,
+        ValueExpression::DataValue(DataValue::Null)
+    );
+
+    assert!(Command::le_from_string(String::from("NULL IS NULL")).unwrap().evaluate().unwrap());
+    assert!(!Command::le_from_string(String::from("5 IS NULL")).unwrap().evaluate().unwrap());
+    assert!(Command::le_from_string(String::from("5 IS NOT NULL")).unwrap().evaluate().unwrap());
+    assert!(!Command::le_from_string(String::from("NULL = NULL")).unwrap().evaluate().unwrap());
+
+    assert_eq!(Command::le_from_string(String::from("5 < NULL")).unwrap().evaluate_nullable().unwrap(), None);
+    assert_eq!(Command::le_from_string(String::from("NULL AND FALSE")).unwrap().evaluate_nullable().unwrap(), Some(false));
+    assert_eq!(Command::le_from_string(String::from("NULL AND TRUE")).unwrap().evaluate_nullable().unwrap(), None);
+    assert_eq!(Command::le_from_string(String::from("TRUE OR NULL")).unwrap().evaluate_nullable().unwrap(), Some(true));
+    assert_eq!(Command::le_from_string(String::from("FALSE OR NULL")).unwrap().evaluate_nullable().unwrap(), None);
+
+    let tabledef = TableDefinition {
+        name: String::from("users"),
+        column_defs: vec![
+            ColumnDefinition::new(String::from("id"), Datatype::Integer, 0),
+            ColumnDefinition::new(String::from("name"), Datatype::CharacterVarying, 8),
+        ],
+        foreign_keys: vec![],
+        unique_constraints: vec![],
+        check_constraints: vec![],
+    };
+    let values = vec![DataValue::I64Value(7), DataValue::Null];
+    let row = tabledef.encode_row(&values)?;
+    assert_eq!(row.len(), tabledef.get_byte_size());
+    assert_eq!(row[0], 0b10);
+    assert_eq!(tabledef.decode_row(&row)?, values);
+
+    Ok(())
+}
+
+#[test]
+#[allow(clippy::bool_assert_comparison)]
 fn insert_statement() -> anyhow::Result<()> {
     let empty_statement = "";
     let regular_statement = "INSERT INTO users (id, name) VALUES (1, \"Test\");";
@@ -112,42 +758,44 @@ fn insert_statement() -> anyhow::Result<()> {
 
     let expected_output = Command::Insert(InsertCommand {
         table_name: "users".to_string(),
-        items: HashMap::from([
+        source: InsertSource::Values(vec![HashMap::from([
             (
                 "id".to_string(),
                 InsertItem {
                     column_name: "id".to_string(),
-                    column_value: "1".to_string(),
+                    column_value: DataValue::I64Value(1),
                 },
             ),
             (
                 "name".to_string(),
                 InsertItem {
                     column_name: "name".to_string(),
-                    column_value: "Test".to_string(),
+                    column_value: DataValue::StringValue("Test".to_string()),
                 },
             ),
-        ]),
+        ])]),
+        returning: vec![],
     });
 
     let expected_output_comma = Command::Insert(InsertCommand {
         table_name: "users".to_string(),
-        items: HashMap::from([
+        source: InsertSource::Values(vec![HashMap::from([
             (
                 "id".to_string(),
                 InsertItem {
                     column_name: "id".to_string(),
-                    column_value: "1".to_string(),
+                    column_value: DataValue::I64Value(1),
                 },
             ),
             (
                 "name".to_string(),
                 InsertItem {
                     column_name: "name".to_string(),
-                    column_value: "Firstname, Lastname".to_string(),
+                    column_value: DataValue::StringValue("Firstname, Lastname".to_string()),
                 },
             ),
-        ]),
+        ])]),
+        returning: vec![],
     });
 
     assert_eq!(
@@ -175,6 +823,396 @@ fn insert_statement() -> anyhow::Result<()> {
         expected_output_comma
     );
 
+    let Command::Insert(insert) = Command::from_string(String::from("INSERT INTO users (id) VALUES (1), (2), (3);"))? else {
+        return Err(anyhow!("not an insert"));
+    };
+    let InsertSource::Values(rows) = insert.source else {
+        return Err(anyhow!("not a VALUES insert"));
+    };
+    let ids: Vec<DataValue> = rows.iter().map(|row| row["id"].column_value.clone()).collect();
+    assert_eq!(ids, vec![DataValue::I64Value(1), DataValue::I64Value(2), DataValue::I64Value(3)]);
+    assert!(Command::from_string(String::from("INSERT INTO users (id) VALUES (1), (2, 3);")).is_err());
+
+    let Command::Insert(insert) = Command::from_string(String::from("INSERT INTO users (id, name) SELECT id, name FROM staff;"))? else {
+        return Err(anyhow!("not an insert"));
+    };
+    let InsertSource::Select(column_names, query) = insert.source else {
+        return Err(anyhow!("not an INSERT ... SELECT"));
+    };
+    assert_eq!((column_names, query.table_name.as_str()), (vec![String::from("id"), String::from("name")], "staff"));
+
+
+    Ok(())
+}
+
+#[test]
+fn returning_clause() -> anyhow::Result<()> {
+    assert_eq!(
+        Command::from_string(String::from("DELETE FROM users WHERE id = 1 RETURNING *, name AS n;"))?,
+        Command::Delete(DeleteCommand {
+            table_name: "users".to_string(),
+            logic_expression: Some(Command::le_from_string(String::from("id = 1"))?),
+            returning: vec![
+                SelectColumn::Wildcard,
+                SelectColumn::Expression(ValueExpression::ColumnName("name".to_string()), Some("n".to_string())),
+            ],
+        })
+    );
+
+    // The RETURNING of an INSERT ... SELECT isn't part of the query, even
+    // when the query has a string that looks like one
+    let Command::Insert(insert) = Command::from_string(String::from(
+        "INSERT INTO users (id) SELECT id FROM staff WHERE name = 'RETURNING' RETURNING id;",
+    ))?
+    else {
+        return Err(anyhow!("not an insert"));
+    };
+    let InsertSource::Select(_, query) = insert.source else {
+        return Err(anyhow!("not an INSERT ... SELECT"));
+    };
+    assert!(query.logic_expression.is_some());
+    assert_eq!(insert.returning, vec![SelectColumn::Expression(ValueExpression::ColumnName("id".to_string()), None)]);
+
+    let Command::Update(update) = Command::from_string(String::from("UPDATE users SET age = age + 1 RETURNING age;"))? else {
+        return Err(anyhow!("not an update"));
+    };
+    assert_eq!(update.returning.len(), 1);
+    assert!(Command::from_string(String::from("DELETE FROM users RETURNING;")).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn update_statement() -> anyhow::Result<()> {
+    let regular_statement = "UPDATE users SET age = 34, name = \"Test\" WHERE id = 1;";
+    let no_where_statement = "UPDATE users SET age = id;";
+
+    let expected_output = Command::Update(UpdateCommand {
+        table_name: "users".to_string(),
+        items: HashMap::from([
+            (
+                "age".to_string(),
+                UpdateItem {
+                    column_name: "age".to_string(),
+                    column_value: ValueExpression::DataValue(DataValue::I64Value(34)),
+                },
+            ),
+            (
+                "name".to_string(),
+                UpdateItem {
+                    column_name: "name".to_string(),
+                    column_value: ValueExpression::DataValue(DataValue::StringValue("Test".to_string())),
+                },
+            ),
+        ]),
+        logic_expression: Some(Command::le_from_string(String::from("id = 1"))?),
+        returning: vec![],
+    });
+
+    let expected_output_no_where = Command::Update(UpdateCommand {
+        table_name: "users".to_string(),
+        items: HashMap::from([
+            (
+                "age".to_string(),
+                UpdateItem {
+                    column_name: "age".to_string(),
+                    column_value: ValueExpression::ColumnName("id".to_string()),
+                },
+            ),
+        ]),
+        logic_expression: None,
+        returning: vec![],
+    });
+
+    assert_eq!(
+        Command::from_string(String::from(regular_statement))?,
+        expected_output
+    );
+
+    assert_eq!(
+        Command::from_string(String::from(no_where_statement))?,
+        expected_output_no_where
+    );
+
+    assert!(
+        Command::from_string(String::from("UPDATE users age = 1;")).is_err()
+    );
+
+    assert!(
+        Command::from_string(String::from("UPDATE users SET age = 1, age = 2;")).is_err()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn create_and_drop_index() -> anyhow::Result<()> {
+    assert_eq!(
+        Command::from_string(String::from("CREATE UNIQUE INDEX users_email ON users (email, name);"))?,
+        Command::CreateIndex(CreateIndexCommand {
+            index_name: "users_email".to_string(),
+            table_name: "users".to_string(),
+            columns: vec!["email".to_string(), "name".to_string()],
+            unique: true,
+        })
+    );
+
+    assert_eq!(
+        Command::from_string(String::from("create index users_age on users (age);"))?,
+        Command::CreateIndex(CreateIndexCommand {
+            index_name: "users_age".to_string(),
+            table_name: "users".to_string(),
+            columns: vec!["age".to_string()],
+            unique: false,
+        })
+    );
+
+    assert_eq!(
+        Command::from_string(String::from("DROP INDEX users_age;"))?,
+        Command::DropIndex(DropIndexCommand { index_name: "users_age".to_string() })
+    );
+
+    assert!(Command::from_string(String::from("CREATE INDEX ON users (age);")).is_err());
+    assert!(Command::from_string(String::from("CREATE INDEX users_age ON users age;")).is_err());
+    assert!(Command::from_string(String::from("CREATE INDEX users_age ON users (age, age);")).is_err());
+    assert!(Command::from_string(String::from("DROP TABLE users;")).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn foreign_keys() -> anyhow::Result<()> {
+    let create_statement = "CREATE TABLE orders (id int PRIMARY KEY, user_id int REFERENCES users ON DELETE CASCADE, sku varchar 10, store int, FOREIGN KEY (sku, store) REFERENCES stock (sku, store_id) ON DELETE SET NULL ON UPDATE RESTRICT);";
+    if let Command::Create(create_command) = Command::from_string(String::from(create_statement))? {
+        assert_eq!(
+            create_command.table_definition.foreign_keys,
+            vec![
+                ForeignKey {
+                    name: "orders_user_id_fkey".to_string(),
+                    columns: vec!["user_id".to_string()],
+                    referenced_table: "users".to_string(),
+                    referenced_columns: vec![],
+                    on_delete: ReferentialAction::Cascade,
+                },
+                ForeignKey {
+                    name: "orders_sku_store_fkey".to_string(),
+                    columns: vec!["sku".to_string(), "store".to_string()],
+                    referenced_table: "stock".to_string(),
+                    referenced_columns: vec!["sku".to_string(), "store_id".to_string()],
+                    on_delete: ReferentialAction::SetNull,
+                },
+            ]
+        );
+        assert_eq!(create_command.table_definition.column_defs.len(), 4);
+    } else {
+        panic!("Expected CREATE command");
+    }
+
+    if let Command::Create(create_command) = Command::from_string(String::from("CREATE TABLE a (b int REFERENCES c (d));"))? {
+        assert_eq!(create_command.table_definition.foreign_keys[0].on_delete, ReferentialAction::NoAction);
+    } else {
+        panic!("Expected CREATE command");
+    }
+
+    assert!(Command::from_string(String::from("CREATE TABLE a (b int, FOREIGN KEY (x) REFERENCES c);")).is_err());
+    assert!(Command::from_string(String::from("CREATE TABLE a (b int REFERENCES c (d, e));")).is_err());
+    assert!(Command::from_string(String::from("CREATE TABLE a (b int REFERENCES c ON UPDATE CASCADE);")).is_err());
+    assert!(Command::from_string(String::from("CREATE TABLE a (b int REFERENCES c ON DELETE SET DEFAULT);")).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn column_constraints() -> anyhow::Result<()> {
+    let create_statement = "CREATE TABLE items (id int PRIMARY KEY, name varchar 20 NOT NULL DEFAULT 'new item', sku varchar 10 UNIQUE, price int CHECK (price > 0) DEFAULT 1, stock int NULL, CONSTRAINT items_stock_price CHECK (stock < price * 10), UNIQUE (name, price));";
+    let Command::Create(create_command) = Command::from_string(String::from(create_statement))? else {
+        panic!("Expected CREATE command");
+    };
+    let table_definition = create_command.table_definition;
+
+    let not_null: Vec<bool> = table_definition.column_defs.iter().map(|col_def| col_def.not_null).collect();
+    assert_eq!(not_null, vec![false, true, false, false, false]);
+    let defaults: Vec<Option<String>> = table_definition.column_defs.iter().map(|col_def| col_def.default.clone()).collect();
+    assert_eq!(defaults, vec![None, Some("'new item'".to_string()), None, Some("1".to_string()), None]);
+    assert_eq!(
+        table_definition.unique_constraints,
+        vec![
+            UniqueConstraint { name: "items_sku_key".to_string(), columns: vec!["sku".to_string()] },
+            UniqueConstraint { name: "items_name_price_key".to_string(), columns: vec!["name".to_string(), "price".to_string()] },
+        ]
+    );
+    assert_eq!(
+        table_definition.check_constraints,
+        vec![
+            CheckConstraint { name: "items_price_check".to_string(), expression: "price > 0".to_string() },
+            CheckConstraint { name: "items_stock_price".to_string(), expression: "stock < price * 10".to_string() },
+        ]
+    );
+    assert_eq!(
+        Command::stored_value_expression(&table_definition.column_defs[1].default.clone().unwrap())?,
+        ValueExpression::DataValue(DataValue::StringValue("new item".to_string()))
+    );
+
+    // Unnamed constraints that would share a name are numbered
+    let Command::Create(create_command) = Command::from_string(String::from("CREATE TABLE t (a int CHECK (a > 0) CHECK (a < 9));"))? else {
+        panic!("Expected CREATE command");
+    };
+    let names: Vec<String> = create_command.table_definition.check_constraints.into_iter().map(|check| check.name).collect();
+    assert_eq!(names, vec!["t_a_check".to_string(), "t_a_check1".to_string()]);
+
+    assert!(Command::from_string(String::from("CREATE TABLE t (a int DEFAULT b);")).is_err());
+    assert!(Command::from_string(String::from("CREATE TABLE t (a int CHECK (b > 0));")).is_err());
+    assert!(Command::from_string(String::from("CREATE TABLE t (a int, UNIQUE (b));")).is_err());
+    assert!(Command::from_string(String::from("CREATE TABLE t (a int CONSTRAINT c CHECK (a > 0), CONSTRAINT c UNIQUE (a));")).is_err());
+    assert!(Command::from_string(String::from("CREATE TABLE t (a int NOT);")).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn sequences() -> anyhow::Result<()> {
+    assert_eq!(
+        Command::from_string(String::from("CREATE SEQUENCE order_no INCREMENT BY 10 START WITH 100 MAXVALUE 1000 CYCLE;"))?,
+        Command::CreateSequence(CreateSequenceCommand {
+            sequence_definition: SequenceDefinition {
+                name: "order_no".to_string(),
+                increment: 10,
+                min_value: 1,
+                max_value: 1000,
+                start: 100,
+                cycle: true,
+            },
+        })
+    );
+    // Descending sequences count down from -1
+    assert_eq!(
+        Command::from_string(String::from("CREATE SEQUENCE countdown AS smallint INCREMENT -1;"))?,
+        Command::CreateSequence(CreateSequenceCommand {
+            sequence_definition: SequenceDefinition {
+                name: "countdown".to_string(),
+                increment: -1,
+                min_value: i16::MIN as i64,
+                max_value: -1,
+                start: -1,
+                cycle: false,
+            },
+        })
+    );
+    assert_eq!(
+        Command::from_string(String::from("DROP SEQUENCE order_no;"))?,
+        Command::DropSequence(DropSequenceCommand { sequence_name: "order_no".to_string() })
+    );
+
+    let create_statement = "CREATE TABLE orders (id serial PRIMARY KEY, line bigserial, ref int GENERATED ALWAYS AS IDENTITY (START WITH 5), note int GENERATED BY DEFAULT AS IDENTITY);";
+    let Command::Create(create_command) = Command::from_string(String::from(create_statement))? else {
+        panic!("Expected CREATE command");
+    };
+    let column_defs = &create_command.table_definition.column_defs;
+    let data_types: Vec<Datatype> = column_defs.iter().map(|col_def| col_def.data_type.clone()).collect();
+    assert_eq!(data_types, vec![Datatype::Integer, Datatype::BigInt, Datatype::Integer, Datatype::Integer]);
+    assert!(column_defs.iter().all(|col_def| col_def.not_null));
+    let identities: Vec<Option<Identity>> = column_defs.iter().map(|col_def| col_def.identity).collect();
+    assert_eq!(identities, vec![None, None, Some(Identity::Always), Some(Identity::ByDefault)]);
+    assert_eq!(column_defs[0].default, Some("nextval('orders_id_seq')".to_string()));
+    assert_eq!(column_defs[0].sequence, Some("orders_id_seq".to_string()));
+    let sequences: Vec<(String, i64, i64)> =
+        create_command.sequences.into_iter().map(|sequence| (sequence.name, sequence.start, sequence.max_value)).collect();
+    assert_eq!(
+        sequences,
+        vec![
+            ("orders_id_seq".to_string(), 1, i32::MAX as i64),
+            ("orders_line_seq".to_string(), 1, i64::MAX),
+            ("orders_ref_seq".to_string(), 5, i32::MAX as i64),
+            ("orders_note_seq".to_string(), 1, i32::MAX as i64),
+        ]
+    );
+
+    // Sequence functions are called without a FROM
+    let Command::Select(select_command) = Command::from_string(String::from("SELECT nextval('order_no');"))? else {
+        panic!("Expected SELECT command");
+    };
+    assert_eq!(select_command.table_name, "");
+    assert_eq!(
+        select_command.columns,
+        vec![SelectColumn::Expression(
+            ValueExpression::FunctionCall(FunctionCall {
+                function_name: "nextval".to_string(),
+                parameters: vec![ValueExpression::DataValue(DataValue::StringValue("order_no".to_string()))],
+            }),
+            None
+        )]
+    );
+
+    assert!(Command::from_string(String::from("CREATE SEQUENCE s INCREMENT 0;")).is_err());
+    assert!(Command::from_string(String::from("CREATE SEQUENCE s START 0;")).is_err());
+    assert!(Command::from_string(String::from("CREATE SEQUENCE s AS varchar;")).is_err());
+    assert!(Command::from_string(String::from("CREATE TABLE t (a serial DEFAULT 1);")).is_err());
+    assert!(Command::from_string(String::from("CREATE TABLE t (a varchar 10 GENERATED ALWAYS AS IDENTITY);")).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn alter_table() -> anyhow::Result<()> {
+    let Command::AlterTable(alter_command) = Command::from_string(String::from(
+        "ALTER TABLE users ADD COLUMN email varchar 64 NOT NULL DEFAULT 'none' UNIQUE, DROP age, ALTER COLUMN score SET DATA TYPE double precision, ALTER name TYPE varchar 40;",
+    ))?
+    else {
+        panic!("Expected ALTER TABLE command");
+    };
+    assert_eq!(alter_command.table_name, "users");
+    let AlterTableAction::AddColumn(column, sequences) = &alter_command.actions[0] else {
+        panic!("Expected ADD COLUMN");
+    };
+    assert_eq!(column.column_defs.len(), 1);
+    assert_eq!(column.column_defs[0].name, "email");
+    assert_eq!(column.column_defs[0].length, 64);
+    assert!(column.column_defs[0].not_null);
+    assert_eq!(column.column_defs[0].default, Some("'none'".to_string()));
+    assert_eq!(column.unique_constraints, vec![UniqueConstraint { name: "users_email_key".to_string(), columns: vec!["email".to_string()] }]);
+    assert!(sequences.is_empty());
+    assert_eq!(
+        alter_command.actions[1..],
+        [
+            AlterTableAction::DropColumn("age".to_string()),
+            AlterTableAction::AlterColumnType("score".to_string(), Datatype::DoublePrecision, 0),
+            AlterTableAction::AlterColumnType("name".to_string(), Datatype::CharacterVarying, 40),
+        ]
+    );
+
+    let Command::AlterTable(alter_command) = Command::from_string(String::from("ALTER TABLE users ADD id bigserial;"))? else {
+        panic!("Expected ALTER TABLE command");
+    };
+    let AlterTableAction::AddColumn(column, sequences) = &alter_command.actions[0] else {
+        panic!("Expected ADD COLUMN");
+    };
+    assert_eq!(column.column_defs[0].default, Some("nextval('users_id_seq')".to_string()));
+    assert_eq!(sequences[0].name, "users_id_seq");
+
+    assert_eq!(
+        Command::from_string(String::from("ALTER TABLE users RENAME COLUMN name TO full_name;"))?,
+        Command::AlterTable(AlterTableCommand {
+            table_name: "users".to_string(),
+            actions: vec![AlterTableAction::RenameColumn("name".to_string(), "full_name".to_string())],
+        })
+    );
+    assert_eq!(
+        Command::from_string(String::from("ALTER TABLE users RENAME TO people;"))?,
+        Command::AlterTable(AlterTableCommand {
+            table_name: "users".to_string(),
+            actions: vec![AlterTableAction::RenameTable("people".to_string())],
+        })
+    );
+    assert_eq!(
+        Command::rename_stored_column("price > 0 AND items.price < 10 AND note <> 'price'", "price", "cost"),
+        "cost > 0 AND items.cost < 10 AND note <> 'price'"
+    );
+
+    assert!(Command::from_string(String::from("ALTER TABLE users RENAME TO people, DROP age;")).is_err());
+    assert!(Command::from_string(String::from("ALTER TABLE users DROP age, RENAME TO people;")).is_err());
+    assert!(Command::from_string(String::from("ALTER TABLE users ADD email varchar 64 CHECK (other > 0);")).is_err());
+    assert!(Command::from_string(String::from("ALTER TABLE users TRUNCATE;")).is_err());
+    assert!(Command::from_string(String::from("ALTER INDEX users_pkey RENAME TO x;")).is_err());
 
     Ok(())
 }
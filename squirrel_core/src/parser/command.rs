@@ -1,90 +1,361 @@
+use std::cmp::{self, Ordering};
 use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::mem;
 
-use crate::table::table_definition::{TableDefinition, ColumnDefinition};
+use crate::table::table_definition::{TableDefinition, ColumnDefinition, CheckConstraint, ForeignKey, Identity, ReferentialAction, UniqueConstraint};
+use crate::table::sequence_definition::{SequenceDefinition, SequenceOptions};
 use crate::table::datatypes::{Datatype};
+use crate::table::{datetime, numeric};
+use crate::functions;
 use anyhow::anyhow;
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, PartialEq)]
 pub enum Command {
-    Select(SelectCommand),
+    Select(Box<SelectCommand>),
     Create(CreateCommand),
+    CreateIndex(CreateIndexCommand),
+    DropIndex(DropIndexCommand),
+    CreateSequence(CreateSequenceCommand),
+    DropSequence(DropSequenceCommand),
+    AlterTable(AlterTableCommand),
     Insert(InsertCommand),
     Delete(DeleteCommand),
+    Update(UpdateCommand),
 }
 
 #[derive(Debug, Eq, PartialEq)]
 pub struct CreateCommand {
     pub table_definition: TableDefinition,
+    // Made for the table's serial and identity columns
+    pub sequences: Vec<SequenceDefinition>,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct CreateIndexCommand {
+    pub index_name: String,
+    pub table_name: String,
+    pub columns: Vec<String>,
+    pub unique: bool,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct DropIndexCommand {
+    pub index_name: String,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct CreateSequenceCommand {
+    pub sequence_definition: SequenceDefinition,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct DropSequenceCommand {
+    pub sequence_name: String,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct AlterTableCommand {
+    pub table_name: String,
+    // Done in order. A RENAME is always the only action.
+    pub actions: Vec<AlterTableAction>,
 }
 
 #[derive(Debug, Eq, PartialEq)]
+pub enum AlterTableAction {
+    // The new column and its constraints, in a table definition of their
+    // own, and the sequence a serial or identity column needs
+    AddColumn(TableDefinition, Vec<SequenceDefinition>),
+    DropColumn(String),
+    RenameColumn(String, String), // (column, new name)
+    RenameTable(String),
+    AlterColumnType(String, Datatype, usize), // (column, type, length)
+}
+
+#[derive(Debug, PartialEq)]
 pub struct DeleteCommand {
     pub table_name: String,
     pub logic_expression: Option<LogicExpression>,
+    pub returning: Vec<SelectColumn>,
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, PartialEq)]
+pub struct UpdateCommand {
+    pub table_name: String,
+    pub items: HashMap<String, UpdateItem>,
+    pub logic_expression: Option<LogicExpression>,
+    pub returning: Vec<SelectColumn>,
+}
+
+#[derive(Debug, PartialEq)]
 pub struct InsertCommand {
     pub table_name: String,
-    pub items: HashMap<String, InsertItem>,
+    pub source: InsertSource,
+    pub returning: Vec<SelectColumn>, // empty without a RETURNING clause
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, PartialEq)]
+pub enum InsertSource {
+    Values(Vec<HashMap<String, InsertItem>>), // one map per row
+    Select(Vec<String>, Box<SelectCommand>), // the target columns and the query
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub struct SelectCommand {
+    pub with: Vec<CommonTableExpression>,
     pub table_name: String,
-    pub column_names: Vec<String>,
+    pub table_alias: Option<String>,
+    pub joins: Vec<Join>,
+    pub distinct: Option<Distinct>,
+    pub columns: Vec<SelectColumn>,
     pub logic_expression: Option<LogicExpression>,
+    pub group_by: Vec<ValueExpression>,
+    pub having: Option<LogicExpression>,
+    pub order_by: Vec<OrderByItem>,
+    pub limit: Option<usize>,
+    pub offset: usize,
+    // Queries whose rows are combined with this one's, in order. ORDER BY,
+    // LIMIT and OFFSET are applied to the combined rows.
+    pub set_operations: Vec<SetOperation>,
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SetOperator {
+    Union,
+    Intersect,
+    Except,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct SetOperation {
+    pub operator: SetOperator,
+    pub all: bool,
+    pub query: Box<SelectCommand>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum JoinType {
+    Inner,
+    Left,
+    Right,
+    Full,
+    Cross,
+}
+
+// WITH name [(column, ...)] AS (SELECT ...). In a WITH RECURSIVE, a query
+// ending in UNION [ALL] a query that reads from the WITH query itself is
+// run again on the rows the previous run added, until it adds none.
+#[derive(Debug, PartialEq, Clone)]
+pub struct CommonTableExpression {
+    pub name: String,
+    pub column_names: Vec<String>,
+    pub query: Box<SelectCommand>,
+    pub recursive: bool,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Join {
+    pub join_type: JoinType,
+    pub table_name: String,
+    pub table_alias: Option<String>,
+    pub condition: Option<LogicExpression>, // None for a CROSS JOIN
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct OrderByItem {
+    pub expression: ValueExpression,
+    pub descending: bool,
+    pub nulls_first: bool,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum SelectColumn {
+    Wildcard,
+    TableWildcard(String), // alias.*
+    Expression(ValueExpression, Option<String>), // expression, AS alias
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Distinct {
+    AllColumns,
+    // Keeps the first row for each value of the expressions
+    On(Vec<ValueExpression>),
+}
+
+#[derive(Debug, PartialEq)]
 pub struct InsertItem {
     pub column_name: String,
-    pub column_value: String,
+    pub column_value: DataValue,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct UpdateItem {
+    pub column_name: String,
+    pub column_value: ValueExpression,
 }
 
 #[derive(Debug, Eq, PartialEq, Clone)]
-enum LogicalOperator {
+pub enum LogicalOperator {
     Equal,
     GreaterThan,
     LessThan,
     GreaterThanEqualTo,
     LessThanEqualTo,
-    And,
-    Or,
+    NotEqual,
+    Like,
+    NotLike,
+    ILike,
+    NotILike,
+    Is,
+    IsNot,
 }
 
 #[derive(Debug, Eq, PartialEq, Clone)]
+pub enum ValueOperator {
+    Concatenate,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum DataValue {
     StringValue(String),
-    U8Value(u8),
+    I64Value(i64),
+    F64Value(f64),
+    NumericValue(i128, u32), // unscaled value, scale
     BoolValue(bool),
+    DateValue(i32), // days since 1970-01-01
+    TimestampValue(i64), // microseconds since 1970-01-01 00:00:00
+    Null,
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, PartialEq)]
 pub enum LogicSide {
    // pub expression: LogicExpression,
     Value(DataValue)
 }
 
-#[derive(Debug, Eq, PartialEq, Clone)]
-pub struct LogicExpression {
+#[derive(Debug, PartialEq, Clone)]
+pub enum LogicExpression {
+    Comparison(Comparison),
+    Value(ValueExpression), // bare boolean operand, e.g. WHERE active
+    And(Box<LogicExpression>, Box<LogicExpression>),
+    Or(Box<LogicExpression>, Box<LogicExpression>),
+    Not(Box<LogicExpression>),
+    In(InExpression),
+    Exists(Box<SelectCommand>),
+}
+
+// value [NOT] IN (list) or value [NOT] IN (SELECT ...)
+#[derive(Debug, PartialEq, Clone)]
+pub struct InExpression {
+    pub value: ValueExpression,
+    pub source: InSource,
+    pub negated: bool,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum InSource {
+    List(Vec<ValueExpression>),
+    Subquery(Box<SelectCommand>),
+}
+
+// A subquery's column count and rows
+pub type SubqueryResult = (usize, Vec<Vec<DataValue>>);
+
+// Runs a subquery for resolve_subqueries
+pub type SubqueryRunner<'a> = dyn FnMut(&SelectCommand) -> ::anyhow::Result<SubqueryResult> + 'a;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Comparison {
     pub left_hand: ValueExpression,
     pub right_hand: ValueExpression,
     pub operator: LogicalOperator
 }
 
-#[derive(Debug, Eq, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct FunctionCall {
     pub function_name: String,
-    pub parameters: Vec<String>,
+    pub parameters: Vec<ValueExpression>,
 }
 
-#[derive(Debug, Eq, PartialEq, Clone)]
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum AggregateFunction {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Aggregate {
+    pub function: AggregateFunction,
+    pub argument: Option<Box<ValueExpression>>, // None is COUNT(*)
+    pub distinct: bool,
+}
+
+// function(...) OVER ([PARTITION BY ...] [ORDER BY ...] [frame])
+#[derive(Debug, PartialEq, Clone)]
+pub struct WindowFunction {
+    pub function: WindowFunctionType,
+    pub arguments: Vec<ValueExpression>, // LAG and LEAD's, an aggregate keeps its own
+    pub partition_by: Vec<ValueExpression>,
+    pub order_by: Vec<OrderByItem>,
+    pub frame: Option<WindowFrame>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum WindowFunctionType {
+    RowNumber,
+    Rank,
+    DenseRank,
+    Lag,
+    Lead,
+    Aggregate(Aggregate),
+}
+
+// ROWS counts rows, RANGE goes by the ORDER BY values so a row's peers
+// (rows with the same values) are always in its frame together.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FrameUnits {
+    Rows,
+    Range,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FrameBound {
+    UnboundedPreceding,
+    Preceding(usize),
+    CurrentRow,
+    Following(usize),
+    UnboundedFollowing,
+}
+
+// Without a frame, aggregates see the partition up to the current row and
+// its peers, or all of it without an ORDER BY
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct WindowFrame {
+    pub units: FrameUnits,
+    pub start: FrameBound,
+    pub end: FrameBound,
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum ValueExpression {
     FunctionCall(FunctionCall),
+    Aggregate(Aggregate),
     DataValue(DataValue),
+    // A value that keeps its declared type, i.e. a smallint column's once
+    // the row is filled in
+    TypedValue(DataValue, Datatype),
     ColumnName(String),
+    Operation(Box<ValueExpression>, ValueOperator, Box<ValueExpression>),
+    Subquery(Box<SelectCommand>), // scalar subquery
+    Window(Box<WindowFunction>),
 }
 
 enum CreateParserState {
@@ -95,6 +366,25 @@ enum CreateParserState {
     ColumnDatatype,
     ColumnDefinitionEnd,
     ColumnLength,
+    ConstraintEnd,
+    Semicolon,
+}
+
+enum AlterTableParserState {
+    TableKeyword,
+    TableName,
+    Action,
+    ActionEnd,
+}
+
+enum CreateIndexParserState {
+    IndexKeyword,
+    IndexName,
+    OnKeyword,
+    TableName,
+    ColumnList,
+    ColumnName,
+    ColumnNameCommaOrEnd,
     Semicolon,
 }
 
@@ -102,8 +392,25 @@ enum SelectParserState {
     ColumnName,
     ColumnNameCommaOrFrom,
     TableName,
-    WhereKeywordOrSemicolon,
-    Semicolon,
+    ClauseOrSemicolon,
+}
+
+// Keywords that can follow a table in FROM, so can't be its alias
+const FROM_KEYWORDS: &[&str] = &[
+    "WHERE", "GROUP", "HAVING", "ORDER", "LIMIT", "OFFSET", "JOIN", "INNER", "LEFT", "RIGHT", "FULL", "CROSS", "ON", "UNION",
+    "INTERSECT", "EXCEPT",
+];
+
+// Clauses that can follow FROM, in the order they have to appear
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum SelectClause {
+    From,
+    Where,
+    GroupBy,
+    Having,
+    OrderBy,
+    Limit,
+    Offset,
 }
 
 enum DeleteParserState {
@@ -113,6 +420,16 @@ enum DeleteParserState {
     Semicolon,
 }
 
+enum UpdateParserState {
+    TableName,
+    SetKeyword,
+    ColumnName,
+    EqualsSign,
+    Value,
+    ValueEnd,
+    Semicolon,
+}
+
 enum InsertParserState {
     IntoKeyword,
     TableName,
@@ -123,7 +440,7 @@ enum InsertParserState {
     ValuesListBegin,
     Value,
     ValueEnd,
-    Semicolon,
+    ValuesListEnd,
 }
 
 enum ValueExpressionParserState {
@@ -131,20 +448,18 @@ enum ValueExpressionParserState {
     StringValue,
     EndQuote,
     FunctionOpenParenOrEnd,
-    FunctionCloseParen,
-}
-
-#[derive(Debug)]
-enum LogicExpressionParserState {
-   NumberOrQuoteOrColname,
-   StringValue,
-   EndQuote,
-   Operator,
+    FunctionParameterOrCloseParen,
+    FunctionParameter,
+    FunctionParameterEnd,
 }
 
 
 pub fn tokenizer(text: String) -> Vec<String> {
     let parts = HashSet::from([' ', ',', ';', '(', ')', '\'', '\"']);
+    // Operators are split from whatever is next to them, so `id>=5` is the
+    // same as `id >= 5`
+    let operator_chars = HashSet::from(['<', '>', '=', '!', '|', '+', '-', '*', '/', '%']);
+    let multi_char_operators = HashSet::from(["<=", ">=", "<>", "!=", "||"]);
     let mut tokens: Vec<String> = vec![];
     let mut cur_str = String::new();
     let mut in_quotes = false;
@@ -152,6 +467,10 @@ pub fn tokenizer(text: String) -> Vec<String> {
 
     for cur_char in text.chars() {
         if !in_quotes && (cur_char == '\"'  || cur_char == '\''){
+            if !cur_str.is_empty() {
+                tokens.push(cur_str);
+                cur_str = String::new();
+            }
             tokens.push(cur_char.to_string());
             in_quotes = true;
             cur_quote = cur_char;
@@ -166,6 +485,25 @@ pub fn tokenizer(text: String) -> Vec<String> {
             continue;
         }
 
+        let in_operator = cur_str.starts_with(|c: char| operator_chars.contains(&c));
+        if !in_quotes && operator_chars.contains(&cur_char) {
+            // The sign of an exponent, i.e. 1e-3, is part of the number
+            let in_exponent = (cur_char == '-' || cur_char == '+')
+                && cur_str.starts_with(|c: char| c.is_ascii_digit())
+                && cur_str.ends_with(['e', 'E']);
+            let continues_operator = in_operator && multi_char_operators.contains(format!("{}{}", cur_str, cur_char).as_str());
+            if !cur_str.is_empty() && !in_exponent && !continues_operator {
+                tokens.push(cur_str);
+                cur_str = String::new();
+            }
+            cur_str.push(cur_char);
+            continue;
+        }
+        if !in_quotes && in_operator {
+            tokens.push(cur_str);
+            cur_str = String::new();
+        }
+
         if !in_quotes && parts.contains(&cur_char) {
             if !cur_str.is_empty() {
                 tokens.push(cur_str);
@@ -179,237 +517,1250 @@ pub fn tokenizer(text: String) -> Vec<String> {
         }
     }
 
-    if cur_str.len() > 0 {
+    if !cur_str.is_empty() {
         tokens.push(cur_str);
     }
 
     tokens
 }
 
+// Matches a LIKE pattern, where % is any run of characters, _ is any
+// single character and \ escapes the next character.
+fn like_match(value: &[char], pattern: &[char]) -> bool {
+    match pattern.first() {
+        None => value.is_empty(),
+        Some('%') => (0..=value.len()).any(|idx| like_match(&value[idx..], &pattern[1..])),
+        Some('_') => !value.is_empty() && like_match(&value[1..], &pattern[1..]),
+        Some('\\') if pattern.len() > 1 => {
+            value.first() == Some(&pattern[1]) && like_match(&value[1..], &pattern[2..])
+        }
+        Some(c) => value.first() == Some(c) && like_match(&value[1..], &pattern[1..]),
+    }
+}
+
 impl DataValue {
     pub fn from_string(string: String) -> ::anyhow::Result<DataValue> {
-        let test = string.parse::<u8>();
+        let test = string.parse::<i64>();
         match test {
-           Ok(u8_val) => {
-               return Ok(DataValue::U8Value(u8_val));
+           Ok(i64_val) => {
+               Ok(DataValue::I64Value(i64_val))
            },
            Err(_) => {
                let res = string.trim_matches(char::from(0));
-               return Ok(DataValue::StringValue(res.to_string()));
+               Ok(DataValue::StringValue(res.to_string()))
            }, 
         }  
     }
 }
 
-impl LogicExpression {
-    pub fn is_valid(&self) -> bool {
-        println!("{:?}", self.left_hand);
-        if !self.is_evaluatable() {
-            return false;
+impl fmt::Display for DataValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DataValue::StringValue(val) => write!(f, "{}", val),
+            DataValue::I64Value(val) => write!(f, "{}", val),
+            DataValue::F64Value(val) => write!(f, "{}", val),
+            DataValue::NumericValue(val, scale) => write!(f, "{}", numeric::format_numeric(*val, *scale)),
+            DataValue::BoolValue(val) => write!(f, "{}", if *val { "TRUE" } else { "FALSE" }),
+            DataValue::DateValue(val) => write!(f, "{}", datetime::format_date(*val)),
+            DataValue::TimestampValue(val) => write!(f, "{}", datetime::format_timestamp(*val)),
+            DataValue::Null => write!(f, "NULL"),
+        }
+    }
+}
+
+impl DataValue {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            DataValue::StringValue(_) => "varchar",
+            DataValue::I64Value(_) => "integer",
+            DataValue::F64Value(_) => "double precision",
+            DataValue::NumericValue(_, _) => "numeric",
+            DataValue::BoolValue(_) => "boolean",
+            DataValue::DateValue(_) => "date",
+            DataValue::TimestampValue(_) => "timestamp",
+            DataValue::Null => "unknown",
         }
-        if let ValueExpression::DataValue(left) = self.left_hand.clone() {
-            if let ValueExpression::DataValue(right) = self.right_hand.clone() {
-                return mem::discriminant(&left) == mem::discriminant(&right);
+    }
+
+    // The column type a value would be read back from, None for NULL
+    pub fn datatype(&self) -> Option<Datatype> {
+        match self {
+            DataValue::StringValue(_) => Some(Datatype::CharacterVarying),
+            DataValue::I64Value(_) => Some(Datatype::BigInt),
+            DataValue::F64Value(_) => Some(Datatype::DoublePrecision),
+            DataValue::NumericValue(_, _) => Some(Datatype::Numeric(None)),
+            DataValue::BoolValue(_) => Some(Datatype::Boolean),
+            DataValue::DateValue(_) => Some(Datatype::Date),
+            DataValue::TimestampValue(_) => Some(Datatype::Timestamp),
+            DataValue::Null => None,
+        }
+    }
+
+    // Brings two values to a common type before they're compared. Follows
+    // Postgres' implicit casts: integer -> numeric -> double precision,
+    // date -> timestamp, and string literals -> date/timestamp.
+    pub fn coerce(left: DataValue, right: DataValue) -> ::anyhow::Result<(DataValue, DataValue)> {
+        use DataValue::*;
+        match (left, right) {
+            (I64Value(l), F64Value(r)) => Ok((F64Value(l as f64), F64Value(r))),
+            (F64Value(l), I64Value(r)) => Ok((F64Value(l), F64Value(r as f64))),
+            (I64Value(l), NumericValue(r, scale)) => Ok((NumericValue(l.into(), 0), NumericValue(r, scale))),
+            (NumericValue(l, scale), I64Value(r)) => Ok((NumericValue(l, scale), NumericValue(r.into(), 0))),
+            (NumericValue(l, scale), F64Value(r)) => Ok((F64Value(numeric::to_f64(l, scale)), F64Value(r))),
+            (F64Value(l), NumericValue(r, scale)) => Ok((F64Value(l), F64Value(numeric::to_f64(r, scale)))),
+            (DateValue(l), TimestampValue(r)) => Ok((TimestampValue(i64::from(l) * datetime::MICROS_PER_DAY), TimestampValue(r))),
+            (TimestampValue(l), DateValue(r)) => Ok((TimestampValue(l), TimestampValue(i64::from(r) * datetime::MICROS_PER_DAY))),
+            (DateValue(l), StringValue(r)) => Ok((DateValue(l), DateValue(datetime::parse_date(&r)?))),
+            (StringValue(l), DateValue(r)) => Ok((DateValue(datetime::parse_date(&l)?), DateValue(r))),
+            (TimestampValue(l), StringValue(r)) => Ok((TimestampValue(l), TimestampValue(datetime::parse_timestamp(&r)?))),
+            (StringValue(l), TimestampValue(r)) => Ok((TimestampValue(datetime::parse_timestamp(&l)?), TimestampValue(r))),
+            (l, r) => {
+                if mem::discriminant(&l) == mem::discriminant(&r) {
+                    Ok((l, r))
+                } else {
+                    Err(anyhow!("Logical expression is comparing 2 differing datatypes"))
+                }
             }
         }
-        return false;
     }
-    
-    pub fn is_evaluatable(&self) -> bool {
-        return mem::discriminant(&self.left_hand) == mem::discriminant(&ValueExpression::DataValue(DataValue::U8Value(0))) &&  
-                    mem::discriminant(&self.right_hand) == mem::discriminant(&ValueExpression::DataValue(DataValue::U8Value(0)));
+
+    fn is_number(&self) -> bool {
+        matches!(self, DataValue::I64Value(_) | DataValue::F64Value(_) | DataValue::NumericValue(_, _))
     }
 
-    pub fn fill_values(&mut self, hmap: HashMap<String, ValueExpression>) -> ::anyhow::Result<()> {
-        for (name, value) in hmap {
-            if self.left_hand == ValueExpression::ColumnName(name.clone()) {
-                self.left_hand = value.clone();
+    // Applies +, -, *, / or % to two values. Numbers are brought to a common
+    // type the same way they are for comparisons, and dates can be offset by
+    // a number of days or subtracted from each other.
+    pub fn arithmetic(operator: &ValueOperator, left: DataValue, right: DataValue) -> ::anyhow::Result<DataValue> {
+        use DataValue::*;
+        let (left_type, right_type) = (left.type_name(), right.type_name());
+        let undefined = || anyhow!("ERROR: operator does not exist: {} {} {}", left_type, operator.as_str(), right_type);
+        let division_by_zero = || anyhow!("ERROR: division by zero");
+
+        match (operator, left, right) {
+            (_, Null, _) | (_, _, Null) => Ok(Null),
+            (ValueOperator::Add, DateValue(date), I64Value(days)) | (ValueOperator::Add, I64Value(days), DateValue(date)) => {
+                Self::offset_date(date, Some(days))
             }
-            if self.right_hand == ValueExpression::ColumnName(name.clone()) {
-                self.right_hand = value.clone();
+            (ValueOperator::Subtract, DateValue(date), I64Value(days)) => Self::offset_date(date, days.checked_neg()),
+            (ValueOperator::Subtract, DateValue(l), DateValue(r)) => Ok(I64Value(i64::from(l) - i64::from(r))),
+            (_, l, r) if l.is_number() && r.is_number() => match Self::coerce(l, r)? {
+                (I64Value(l), I64Value(r)) => {
+                    let value = match operator {
+                        ValueOperator::Add => l.checked_add(r),
+                        ValueOperator::Subtract => l.checked_sub(r),
+                        ValueOperator::Multiply => l.checked_mul(r),
+                        ValueOperator::Divide if r == 0 => return Err(division_by_zero()),
+                        ValueOperator::Divide => l.checked_div(r),
+                        ValueOperator::Modulo if r == 0 => return Err(division_by_zero()),
+                        ValueOperator::Modulo => l.checked_rem(r),
+                        ValueOperator::Concatenate => return Err(undefined()),
+                    };
+                    value.map(I64Value).ok_or_else(|| anyhow!("ERROR: integer out of range"))
+                }
+                (NumericValue(l, l_scale), NumericValue(r, r_scale)) => {
+                    let (l, r) = ((l, l_scale), (r, r_scale));
+                    let value = match operator {
+                        ValueOperator::Add => numeric::add(l, r),
+                        ValueOperator::Subtract => numeric::add(l, (-r.0, r.1)),
+                        ValueOperator::Multiply => numeric::multiply(l, r),
+                        ValueOperator::Divide if r.0 == 0 => return Err(division_by_zero()),
+                        ValueOperator::Divide => numeric::divide(l, r),
+                        ValueOperator::Modulo if r.0 == 0 => return Err(division_by_zero()),
+                        ValueOperator::Modulo => numeric::modulo(l, r),
+                        ValueOperator::Concatenate => return Err(undefined()),
+                    };
+                    match value {
+                        Some((value, scale)) if numeric::num_digits(value) <= numeric::MAX_PRECISION => {
+                            Ok(NumericValue(value, scale))
+                        }
+                        _ => Err(anyhow!("ERROR: value overflows numeric format")),
+                    }
+                }
+                (F64Value(l), F64Value(r)) => {
+                    let value = match operator {
+                        ValueOperator::Add => l + r,
+                        ValueOperator::Subtract => l - r,
+                        ValueOperator::Multiply => l * r,
+                        ValueOperator::Divide if r == 0.0 => return Err(division_by_zero()),
+                        ValueOperator::Divide => l / r,
+                        ValueOperator::Modulo | ValueOperator::Concatenate => return Err(undefined()),
+                    };
+                    if value.is_infinite() && l.is_finite() && r.is_finite() {
+                        return Err(anyhow!("ERROR: value out of range: overflow"));
+                    }
+                    Ok(F64Value(value))
+                }
+                _ => Err(undefined()),
+            },
+            _ => Err(undefined()),
+        }
+    }
+
+    fn offset_date(date: i32, days: Option<i64>) -> ::anyhow::Result<DataValue> {
+        days.and_then(|days| i64::from(date).checked_add(days))
+            .and_then(|date| i32::try_from(date).ok())
+            .map(DataValue::DateValue)
+            .ok_or_else(|| anyhow!("ERROR: date out of range"))
+    }
+
+    // Ordering between two values that have already been coerced to the
+    // same type. None if the type has no ordering (or for NaN).
+    pub fn compare(&self, other: &DataValue) -> Option<Ordering> {
+        match (self, other) {
+            (DataValue::I64Value(l), DataValue::I64Value(r)) => l.partial_cmp(r),
+            (DataValue::F64Value(l), DataValue::F64Value(r)) => l.partial_cmp(r),
+            (DataValue::NumericValue(l, l_scale), DataValue::NumericValue(r, r_scale)) => {
+                let scale = *l_scale.max(r_scale);
+                match (numeric::rescale(*l, *l_scale, scale), numeric::rescale(*r, *r_scale, scale)) {
+                    (Some(l), Some(r)) => l.partial_cmp(&r),
+                    _ => numeric::to_f64(*l, *l_scale).partial_cmp(&numeric::to_f64(*r, *r_scale)),
+                }
             }
+            (DataValue::DateValue(l), DataValue::DateValue(r)) => l.partial_cmp(r),
+            (DataValue::TimestampValue(l), DataValue::TimestampValue(r)) => l.partial_cmp(r),
+            (DataValue::StringValue(l), DataValue::StringValue(r)) => l.partial_cmp(r),
+            (DataValue::BoolValue(l), DataValue::BoolValue(r)) => l.partial_cmp(r),
+            _ => None,
         }
-        Ok(())
     }
+}
 
-    pub fn evaluate(&self) -> ::anyhow::Result<bool> {
-        if !self.is_evaluatable() {
-            return Err(anyhow!("Logical expression has not been properly filled. (Do you have a typo in a column name?)")); 
+impl ValueOperator {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ValueOperator::Concatenate => "||",
+            ValueOperator::Add => "+",
+            ValueOperator::Subtract => "-",
+            ValueOperator::Multiply => "*",
+            ValueOperator::Divide => "/",
+            ValueOperator::Modulo => "%",
+        }
+    }
+
+    fn from_token(token: &str) -> Option<ValueOperator> {
+        match token {
+            "||" => Some(ValueOperator::Concatenate),
+            "+" => Some(ValueOperator::Add),
+            "-" => Some(ValueOperator::Subtract),
+            "*" => Some(ValueOperator::Multiply),
+            "/" => Some(ValueOperator::Divide),
+            "%" => Some(ValueOperator::Modulo),
+            _ => None,
         }
-        if !self.is_valid() {
-            return Err(anyhow!("Logical expression is comparing 2 differing datatypes")); 
+    }
+}
+
+impl AggregateFunction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AggregateFunction::Count => "count",
+            AggregateFunction::Sum => "sum",
+            AggregateFunction::Avg => "avg",
+            AggregateFunction::Min => "min",
+            AggregateFunction::Max => "max",
         }
-        println!("{:?}", self);
-        match self.left_hand {
-            ValueExpression::DataValue(DataValue::StringValue(_)) => {
-                return self.evaluate_string();
-            }
-            ValueExpression::DataValue(DataValue::BoolValue(_)) => {
-                return self.evaluate_bool();
-            }
-            ValueExpression::DataValue(DataValue::U8Value(_)) => {
-                return self.evaluate_u8();
-            }
-            _ => {return Err(anyhow!("Cannot compare non-finalized value expressions"))}
+    }
+
+    pub fn from_name(name: &str) -> Option<AggregateFunction> {
+        match name.to_uppercase().as_str() {
+            "COUNT" => Some(AggregateFunction::Count),
+            "SUM" => Some(AggregateFunction::Sum),
+            "AVG" => Some(AggregateFunction::Avg),
+            "MIN" => Some(AggregateFunction::Min),
+            "MAX" => Some(AggregateFunction::Max),
+            _ => None,
         }
     }
+}
 
-    fn evaluate_string(&self) -> ::anyhow::Result<bool> {
-        match self.operator {
-            LogicalOperator::Equal => {
-                return Ok(self.left_hand == self.right_hand);
-            }
-            _ => {
-                return Err(anyhow!("Invalid operator for datatype varchar"));
-            }
+impl WindowFunctionType {
+    pub fn from_name(name: &str) -> Option<WindowFunctionType> {
+        match name.to_uppercase().as_str() {
+            "ROW_NUMBER" => Some(WindowFunctionType::RowNumber),
+            "RANK" => Some(WindowFunctionType::Rank),
+            "DENSE_RANK" => Some(WindowFunctionType::DenseRank),
+            "LAG" => Some(WindowFunctionType::Lag),
+            "LEAD" => Some(WindowFunctionType::Lead),
+            _ => None,
         }
     }
 
-    fn evaluate_bool(&self) -> ::anyhow::Result<bool>{
-        match self.operator {
-            LogicalOperator::Equal => {
-                return Ok(self.left_hand == self.right_hand);
-            }
-            LogicalOperator::And => {
-                if let ValueExpression::DataValue(DataValue::BoolValue(left)) = self.left_hand {
-                    if let ValueExpression::DataValue(DataValue::BoolValue(right)) = self.right_hand {
-                        return Ok(left && right);
-                    }
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WindowFunctionType::RowNumber => "row_number",
+            WindowFunctionType::Rank => "rank",
+            WindowFunctionType::DenseRank => "dense_rank",
+            WindowFunctionType::Lag => "lag",
+            WindowFunctionType::Lead => "lead",
+            WindowFunctionType::Aggregate(aggregate) => aggregate.function.as_str(),
+        }
+    }
+}
+
+impl WindowFunction {
+    // Everything in the window that's evaluated per row
+    pub fn expressions(&self) -> Vec<&ValueExpression> {
+        let mut expressions: Vec<&ValueExpression> = self.arguments.iter().collect();
+        if let WindowFunctionType::Aggregate(Aggregate { argument: Some(argument), .. }) = &self.function {
+            expressions.push(argument);
+        }
+        expressions.extend(self.partition_by.iter());
+        expressions.extend(self.order_by.iter().map(|item| &item.expression));
+        expressions
+    }
+
+    pub fn expressions_mut(&mut self) -> Vec<&mut ValueExpression> {
+        let mut expressions: Vec<&mut ValueExpression> = self.arguments.iter_mut().collect();
+        if let WindowFunctionType::Aggregate(Aggregate { argument: Some(argument), .. }) = &mut self.function {
+            expressions.push(argument);
+        }
+        expressions.extend(self.partition_by.iter_mut());
+        expressions.extend(self.order_by.iter_mut().map(|item| &mut item.expression));
+        expressions
+    }
+}
+
+impl ValueExpression {
+    pub fn fill_values(&mut self, hmap: &HashMap<String, ValueExpression>) {
+        match self {
+            ValueExpression::ColumnName(name) => {
+                if let Some(value) = hmap.get(name) {
+                    *self = value.clone();
                 }
-                return Err(anyhow!("Mismatched datatypes"));
             }
-            LogicalOperator::Or => {
-                if let ValueExpression::DataValue(DataValue::BoolValue(left)) = self.left_hand {
-                    if let ValueExpression::DataValue(DataValue::BoolValue(right)) = self.right_hand {
-                        return Ok(left || right);
-                    }
+            ValueExpression::Operation(left, _, right) => {
+                left.fill_values(hmap);
+                right.fill_values(hmap);
+            }
+            ValueExpression::FunctionCall(function_call) => {
+                for parameter in function_call.parameters.iter_mut() {
+                    parameter.fill_values(hmap);
                 }
-                return Err(anyhow!("Mismatched datatypes"));
             }
-            _ => {
-                return Err(anyhow!("Invalid operator for datatype bool"));
+            ValueExpression::Aggregate(Aggregate { argument: Some(argument), .. }) => argument.fill_values(hmap),
+            ValueExpression::Window(window) => {
+                for expression in window.expressions_mut() {
+                    expression.fill_values(hmap);
+                }
             }
+            _ => {}
         }
     }
 
-    fn evaluate_u8(&self) -> ::anyhow::Result<bool>{
-        match self.operator {
-            LogicalOperator::Equal => {
-                return Ok(self.left_hand == self.right_hand);
+    // Every column the expression refers to.
+    pub fn column_names(&self) -> Vec<&String> {
+        match self {
+            ValueExpression::ColumnName(name) => vec![name],
+            ValueExpression::Operation(left, _, right) => {
+                let mut names = left.column_names();
+                names.extend(right.column_names());
+                names
             }
-            LogicalOperator::GreaterThan => {
-                if let ValueExpression::DataValue(DataValue::U8Value(left)) = self.left_hand {
-                    if let ValueExpression::DataValue(DataValue::U8Value(right)) = self.right_hand {
-                        return Ok(left > right);
-                    }
-                }
-                return Err(anyhow!("Mismatched datatypes"));
+            ValueExpression::FunctionCall(function_call) => {
+                function_call.parameters.iter().flat_map(|parameter| parameter.column_names()).collect()
             }
-            LogicalOperator::LessThan => {
-                if let ValueExpression::DataValue(DataValue::U8Value(left)) = self.left_hand {
-                    if let ValueExpression::DataValue(DataValue::U8Value(right)) = self.right_hand {
-                        return Ok(left < right);
-                    }
-                }
-                return Err(anyhow!("Mismatched datatypes"));
+            ValueExpression::Aggregate(aggregate) => match &aggregate.argument {
+                Some(argument) => argument.column_names(),
+                None => vec![],
+            },
+            ValueExpression::Window(window) => {
+                window.expressions().into_iter().flat_map(|expression| expression.column_names()).collect()
             }
-            LogicalOperator::GreaterThanEqualTo => {
-                if let ValueExpression::DataValue(DataValue::U8Value(left)) = self.left_hand {
-                    if let ValueExpression::DataValue(DataValue::U8Value(right)) = self.right_hand {
-                        return Ok(left >= right);
-                    }
+            _ => vec![],
+        }
+    }
+
+    // Replaces every subquery with its result. Subqueries are left alone by
+    // everything else, their columns belong to their own FROM.
+    pub fn resolve_subqueries(&mut self, run: &mut SubqueryRunner) -> ::anyhow::Result<()> {
+        match self {
+            ValueExpression::Subquery(subquery) => {
+                let (column_count, mut rows) = run(subquery)?;
+                if column_count != 1 {
+                    return Err(anyhow!("ERROR: subquery must return only one column"));
+                }
+                if rows.len() > 1 {
+                    return Err(anyhow!("ERROR: more than one row returned by a subquery used as an expression"));
                 }
-                return Err(anyhow!("Mismatched datatypes"));
+                let value = rows.pop().and_then(|mut row| row.pop()).unwrap_or(DataValue::Null);
+                *self = ValueExpression::computed_value(value);
             }
-            LogicalOperator::LessThanEqualTo => {
-                if let ValueExpression::DataValue(DataValue::U8Value(left)) = self.left_hand {
-                    if let ValueExpression::DataValue(DataValue::U8Value(right)) = self.right_hand {
-                        return Ok(left <= right);
-                    }
+            ValueExpression::Operation(left, _, right) => {
+                left.resolve_subqueries(run)?;
+                right.resolve_subqueries(run)?;
+            }
+            ValueExpression::FunctionCall(function_call) => {
+                for parameter in function_call.parameters.iter_mut() {
+                    parameter.resolve_subqueries(run)?;
                 }
-                return Err(anyhow!("Mismatched datatypes"));
             }
-            _ => {
-                return Err(anyhow!("Invalid operator for datatype integer"));
+            ValueExpression::Aggregate(Aggregate { argument: Some(argument), .. }) => argument.resolve_subqueries(run)?,
+            ValueExpression::Window(window) => {
+                for expression in window.expressions_mut() {
+                    expression.resolve_subqueries(run)?;
+                }
             }
+            _ => {}
         }
+        Ok(())
     }
-}
-
-impl Command {
-    fn parse_value_expression(tokens: &mut Vec<String>) -> ::anyhow::Result<ValueExpression> {
-        let mut state: ValueExpressionParserState = ValueExpressionParserState::NumericOrQuoteOrColumnOrFunction;
-        let mut quote_type = "'";
-        let mut value_expr: Option<ValueExpression> = None;
-        let mut ref_name = String::from("");
-
-        while let Some(token) = &tokens.pop() {
-            match state {
-                ValueExpressionParserState::NumericOrQuoteOrColumnOrFunction => {
-                    // String test
-                    if token == "'" || token == "\"" {
-                        quote_type = if token == "'" { "'" } else { "\"" };
-                        state = ValueExpressionParserState::StringValue;
-                        continue;
-                    }
 
-                    // Numeric Test
-                    let test = token.parse::<u8>();
-                     match test {
-                        Ok(u8_val) => {
-                            return Ok(ValueExpression::DataValue(DataValue::U8Value(u8_val)));
-                        },
-                        Err(_) => { }, 
-                    }  
+    pub fn has_subqueries(&self) -> bool {
+        match self {
+            ValueExpression::Subquery(_) => true,
+            ValueExpression::Operation(left, _, right) => left.has_subqueries() || right.has_subqueries(),
+            ValueExpression::FunctionCall(function_call) => function_call.parameters.iter().any(|parameter| parameter.has_subqueries()),
+            ValueExpression::Aggregate(Aggregate { argument: Some(argument), .. }) => argument.has_subqueries(),
+            ValueExpression::Window(window) => window.expressions().iter().any(|expression| expression.has_subqueries()),
+            _ => false,
+        }
+    }
 
-                    // Function name / Column name test
-                    ref_name = token.clone();
-                    state = ValueExpressionParserState::FunctionOpenParenOrEnd;
-                }
-                ValueExpressionParserState::StringValue => {
-                    value_expr = Some(ValueExpression::DataValue(DataValue::StringValue(token.to_string())));
-                    state = ValueExpressionParserState::EndQuote;
-                }
-                ValueExpressionParserState::EndQuote => {
-                    if token == quote_type {
-                        return Ok(value_expr.unwrap());
-                    } else {
-                        return Err(anyhow!("Mismatched quotes at or near {}", token));
-                    }
-                }
-                ValueExpressionParserState::FunctionOpenParenOrEnd => {
-                    if token == "(" {
-                        state = ValueExpressionParserState::FunctionCloseParen;
-                    } else {
-                        tokens.push(token.to_string());
-                        return Ok(ValueExpression::ColumnName(ref_name.to_string()));
-                    }
+    // Rewrites every column reference, i.e. to qualify it with its table.
+    pub fn rename_columns(&mut self, rename: &dyn Fn(&str) -> ::anyhow::Result<String>) -> ::anyhow::Result<()> {
+        match self {
+            ValueExpression::ColumnName(name) => *name = rename(name)?,
+            ValueExpression::Operation(left, _, right) => {
+                left.rename_columns(rename)?;
+                right.rename_columns(rename)?;
+            }
+            ValueExpression::FunctionCall(function_call) => {
+                for parameter in function_call.parameters.iter_mut() {
+                    parameter.rename_columns(rename)?;
                 }
-                ValueExpressionParserState::FunctionCloseParen => {
-                    if token == ")" {
-                        return Ok(ValueExpression::FunctionCall(FunctionCall { function_name: ref_name, parameters: vec![] }));
-                    } else {
-                        return Err(anyhow!("Expected funciton closing parenthesis at or near {}", token));
-                    }
+            }
+            ValueExpression::Aggregate(Aggregate { argument: Some(argument), .. }) => argument.rename_columns(rename)?,
+            ValueExpression::Window(window) => {
+                for expression in window.expressions_mut() {
+                    expression.rename_columns(rename)?;
                 }
             }
+            _ => {}
         }
+        Ok(())
+    }
 
-        if ref_name.len() != 0 {
-            return Ok(ValueExpression::ColumnName(ref_name.to_string()));
+    // Header used for the expression in a result set, Postgres calls
+    // anything that isn't a column or function ?column?
+    pub fn output_name(&self) -> String {
+        match self {
+            ValueExpression::ColumnName(name) => name.rsplit('.').next().unwrap_or(name).to_string(),
+            ValueExpression::FunctionCall(function_call) => function_call.function_name.to_lowercase(),
+            ValueExpression::Aggregate(aggregate) => aggregate.function.as_str().to_string(),
+            ValueExpression::Window(window) => window.function.as_str().to_string(),
+            // A scalar subquery is named after the column it returns
+            ValueExpression::Subquery(subquery) => match subquery.columns.first() {
+                Some(SelectColumn::Expression(expression, alias)) => {
+                    alias.clone().unwrap_or_else(|| expression.output_name())
+                }
+                _ => String::from("?column?"),
+            },
+            _ => String::from("?column?"),
         }
-        return Err(anyhow!("Unexpected end of statement"));
     }
 
-    fn parse_insert_command(tokens: &mut Vec<String>) -> ::anyhow::Result<Command> {
+    // Every aggregate call in the expression, outermost first. An aggregate
+    // used as a window function isn't one, but its argument can have them.
+    pub fn aggregates(&self) -> Vec<&Aggregate> {
+        match self {
+            ValueExpression::Aggregate(aggregate) => {
+                let mut aggregates = vec![aggregate];
+                if let Some(argument) = &aggregate.argument {
+                    aggregates.extend(argument.aggregates());
+                }
+                aggregates
+            }
+            ValueExpression::Operation(left, _, right) => {
+                let mut aggregates = left.aggregates();
+                aggregates.extend(right.aggregates());
+                aggregates
+            }
+            ValueExpression::FunctionCall(function_call) => {
+                function_call.parameters.iter().flat_map(|parameter| parameter.aggregates()).collect()
+            }
+            ValueExpression::Window(window) => {
+                window.expressions().into_iter().flat_map(|expression| expression.aggregates()).collect()
+            }
+            _ => vec![],
+        }
+    }
+
+    // Every window function call in the expression, outermost first.
+    pub fn windows(&self) -> Vec<&WindowFunction> {
+        match self {
+            ValueExpression::Window(window) => {
+                let mut windows = vec![window.as_ref()];
+                windows.extend(window.expressions().into_iter().flat_map(|expression| expression.windows()));
+                windows
+            }
+            ValueExpression::Operation(left, _, right) => {
+                let mut windows = left.windows();
+                windows.extend(right.windows());
+                windows
+            }
+            ValueExpression::FunctionCall(function_call) => {
+                function_call.parameters.iter().flat_map(|parameter| parameter.windows()).collect()
+            }
+            ValueExpression::Aggregate(Aggregate { argument: Some(argument), .. }) => argument.windows(),
+            _ => vec![],
+        }
+    }
+
+    // Replaces every occurrence of a sub-expression with a value, i.e. an
+    // aggregate with its result or a GROUP BY expression with the group's key.
+    pub fn substitute(&mut self, find: &ValueExpression, value: &DataValue) {
+        if self == find {
+            *self = ValueExpression::computed_value(value.clone());
+            return;
+        }
+        match self {
+            ValueExpression::Operation(left, _, right) => {
+                left.substitute(find, value);
+                right.substitute(find, value);
+            }
+            ValueExpression::FunctionCall(function_call) => {
+                for parameter in function_call.parameters.iter_mut() {
+                    parameter.substitute(find, value);
+                }
+            }
+            ValueExpression::Aggregate(Aggregate { argument: Some(argument), .. }) => argument.substitute(find, value),
+            ValueExpression::Window(window) => {
+                for expression in window.expressions_mut() {
+                    expression.substitute(find, value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn is_evaluatable(&self) -> bool {
+        match self {
+            ValueExpression::DataValue(_) | ValueExpression::TypedValue(_, _) => true,
+            ValueExpression::Operation(left, _, right) => left.is_evaluatable() && right.is_evaluatable(),
+            ValueExpression::FunctionCall(function_call) => {
+                function_call.parameters.iter().all(|parameter| parameter.is_evaluatable())
+            }
+            _ => false,
+        }
+    }
+
+    // Reduces a filled expression down to a single value.
+    pub fn evaluate(&self) -> ::anyhow::Result<DataValue> {
+        match self {
+            ValueExpression::DataValue(value) | ValueExpression::TypedValue(value, _) => Ok(value.clone()),
+            ValueExpression::ColumnName(name) => Err(anyhow!("ERROR: column '{}' does not exist", name)),
+            ValueExpression::FunctionCall(function_call) => {
+                let args = function_call
+                    .parameters
+                    .iter()
+                    .map(|parameter| parameter.evaluate())
+                    .collect::<::anyhow::Result<Vec<DataValue>>>()?;
+                functions::call(&function_call.function_name, args)
+            }
+            ValueExpression::Aggregate(aggregate) => {
+                Err(anyhow!("ERROR: aggregate function {} is not allowed here", aggregate.function.as_str()))
+            }
+            ValueExpression::Subquery(_) => Err(anyhow!("ERROR: subqueries are not supported here")),
+            ValueExpression::Window(window) => {
+                Err(anyhow!("ERROR: window function {} is not allowed here", window.function.as_str()))
+            }
+            ValueExpression::Operation(left, operator, right) => {
+                let left = left.evaluate()?;
+                let right = right.evaluate()?;
+                let value = match operator {
+                    ValueOperator::Concatenate => return Self::concatenate(left, right),
+                    _ => DataValue::arithmetic(operator, left, right)?,
+                };
+                // Integers are computed in i64, then have to fit the type
+                // the operation is done in
+                match (&value, self.integer_type()) {
+                    (DataValue::I64Value(_), Some(datatype @ (Datatype::SmallInt | Datatype::Integer))) => datatype
+                        .cast_value(value)
+                        .map_err(|_| anyhow!("ERROR: {} out of range", datatype.as_str())),
+                    _ => Ok(value),
+                }
+            }
+        }
+    }
+
+    // A column's value for fill_values. Integers keep the column's type, so
+    // arithmetic on them is checked against it.
+    pub fn column_value(value: DataValue, datatype: &Datatype) -> ValueExpression {
+        match datatype {
+            Datatype::SmallInt | Datatype::Integer | Datatype::BigInt => ValueExpression::TypedValue(value, datatype.clone()),
+            _ => ValueExpression::DataValue(value),
+        }
+    }
+
+    // A value worked out while running the query, i.e. an aggregate's
+    // result. Its integers are bigints, where a literal's can be narrower.
+    pub fn computed_value(value: DataValue) -> ValueExpression {
+        match value.datatype() {
+            Some(datatype) => Self::column_value(value, &datatype),
+            None => ValueExpression::DataValue(value),
+        }
+    }
+
+    // The integer type arithmetic is done in, the way Postgres picks it. A
+    // literal is an integer if it fits one, and an operation is done in the
+    // wider of its two sides. None for anything else, which is left as i64.
+    fn integer_type(&self) -> Option<Datatype> {
+        match self {
+            ValueExpression::DataValue(DataValue::I64Value(value)) if i32::try_from(*value).is_ok() => Some(Datatype::Integer),
+            ValueExpression::DataValue(DataValue::I64Value(_)) => Some(Datatype::BigInt),
+            ValueExpression::TypedValue(_, datatype @ (Datatype::SmallInt | Datatype::Integer | Datatype::BigInt)) => {
+                Some(datatype.clone())
+            }
+            ValueExpression::Operation(_, ValueOperator::Concatenate, _) => None,
+            ValueExpression::Operation(left, _, right) => left.integer_type()?.common_type(&right.integer_type()?),
+            _ => None,
+        }
+    }
+
+    // Like Postgres, anything can be concatenated to a string but at least
+    // one side has to be a string.
+    fn concatenate(left: DataValue, right: DataValue) -> ::anyhow::Result<DataValue> {
+        match (&left, &right) {
+            (DataValue::Null, _) | (_, DataValue::Null) => Ok(DataValue::Null),
+            (DataValue::StringValue(_), _) | (_, DataValue::StringValue(_)) => {
+                Ok(DataValue::StringValue(format!("{}{}", left, right)))
+            }
+            _ => Err(anyhow!(
+                "ERROR: operator does not exist: {} || {}",
+                left.type_name(),
+                right.type_name()
+            )),
+        }
+    }
+}
+
+impl LogicExpression {
+    pub fn is_valid(&self) -> bool {
+        match self {
+            LogicExpression::Comparison(comparison) => comparison.is_valid(),
+            LogicExpression::Value(value) => matches!(value.evaluate(), Ok(DataValue::BoolValue(_) | DataValue::Null)),
+            LogicExpression::And(left, right) | LogicExpression::Or(left, right) => left.is_valid() && right.is_valid(),
+            LogicExpression::Not(inner) => inner.is_valid(),
+            LogicExpression::In(_) | LogicExpression::Exists(_) => self.is_evaluatable(),
+        }
+    }
+
+    pub fn is_evaluatable(&self) -> bool {
+        match self {
+            LogicExpression::Comparison(comparison) => comparison.is_evaluatable(),
+            LogicExpression::Value(value) => value.is_evaluatable(),
+            LogicExpression::And(left, right) | LogicExpression::Or(left, right) => left.is_evaluatable() && right.is_evaluatable(),
+            LogicExpression::Not(inner) => inner.is_evaluatable(),
+            LogicExpression::In(in_expression) => match &in_expression.source {
+                InSource::List(list) => in_expression.value.is_evaluatable() && list.iter().all(|item| item.is_evaluatable()),
+                InSource::Subquery(_) => false,
+            },
+            LogicExpression::Exists(_) => false,
+        }
+    }
+
+    pub fn fill_values(&mut self, hmap: HashMap<String, ValueExpression>) -> ::anyhow::Result<()> {
+        self.fill_values_ref(&hmap);
+        Ok(())
+    }
+
+    // Every operand in the expression tree.
+    pub fn value_expressions(&self) -> Vec<&ValueExpression> {
+        match self {
+            LogicExpression::Comparison(comparison) => vec![&comparison.left_hand, &comparison.right_hand],
+            LogicExpression::Value(value) => vec![value],
+            LogicExpression::And(left, right) | LogicExpression::Or(left, right) => {
+                let mut values = left.value_expressions();
+                values.extend(right.value_expressions());
+                values
+            }
+            LogicExpression::Not(inner) => inner.value_expressions(),
+            LogicExpression::In(in_expression) => {
+                let mut values = vec![&in_expression.value];
+                if let InSource::List(list) = &in_expression.source {
+                    values.extend(list);
+                }
+                values
+            }
+            LogicExpression::Exists(_) => vec![],
+        }
+    }
+
+    pub fn value_expressions_mut(&mut self) -> Vec<&mut ValueExpression> {
+        match self {
+            LogicExpression::Comparison(comparison) => vec![&mut comparison.left_hand, &mut comparison.right_hand],
+            LogicExpression::Value(value) => vec![value],
+            LogicExpression::And(left, right) | LogicExpression::Or(left, right) => {
+                let mut values = left.value_expressions_mut();
+                values.extend(right.value_expressions_mut());
+                values
+            }
+            LogicExpression::Not(inner) => inner.value_expressions_mut(),
+            LogicExpression::In(in_expression) => {
+                let mut values = vec![&mut in_expression.value];
+                if let InSource::List(list) = &mut in_expression.source {
+                    values.extend(list.iter_mut());
+                }
+                values
+            }
+            LogicExpression::Exists(_) => vec![],
+        }
+    }
+
+    // Replaces every subquery with its result, IN (SELECT ...) becomes a
+    // list of the values and EXISTS (SELECT ...) a boolean.
+    pub fn resolve_subqueries(&mut self, run: &mut SubqueryRunner) -> ::anyhow::Result<()> {
+        match self {
+            LogicExpression::Exists(subquery) => {
+                let (_, rows) = run(subquery)?;
+                *self = LogicExpression::Value(ValueExpression::DataValue(DataValue::BoolValue(!rows.is_empty())));
+            }
+            LogicExpression::In(in_expression) => {
+                if let InSource::Subquery(subquery) = &in_expression.source {
+                    let (column_count, rows) = run(subquery)?;
+                    if column_count != 1 {
+                        return Err(anyhow!("ERROR: subquery has too many columns"));
+                    }
+                    let list = rows.into_iter().flatten().map(ValueExpression::DataValue).collect();
+                    in_expression.source = InSource::List(list);
+                }
+                for value in self.value_expressions_mut() {
+                    value.resolve_subqueries(run)?;
+                }
+            }
+            LogicExpression::And(left, right) | LogicExpression::Or(left, right) => {
+                left.resolve_subqueries(run)?;
+                right.resolve_subqueries(run)?;
+            }
+            LogicExpression::Not(inner) => inner.resolve_subqueries(run)?,
+            _ => {
+                for value in self.value_expressions_mut() {
+                    value.resolve_subqueries(run)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn has_subqueries(&self) -> bool {
+        match self {
+            LogicExpression::Exists(_) => true,
+            LogicExpression::In(InExpression { source: InSource::Subquery(_), .. }) => true,
+            LogicExpression::And(left, right) | LogicExpression::Or(left, right) => left.has_subqueries() || right.has_subqueries(),
+            LogicExpression::Not(inner) => inner.has_subqueries(),
+            _ => self.value_expressions().iter().any(|value| value.has_subqueries()),
+        }
+    }
+
+    fn fill_values_ref(&mut self, hmap: &HashMap<String, ValueExpression>) {
+        match self {
+            LogicExpression::Comparison(comparison) => {
+                comparison.left_hand.fill_values(hmap);
+                comparison.right_hand.fill_values(hmap);
+            }
+            LogicExpression::Value(value) => value.fill_values(hmap),
+            LogicExpression::And(left, right) | LogicExpression::Or(left, right) => {
+                left.fill_values_ref(hmap);
+                right.fill_values_ref(hmap);
+            }
+            LogicExpression::Not(inner) => inner.fill_values_ref(hmap),
+            LogicExpression::In(_) => {
+                for value in self.value_expressions_mut() {
+                    value.fill_values(hmap);
+                }
+            }
+            LogicExpression::Exists(_) => {}
+        }
+    }
+
+    // Evaluates the expression as a WHERE clause would, where an unknown
+    // result (e.g. a comparison against NULL) doesn't match.
+    pub fn evaluate(&self) -> ::anyhow::Result<bool> {
+        Ok(self.evaluate_nullable()?.unwrap_or(false))
+    }
+
+    // Evaluates the expression using SQL's three-valued logic, None is
+    // the unknown truth value.
+    pub fn evaluate_nullable(&self) -> ::anyhow::Result<Option<bool>> {
+        match self {
+            LogicExpression::Comparison(comparison) => comparison.evaluate_nullable(),
+            LogicExpression::Value(value) => match value.evaluate()? {
+                DataValue::BoolValue(val) => Ok(Some(val)),
+                DataValue::Null => Ok(None),
+                value => Err(anyhow!("Argument of logical expression must be type boolean, not type {}", value.type_name())),
+            },
+            // FALSE AND NULL is FALSE, TRUE OR NULL is TRUE, anything else with a NULL is unknown
+            LogicExpression::And(left, right) => {
+                let left = left.evaluate_nullable()?;
+                let right = right.evaluate_nullable()?;
+                match (left, right) {
+                    (Some(false), _) | (_, Some(false)) => Ok(Some(false)),
+                    (Some(true), Some(true)) => Ok(Some(true)),
+                    _ => Ok(None),
+                }
+            }
+            LogicExpression::Or(left, right) => {
+                let left = left.evaluate_nullable()?;
+                let right = right.evaluate_nullable()?;
+                match (left, right) {
+                    (Some(true), _) | (_, Some(true)) => Ok(Some(true)),
+                    (Some(false), Some(false)) => Ok(Some(false)),
+                    _ => Ok(None),
+                }
+            }
+            LogicExpression::Not(inner) => Ok(inner.evaluate_nullable()?.map(|val| !val)),
+            // Like a chain of = joined by OR, so a NULL anywhere without a
+            // match is unknown
+            LogicExpression::In(in_expression) => {
+                let InSource::List(list) = &in_expression.source else {
+                    return Err(anyhow!("ERROR: subqueries are not supported here"));
+                };
+                let mut result = Some(false);
+                for item in list {
+                    let comparison = Comparison {
+                        left_hand: in_expression.value.clone(),
+                        right_hand: item.clone(),
+                        operator: LogicalOperator::Equal,
+                    };
+                    match comparison.evaluate_nullable()? {
+                        Some(true) => {
+                            result = Some(true);
+                            break;
+                        }
+                        Some(false) => {}
+                        None => result = None,
+                    }
+                }
+                Ok(if in_expression.negated { result.map(|val| !val) } else { result })
+            }
+            LogicExpression::Exists(_) => Err(anyhow!("ERROR: subqueries are not supported here")),
+        }
+    }
+}
+
+impl Comparison {
+    pub fn is_valid(&self) -> bool {
+        if !self.is_evaluatable() {
+            return false;
+        }
+        match (self.left_hand.evaluate(), self.right_hand.evaluate()) {
+            (Ok(left), Ok(right)) => {
+                left == DataValue::Null || right == DataValue::Null || DataValue::coerce(left, right).is_ok()
+            }
+            _ => false,
+        }
+    }
+    
+    pub fn is_evaluatable(&self) -> bool {
+        self.left_hand.is_evaluatable() && self.right_hand.is_evaluatable()
+    }
+
+    pub fn evaluate_nullable(&self) -> ::anyhow::Result<Option<bool>> {
+        if !self.is_evaluatable() {
+            return Err(anyhow!("Logical expression has not been properly filled. (Do you have a typo in a column name?)")); 
+        }
+        let left = self.left_hand.evaluate()?;
+        let right = self.right_hand.evaluate()?;
+
+        if let LogicalOperator::Is | LogicalOperator::IsNot = self.operator {
+            if right != DataValue::Null {
+                return Err(anyhow!("IS only supports comparing against NULL"));
+            }
+            return Ok(Some((left == DataValue::Null) == (self.operator == LogicalOperator::Is)));
+        }
+        if left == DataValue::Null || right == DataValue::Null {
+            return Ok(None);
+        }
+
+        let (left, right) = DataValue::coerce(left, right)?;
+        match left {
+            DataValue::StringValue(_) => {
+                self.evaluate_string(&left, &right).map(Some)
+            }
+            DataValue::BoolValue(_) => {
+                self.evaluate_bool(&left, &right).map(Some)
+            }
+            _ => {
+                self.evaluate_ordered(&left, &right).map(Some)
+            }
+        }
+    }
+
+    fn evaluate_string(&self, left: &DataValue, right: &DataValue) -> ::anyhow::Result<bool> {
+        let (DataValue::StringValue(value), DataValue::StringValue(pattern)) = (left, right) else {
+            return Err(anyhow!("Invalid operator for datatype varchar"));
+        };
+        match self.operator {
+            LogicalOperator::Like | LogicalOperator::NotLike => {
+                let value: Vec<char> = value.chars().collect();
+                let pattern: Vec<char> = pattern.chars().collect();
+                Ok(like_match(&value, &pattern) == (self.operator == LogicalOperator::Like))
+            }
+            LogicalOperator::ILike | LogicalOperator::NotILike => {
+                let value: Vec<char> = value.to_lowercase().chars().collect();
+                let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+                Ok(like_match(&value, &pattern) == (self.operator == LogicalOperator::ILike))
+            }
+            _ => self.evaluate_ordered(left, right),
+        }
+    }
+
+    fn evaluate_bool(&self, left: &DataValue, right: &DataValue) -> ::anyhow::Result<bool>{
+        match self.operator {
+            LogicalOperator::Equal => Ok(left == right),
+            LogicalOperator::NotEqual => Ok(left != right),
+            _ => Err(anyhow!("Invalid operator for datatype boolean")),
+        }
+    }
+
+    // Integers, floats, numerics, dates, timestamps and strings
+    fn evaluate_ordered(&self, left: &DataValue, right: &DataValue) -> ::anyhow::Result<bool>{
+        let ordering = left.compare(right);
+        match self.operator {
+            LogicalOperator::Equal => Ok(ordering == Some(Ordering::Equal)),
+            LogicalOperator::NotEqual => Ok(ordering != Some(Ordering::Equal)),
+            LogicalOperator::GreaterThan => Ok(ordering == Some(Ordering::Greater)),
+            LogicalOperator::LessThan => Ok(ordering == Some(Ordering::Less)),
+            LogicalOperator::GreaterThanEqualTo => Ok(matches!(ordering, Some(Ordering::Greater | Ordering::Equal))),
+            LogicalOperator::LessThanEqualTo => Ok(matches!(ordering, Some(Ordering::Less | Ordering::Equal))),
+            _ => Err(anyhow!("Invalid operator for datatype {}", left.type_name())),
+        }
+    }
+}
+
+impl Command {
+    // Value operators are parsed by precedence, loosest first: ||, then
+    // + and -, then *, / and %, then unary minus.
+    fn parse_value_expression(tokens: &mut Vec<String>) -> ::anyhow::Result<ValueExpression> {
+        Self::parse_value_binary(tokens, 0)
+    }
+
+    fn parse_value_binary(tokens: &mut Vec<String>, level: usize) -> ::anyhow::Result<ValueExpression> {
+        const LEVELS: [&[ValueOperator]; 3] = [
+            &[ValueOperator::Concatenate],
+            &[ValueOperator::Add, ValueOperator::Subtract],
+            &[ValueOperator::Multiply, ValueOperator::Divide, ValueOperator::Modulo],
+        ];
+        let Some(operators) = LEVELS.get(level) else {
+            return Self::parse_value_unary(tokens);
+        };
+
+        let mut expression = Self::parse_value_binary(tokens, level + 1)?;
+        while let Some(operator) = tokens.last().and_then(|token| ValueOperator::from_token(token)) {
+            if !operators.contains(&operator) {
+                break;
+            }
+            tokens.pop();
+            let right = Self::parse_value_binary(tokens, level + 1)?;
+            expression = ValueExpression::Operation(Box::new(expression), operator, Box::new(right));
+        }
+        Ok(expression)
+    }
+
+    fn parse_value_unary(tokens: &mut Vec<String>) -> ::anyhow::Result<ValueExpression> {
+        match tokens.last().map(|token| token.as_str()) {
+            Some("+") => {
+                tokens.pop();
+                Self::parse_value_unary(tokens)
+            }
+            Some("-") => {
+                tokens.pop();
+                // Negative literals are folded right away, everything else is
+                // 0 - x with a smallint 0, so the result keeps x's type
+                Ok(match Self::parse_value_unary(tokens)? {
+                    ValueExpression::DataValue(DataValue::I64Value(val)) if val != i64::MIN => {
+                        ValueExpression::DataValue(DataValue::I64Value(-val))
+                    }
+                    ValueExpression::DataValue(DataValue::NumericValue(val, scale)) => {
+                        ValueExpression::DataValue(DataValue::NumericValue(-val, scale))
+                    }
+                    ValueExpression::DataValue(DataValue::F64Value(val)) => {
+                        ValueExpression::DataValue(DataValue::F64Value(-val))
+                    }
+                    expression => ValueExpression::Operation(
+                        Box::new(ValueExpression::TypedValue(DataValue::I64Value(0), Datatype::SmallInt)),
+                        ValueOperator::Subtract,
+                        Box::new(expression),
+                    ),
+                })
+            }
+            _ => Self::parse_value_operand(tokens),
+        }
+    }
+
+    fn parse_value_operand(tokens: &mut Vec<String>) -> ::anyhow::Result<ValueExpression> {
+        if Self::is_subquery(tokens) {
+            return Ok(ValueExpression::Subquery(Self::parse_subquery(tokens)?));
+        }
+        if tokens.last().is_some_and(|token| token == "(") {
+            tokens.pop();
+            let expression = Self::parse_value_expression(tokens)?;
+            return match tokens.pop() {
+                Some(token) if token == ")" => Ok(expression),
+                Some(token) => Err(anyhow!("Expected closing parenthesis at or near '{}'", token)),
+                None => Err(anyhow!("Unexpected end of input")),
+            };
+        }
+
+        let mut state: ValueExpressionParserState = ValueExpressionParserState::NumericOrQuoteOrColumnOrFunction;
+        let mut quote_type = "'";
+        let mut value_expr: Option<ValueExpression> = None;
+        let mut literal_type: Option<Datatype> = None;
+        let mut ref_name = String::from("");
+        let mut parameters: Vec<ValueExpression> = vec![];
+
+        while let Some(token) = &tokens.pop() {
+            match state {
+                ValueExpressionParserState::NumericOrQuoteOrColumnOrFunction => {
+                    // String test
+                    if token == "'" || token == "\"" {
+                        quote_type = if token == "'" { "'" } else { "\"" };
+                        state = ValueExpressionParserState::StringValue;
+                        continue;
+                    }
+
+                    // Numeric Test
+                    if let Ok(i64_val) = token.parse::<i64>() {
+                        return Ok(ValueExpression::DataValue(DataValue::I64Value(i64_val)));
+                    }
+                    if token.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '.') {
+                        if let Ok((value, scale)) = numeric::parse_numeric(token) {
+                            return Ok(ValueExpression::DataValue(DataValue::NumericValue(value, scale)));
+                        }
+                        if let Ok(f64_val) = token.parse::<f64>() {
+                            return Ok(ValueExpression::DataValue(DataValue::F64Value(f64_val)));
+                        }
+                    }
+
+                    // Boolean Test
+                    if token.eq_ignore_ascii_case("TRUE") || token.eq_ignore_ascii_case("FALSE") {
+                        return Ok(ValueExpression::DataValue(DataValue::BoolValue(token.eq_ignore_ascii_case("TRUE"))));
+                    }
+
+                    // NULL Test
+                    if token.eq_ignore_ascii_case("NULL") {
+                        return Ok(ValueExpression::DataValue(DataValue::Null));
+                    }
+
+                    if [")", ",", ";"].contains(&token.as_str()) || ValueOperator::from_token(token).is_some() {
+                        return Err(anyhow!("Syntax error at or near '{}'", token));
+                    }
+
+                    // Function name / Column name test
+                    ref_name = token.clone();
+                    state = ValueExpressionParserState::FunctionOpenParenOrEnd;
+                }
+                ValueExpressionParserState::StringValue => {
+                    value_expr = Some(ValueExpression::DataValue(DataValue::StringValue(token.to_string())));
+                    state = ValueExpressionParserState::EndQuote;
+                }
+                ValueExpressionParserState::EndQuote => {
+                    if token != quote_type {
+                        return Err(anyhow!("Mismatched quotes at or near {}", token));
+                    }
+                    // Typed literal, e.g. DATE '2024-01-01'
+                    if let (Some(datatype), Some(ValueExpression::DataValue(DataValue::StringValue(string)))) = (&literal_type, &value_expr) {
+                        return Ok(ValueExpression::DataValue(datatype.parse_value(string.clone())?));
+                    }
+                    return Ok(value_expr.unwrap());
+                }
+                ValueExpressionParserState::FunctionOpenParenOrEnd => {
+                    if let (true, Some(function)) = (token == "(", AggregateFunction::from_name(&ref_name)) {
+                        let aggregate = Self::parse_aggregate(function, tokens)?;
+                        return Self::parse_window(aggregate, tokens);
+                    } else if token == "(" {
+                        state = ValueExpressionParserState::FunctionParameterOrCloseParen;
+                    } else if token == "'" && Datatype::parse_from_str(&ref_name).is_ok() {
+                        literal_type = Some(Datatype::parse_from_str(&ref_name)?);
+                        state = ValueExpressionParserState::StringValue;
+                    } else {
+                        tokens.push(token.to_string());
+                        return Ok(ValueExpression::ColumnName(ref_name.to_string()));
+                    }
+                }
+                ValueExpressionParserState::FunctionParameterOrCloseParen | ValueExpressionParserState::FunctionParameter => {
+                    if token == ")" && matches!(state, ValueExpressionParserState::FunctionParameterOrCloseParen) {
+                        let function_call = ValueExpression::FunctionCall(FunctionCall { function_name: ref_name, parameters });
+                        return Self::parse_window(function_call, tokens);
+                    }
+                    tokens.push(token.to_string());
+                    parameters.push(Self::parse_value_expression(tokens)?);
+                    state = ValueExpressionParserState::FunctionParameterEnd;
+                }
+                ValueExpressionParserState::FunctionParameterEnd => {
+                    // SUBSTRING also takes the SQL standard SUBSTRING(string FROM start FOR count)
+                    let substring_keyword = ref_name.eq_ignore_ascii_case("SUBSTRING")
+                        && (token.eq_ignore_ascii_case("FROM") || token.eq_ignore_ascii_case("FOR"));
+                    if token == "," || substring_keyword {
+                        state = ValueExpressionParserState::FunctionParameter;
+                    } else if token == ")" {
+                        let function_call = ValueExpression::FunctionCall(FunctionCall { function_name: ref_name, parameters });
+                        return Self::parse_window(function_call, tokens);
+                    } else {
+                        return Err(anyhow!("Expected comma or function closing parenthesis at or near {}", token));
+                    }
+                }
+            }
+        }
+
+        if !ref_name.is_empty() {
+            return Ok(ValueExpression::ColumnName(ref_name.to_string()));
+        }
+        Err(anyhow!("Unexpected end of statement"))
+    }
+
+    // Parses what comes after the open paren of an aggregate call, i.e.
+    // COUNT(*), SUM(DISTINCT price) or MAX(age + 1)
+    fn parse_aggregate(function: AggregateFunction, tokens: &mut Vec<String>) -> ::anyhow::Result<ValueExpression> {
+        let distinct = tokens.last().is_some_and(|token| token.eq_ignore_ascii_case("DISTINCT"));
+        if distinct {
+            tokens.pop();
+        }
+
+        let argument = if function == AggregateFunction::Count && !distinct && tokens.last().is_some_and(|token| token == "*") {
+            tokens.pop();
+            None
+        } else {
+            Some(Box::new(Self::parse_value_expression(tokens)?))
+        };
+
+        match tokens.pop() {
+            Some(token) if token == ")" => Ok(ValueExpression::Aggregate(Aggregate { function, argument, distinct })),
+            Some(token) => Err(anyhow!("Expected closing parenthesis at or near '{}'", token)),
+            None => Err(anyhow!("Unexpected end of input")),
+        }
+    }
+
+    // The OVER (...) after a function call. ROW_NUMBER, RANK, DENSE_RANK, LAG
+    // and LEAD need one, an aggregate with one is a window function.
+    fn parse_window(expression: ValueExpression, tokens: &mut Vec<String>) -> ::anyhow::Result<ValueExpression> {
+        let over = tokens.last().is_some_and(|token| token.eq_ignore_ascii_case("OVER"));
+        let (function, arguments) = match expression {
+            ValueExpression::Aggregate(aggregate) if over => {
+                if aggregate.distinct {
+                    return Err(anyhow!("ERROR: DISTINCT is not implemented for window functions"));
+                }
+                (WindowFunctionType::Aggregate(aggregate), vec![])
+            }
+            ValueExpression::FunctionCall(function_call) => match WindowFunctionType::from_name(&function_call.function_name) {
+                Some(function) if !over => {
+                    return Err(anyhow!("ERROR: window function {} requires an OVER clause", function.as_str()));
+                }
+                Some(function) => {
+                    let argument_count = function_call.parameters.len();
+                    let valid = match function {
+                        WindowFunctionType::Lag | WindowFunctionType::Lead => (1..=3).contains(&argument_count),
+                        _ => argument_count == 0,
+                    };
+                    if !valid {
+                        return Err(anyhow!("ERROR: wrong number of arguments for window function {}", function.as_str()));
+                    }
+                    (function, function_call.parameters)
+                }
+                None if over => {
+                    return Err(anyhow!(
+                        "ERROR: OVER specified, but {} is not a window function nor an aggregate function",
+                        function_call.function_name.to_lowercase()
+                    ));
+                }
+                None => return Ok(ValueExpression::FunctionCall(function_call)),
+            },
+            expression => return Ok(expression),
+        };
+
+        tokens.pop();
+        Self::expect_keyword(tokens, "(")?;
+        let mut partition_by: Vec<ValueExpression> = vec![];
+        if tokens.last().is_some_and(|token| token.eq_ignore_ascii_case("PARTITION")) {
+            tokens.pop();
+            Self::expect_keyword(tokens, "BY")?;
+            partition_by = Self::parse_value_expression_list(tokens)?;
+        }
+        let mut order_by: Vec<OrderByItem> = vec![];
+        if tokens.last().is_some_and(|token| token.eq_ignore_ascii_case("ORDER")) {
+            tokens.pop();
+            Self::expect_keyword(tokens, "BY")?;
+            order_by = Self::parse_order_by(tokens)?;
+        }
+        let frame = Self::parse_window_frame(tokens)?;
+        Self::expect_keyword(tokens, ")")?;
+        Ok(ValueExpression::Window(Box::new(WindowFunction { function, arguments, partition_by, order_by, frame })))
+    }
+
+    // {ROWS | RANGE} start or {ROWS | RANGE} BETWEEN start AND end, where the
+    // end defaults to CURRENT ROW
+    fn parse_window_frame(tokens: &mut Vec<String>) -> ::anyhow::Result<Option<WindowFrame>> {
+        let units = match tokens.last().map(|token| token.to_uppercase()).as_deref() {
+            Some("ROWS") => FrameUnits::Rows,
+            Some("RANGE") => FrameUnits::Range,
+            _ => return Ok(None),
+        };
+        tokens.pop();
+        let between = tokens.last().is_some_and(|token| token.eq_ignore_ascii_case("BETWEEN"));
+        if between {
+            tokens.pop();
+        }
+        let start = Self::parse_frame_bound(tokens)?;
+        let end = if between {
+            Self::expect_keyword(tokens, "AND")?;
+            Self::parse_frame_bound(tokens)?
+        } else {
+            FrameBound::CurrentRow
+        };
+
+        match (start, end) {
+            (FrameBound::UnboundedFollowing, _) => Err(anyhow!("ERROR: frame start cannot be UNBOUNDED FOLLOWING")),
+            (_, FrameBound::UnboundedPreceding) => Err(anyhow!("ERROR: frame end cannot be UNBOUNDED PRECEDING")),
+            (FrameBound::CurrentRow, FrameBound::Preceding(_)) => {
+                Err(anyhow!("ERROR: frame starting from current row cannot have preceding rows"))
+            }
+            (FrameBound::Following(_), FrameBound::Preceding(_) | FrameBound::CurrentRow) => {
+                Err(anyhow!("ERROR: frame starting from following row cannot have preceding rows"))
+            }
+            (FrameBound::Preceding(_) | FrameBound::Following(_), _) | (_, FrameBound::Preceding(_) | FrameBound::Following(_))
+                if units == FrameUnits::Range =>
+            {
+                Err(anyhow!("ERROR: RANGE with offset PRECEDING/FOLLOWING is not supported"))
+            }
+            _ => Ok(Some(WindowFrame { units, start, end })),
+        }
+    }
+
+    // UNBOUNDED PRECEDING, n PRECEDING, CURRENT ROW, n FOLLOWING or
+    // UNBOUNDED FOLLOWING
+    fn parse_frame_bound(tokens: &mut Vec<String>) -> ::anyhow::Result<FrameBound> {
+        if tokens.last().is_some_and(|token| token.eq_ignore_ascii_case("CURRENT")) {
+            tokens.pop();
+            Self::expect_keyword(tokens, "ROW")?;
+            return Ok(FrameBound::CurrentRow);
+        }
+        let offset = if tokens.last().is_some_and(|token| token.eq_ignore_ascii_case("UNBOUNDED")) {
+            tokens.pop();
+            None
+        } else {
+            Some(Self::parse_row_count(tokens, "ROWS")?)
+        };
+        match (tokens.pop().map(|token| token.to_uppercase()).as_deref(), offset) {
+            (Some("PRECEDING"), None) => Ok(FrameBound::UnboundedPreceding),
+            (Some("PRECEDING"), Some(offset)) => Ok(FrameBound::Preceding(offset)),
+            (Some("FOLLOWING"), None) => Ok(FrameBound::UnboundedFollowing),
+            (Some("FOLLOWING"), Some(offset)) => Ok(FrameBound::Following(offset)),
+            (Some(token), _) => Err(anyhow!("Expected PRECEDING or FOLLOWING at or near '{}'", token)),
+            (None, _) => Err(anyhow!("Unexpected end of input")),
+        }
+    }
+
+    fn parse_insert_command(tokens: &mut Vec<String>) -> ::anyhow::Result<Command> {
         let mut state: InsertParserState = InsertParserState::IntoKeyword;
 
         let mut table_name = String::new();
         let mut column_name = String::new();
-        let mut column_val = String::new();
+        let mut column_val = DataValue::Null;
 
         let mut column_list: Vec<String> = vec![];
-        let mut value_list: Vec<String> = vec![];
+        let mut value_list: Vec<DataValue> = vec![];
+        let mut rows: Vec<HashMap<String, InsertItem>> = vec![];
 
         while let Some(token) = &tokens.pop() {
             match state {
@@ -451,6 +1802,32 @@ impl Command {
                     column_list.push(column_name.clone());
                 }
                 InsertParserState::ValuesKeyword => {
+                    if token.eq_ignore_ascii_case("SELECT") || token.eq_ignore_ascii_case("WITH") || token == "(" {
+                        tokens.push(token.to_string());
+                        // The query ends where the RETURNING clause starts
+                        let mut query_tokens = match Self::find_returning(tokens) {
+                            Some(idx) => {
+                                let mut query_tokens = vec![String::from(";")];
+                                query_tokens.extend(tokens.drain(idx + 1..));
+                                query_tokens
+                            }
+                            None => mem::take(tokens),
+                        };
+                        let query = Self::parse_query(&mut query_tokens, false)?;
+                        if let Some(token) = query_tokens.last() {
+                            return Err(anyhow!("Syntax error at or near '{}'", token));
+                        }
+                        let returning = Self::parse_returning(tokens)?;
+                        if !returning.is_empty() {
+                            Self::expect_keyword(tokens, ";")?;
+                        }
+                        let column_list = column_list.iter().map(|col_name| col_name.trim().to_string()).collect();
+                        return Ok(Command::Insert(InsertCommand {
+                            table_name,
+                            source: InsertSource::Select(column_list, Box::new(query)),
+                            returning,
+                        }));
+                    }
                     if token != "VALUES" {
                         return Err(anyhow!(
                             "Unexpected token at or near '{}'. Expected 'VALUES'.",
@@ -471,28 +1848,17 @@ impl Command {
                 InsertParserState::Value => {
                     tokens.push(token.to_string());
                     let expr = Self::parse_value_expression(tokens)?;
-                    if let ValueExpression::DataValue(value) = expr {
-                        match value {
-                            DataValue::StringValue(val) => {
-                                column_val = val.to_string() 
-                            },
-                            DataValue::U8Value(val) => {
-                                column_val = val.to_string(); 
-                            }
-                            DataValue::BoolValue(val) => {
-                                column_val = if val { String::from("TRUE") } else { String::from("FALSE") };
-                            }
-                        }
-                    } else {
+                    if !expr.is_evaluatable() {
                         return Err(anyhow!("Expected data at or near {}", token));
                     }
+                    column_val = expr.evaluate()?;
                     state = InsertParserState::ValueEnd;
                 }
                 InsertParserState::ValueEnd => {
                     if token == "," {
                         state = InsertParserState::Value;
                     } else if token == ")" {
-                        state = InsertParserState::Semicolon;
+                        state = InsertParserState::ValuesListEnd;
                     } else {
                         return Err(anyhow!(
                             "Unexpected token at or near '{}'. Expected comma or rparen.",
@@ -501,132 +1867,389 @@ impl Command {
                     }
 
                     value_list.push(column_val.clone());
-                }
-                InsertParserState::Semicolon => {
-                    if token != ";" {
-                        return Err(anyhow!("Expected semicolon at or near '{}'", token));
-                    } else {
+                    if matches!(state, InsertParserState::ValuesListEnd) {
+                        if column_list.len() != value_list.len() {
+                            return Err(anyhow!(
+                                "INSERT has {} target columns but {} expressions",
+                                column_list.len(),
+                                value_list.len()
+                            ));
+                        }
                         let mut insert_item_list: HashMap<String, InsertItem> = HashMap::new();
-                        for item in column_list.iter().zip(&mut value_list.iter_mut()) {
-                            let (col_name, value) = item;
-
+                        for (col_name, value) in column_list.iter().zip(value_list.drain(..)) {
                             insert_item_list.insert(
-                                col_name.clone().trim().to_string(),
+                                col_name.trim().to_string(),
                                 InsertItem {
                                     column_name: col_name.trim().to_string(),
-                                    column_value: value.trim().to_string(),
+                                    column_value: value,
                                 },
                             );
                         }
-                        return Ok(Command::Insert(InsertCommand {
-                            table_name,
-                            items: insert_item_list,
-                        }));
+                        rows.push(insert_item_list);
                     }
                 }
+                // VALUES (...), (...), ... ends with a semicolon
+                InsertParserState::ValuesListEnd => {
+                    if token == "," {
+                        state = InsertParserState::ValuesListBegin;
+                        continue;
+                    }
+                    tokens.push(token.to_string());
+                    let returning = Self::parse_returning(tokens)?;
+                    Self::expect_keyword(tokens, ";")?;
+                    return Ok(Command::Insert(InsertCommand {
+                        table_name,
+                        source: InsertSource::Values(rows),
+                        returning,
+                    }));
+                }
             }
         }
 
         Err(anyhow!("Unexpected end of input"))
     }
 
+    fn is_comparison_operator(tokens: &[String]) -> bool {
+        let Some(token) = tokens.last() else {
+            return false;
+        };
+        if token.eq_ignore_ascii_case("NOT") {
+            return tokens.len() > 1
+                && (tokens[tokens.len() - 2].eq_ignore_ascii_case("LIKE")
+                    || tokens[tokens.len() - 2].eq_ignore_ascii_case("ILIKE"));
+        }
+        ["=", ">", "<", ">=", "<=", "<>", "!="].contains(&token.as_str())
+            || ["LIKE", "ILIKE", "IS"].iter().any(|keyword| token.eq_ignore_ascii_case(keyword))
+    }
+
+    // Logic expressions are parsed by precedence, loosest first:
+    // OR, AND, NOT, then comparisons and parenthesized groups.
     fn parse_logic_expression(tokens: &mut Vec<String>) -> ::anyhow::Result<LogicExpression> {
-        let mut state: LogicExpressionParserState = LogicExpressionParserState::NumberOrQuoteOrColname;
-        let mut left_hand: Option<ValueExpression> = None;
-        let mut right_hand: Option<ValueExpression> = None;
-        let mut operator: Option<LogicalOperator> = None;
+        let mut expression = Self::parse_logic_and(tokens)?;
+        while tokens.last().is_some_and(|token| token.eq_ignore_ascii_case("OR")) {
+            tokens.pop();
+            let right = Self::parse_logic_and(tokens)?;
+            expression = LogicExpression::Or(Box::new(expression), Box::new(right));
+        }
+        Ok(expression)
+    }
 
-        while let Some(token) = &tokens.pop() {
-            match state {
-                LogicExpressionParserState::NumberOrQuoteOrColname => {
-                    if token == "'" {
-                        state = LogicExpressionParserState::StringValue;
-                    } else {
-                        let test = token.parse::<u8>();
-                         match test {
-                            Ok(u8_val) => {
-                                if left_hand.is_none() {
-                                    left_hand = Some(ValueExpression::DataValue(DataValue::U8Value(u8_val))); 
-                                    state = LogicExpressionParserState::Operator;
-                                } else {
-                                    right_hand = Some(ValueExpression::DataValue(DataValue::U8Value(u8_val))); 
-                                    return Ok(LogicExpression {left_hand: left_hand.unwrap(), right_hand: right_hand.unwrap(), operator: operator.unwrap()});
-                                } 
-                            },
-                            Err(_) => {
-                                if left_hand.is_none() {
-                                    left_hand = Some(ValueExpression::ColumnName(token.to_string())); 
-                                    state = LogicExpressionParserState::Operator;
-                                } else {
-                                    right_hand = Some(ValueExpression::ColumnName(token.to_string())); 
-                                    return Ok(LogicExpression {left_hand: left_hand.unwrap(), right_hand: right_hand.unwrap(), operator: operator.unwrap()});
-                                } 
-                            }, 
-                        }  
-                    }
-
-                }
-                LogicExpressionParserState::StringValue => {
-                    let mut value:  Option<ValueExpression> = None;
-                    if token == "'" {
-                        value = Some(ValueExpression::DataValue(DataValue::StringValue("".to_string())));
-                    } else {
-                        value = Some(ValueExpression::DataValue(DataValue::StringValue(token.to_string())));
-                    }
-                    if left_hand.is_none() {
-                       left_hand = value; 
-                    } else {
-                       right_hand = value; 
-                    } 
-                    state = LogicExpressionParserState::EndQuote;
-                }
-                LogicExpressionParserState::EndQuote => {
-                    if token == "'" {
-                        if right_hand.is_none() {
-                            state = LogicExpressionParserState::Operator; 
-                        } else {
-                            return Ok(LogicExpression {left_hand: left_hand.unwrap(), right_hand: right_hand.unwrap(), operator: operator.unwrap()});
-                        } 
-                    } else {
-                        return Err(anyhow!("Expected end quote at or near {}", token));
-                    }
-                }
-                LogicExpressionParserState::Operator => {
-                    operator = match token.as_str() {
-                        "OR" => Some(LogicalOperator::Or),
-                        "AND" => Some(LogicalOperator::And),
-                        "=" => Some(LogicalOperator::Equal),
-                        ">" => Some(LogicalOperator::GreaterThan),
-                        "<" => Some(LogicalOperator::LessThan),
-                        ">=" => Some(LogicalOperator::GreaterThanEqualTo),
-                        "<=" => Some(LogicalOperator::LessThanEqualTo),
-                        _ => return Err(anyhow!("Unknown operator {}", token))
-                    };
-                    state = LogicExpressionParserState::NumberOrQuoteOrColname;
+    fn parse_logic_and(tokens: &mut Vec<String>) -> ::anyhow::Result<LogicExpression> {
+        let mut expression = Self::parse_logic_not(tokens)?;
+        while tokens.last().is_some_and(|token| token.eq_ignore_ascii_case("AND")) {
+            tokens.pop();
+            let right = Self::parse_logic_not(tokens)?;
+            expression = LogicExpression::And(Box::new(expression), Box::new(right));
+        }
+        Ok(expression)
+    }
+
+    fn parse_logic_not(tokens: &mut Vec<String>) -> ::anyhow::Result<LogicExpression> {
+        if tokens.last().is_some_and(|token| token.eq_ignore_ascii_case("NOT")) {
+            tokens.pop();
+            return Ok(LogicExpression::Not(Box::new(Self::parse_logic_not(tokens)?)));
+        }
+        Self::parse_comparison(tokens)
+    }
+
+    fn parse_comparison(tokens: &mut Vec<String>) -> ::anyhow::Result<LogicExpression> {
+        // A parenthesis either groups logic, i.e. (a = 1 OR b = 2), or is the
+        // start of a value, i.e. (a || b) = 'ab'. Try the former first and
+        // back up if it doesn't pan out.
+        if tokens.last().is_some_and(|token| token.eq_ignore_ascii_case("EXISTS")) {
+            tokens.pop();
+            return Ok(LogicExpression::Exists(Self::parse_subquery(tokens)?));
+        }
+        if tokens.last().is_some_and(|token| token == "(") && !Self::is_subquery(tokens) {
+            let snapshot = tokens.clone();
+            tokens.pop();
+            if let Ok(expression) = Self::parse_logic_expression(tokens) {
+                if tokens.pop().is_some_and(|token| token == ")")
+                    && tokens.last().is_none_or(|token| ValueOperator::from_token(token).is_none())
+                    && !Self::is_comparison_operator(tokens)
+                {
+                    return Ok(expression);
                 }
             }
+            *tokens = snapshot;
         }
 
-        Err(anyhow!("Unexpected end of input"))
+        let left_hand = Self::parse_value_expression(tokens)?;
+        let negated = tokens.last().is_some_and(|token| token.eq_ignore_ascii_case("NOT"))
+            && tokens.len() > 1
+            && tokens[tokens.len() - 2].eq_ignore_ascii_case("IN");
+        if negated || tokens.last().is_some_and(|token| token.eq_ignore_ascii_case("IN")) {
+            tokens.truncate(tokens.len() - if negated { 2 } else { 1 });
+            let source = if Self::is_subquery(tokens) {
+                InSource::Subquery(Self::parse_subquery(tokens)?)
+            } else {
+                Self::expect_keyword(tokens, "(")?;
+                let list = Self::parse_value_expression_list(tokens)?;
+                Self::expect_keyword(tokens, ")")?;
+                InSource::List(list)
+            };
+            return Ok(LogicExpression::In(InExpression { value: left_hand, source, negated }));
+        }
+        if !Self::is_comparison_operator(tokens) {
+            return Ok(LogicExpression::Value(left_hand));
+        }
+
+        let token = tokens.pop().unwrap_or_default();
+        let operator = match token.to_uppercase().as_str() {
+            "=" => LogicalOperator::Equal,
+            ">" => LogicalOperator::GreaterThan,
+            "<" => LogicalOperator::LessThan,
+            ">=" => LogicalOperator::GreaterThanEqualTo,
+            "<=" => LogicalOperator::LessThanEqualTo,
+            "<>" | "!=" => LogicalOperator::NotEqual,
+            "LIKE" => LogicalOperator::Like,
+            "ILIKE" => LogicalOperator::ILike,
+            "IS" => {
+                if tokens.last().is_some_and(|next| next.eq_ignore_ascii_case("NOT")) {
+                    tokens.pop();
+                    LogicalOperator::IsNot
+                } else {
+                    LogicalOperator::Is
+                }
+            }
+            _ => match tokens.pop() {
+                // NOT LIKE / NOT ILIKE
+                Some(next) if next.eq_ignore_ascii_case("LIKE") => LogicalOperator::NotLike,
+                Some(next) if next.eq_ignore_ascii_case("ILIKE") => LogicalOperator::NotILike,
+                _ => return Err(anyhow!("Expected LIKE or ILIKE after NOT")),
+            },
+        };
+
+        let right_hand = Self::parse_value_expression(tokens)?;
+        Ok(LogicExpression::Comparison(Comparison { left_hand, right_hand, operator }))
+    }
+
+    fn is_subquery(tokens: &[String]) -> bool {
+        tokens.len() > 1
+            && tokens[tokens.len() - 1] == "("
+            && (tokens[tokens.len() - 2].eq_ignore_ascii_case("SELECT") || tokens[tokens.len() - 2].eq_ignore_ascii_case("WITH"))
+    }
+
+    // (SELECT ...)
+    fn parse_subquery(tokens: &mut Vec<String>) -> ::anyhow::Result<Box<SelectCommand>> {
+        Self::expect_keyword(tokens, "(")?;
+        let subquery = Self::parse_query(tokens, true)?;
+        Self::expect_keyword(tokens, ")")?;
+        Ok(Box::new(subquery))
     }
 
     fn parse_select_command(tokens: &mut Vec<String>) -> ::anyhow::Result<Command> {
+        Ok(Command::Select(Box::new(Self::parse_query(tokens, false)?)))
+    }
+
+    // [WITH ...] SELECT ... [{UNION | INTERSECT | EXCEPT} [ALL] SELECT ...]
+    // [ORDER BY ...] [LIMIT ...] [OFFSET ...]
+    //
+    // INTERSECT binds tighter than UNION and EXCEPT, so it's nested in the
+    // query to its left. The ORDER BY, LIMIT and OFFSET parsed as part of the
+    // last query belong to the whole thing.
+    fn parse_query(tokens: &mut Vec<String>, subquery: bool) -> ::anyhow::Result<SelectCommand> {
+        let mut with = Self::parse_with(tokens)?;
+        let (mut query, mut last_parenthesized) = Self::parse_set_term(tokens, subquery)?;
+        while let Some((operator, all)) = Self::parse_set_operator(tokens) {
+            let (operand, parenthesized) = match operator {
+                SetOperator::Intersect => Self::parse_set_operand(tokens, subquery)?,
+                _ => Self::parse_set_term(tokens, subquery)?,
+            };
+            Self::push_set_operation(&mut query, SetOperation { operator, all, query: Box::new(operand) })?;
+            last_parenthesized = parenthesized;
+        }
+
+        if !last_parenthesized {
+            let mut last = &mut query;
+            while let Some(set_operation) = last.set_operations.last_mut() {
+                last = &mut set_operation.query;
+            }
+            let order_by = mem::take(&mut last.order_by);
+            let (limit, offset) = (last.limit.take(), mem::take(&mut last.offset));
+            (query.order_by, query.limit, query.offset) = (order_by, limit, offset);
+        } else {
+            Self::parse_trailing_clauses(tokens, &mut query)?;
+            if !subquery {
+                Self::expect_keyword(tokens, ";")?;
+            }
+        }
+        with.append(&mut query.with);
+        query.with = with;
+        Ok(query)
+    }
+
+    // A query followed by any number of INTERSECTs
+    fn parse_set_term(tokens: &mut Vec<String>, subquery: bool) -> ::anyhow::Result<(SelectCommand, bool)> {
+        let (mut query, mut last_parenthesized) = Self::parse_set_operand(tokens, subquery)?;
+        while tokens.last().is_some_and(|token| token.eq_ignore_ascii_case("INTERSECT")) {
+            let Some((operator, all)) = Self::parse_set_operator(tokens) else { break };
+            let (operand, parenthesized) = Self::parse_set_operand(tokens, subquery)?;
+            Self::push_set_operation(&mut query, SetOperation { operator, all, query: Box::new(operand) })?;
+            last_parenthesized = parenthesized;
+        }
+        Ok((query, last_parenthesized))
+    }
+
+    // SELECT ... or a parenthesized query, and whether it was parenthesized
+    fn parse_set_operand(tokens: &mut Vec<String>, subquery: bool) -> ::anyhow::Result<(SelectCommand, bool)> {
+        if tokens.last().is_some_and(|token| token == "(") {
+            tokens.pop();
+            let query = Self::parse_query(tokens, true)?;
+            Self::expect_keyword(tokens, ")")?;
+            return Ok((query, true));
+        }
+        Self::expect_keyword(tokens, "SELECT")?;
+        Ok((Self::parse_select(tokens, subquery)?, false))
+    }
+
+    // UNION | INTERSECT | EXCEPT [ALL | DISTINCT]
+    fn parse_set_operator(tokens: &mut Vec<String>) -> Option<(SetOperator, bool)> {
+        let operator = match tokens.last()?.to_uppercase().as_str() {
+            "UNION" => SetOperator::Union,
+            "INTERSECT" => SetOperator::Intersect,
+            "EXCEPT" => SetOperator::Except,
+            _ => return None,
+        };
+        tokens.pop();
+        let all = tokens.last().is_some_and(|token| token.eq_ignore_ascii_case("ALL"));
+        if all || tokens.last().is_some_and(|token| token.eq_ignore_ascii_case("DISTINCT")) {
+            tokens.pop();
+        }
+        Some((operator, all))
+    }
+
+    // Only the last query can have an ORDER BY, LIMIT or OFFSET, unless it's
+    // in parentheses. A parenthesized one that isn't last can't be combined
+    // with the rest without its rows being reordered.
+    fn push_set_operation(query: &mut SelectCommand, set_operation: SetOperation) -> ::anyhow::Result<()> {
+        if !query.order_by.is_empty() || query.limit.is_some() || query.offset > 0 {
+            return Err(anyhow!(
+                "ERROR: ORDER BY, LIMIT and OFFSET are only supported on the last query of a UNION, INTERSECT or EXCEPT"
+            ));
+        }
+        query.set_operations.push(set_operation);
+        Ok(())
+    }
+
+    // ORDER BY, LIMIT and OFFSET after a parenthesized query
+    fn parse_trailing_clauses(tokens: &mut Vec<String>, query: &mut SelectCommand) -> ::anyhow::Result<()> {
+        let mut last_clause = SelectClause::Having;
+        while let Some(token) = tokens.last() {
+            let clause = match token.to_uppercase().as_str() {
+                "ORDER" => SelectClause::OrderBy,
+                "LIMIT" => SelectClause::Limit,
+                "OFFSET" => SelectClause::Offset,
+                _ => return Ok(()),
+            };
+            let limit_after_offset = clause == SelectClause::Limit && last_clause == SelectClause::Offset;
+            if clause <= last_clause && !limit_after_offset {
+                return Err(anyhow!("Syntax error at or near '{}'", token));
+            }
+            let token = tokens.pop().unwrap_or_default();
+            match clause {
+                SelectClause::OrderBy if !query.order_by.is_empty() => {
+                    return Err(anyhow!("ERROR: multiple ORDER BY clauses not allowed"));
+                }
+                SelectClause::OrderBy => {
+                    Self::expect_keyword(tokens, "BY")?;
+                    query.order_by = Self::parse_order_by(tokens)?;
+                }
+                SelectClause::Limit if query.limit.is_some() => {
+                    return Err(anyhow!("ERROR: multiple LIMIT clauses not allowed"));
+                }
+                SelectClause::Limit => query.limit = Self::parse_limit(tokens)?,
+                SelectClause::Offset if query.offset > 0 => {
+                    return Err(anyhow!("ERROR: multiple OFFSET clauses not allowed"));
+                }
+                SelectClause::Offset => query.offset = Self::parse_offset(tokens)?,
+                _ => return Err(anyhow!("Syntax error at or near '{}'", token)),
+            }
+            last_clause = cmp::max(clause, last_clause);
+        }
+        Ok(())
+    }
+
+    // WITH [RECURSIVE] name [(column, ...)] AS (query), ...
+    fn parse_with(tokens: &mut Vec<String>) -> ::anyhow::Result<Vec<CommonTableExpression>> {
+        let mut ctes: Vec<CommonTableExpression> = vec![];
+        if !tokens.last().is_some_and(|token| token.eq_ignore_ascii_case("WITH")) {
+            return Ok(ctes);
+        }
+        tokens.pop();
+        let recursive = tokens.last().is_some_and(|token| token.eq_ignore_ascii_case("RECURSIVE"));
+        if recursive {
+            tokens.pop();
+        }
+
+        loop {
+            let name = match tokens.pop() {
+                Some(token) if Self::is_identifier(&token) && !token.eq_ignore_ascii_case("AS") => token,
+                Some(token) => return Err(anyhow!("Syntax error at or near '{}'", token)),
+                None => return Err(anyhow!("Unexpected end of input")),
+            };
+            if ctes.iter().any(|cte| cte.name == name) {
+                return Err(anyhow!("ERROR: WITH query name '{}' specified more than once", name));
+            }
+
+            let mut column_names: Vec<String> = vec![];
+            if tokens.last().is_some_and(|token| token == "(") {
+                tokens.pop();
+                loop {
+                    match tokens.pop() {
+                        Some(token) if Self::is_identifier(&token) => column_names.push(token),
+                        Some(token) => return Err(anyhow!("Syntax error at or near '{}'", token)),
+                        None => return Err(anyhow!("Unexpected end of input")),
+                    }
+                    if tokens.last().is_none_or(|token| token != ",") {
+                        break;
+                    }
+                    tokens.pop();
+                }
+                Self::expect_keyword(tokens, ")")?;
+            }
+
+            Self::expect_keyword(tokens, "AS")?;
+            Self::expect_keyword(tokens, "(")?;
+            let query = Box::new(Self::parse_query(tokens, true)?);
+            Self::expect_keyword(tokens, ")")?;
+            ctes.push(CommonTableExpression { name, column_names, query, recursive });
+
+            if tokens.last().is_none_or(|token| token != ",") {
+                return Ok(ctes);
+            }
+            tokens.pop();
+        }
+    }
+
+    // A subquery ends at its closing parenthesis rather than a semicolon.
+    // Either ends at a UNION, INTERSECT or EXCEPT, which parse_query handles.
+    fn parse_select(tokens: &mut Vec<String>, subquery: bool) -> ::anyhow::Result<SelectCommand> {
         let mut state: SelectParserState = SelectParserState::ColumnName;
+        let mut last_clause = SelectClause::From;
 
         // intermediate tmp vars
         let mut table_name = String::new();
-        let mut column_names: Vec<String> = vec![];
+        let mut table_alias: Option<String> = None;
+        let mut joins: Vec<Join> = vec![];
+        let mut columns: Vec<SelectColumn> = vec![];
         let mut logic_expression: Option<LogicExpression> = None;
+        let mut group_by: Vec<ValueExpression> = vec![];
+        let mut having: Option<LogicExpression> = None;
+        let mut order_by: Vec<OrderByItem> = vec![];
+        let mut limit: Option<usize> = None;
+        let mut offset: usize = 0;
+        let distinct = Self::parse_distinct(tokens)?;
 
         while let Some(token) = &tokens.pop() {
             match state {
                 SelectParserState::ColumnName => {
                     if token.eq_ignore_ascii_case("FROM") {
                         return Err(anyhow!("Did not expect FROM keyword at or near '{}'", token));
-                    } else {
-                        column_names.push(token.clone());
-                        state = SelectParserState::ColumnNameCommaOrFrom;
                     }
+                    tokens.push(token.to_string());
+                    columns.push(Self::parse_select_column(tokens)?);
+                    state = SelectParserState::ColumnNameCommaOrFrom;
                 }
                 SelectParserState::ColumnNameCommaOrFrom => {
                     if token == "," {
@@ -634,35 +2257,309 @@ impl Command {
                     } else if token.eq_ignore_ascii_case("FROM") {
                         state = SelectParserState::TableName;
                     } else {
-                        return Err(anyhow!("Expected comma or FROM keyword at or near '{}'", token));
+                        // No FROM, i.e. SELECT nextval('s'), reads one row
+                        // without any columns. table_name stays empty.
+                        tokens.push(token.to_string());
+                        state = SelectParserState::ClauseOrSemicolon;
                     }
                 }
                 SelectParserState::TableName => {
-                    table_name = token.to_string();
-                    state = SelectParserState::WhereKeywordOrSemicolon;
+                    tokens.push(token.to_string());
+                    (table_name, table_alias) = Self::parse_table_reference(tokens)?;
+                    joins = Self::parse_joins(tokens)?;
+                    state = SelectParserState::ClauseOrSemicolon;
                 }
-                SelectParserState::WhereKeywordOrSemicolon => {
-                    if token == ";" {
-                        return Ok(Command::Select(SelectCommand { table_name, column_names, logic_expression: None }));
-                    } else if token == "WHERE" {
-                        logic_expression = Some(Self::parse_logic_expression(tokens)?);
-                        state = SelectParserState::Semicolon;
-                    } else {
-                        return Err(anyhow!("Expected semicolon at or near '{}'", token));
+                SelectParserState::ClauseOrSemicolon => {
+                    let set_operator = ["UNION", "INTERSECT", "EXCEPT"].iter().any(|keyword| token.eq_ignore_ascii_case(keyword));
+                    if set_operator && last_clause >= SelectClause::OrderBy {
+                        return Err(anyhow!("Syntax error at or near '{}'", token));
+                    }
+                    if (token == ";" && !subquery) || (token == ")" && subquery) || set_operator {
+                        if subquery || set_operator {
+                            tokens.push(token.to_string());
+                        }
+                        return Ok(SelectCommand {
+                            with: vec![],
+                            table_name,
+                            table_alias,
+                            joins,
+                            distinct,
+                            columns,
+                            logic_expression,
+                            group_by,
+                            having,
+                            order_by,
+                            limit,
+                            offset,
+                            set_operations: vec![],
+                        });
+                    }
+
+                    let clause = match token.to_uppercase().as_str() {
+                        "WHERE" => SelectClause::Where,
+                        "GROUP" => SelectClause::GroupBy,
+                        "HAVING" => SelectClause::Having,
+                        "ORDER" => SelectClause::OrderBy,
+                        "LIMIT" => SelectClause::Limit,
+                        "OFFSET" => SelectClause::Offset,
+                        _ => return Err(anyhow!("Expected semicolon at or near '{}'", token)),
+                    };
+                    // LIMIT and OFFSET can come in either order
+                    let limit_after_offset = clause == SelectClause::Limit && last_clause == SelectClause::Offset;
+                    if clause <= last_clause && !limit_after_offset {
+                        return Err(anyhow!("Syntax error at or near '{}'", token));
+                    }
+                    match clause {
+                        SelectClause::Where => logic_expression = Some(Self::parse_logic_expression(tokens)?),
+                        SelectClause::GroupBy => {
+                            Self::expect_keyword(tokens, "BY")?;
+                            group_by = Self::parse_value_expression_list(tokens)?;
+                        }
+                        SelectClause::Having => having = Some(Self::parse_logic_expression(tokens)?),
+                        SelectClause::OrderBy => {
+                            Self::expect_keyword(tokens, "BY")?;
+                            order_by = Self::parse_order_by(tokens)?;
+                        }
+                        SelectClause::Limit => limit = Self::parse_limit(tokens)?,
+                        SelectClause::Offset => offset = Self::parse_offset(tokens)?,
+                        SelectClause::From => {}
+                    }
+                    last_clause = cmp::max(clause, last_clause);
+                }
+            }
+        }
+
+        Err(anyhow!("Unexpected end of input"))
+    }
+
+    // *, table.* or expr [AS alias]
+    fn parse_select_column(tokens: &mut Vec<String>) -> ::anyhow::Result<SelectColumn> {
+        match tokens.pop() {
+            Some(token) if token == "*" => Ok(SelectColumn::Wildcard),
+            Some(token) if token.ends_with('.') && tokens.last().is_some_and(|next| next == "*") => {
+                tokens.pop();
+                Ok(SelectColumn::TableWildcard(token.trim_end_matches('.').to_string()))
+            }
+            Some(token) => {
+                tokens.push(token);
+                let expression = Self::parse_value_expression(tokens)?;
+                Ok(SelectColumn::Expression(expression, Self::parse_alias(tokens)?))
+            }
+            None => Err(anyhow!("Unexpected end of input")),
+        }
+    }
+
+    // [RETURNING column, ...] after an INSERT, UPDATE or DELETE, the columns
+    // are the same as a select list's
+    fn parse_returning(tokens: &mut Vec<String>) -> ::anyhow::Result<Vec<SelectColumn>> {
+        if !tokens.last().is_some_and(|token| token.eq_ignore_ascii_case("RETURNING")) {
+            return Ok(vec![]);
+        }
+        tokens.pop();
+        let mut columns = vec![Self::parse_select_column(tokens)?];
+        while tokens.last().is_some_and(|token| token == ",") {
+            tokens.pop();
+            columns.push(Self::parse_select_column(tokens)?);
+        }
+        Ok(columns)
+    }
+
+    // Where the RETURNING of an INSERT ... SELECT is, the first one that
+    // isn't in parentheses or a string
+    fn find_returning(tokens: &[String]) -> Option<usize> {
+        let mut depth: usize = 0;
+        let mut quote: Option<&str> = None;
+        for (idx, token) in tokens.iter().enumerate().rev() {
+            match (quote, token.as_str()) {
+                (Some(open), token) if token == open => quote = None,
+                (Some(_), _) => {}
+                (None, "'" | "\"") => quote = Some(token),
+                (None, "(") => depth += 1,
+                (None, ")") => depth = depth.saturating_sub(1),
+                (None, token) if depth == 0 && token.eq_ignore_ascii_case("RETURNING") => return Some(idx),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    // table [[AS] alias]
+    fn parse_table_reference(tokens: &mut Vec<String>) -> ::anyhow::Result<(String, Option<String>)> {
+        let table_name = match tokens.pop() {
+            Some(token) if Self::is_identifier(&token) => token,
+            Some(token) => return Err(anyhow!("Syntax error at or near '{}'", token)),
+            None => return Err(anyhow!("Unexpected end of input")),
+        };
+        let explicit_alias = tokens.last().is_some_and(|token| token.eq_ignore_ascii_case("AS"));
+        if explicit_alias {
+            tokens.pop();
+        }
+        let alias = match tokens.last() {
+            Some(token) if Self::is_identifier(token) && !FROM_KEYWORDS.iter().any(|keyword| token.eq_ignore_ascii_case(keyword)) => tokens.pop(),
+            Some(token) if explicit_alias => return Err(anyhow!("Syntax error at or near '{}'", token)),
+            _ => None,
+        };
+        Ok((table_name, alias))
+    }
+
+    // Any number of [INNER | LEFT | RIGHT | FULL [OUTER] | CROSS] JOIN table
+    // ON condition, a comma is the same as a CROSS JOIN
+    fn parse_joins(tokens: &mut Vec<String>) -> ::anyhow::Result<Vec<Join>> {
+        let mut joins: Vec<Join> = vec![];
+        loop {
+            let Some(token) = tokens.last().map(|token| token.to_uppercase()) else {
+                return Ok(joins);
+            };
+            let join_type = match token.as_str() {
+                "," => JoinType::Cross,
+                "JOIN" | "INNER" => JoinType::Inner,
+                "LEFT" => JoinType::Left,
+                "RIGHT" => JoinType::Right,
+                "FULL" => JoinType::Full,
+                "CROSS" => JoinType::Cross,
+                _ => return Ok(joins),
+            };
+            tokens.pop();
+            if token != "," && token != "JOIN" {
+                let outer = tokens.last().is_some_and(|next| next.eq_ignore_ascii_case("OUTER"));
+                if outer && matches!(join_type, JoinType::Left | JoinType::Right | JoinType::Full) {
+                    tokens.pop();
+                }
+                Self::expect_keyword(tokens, "JOIN")?;
+            }
+
+            let (table_name, table_alias) = Self::parse_table_reference(tokens)?;
+            let condition = match join_type {
+                JoinType::Cross => None,
+                _ => {
+                    Self::expect_keyword(tokens, "ON")?;
+                    Some(Self::parse_logic_expression(tokens)?)
+                }
+            };
+            joins.push(Join { join_type, table_name, table_alias, condition });
+        }
+    }
+
+    fn is_identifier(token: &str) -> bool {
+        !token.is_empty() && token.chars().all(|c| c.is_alphanumeric() || c == '_')
+    }
+
+    // [ALL | DISTINCT | DISTINCT ON (expr, ...)] before the select list
+    fn parse_distinct(tokens: &mut Vec<String>) -> ::anyhow::Result<Option<Distinct>> {
+        if tokens.last().is_some_and(|token| token.eq_ignore_ascii_case("ALL")) {
+            tokens.pop();
+            return Ok(None);
+        }
+        if !tokens.last().is_some_and(|token| token.eq_ignore_ascii_case("DISTINCT")) {
+            return Ok(None);
+        }
+        tokens.pop();
+        if !tokens.last().is_some_and(|token| token.eq_ignore_ascii_case("ON")) {
+            return Ok(Some(Distinct::AllColumns));
+        }
+        tokens.pop();
+        Self::expect_keyword(tokens, "(")?;
+        let expressions = Self::parse_value_expression_list(tokens)?;
+        Self::expect_keyword(tokens, ")")?;
+        Ok(Some(Distinct::On(expressions)))
+    }
+
+    // AS alias after a select list expression, the alias can be quoted
+    fn parse_alias(tokens: &mut Vec<String>) -> ::anyhow::Result<Option<String>> {
+        if !tokens.last().is_some_and(|token| token.eq_ignore_ascii_case("AS")) {
+            return Ok(None);
+        }
+        tokens.pop();
+        match tokens.pop() {
+            Some(quote) if quote == "\"" => {
+                let alias = tokens.pop().ok_or_else(|| anyhow!("Unexpected end of input"))?;
+                Self::expect_keyword(tokens, "\"")?;
+                Ok(Some(alias))
+            }
+            Some(alias) if alias.chars().all(|c| c.is_alphanumeric() || c == '_') && !alias.eq_ignore_ascii_case("FROM") => {
+                Ok(Some(alias))
+            }
+            Some(token) => Err(anyhow!("Syntax error at or near '{}'", token)),
+            None => Err(anyhow!("Unexpected end of input")),
+        }
+    }
+
+    // expr [ASC | DESC] [NULLS FIRST | NULLS LAST], ...
+    fn parse_order_by(tokens: &mut Vec<String>) -> ::anyhow::Result<Vec<OrderByItem>> {
+        let mut items: Vec<OrderByItem> = vec![];
+        loop {
+            let expression = Self::parse_value_expression(tokens)?;
+            let mut descending = false;
+            if tokens.last().is_some_and(|token| token.eq_ignore_ascii_case("ASC") || token.eq_ignore_ascii_case("DESC")) {
+                descending = tokens.pop().is_some_and(|token| token.eq_ignore_ascii_case("DESC"));
+            }
+            // NULLs sort as if they were larger than any other value by default
+            let mut nulls_first = descending;
+            if tokens.last().is_some_and(|token| token.eq_ignore_ascii_case("NULLS")) {
+                tokens.pop();
+                nulls_first = match tokens.pop() {
+                    Some(token) if token.eq_ignore_ascii_case("FIRST") => true,
+                    Some(token) if token.eq_ignore_ascii_case("LAST") => false,
+                    Some(token) => return Err(anyhow!("Expected FIRST or LAST at or near '{}'", token)),
+                    None => return Err(anyhow!("Unexpected end of input")),
+                };
+            }
+            items.push(OrderByItem { expression, descending, nulls_first });
+
+            if tokens.last().is_none_or(|token| token != ",") {
+                return Ok(items);
+            }
+            tokens.pop();
+        }
+    }
+
+    // LIMIT count or LIMIT ALL
+    fn parse_limit(tokens: &mut Vec<String>) -> ::anyhow::Result<Option<usize>> {
+        if tokens.last().is_some_and(|token| token.eq_ignore_ascii_case("ALL")) {
+            tokens.pop();
+            return Ok(None);
+        }
+        Ok(Some(Self::parse_row_count(tokens, "LIMIT")?))
+    }
+
+    // OFFSET count [ROW | ROWS]
+    fn parse_offset(tokens: &mut Vec<String>) -> ::anyhow::Result<usize> {
+        let offset = Self::parse_row_count(tokens, "OFFSET")?;
+        if tokens.last().is_some_and(|token| token.eq_ignore_ascii_case("ROW") || token.eq_ignore_ascii_case("ROWS")) {
+            tokens.pop();
+        }
+        Ok(offset)
+    }
+
+    // The argument of LIMIT or OFFSET, a constant non-negative integer
+    fn parse_row_count(tokens: &mut Vec<String>, clause: &str) -> ::anyhow::Result<usize> {
+        let expression = Self::parse_value_expression(tokens)?;
+        if !expression.is_evaluatable() {
+            return Err(anyhow!("ERROR: argument of {} must not contain variables", clause));
+        }
+        match expression.evaluate()? {
+            DataValue::I64Value(count) if count < 0 => Err(anyhow!("ERROR: {} must not be negative", clause)),
+            DataValue::I64Value(count) => Ok(usize::try_from(count)?),
+            value => Err(anyhow!("ERROR: argument of {} must be type bigint, not type {}", clause, value.type_name())),
+        }
+    }
 
-                    }
-                }
-                SelectParserState::Semicolon => {
-                    if token != ";" {
-                        return Err(anyhow!("Expected semicolon at or near '{}'", token));
-                    } else {
-                        return Ok(Command::Select(SelectCommand { table_name, column_names, logic_expression }));
-                    }
-                }
-            }
+    fn expect_keyword(tokens: &mut Vec<String>, keyword: &str) -> ::anyhow::Result<()> {
+        match tokens.pop() {
+            Some(token) if token.eq_ignore_ascii_case(keyword) => Ok(()),
+            Some(token) => Err(anyhow!("Expected {} at or near '{}'", keyword, token)),
+            None => Err(anyhow!("Unexpected end of input")),
         }
+    }
 
-        Err(anyhow!("Unexpected end of input"))
+    // Comma separated expressions, i.e. the list after GROUP BY
+    fn parse_value_expression_list(tokens: &mut Vec<String>) -> ::anyhow::Result<Vec<ValueExpression>> {
+        let mut expressions = vec![Self::parse_value_expression(tokens)?];
+        while tokens.last().is_some_and(|token| token == ",") {
+            tokens.pop();
+            expressions.push(Self::parse_value_expression(tokens)?);
+        }
+        Ok(expressions)
     }
 
 
@@ -687,36 +2584,142 @@ impl Command {
                     state = DeleteParserState::WhereKeywordOrSemicolon;
                 }
                 DeleteParserState::WhereKeywordOrSemicolon => {
-                    if token == ";" {
-                        return Ok(Command::Delete(DeleteCommand { table_name, logic_expression: None }));
-                    } else if token == "WHERE" {
+                    if token == "WHERE" {
                         logic_expression = Some(Self::parse_logic_expression(tokens)?);
-                        state = DeleteParserState::Semicolon;
                     } else {
-                        return Err(anyhow!("Expected semicolon at or near '{}'", token));
+                        tokens.push(token.to_string());
                     }
+                    state = DeleteParserState::Semicolon;
                 }
                 DeleteParserState::Semicolon => {
-                    if token != ";" {
-                        return Err(anyhow!("Expected semicolon at or near '{}'", token));
+                    tokens.push(token.to_string());
+                    let returning = Self::parse_returning(tokens)?;
+                    Self::expect_keyword(tokens, ";")?;
+                    return Ok(Command::Delete(DeleteCommand { table_name, logic_expression, returning }));
+                }
+            }
+        }
+
+        Err(anyhow!("Unexpected end of input"))
+    }
+
+    fn parse_update_command(tokens: &mut Vec<String>) -> ::anyhow::Result<Command> {
+        let mut state: UpdateParserState = UpdateParserState::TableName;
+
+        // intermediate tmp vars
+        let mut table_name = String::new();
+        let mut column_name = String::new();
+        let mut items: HashMap<String, UpdateItem> = HashMap::new();
+        let mut logic_expression: Option<LogicExpression> = None;
+
+        while let Some(token) = &tokens.pop() {
+            match state {
+                UpdateParserState::TableName => {
+                    table_name = token.to_string();
+                    state = UpdateParserState::SetKeyword;
+                }
+                UpdateParserState::SetKeyword => {
+                    if !token.eq_ignore_ascii_case("SET") {
+                        return Err(anyhow!("Expected SET keyword at or near '{}'", token));
+                    } else {
+                        state = UpdateParserState::ColumnName;
+                    }
+                }
+                UpdateParserState::ColumnName => {
+                    if items.contains_key(token) {
+                        return Err(anyhow!("Multiple assignments to same column '{}'", token));
+                    }
+                    column_name = token.to_string();
+                    state = UpdateParserState::EqualsSign;
+                }
+                UpdateParserState::EqualsSign => {
+                    if token != "=" {
+                        return Err(anyhow!("Expected '=' at or near '{}'", token));
+                    } else {
+                        state = UpdateParserState::Value;
+                    }
+                }
+                UpdateParserState::Value => {
+                    tokens.push(token.to_string());
+                    let column_value = Self::parse_value_expression(tokens)?;
+                    items.insert(
+                        column_name.clone(),
+                        UpdateItem {
+                            column_name: column_name.clone(),
+                            column_value,
+                        },
+                    );
+                    state = UpdateParserState::ValueEnd;
+                }
+                UpdateParserState::ValueEnd => {
+                    if token == "," {
+                        state = UpdateParserState::ColumnName;
+                    } else if token == ";" || token.eq_ignore_ascii_case("RETURNING") {
+                        tokens.push(token.to_string());
+                        state = UpdateParserState::Semicolon;
+                    } else if token.eq_ignore_ascii_case("WHERE") {
+                        logic_expression = Some(Self::parse_logic_expression(tokens)?);
+                        state = UpdateParserState::Semicolon;
                     } else {
-                        return Ok(Command::Delete(DeleteCommand { table_name, logic_expression }));
+                        return Err(anyhow!("Expected comma, WHERE keyword or semicolon at or near '{}'", token));
                     }
                 }
+                UpdateParserState::Semicolon => {
+                    tokens.push(token.to_string());
+                    let returning = Self::parse_returning(tokens)?;
+                    Self::expect_keyword(tokens, ";")?;
+                    return Ok(Command::Update(UpdateCommand { table_name, items, logic_expression, returning }));
+                }
             }
         }
 
         Err(anyhow!("Unexpected end of input"))
     }
 
+    // Reads a datatype starting at `token`, including multi word names
+    // (double precision) and type modifiers (numeric(10,2)).
+    fn parse_datatype(token: &str, tokens: &mut Vec<String>) -> ::anyhow::Result<Datatype> {
+        let mut type_name = token.to_string();
+
+        if let Some(next) = tokens.last() {
+            let combined = format!("{} {}", type_name, next);
+            if Datatype::parse_from_str(&combined).is_ok() {
+                tokens.pop();
+                type_name = combined;
+            }
+        }
+
+        if tokens.last().is_some_and(|next| next == "(") && !Datatype::parse_from_str(&type_name)?.has_len() {
+            let mut modifiers: Vec<String> = vec![];
+            tokens.pop();
+            loop {
+                match tokens.pop() {
+                    Some(token) if token == ")" => break,
+                    Some(token) if token == "," => {}
+                    Some(token) => modifiers.push(token),
+                    None => return Err(anyhow!("Unexpected end of input")),
+                }
+            }
+            type_name = format!("{}({})", type_name, modifiers.join(","));
+        }
+
+        Datatype::parse_from_str(&type_name)
+    }
+
     fn parse_create_command(tokens: &mut Vec<String>) -> ::anyhow::Result<Command> {
         let mut state: CreateParserState = CreateParserState::Object;
-        let mut col_defs: Vec<ColumnDefinition> = vec![];
+        let mut table_definition = TableDefinition {
+            name: String::new(),
+            column_defs: vec![],
+            foreign_keys: vec![],
+            unique_constraints: vec![],
+            check_constraints: vec![],
+        };
+
+        let mut sequences: Vec<SequenceDefinition> = vec![];
 
         // intermediate tmp vars
-        let mut table_name = String::new();
         let mut data_type: Option<Datatype> = None;
-        let mut length = 0;
         let mut col_name = String::new();
 
         while let Some(token) = &tokens.pop() {
@@ -725,11 +2728,16 @@ impl Command {
                     "TABLE" => {
                         state = CreateParserState::TableName;
                     }
+                    "UNIQUE" | "INDEX" => {
+                        tokens.push(token.to_string());
+                        return Self::parse_create_index_command(tokens);
+                    }
+                    "SEQUENCE" => return Self::parse_create_sequence_command(tokens),
                     _ => return Err(anyhow!("Can't create object of type '{}'", token.as_str())),
                 },
                 CreateParserState::TableName => {
                     state = CreateParserState::ColumnDefinitions;
-                    table_name = token.to_string();
+                    table_definition.name = token.to_string();
                 }
                 CreateParserState::ColumnDefinitions => {
                     if token != "(" {
@@ -739,60 +2747,645 @@ impl Command {
                     }
                 }
                 CreateParserState::ColumnName => {
+                    if ["CONSTRAINT", "FOREIGN", "UNIQUE", "CHECK"].iter().any(|keyword| token.eq_ignore_ascii_case(keyword)) {
+                        tokens.push(token.to_string());
+                        Self::parse_table_constraint(tokens, &mut table_definition)?;
+                        state = CreateParserState::ConstraintEnd;
+                        continue;
+                    }
                     col_name = token.to_string();
                     state = CreateParserState::ColumnDatatype;
                 }
                 CreateParserState::ColumnDatatype => {
-                    let dtype = Datatype::parse_from_str(token)?;
+                    if let Some(dtype) = Self::serial_datatype(token) {
+                        let mut column = ColumnDefinition::new(mem::take(&mut col_name), dtype, 0);
+                        sequences.push(Self::column_sequence(&table_definition.name, &mut column, SequenceOptions::default())?);
+                        table_definition.column_defs.push(column);
+                        state = CreateParserState::ColumnDefinitionEnd;
+                        continue;
+                    }
+                    let dtype = Self::parse_datatype(token, tokens)?;
                     if dtype.has_len() {
+                        data_type = Some(dtype);
                         state = CreateParserState::ColumnLength;
                     } else {
+                        table_definition.column_defs.push(ColumnDefinition::new(mem::take(&mut col_name), dtype, 0));
                         state = CreateParserState::ColumnDefinitionEnd;
                     }
-                    data_type = Some(dtype);
                 }
                 CreateParserState::ColumnLength => {
-                    length = token.parse()?;
+                    let Some(dtype) = data_type.take() else {
+                        return Err(anyhow!("Could not find datatype for column {}", col_name));
+                    };
+                    table_definition.column_defs.push(ColumnDefinition::new(mem::take(&mut col_name), dtype, token.parse()?));
                     state = CreateParserState::ColumnDefinitionEnd;
                 }
-                CreateParserState::ColumnDefinitionEnd => {
-                    if let Some(data_type_val) = data_type {
-                        let column_def = ColumnDefinition {
-                            data_type: data_type_val,
-                            length,
-                            name: col_name,
-                        };
+                // Column constraints, until the comma or parenthesis after
+                // the column
+                CreateParserState::ColumnDefinitionEnd | CreateParserState::ConstraintEnd => match token.as_str() {
+                    "," => state = CreateParserState::ColumnName,
+                    ")" => state = CreateParserState::Semicolon,
+                    _ if matches!(state, CreateParserState::ColumnDefinitionEnd) => {
+                        tokens.push(token.to_string());
+                        Self::parse_column_constraint(tokens, &mut table_definition, &mut sequences)?;
+                    }
+                    _ => return Err(anyhow!("Expected end")),
+                },
+                CreateParserState::Semicolon => {
+                    if token != ";" {
+                        return Err(anyhow!("Expected semicolon at or near '{}'", token));
+                    }
+                    let constraint_columns = table_definition
+                        .foreign_keys
+                        .iter()
+                        .flat_map(|foreign_key| &foreign_key.columns)
+                        .chain(table_definition.unique_constraints.iter().flat_map(|unique| &unique.columns));
+                    for column in constraint_columns {
+                        if table_definition.get_column(column).is_none() {
+                            return Err(anyhow!("ERROR: column '{}' named in key does not exist", column));
+                        }
+                    }
+                    for check in &table_definition.check_constraints {
+                        let expression = Self::stored_logic_expression(&check.expression)?;
+                        for value in expression.value_expressions() {
+                            if let Some(column) = value.column_names().into_iter().find(|column| table_definition.get_column(column).is_none()) {
+                                return Err(anyhow!("ERROR: column '{}' does not exist", column));
+                            }
+                        }
+                    }
+                    return Ok(Command::Create(CreateCommand { table_definition, sequences }));
+                }
+            }
+        }
+
+        Err(anyhow!("Unexpected end of input"))
+    }
+
+    // [CONSTRAINT name] PRIMARY KEY, NOT NULL, NULL, UNIQUE, CHECK (expr),
+    // DEFAULT expr, GENERATED ... AS IDENTITY or REFERENCES ..., on the last
+    // column of the table
+    fn parse_column_constraint(
+        tokens: &mut Vec<String>,
+        table_definition: &mut TableDefinition,
+        sequences: &mut Vec<SequenceDefinition>,
+    ) -> ::anyhow::Result<()> {
+        let name = Self::parse_constraint_name(tokens)?;
+        let table_name = table_definition.name.clone();
+        let Some(column) = table_definition.column_defs.last_mut() else {
+            return Err(anyhow!("Unexpected end of input"));
+        };
+        let column_name = column.name.clone();
+        let token = tokens.pop().unwrap_or_default();
+        match token.to_uppercase().as_str() {
+            "PRIMARY" if name.is_none() => {
+                Self::expect_keyword(tokens, "KEY")?;
+                column.primary_key = true;
+                if table_definition.column_defs.iter().filter(|col_def| col_def.primary_key).count() > 1 {
+                    return Err(anyhow!("ERROR: multiple primary keys for table '{}' are not allowed", table_name));
+                }
+            }
+            "NOT" if name.is_none() => {
+                Self::expect_keyword(tokens, "NULL")?;
+                column.not_null = true;
+            }
+            "NULL" if name.is_none() => {}
+            "DEFAULT" if name.is_none() => {
+                if column.default.is_some() {
+                    return Err(anyhow!("ERROR: multiple default values specified for column '{}' of table '{}'", column_name, table_name));
+                }
+                let (default, text) = Self::parse_with_text(tokens, Self::parse_value_expression)?;
+                if !default.column_names().is_empty() {
+                    return Err(anyhow!("ERROR: cannot use column reference in DEFAULT expression"));
+                }
+                if !default.aggregates().is_empty() || !default.windows().is_empty() || default.has_subqueries() {
+                    return Err(anyhow!("ERROR: DEFAULT expressions can only use constants and functions"));
+                }
+                column.default = Some(text);
+            }
+            // GENERATED { ALWAYS | BY DEFAULT } AS IDENTITY [ ( options ) ]
+            "GENERATED" if name.is_none() => {
+                let identity = match tokens.pop().unwrap_or_default().to_uppercase().as_str() {
+                    "ALWAYS" => Identity::Always,
+                    "BY" => {
+                        Self::expect_keyword(tokens, "DEFAULT")?;
+                        Identity::ByDefault
+                    }
+                    other => return Err(anyhow!("Expected ALWAYS or BY DEFAULT at or near '{}'", other)),
+                };
+                Self::expect_keyword(tokens, "AS")?;
+                Self::expect_keyword(tokens, "IDENTITY")?;
+                let mut options = SequenceOptions::default();
+                if tokens.last().is_some_and(|token| token == "(") {
+                    tokens.pop();
+                    options = Self::parse_sequence_options(tokens)?;
+                    Self::expect_keyword(tokens, ")")?;
+                    if options.data_type.is_some() {
+                        return Err(anyhow!("ERROR: identity columns take their type from the column, AS is not allowed"));
+                    }
+                }
+                if !matches!(column.data_type, Datatype::SmallInt | Datatype::Integer | Datatype::BigInt) {
+                    return Err(anyhow!("ERROR: identity column type must be smallint, integer, or bigint"));
+                }
+                sequences.push(Self::column_sequence(&table_name, column, options)?);
+                column.identity = Some(identity);
+            }
+            "UNIQUE" => {
+                let name = Self::constraint_name(table_definition, name, format!("{}_{}_key", table_name, column_name))?;
+                table_definition.unique_constraints.push(UniqueConstraint { name, columns: vec![column_name] });
+            }
+            "CHECK" => {
+                let expression = Self::parse_check(tokens)?;
+                let name = Self::constraint_name(table_definition, name, format!("{}_{}_check", table_name, column_name))?;
+                table_definition.check_constraints.push(CheckConstraint { name, expression });
+            }
+            "REFERENCES" => {
+                let mut foreign_key = Self::parse_references(tokens, &table_name, vec![column_name])?;
+                foreign_key.name = Self::constraint_name(table_definition, name, foreign_key.name)?;
+                table_definition.foreign_keys.push(foreign_key);
+            }
+            _ if name.is_some() => return Err(anyhow!("Expected UNIQUE, CHECK or REFERENCES at or near '{}'", token)),
+            _ => return Err(anyhow!("Unknown constraint '{}' on column '{}'", token, column_name)),
+        }
+        Ok(())
+    }
+
+    // serial is an integer type with a sequence behind it
+    fn serial_datatype(token: &str) -> Option<Datatype> {
+        match token.to_lowercase().as_str() {
+            "smallserial" | "serial2" => Some(Datatype::SmallInt),
+            "serial" | "serial4" => Some(Datatype::Integer),
+            "bigserial" | "serial8" => Some(Datatype::BigInt),
+            _ => None,
+        }
+    }
+
+    // serial and identity columns are NOT NULL and default to the next value
+    // of a sequence of their own, named <table>_<column>_seq
+    fn column_sequence(table_name: &str, column: &mut ColumnDefinition, mut options: SequenceOptions) -> ::anyhow::Result<SequenceDefinition> {
+        if column.default.is_some() {
+            return Err(anyhow!("ERROR: multiple default values specified for column '{}' of table '{}'", column.name, table_name));
+        }
+        options.data_type = Some(column.data_type.clone());
+        let sequence = SequenceDefinition::new(format!("{}_{}_seq", table_name, column.name), options)?;
+        column.not_null = true;
+        column.default = Some(format!("nextval('{}')", sequence.name));
+        column.sequence = Some(sequence.name.clone());
+        Ok(sequence)
+    }
 
-                        length = 0;
-                        col_name = String::new();
-                        data_type = None;
+    // ALTER TABLE name followed by a comma separated list of
+    //
+    //     ADD [COLUMN] column type [constraints]
+    //     DROP [COLUMN] column
+    //     ALTER [COLUMN] column [SET DATA] TYPE type
+    //
+    // or one of RENAME [COLUMN] column TO new_name and RENAME TO new_name
+    fn parse_alter_command(tokens: &mut Vec<String>) -> ::anyhow::Result<Command> {
+        let mut state: AlterTableParserState = AlterTableParserState::TableKeyword;
 
-                        col_defs.push(column_def);
+        // intermediate tmp vars
+        let mut table_name = String::new();
+        let mut actions: Vec<AlterTableAction> = vec![];
 
-                        match token.as_str() {
-                            "," => {
-                                state = CreateParserState::ColumnName;
+        while let Some(token) = &tokens.pop() {
+            match state {
+                AlterTableParserState::TableKeyword => {
+                    if !token.eq_ignore_ascii_case("TABLE") {
+                        return Err(anyhow!("Can't alter object of type '{}'", token));
+                    }
+                    state = AlterTableParserState::TableName;
+                }
+                AlterTableParserState::TableName => {
+                    table_name = token.to_string();
+                    state = AlterTableParserState::Action;
+                }
+                AlterTableParserState::Action => {
+                    let keyword = token.to_uppercase();
+                    if keyword != "RENAME" && tokens.last().is_some_and(|token| token.eq_ignore_ascii_case("COLUMN")) {
+                        tokens.pop();
+                    }
+                    let action = match keyword.as_str() {
+                        "ADD" => {
+                            let (column, sequences) = Self::parse_added_column(tokens, &table_name)?;
+                            AlterTableAction::AddColumn(column, sequences)
+                        }
+                        "DROP" => AlterTableAction::DropColumn(Self::parse_object_name(tokens)?),
+                        "ALTER" => {
+                            let column_name = Self::parse_object_name(tokens)?;
+                            if tokens.last().is_some_and(|token| token.eq_ignore_ascii_case("SET")) {
+                                tokens.pop();
+                                Self::expect_keyword(tokens, "DATA")?;
                             }
-                            ")" => {
-                                state = CreateParserState::Semicolon;
+                            Self::expect_keyword(tokens, "TYPE")?;
+                            let type_name = Self::parse_object_name(tokens)?;
+                            let data_type = Self::parse_datatype(&type_name, tokens)?;
+                            let length = if data_type.has_len() { Self::parse_object_name(tokens)?.parse()? } else { 0 };
+                            AlterTableAction::AlterColumnType(column_name, data_type, length)
+                        }
+                        "RENAME" if actions.is_empty() => {
+                            let action = if tokens.last().is_some_and(|token| token.eq_ignore_ascii_case("TO")) {
+                                tokens.pop();
+                                AlterTableAction::RenameTable(Self::parse_object_name(tokens)?)
+                            } else {
+                                if tokens.last().is_some_and(|token| token.eq_ignore_ascii_case("COLUMN")) {
+                                    tokens.pop();
+                                }
+                                let column_name = Self::parse_object_name(tokens)?;
+                                Self::expect_keyword(tokens, "TO")?;
+                                AlterTableAction::RenameColumn(column_name, Self::parse_object_name(tokens)?)
+                            };
+                            // RENAME can't be combined with anything else
+                            if tokens.last().is_none_or(|token| token != ";") {
+                                return Err(anyhow!("Expected semicolon at or near '{}'", tokens.last().cloned().unwrap_or_default()));
                             }
-                            _ => return Err(anyhow!("Expected end")),
+                            action
                         }
+                        _ => return Err(anyhow!("Expected ADD, DROP, ALTER or RENAME at or near '{}'", token)),
+                    };
+                    actions.push(action);
+                    state = AlterTableParserState::ActionEnd;
+                }
+                AlterTableParserState::ActionEnd => match token.as_str() {
+                    "," => state = AlterTableParserState::Action,
+                    ";" => return Ok(Command::AlterTable(AlterTableCommand { table_name, actions })),
+                    _ => return Err(anyhow!("Expected comma or semicolon at or near '{}'", token)),
+                },
+            }
+        }
+
+        Err(anyhow!("Unexpected end of input"))
+    }
+
+    // A column name, table name or type name, which can't be punctuation
+    fn parse_object_name(tokens: &mut Vec<String>) -> ::anyhow::Result<String> {
+        match tokens.pop() {
+            Some(token) if [",", ";", "(", ")"].contains(&token.as_str()) => Err(anyhow!("Syntax error at or near '{}'", token)),
+            Some(token) => Ok(token),
+            None => Err(anyhow!("Unexpected end of input")),
+        }
+    }
+
+    // The column after ADD [COLUMN], parsed like a column of CREATE TABLE
+    fn parse_added_column(tokens: &mut Vec<String>, table_name: &str) -> ::anyhow::Result<(TableDefinition, Vec<SequenceDefinition>)> {
+        let mut table_definition = TableDefinition {
+            name: table_name.to_string(),
+            column_defs: vec![],
+            foreign_keys: vec![],
+            unique_constraints: vec![],
+            check_constraints: vec![],
+        };
+        let mut sequences: Vec<SequenceDefinition> = vec![];
+
+        let column_name = Self::parse_object_name(tokens)?;
+        let type_name = Self::parse_object_name(tokens)?;
+        let column = match Self::serial_datatype(&type_name) {
+            Some(data_type) => {
+                let mut column = ColumnDefinition::new(column_name, data_type, 0);
+                sequences.push(Self::column_sequence(table_name, &mut column, SequenceOptions::default())?);
+                column
+            }
+            None => {
+                let data_type = Self::parse_datatype(&type_name, tokens)?;
+                let length = if data_type.has_len() { Self::parse_object_name(tokens)?.parse()? } else { 0 };
+                ColumnDefinition::new(column_name, data_type, length)
+            }
+        };
+        table_definition.column_defs.push(column);
+
+        while !tokens.last().is_some_and(|token| token == "," || token == ";") {
+            if tokens.is_empty() {
+                return Err(anyhow!("Unexpected end of input"));
+            }
+            Self::parse_column_constraint(tokens, &mut table_definition, &mut sequences)?;
+        }
+        for check in &table_definition.check_constraints {
+            let expression = Self::stored_logic_expression(&check.expression)?;
+            let column_names = expression.value_expressions().into_iter().flat_map(|value| value.column_names());
+            if let Some(column) = column_names.into_iter().find(|column| table_definition.get_column(column).is_none()) {
+                return Err(anyhow!("ERROR: column '{}' does not exist", column));
+            }
+        }
+        Ok((table_definition, sequences))
+    }
+
+    // CREATE SEQUENCE name [options], from after SEQUENCE
+    fn parse_create_sequence_command(tokens: &mut Vec<String>) -> ::anyhow::Result<Command> {
+        let sequence_name = match tokens.pop() {
+            Some(token) if token != ";" => token,
+            _ => return Err(anyhow!("Unexpected end of input")),
+        };
+        let options = Self::parse_sequence_options(tokens)?;
+        Self::expect_keyword(tokens, ";")?;
+        let sequence_definition = SequenceDefinition::new(sequence_name, options)?;
+        Ok(Command::CreateSequence(CreateSequenceCommand { sequence_definition }))
+    }
+
+    // AS type, INCREMENT [BY] n, MINVALUE n, NO MINVALUE, MAXVALUE n,
+    // NO MAXVALUE, START [WITH] n, CYCLE and NO CYCLE, in any order, up to
+    // the ; or ) after them
+    fn parse_sequence_options(tokens: &mut Vec<String>) -> ::anyhow::Result<SequenceOptions> {
+        let mut options = SequenceOptions::default();
+        while let Some(token) = tokens.pop() {
+            match token.to_uppercase().as_str() {
+                ";" | ")" => {
+                    tokens.push(token);
+                    return Ok(options);
+                }
+                "AS" => {
+                    let type_name = tokens.pop().unwrap_or_default();
+                    options.data_type = Some(Self::parse_datatype(&type_name, tokens)?);
+                }
+                "INCREMENT" => {
+                    if tokens.last().is_some_and(|token| token.eq_ignore_ascii_case("BY")) {
+                        tokens.pop();
+                    }
+                    options.increment = Some(Self::parse_sequence_number(tokens)?);
+                }
+                "START" => {
+                    if tokens.last().is_some_and(|token| token.eq_ignore_ascii_case("WITH")) {
+                        tokens.pop();
+                    }
+                    options.start = Some(Self::parse_sequence_number(tokens)?);
+                }
+                "MINVALUE" => options.min_value = Some(Self::parse_sequence_number(tokens)?),
+                "MAXVALUE" => options.max_value = Some(Self::parse_sequence_number(tokens)?),
+                "CYCLE" => options.cycle = true,
+                "NO" => match tokens.pop().unwrap_or_default().to_uppercase().as_str() {
+                    "MINVALUE" => options.min_value = None,
+                    "MAXVALUE" => options.max_value = None,
+                    "CYCLE" => options.cycle = false,
+                    other => return Err(anyhow!("Expected MINVALUE, MAXVALUE or CYCLE after NO at or near '{}'", other)),
+                },
+                _ => return Err(anyhow!("Unknown sequence option '{}'", token)),
+            }
+        }
+        Err(anyhow!("Unexpected end of input"))
+    }
+
+    // An integer, which the tokenizer splits from its minus sign
+    fn parse_sequence_number(tokens: &mut Vec<String>) -> ::anyhow::Result<i64> {
+        let mut token = tokens.pop().unwrap_or_default();
+        if token == "-" {
+            token = format!("-{}", tokens.pop().unwrap_or_default());
+        }
+        token.parse::<i64>().map_err(|_| anyhow!("ERROR: invalid sequence value '{}'", token))
+    }
+
+    // [CONSTRAINT name] FOREIGN KEY (columns) REFERENCES ..., UNIQUE (columns)
+    // or CHECK (expr), in the column list
+    fn parse_table_constraint(tokens: &mut Vec<String>, table_definition: &mut TableDefinition) -> ::anyhow::Result<()> {
+        let name = Self::parse_constraint_name(tokens)?;
+        let table_name = table_definition.name.clone();
+        let token = tokens.pop().unwrap_or_default();
+        match token.to_uppercase().as_str() {
+            "FOREIGN" => {
+                Self::expect_keyword(tokens, "KEY")?;
+                let columns = Self::parse_column_name_list(tokens)?;
+                Self::expect_keyword(tokens, "REFERENCES")?;
+                let mut foreign_key = Self::parse_references(tokens, &table_name, columns)?;
+                foreign_key.name = Self::constraint_name(table_definition, name, foreign_key.name)?;
+                table_definition.foreign_keys.push(foreign_key);
+            }
+            "UNIQUE" => {
+                let columns = Self::parse_column_name_list(tokens)?;
+                let name = Self::constraint_name(table_definition, name, format!("{}_{}_key", table_name, columns.join("_")))?;
+                table_definition.unique_constraints.push(UniqueConstraint { name, columns });
+            }
+            "CHECK" => {
+                let expression = Self::parse_check(tokens)?;
+                // Named after the first column it uses, like Postgres does
+                let parsed = Self::stored_logic_expression(&expression)?;
+                let default_name = match parsed.value_expressions().into_iter().flat_map(|value| value.column_names()).next() {
+                    Some(column) => format!("{}_{}_check", table_name, column),
+                    None => format!("{}_check", table_name),
+                };
+                let name = Self::constraint_name(table_definition, name, default_name)?;
+                table_definition.check_constraints.push(CheckConstraint { name, expression });
+            }
+            _ => return Err(anyhow!("Expected FOREIGN KEY, UNIQUE or CHECK at or near '{}'", token)),
+        }
+        Ok(())
+    }
+
+    fn parse_constraint_name(tokens: &mut Vec<String>) -> ::anyhow::Result<Option<String>> {
+        if !tokens.last().is_some_and(|token| token.eq_ignore_ascii_case("CONSTRAINT")) {
+            return Ok(None);
+        }
+        tokens.pop();
+        match tokens.pop() {
+            Some(name) if name != "," && name != ")" && name != "(" => Ok(Some(name)),
+            _ => Err(anyhow!("Expected constraint name after CONSTRAINT")),
+        }
+    }
+
+    // The name given with CONSTRAINT, which has to be new, or the default
+    // one with a number on the end if it's already taken
+    fn constraint_name(table_definition: &TableDefinition, name: Option<String>, default_name: String) -> ::anyhow::Result<String> {
+        let taken = |name: &str| {
+            table_definition.foreign_keys.iter().any(|foreign_key| foreign_key.name == name)
+                || table_definition.unique_constraints.iter().any(|unique| unique.name == name)
+                || table_definition.check_constraints.iter().any(|check| check.name == name)
+        };
+        match name {
+            Some(name) if taken(&name) => Err(anyhow!(
+                "ERROR: constraint '{}' for relation '{}' already exists",
+                name,
+                table_definition.name
+            )),
+            Some(name) => Ok(name),
+            None => Ok((0..)
+                .map(|suffix| if suffix == 0 { default_name.clone() } else { format!("{}{}", default_name, suffix) })
+                .find(|name| !taken(name))
+                .unwrap_or(default_name)),
+        }
+    }
+
+    // ( expr ) after CHECK, as the SQL of the expression
+    fn parse_check(tokens: &mut Vec<String>) -> ::anyhow::Result<String> {
+        Self::expect_keyword(tokens, "(")?;
+        let (check, text) = Self::parse_with_text(tokens, Self::parse_logic_expression)?;
+        Self::expect_keyword(tokens, ")")?;
+        if check.has_subqueries() {
+            return Err(anyhow!("ERROR: cannot use subquery in check constraint"));
+        }
+        if check.value_expressions().iter().any(|value| !value.aggregates().is_empty() || !value.windows().is_empty()) {
+            return Err(anyhow!("ERROR: aggregate and window functions are not allowed in check constraints"));
+        }
+        Ok(text)
+    }
+
+    // Runs a parser, also returning the SQL of the tokens it used so it can
+    // be stored and parsed again later
+    fn parse_with_text<T>(
+        tokens: &mut Vec<String>,
+        parse: fn(&mut Vec<String>) -> ::anyhow::Result<T>,
+    ) -> ::anyhow::Result<(T, String)> {
+        let before = tokens.clone();
+        let parsed = parse(tokens)?;
+        let used: Vec<String> = before[tokens.len()..].iter().rev().cloned().collect();
+        Ok((parsed, Self::join_tokens(&used)))
+    }
+
+    // Tokens back into SQL. Quoted strings are a quote, the string and a
+    // quote, which go back together without spaces.
+    fn join_tokens(tokens: &[String]) -> String {
+        let mut text = String::new();
+        let mut quote: Option<&String> = None;
+        for token in tokens {
+            match quote {
+                Some(open) => {
+                    text.push_str(token);
+                    if token == open {
+                        quote = None;
+                    }
+                }
+                None => {
+                    if !text.is_empty() {
+                        text.push(' ');
+                    }
+                    text.push_str(token);
+                    if token == "'" || token == "\"" {
+                        quote = Some(token);
+                    }
+                }
+            }
+        }
+        text
+    }
+
+    // ( column, ... )
+    fn parse_column_name_list(tokens: &mut Vec<String>) -> ::anyhow::Result<Vec<String>> {
+        Self::expect_keyword(tokens, "(")?;
+        let mut columns: Vec<String> = vec![];
+        loop {
+            match tokens.pop() {
+                Some(token) if token == "(" || token == ")" || token == "," || token == ";" => {
+                    return Err(anyhow!("Expected column name at or near '{}'", token));
+                }
+                Some(token) => columns.push(token),
+                None => return Err(anyhow!("Unexpected end of input")),
+            }
+            match tokens.pop() {
+                Some(token) if token == "," => {}
+                Some(token) if token == ")" => return Ok(columns),
+                Some(token) => return Err(anyhow!("Expected , or ) at or near '{}'", token)),
+                None => return Err(anyhow!("Unexpected end of input")),
+            }
+        }
+    }
+
+    // What follows REFERENCES: table [(columns)] [ON DELETE action]
+    fn parse_references(tokens: &mut Vec<String>, table_name: &str, columns: Vec<String>) -> ::anyhow::Result<ForeignKey> {
+        let referenced_table = match tokens.pop() {
+            Some(token) if token != "(" && token != "," && token != ")" => token,
+            _ => return Err(anyhow!("Expected table name after REFERENCES")),
+        };
+        let mut referenced_columns: Vec<String> = vec![];
+        if tokens.last().is_some_and(|token| token == "(") {
+            referenced_columns = Self::parse_column_name_list(tokens)?;
+            if referenced_columns.len() != columns.len() {
+                return Err(anyhow!("ERROR: number of referencing and referenced columns for foreign key disagree"));
+            }
+        }
+
+        let mut on_delete = ReferentialAction::NoAction;
+        while tokens.last().is_some_and(|token| token.eq_ignore_ascii_case("ON")) {
+            tokens.pop();
+            let event = tokens.pop().unwrap_or_default().to_uppercase();
+            let action = match tokens.pop().unwrap_or_default().to_uppercase().as_str() {
+                "CASCADE" => ReferentialAction::Cascade,
+                "RESTRICT" => ReferentialAction::Restrict,
+                "NO" => {
+                    Self::expect_keyword(tokens, "ACTION")?;
+                    ReferentialAction::NoAction
+                }
+                "SET" => match tokens.pop().unwrap_or_default().to_uppercase().as_str() {
+                    "NULL" => ReferentialAction::SetNull,
+                    other => return Err(anyhow!("ERROR: ON {} SET {} is not supported", event, other)),
+                },
+                other => return Err(anyhow!("Expected a referential action at or near '{}'", other)),
+            };
+            match event.as_str() {
+                "DELETE" => on_delete = action,
+                // Referenced keys can't be changed while they're referenced
+                "UPDATE" if matches!(action, ReferentialAction::NoAction | ReferentialAction::Restrict) => {}
+                "UPDATE" => {
+                    return Err(anyhow!("ERROR: ON UPDATE {} is not supported", action.as_str().replace('_', " ").to_uppercase()));
+                }
+                _ => return Err(anyhow!("Expected DELETE or UPDATE after ON at or near '{}'", event)),
+            }
+        }
+
+        Ok(ForeignKey {
+            name: format!("{}_{}_fkey", table_name, columns.join("_")),
+            columns,
+            referenced_table,
+            referenced_columns,
+            on_delete,
+        })
+    }
+
+    // CREATE [UNIQUE] INDEX name ON table (column, ...), from after CREATE
+    fn parse_create_index_command(tokens: &mut Vec<String>) -> ::anyhow::Result<Command> {
+        let mut state: CreateIndexParserState = CreateIndexParserState::IndexKeyword;
+
+        // intermediate tmp vars
+        let mut unique = false;
+        let mut index_name = String::new();
+        let mut table_name = String::new();
+        let mut columns: Vec<String> = vec![];
+
+        while let Some(token) = &tokens.pop() {
+            match state {
+                CreateIndexParserState::IndexKeyword => {
+                    if token.eq_ignore_ascii_case("UNIQUE") && !unique {
+                        unique = true;
+                    } else if token.eq_ignore_ascii_case("INDEX") {
+                        state = CreateIndexParserState::IndexName;
                     } else {
-                        return Err(anyhow!("Could not find datatype for column {}", col_name));
+                        return Err(anyhow!("Expected INDEX at or near '{}'", token));
                     }
                 }
-                CreateParserState::Semicolon => {
+                CreateIndexParserState::IndexName => {
+                    if token.eq_ignore_ascii_case("ON") {
+                        return Err(anyhow!("ERROR: indexes must be given a name"));
+                    }
+                    index_name = token.to_string();
+                    state = CreateIndexParserState::OnKeyword;
+                }
+                CreateIndexParserState::OnKeyword => {
+                    if !token.eq_ignore_ascii_case("ON") {
+                        return Err(anyhow!("Expected ON at or near '{}'", token));
+                    }
+                    state = CreateIndexParserState::TableName;
+                }
+                CreateIndexParserState::TableName => {
+                    table_name = token.to_string();
+                    state = CreateIndexParserState::ColumnList;
+                }
+                CreateIndexParserState::ColumnList => {
+                    if token != "(" {
+                        return Err(anyhow!("Could not find column list"));
+                    }
+                    state = CreateIndexParserState::ColumnName;
+                }
+                CreateIndexParserState::ColumnName => {
+                    if columns.contains(token) {
+                        return Err(anyhow!("ERROR: column '{}' appears twice in index definition", token));
+                    }
+                    columns.push(token.to_string());
+                    state = CreateIndexParserState::ColumnNameCommaOrEnd;
+                }
+                CreateIndexParserState::ColumnNameCommaOrEnd => match token.as_str() {
+                    "," => state = CreateIndexParserState::ColumnName,
+                    ")" => state = CreateIndexParserState::Semicolon,
+                    _ => return Err(anyhow!("Expected , or ) at or near '{}'", token)),
+                },
+                CreateIndexParserState::Semicolon => {
                     if token != ";" {
                         return Err(anyhow!("Expected semicolon at or near '{}'", token));
-                    } else {
-                        return Ok(Command::Create(CreateCommand {
-                            table_definition: TableDefinition {
-                                name: table_name,
-                                column_defs: col_defs,
-                            },
-                        }));
                     }
+                    return Ok(Command::CreateIndex(CreateIndexCommand { index_name, table_name, columns, unique }));
                 }
             }
         }
@@ -800,15 +3393,38 @@ impl Command {
         Err(anyhow!("Unexpected end of input"))
     }
 
+    fn parse_drop_command(tokens: &mut Vec<String>) -> ::anyhow::Result<Command> {
+        let object = match tokens.pop() {
+            Some(token) if token.eq_ignore_ascii_case("INDEX") || token.eq_ignore_ascii_case("SEQUENCE") => token.to_uppercase(),
+            Some(token) => return Err(anyhow!("Can't drop object of type '{}'", token)),
+            None => return Err(anyhow!("Unexpected end of input")),
+        };
+        let name = match tokens.pop() {
+            Some(token) if token != ";" => token,
+            _ => return Err(anyhow!("Unexpected end of input")),
+        };
+        Self::expect_keyword(tokens, ";")?;
+        match object.as_str() {
+            "INDEX" => Ok(Command::DropIndex(DropIndexCommand { index_name: name })),
+            _ => Ok(Command::DropSequence(DropSequenceCommand { sequence_name: name })),
+        }
+    }
+
     pub fn from_string(command_str: String) -> ::anyhow::Result<Command> {
         let mut tokens: Vec<String> = tokenizer(command_str);
         tokens.reverse();
         if let Some(token) = tokens.pop() {
             return match token.to_uppercase().as_str() {
                 "CREATE" => Self::parse_create_command(&mut tokens),
+                "DROP" => Self::parse_drop_command(&mut tokens),
+                "ALTER" => Self::parse_alter_command(&mut tokens),
                 "INSERT" => Self::parse_insert_command(&mut tokens),
-                "SELECT" => Self::parse_select_command(&mut tokens),
+                "SELECT" | "WITH" | "(" => {
+                    tokens.push(token);
+                    Self::parse_select_command(&mut tokens)
+                }
                 "DELETE" => Self::parse_delete_command(&mut tokens),
+                "UPDATE" => Self::parse_update_command(&mut tokens),
                 _ => Err(anyhow!("Unknown command '{}'", token)),
             };
         }
@@ -816,12 +3432,48 @@ impl Command {
         Err(anyhow!("Unexpected end of statement"))
     }
     
+    // A CHECK constraint's expression, as the parser stored it in the
+    // table definition
+    pub fn stored_logic_expression(text: &str) -> ::anyhow::Result<LogicExpression> {
+        let mut tokens: Vec<String> = tokenizer(text.to_string());
+        tokens.reverse();
+        Self::parse_logic_expression(&mut tokens)
+    }
+
+    // A stored CHECK expression with a column renamed, leaving strings alone
+    pub fn rename_stored_column(text: &str, column_name: &str, new_name: &str) -> String {
+        let mut tokens: Vec<String> = tokenizer(text.to_string());
+        let mut quote: Option<String> = None;
+        for token in tokens.iter_mut() {
+            match &quote {
+                Some(open) if token == open => quote = None,
+                Some(_) => {}
+                None if token == "'" || token == "\"" => quote = Some(token.clone()),
+                None if token == column_name => *token = new_name.to_string(),
+                // table.column
+                None => {
+                    if let Some((qualifier, _)) = token.split_once('.').filter(|(_, name)| *name == column_name) {
+                        *token = format!("{}.{}", qualifier, new_name);
+                    }
+                }
+            }
+        }
+        Self::join_tokens(&tokens)
+    }
+
+    // A column's DEFAULT expression, as the parser stored it
+    pub fn stored_value_expression(text: &str) -> ::anyhow::Result<ValueExpression> {
+        let mut tokens: Vec<String> = tokenizer(text.to_string());
+        tokens.reverse();
+        Self::parse_value_expression(&mut tokens)
+    }
+
     pub fn le_from_string(command_str: String) -> ::anyhow::Result<LogicExpression> {
         let mut tokens: Vec<String> = tokenizer(command_str.clone());
         println!("{}", command_str);
         println!("{:?}", tokens);
         tokens.reverse();
-        return Ok(Self::parse_logic_expression(&mut tokens)?);
+        Self::parse_logic_expression(&mut tokens)
     }
 
     pub fn value_expression_from_string(command_str: String) -> ::anyhow::Result<ValueExpression> {
@@ -829,6 +3481,6 @@ impl Command {
         println!("{}", command_str);
         println!("{:?}", tokens);
         tokens.reverse();
-        return Ok(Self::parse_value_expression(&mut tokens)?);
+        Self::parse_value_expression(&mut tokens)
     }
 }
@@ -1,63 +1,315 @@
+use std::fmt;
+
+use crate::parser::command::DataValue;
+use crate::table::{datetime, numeric};
 use anyhow::anyhow;
+use std::num::{IntErrorKind, ParseIntError};
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub enum Datatype {
+    Boolean,
+    SmallInt,
     Integer,
+    BigInt,
+    Real,
+    DoublePrecision,
+    Numeric(Option<(u32, u32)>), // (precision, scale), None is unconstrained
+    Date,
+    Timestamp,
     CharacterVarying,
 }
 
+impl fmt::Display for Datatype {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Datatype::Numeric(Some((precision, scale))) => write!(f, "numeric({},{})", precision, scale),
+            _ => write!(f, "{}", self.as_str()),
+        }
+    }
+}
+
 impl Datatype {
     pub fn as_str(&self) -> &'static str {
         match self {
             Datatype::CharacterVarying => "varchar",
+            Datatype::Boolean => "boolean",
+            Datatype::SmallInt => "smallint",
             Datatype::Integer => "integer",
+            Datatype::BigInt => "bigint",
+            Datatype::Real => "real",
+            Datatype::DoublePrecision => "float8",
+            Datatype::Numeric(_) => "numeric",
+            Datatype::Date => "date",
+            Datatype::Timestamp => "timestamp",
         }
     }
 
     pub fn has_len(&self) -> bool {
+        matches!(self, Datatype::CharacterVarying)
+    }
+
+    // Number of bytes a value of this type takes up on disk, or None
+    // if it's determined by the column's declared length.
+    pub fn get_byte_size(&self) -> Option<usize> {
         match self {
-            Datatype::CharacterVarying => true,
-            Datatype::Integer => false,
+            Datatype::CharacterVarying => None,
+            Datatype::Boolean => Some(1),
+            Datatype::SmallInt => Some(2),
+            Datatype::Integer => Some(4),
+            Datatype::BigInt => Some(8),
+            Datatype::Real => Some(4),
+            Datatype::DoublePrecision => Some(8),
+            Datatype::Numeric(_) => Some(17), // i128 unscaled value + u8 scale
+            Datatype::Date => Some(4),
+            Datatype::Timestamp => Some(8),
         }
     }
 
-    pub fn to_bytes(&self, data_val: String) -> ::anyhow::Result<Vec<u8>> {
+    // Parses the textual form of a value (as it appears in an INSERT or
+    // inside a quoted literal) into a DataValue of this type.
+    pub fn parse_value(&self, data_val: String) -> ::anyhow::Result<DataValue> {
         match self {
-            Datatype::CharacterVarying => {
-                let mut str_bytes = data_val.as_bytes().to_vec();
-                Ok(str_bytes)
+            Datatype::CharacterVarying => Ok(DataValue::StringValue(data_val)),
+            Datatype::Boolean => match data_val.trim().to_lowercase().as_str() {
+                "t" | "true" | "y" | "yes" | "on" | "1" => Ok(DataValue::BoolValue(true)),
+                "f" | "false" | "n" | "no" | "off" | "0" => Ok(DataValue::BoolValue(false)),
+                _ => Err(self.syntax_error(&data_val)),
+            },
+            Datatype::SmallInt => {
+                let val = data_val.trim().parse::<i16>().map_err(|err| self.int_error(&data_val, err))?;
+                Ok(DataValue::I64Value(val.into()))
             }
             Datatype::Integer => {
-                let val = data_val.parse::<u8>()?;
-                Ok(vec![val])
+                let val = data_val.trim().parse::<i32>().map_err(|err| self.int_error(&data_val, err))?;
+                Ok(DataValue::I64Value(val.into()))
+            }
+            Datatype::BigInt => {
+                let val = data_val.trim().parse::<i64>().map_err(|err| self.int_error(&data_val, err))?;
+                Ok(DataValue::I64Value(val))
             }
+            Datatype::Real => {
+                let val = data_val.trim().parse::<f32>().map_err(|_| self.syntax_error(&data_val))?;
+                // Go through the shortest f32 representation so 0.1 comes back as 0.1
+                Ok(DataValue::F64Value(val.to_string().parse::<f64>()?))
+            }
+            Datatype::DoublePrecision => {
+                let val = data_val.trim().parse::<f64>().map_err(|_| self.syntax_error(&data_val))?;
+                Ok(DataValue::F64Value(val))
+            }
+            Datatype::Numeric(modifiers) => {
+                let (mut value, mut scale) = numeric::parse_numeric(&data_val)?;
+                if let Some((precision, col_scale)) = modifiers {
+                    value = numeric::rescale(value, scale, *col_scale)
+                        .ok_or_else(|| anyhow!("ERROR: numeric field overflow"))?;
+                    scale = *col_scale;
+                    if numeric::num_digits(value) > *precision {
+                        return Err(anyhow!(
+                            "ERROR: numeric field overflow, value '{}' does not fit in {}",
+                            data_val,
+                            self
+                        ));
+                    }
+                }
+                if numeric::num_digits(value) > numeric::MAX_PRECISION || scale > numeric::MAX_PRECISION {
+                    return Err(anyhow!("ERROR: value overflows numeric format: '{}'", data_val));
+                }
+                Ok(DataValue::NumericValue(value, scale))
+            }
+            Datatype::Date => Ok(DataValue::DateValue(datetime::parse_date(&data_val)?)),
+            Datatype::Timestamp => Ok(DataValue::TimestampValue(datetime::parse_timestamp(&data_val)?)),
         }
     }
 
-    pub fn from_bytes(&self, data_val: &[u8]) -> ::anyhow::Result<String> {
+    // Converts a value of some other type to this one, following Postgres'
+    // assignment casts. Strings are parsed like a quoted literal would be.
+    pub fn cast_value(&self, value: DataValue) -> ::anyhow::Result<DataValue> {
+        let cast_error = |value: &DataValue| anyhow!("ERROR: cannot cast type {} to {}", value.type_name(), self.as_str());
+        match (self, value) {
+            (_, DataValue::Null) => Ok(DataValue::Null),
+            (_, DataValue::StringValue(val)) => self.parse_value(val),
+            (Datatype::CharacterVarying, value) => Ok(DataValue::StringValue(value.to_string())),
+            (Datatype::SmallInt | Datatype::Integer | Datatype::BigInt, DataValue::F64Value(val)) => {
+                self.parse_value(val.round().to_string())
+            }
+            (Datatype::SmallInt | Datatype::Integer | Datatype::BigInt, DataValue::NumericValue(val, scale)) => {
+                let rounded = numeric::rescale(val, scale, 0).ok_or_else(|| anyhow!("ERROR: {} out of range", self.as_str()))?;
+                self.parse_value(rounded.to_string())
+            }
+            (
+                Datatype::SmallInt | Datatype::Integer | Datatype::BigInt | Datatype::Real | Datatype::DoublePrecision | Datatype::Numeric(_),
+                value @ (DataValue::I64Value(_) | DataValue::F64Value(_) | DataValue::NumericValue(_, _)),
+            ) => self.parse_value(value.to_string()),
+            (Datatype::Boolean, value @ DataValue::BoolValue(_)) => Ok(value),
+            (Datatype::Date, value @ DataValue::DateValue(_)) => Ok(value),
+            (Datatype::Date, DataValue::TimestampValue(val)) => {
+                let days = val.div_euclid(datetime::MICROS_PER_DAY);
+                Ok(DataValue::DateValue(i32::try_from(days).map_err(|_| anyhow!("ERROR: date out of range"))?))
+            }
+            (Datatype::Timestamp, DataValue::DateValue(val)) => {
+                Ok(DataValue::TimestampValue(i64::from(val) * datetime::MICROS_PER_DAY))
+            }
+            (Datatype::Timestamp, value @ DataValue::TimestampValue(_)) => Ok(value),
+            (_, value) => Err(cast_error(&value)),
+        }
+    }
+
+    // The type two columns combined by i.e. UNION end up as, None if they
+    // can't be. Integers widen to the bigger integer, then numeric, then
+    // double precision, and dates to timestamps.
+    pub fn common_type(&self, other: &Datatype) -> Option<Datatype> {
+        let rank = |datatype: &Datatype| match datatype {
+            Datatype::SmallInt => Some(0),
+            Datatype::Integer => Some(1),
+            Datatype::BigInt => Some(2),
+            Datatype::Numeric(_) => Some(3),
+            Datatype::Real => Some(4),
+            Datatype::DoublePrecision => Some(5),
+            _ => None,
+        };
+        match (self, other) {
+            (Datatype::Numeric(left), Datatype::Numeric(right)) if left != right => Some(Datatype::Numeric(None)),
+            _ if self == other => Some(self.clone()),
+            (Datatype::Date | Datatype::Timestamp, Datatype::Date | Datatype::Timestamp) => Some(Datatype::Timestamp),
+            // Integers don't keep a numeric's precision and scale
+            _ => match (rank(self)?, rank(other)?) {
+                (3, 0..=2) | (0..=2, 3) => Some(Datatype::Numeric(None)),
+                (left, right) if left >= right => Some(self.clone()),
+                _ => Some(other.clone()),
+            },
+        }
+    }
+
+    pub fn to_bytes(&self, data_val: String) -> ::anyhow::Result<Vec<u8>> {
+        match (self, self.parse_value(data_val)?) {
+            (Datatype::CharacterVarying, DataValue::StringValue(val)) => Ok(val.as_bytes().to_vec()),
+            (Datatype::Boolean, DataValue::BoolValue(val)) => Ok(vec![val as u8]),
+            (Datatype::SmallInt, DataValue::I64Value(val)) => Ok((val as i16).to_le_bytes().to_vec()),
+            (Datatype::Integer, DataValue::I64Value(val)) => Ok((val as i32).to_le_bytes().to_vec()),
+            (Datatype::BigInt, DataValue::I64Value(val)) => Ok(val.to_le_bytes().to_vec()),
+            (Datatype::Real, DataValue::F64Value(val)) => Ok((val as f32).to_le_bytes().to_vec()),
+            (Datatype::DoublePrecision, DataValue::F64Value(val)) => Ok(val.to_le_bytes().to_vec()),
+            (Datatype::Numeric(_), DataValue::NumericValue(val, scale)) => {
+                let mut bytes = val.to_le_bytes().to_vec();
+                bytes.push(scale as u8);
+                Ok(bytes)
+            }
+            (Datatype::Date, DataValue::DateValue(val)) => Ok(val.to_le_bytes().to_vec()),
+            (Datatype::Timestamp, DataValue::TimestampValue(val)) => Ok(val.to_le_bytes().to_vec()),
+            _ => Err(anyhow!("Unable to encode value as {}", self)),
+        }
+    }
+
+    pub fn value_from_bytes(&self, data_val: &[u8]) -> ::anyhow::Result<DataValue> {
+        let parse_error = || anyhow!("Unable to parse {}", self);
         match self {
             Datatype::CharacterVarying => {
                 let str_val = String::from_utf8(data_val.to_vec())?;
-                Ok(str_val)
+                Ok(DataValue::StringValue(str_val.trim_matches(char::from(0)).to_string()))
+            }
+            Datatype::Boolean => match data_val.first() {
+                Some(val) => Ok(DataValue::BoolValue(*val != 0)),
+                None => Err(parse_error()),
+            },
+            Datatype::SmallInt => {
+                let val = i16::from_le_bytes(data_val.try_into().map_err(|_| parse_error())?);
+                Ok(DataValue::I64Value(val.into()))
             }
             Datatype::Integer => {
-                if let Some(val) = data_val.first() {
-                    Ok(format!("{}", val))
-                } else {
-                    Err(anyhow!("Unable to parse Integer"))
+                let val = i32::from_le_bytes(data_val.try_into().map_err(|_| parse_error())?);
+                Ok(DataValue::I64Value(val.into()))
+            }
+            Datatype::BigInt => {
+                let val = i64::from_le_bytes(data_val.try_into().map_err(|_| parse_error())?);
+                Ok(DataValue::I64Value(val))
+            }
+            Datatype::Real => {
+                let val = f32::from_le_bytes(data_val.try_into().map_err(|_| parse_error())?);
+                Ok(DataValue::F64Value(val.to_string().parse::<f64>()?))
+            }
+            Datatype::DoublePrecision => {
+                let val = f64::from_le_bytes(data_val.try_into().map_err(|_| parse_error())?);
+                Ok(DataValue::F64Value(val))
+            }
+            Datatype::Numeric(_) => {
+                if data_val.len() != 17 {
+                    return Err(parse_error());
                 }
+                let val = i128::from_le_bytes(data_val[..16].try_into()?);
+                Ok(DataValue::NumericValue(val, data_val[16].into()))
+            }
+            Datatype::Date => {
+                let val = i32::from_le_bytes(data_val.try_into().map_err(|_| parse_error())?);
+                Ok(DataValue::DateValue(val))
+            }
+            Datatype::Timestamp => {
+                let val = i64::from_le_bytes(data_val.try_into().map_err(|_| parse_error())?);
+                Ok(DataValue::TimestampValue(val))
             }
         }
     }
 
+    pub fn from_bytes(&self, data_val: &[u8]) -> ::anyhow::Result<String> {
+        Ok(self.value_from_bytes(data_val)?.to_string())
+    }
+
     pub fn parse_from_str(string: &str) -> ::anyhow::Result<Datatype> {
-        match string {
+        let string = string.to_lowercase();
+
+        // numeric(p,s) / numeric(p)
+        if let Some(modifiers) = string
+            .strip_prefix("numeric(")
+            .or_else(|| string.strip_prefix("decimal("))
+            .and_then(|rest| rest.strip_suffix(')'))
+        {
+            let values: Vec<&str> = modifiers.split(',').map(|val| val.trim()).collect();
+            let precision: u32 = values[0].parse()?;
+            let scale: u32 = match values.get(1) {
+                Some(scale) => scale.parse()?,
+                None => 0,
+            };
+            if values.len() > 2 || precision == 0 || precision > numeric::MAX_PRECISION {
+                return Err(anyhow!("NUMERIC precision must be between 1 and {}", numeric::MAX_PRECISION));
+            }
+            if scale > precision {
+                return Err(anyhow!("NUMERIC scale {} must be between 0 and precision {}", scale, precision));
+            }
+            return Ok(Datatype::Numeric(Some((precision, scale))));
+        }
+
+        match string.as_str() {
             "varchar" => Ok(Datatype::CharacterVarying),
             "character varying" => Ok(Datatype::CharacterVarying),
+            "boolean" => Ok(Datatype::Boolean),
+            "bool" => Ok(Datatype::Boolean),
+            "smallint" => Ok(Datatype::SmallInt),
+            "int2" => Ok(Datatype::SmallInt),
             "integer" => Ok(Datatype::Integer),
             "int" => Ok(Datatype::Integer),
-            "int8" => Ok(Datatype::Integer),
+            "int4" => Ok(Datatype::Integer),
+            "bigint" => Ok(Datatype::BigInt),
+            "int8" => Ok(Datatype::BigInt),
+            "real" => Ok(Datatype::Real),
+            "float4" => Ok(Datatype::Real),
+            "double precision" => Ok(Datatype::DoublePrecision),
+            "float8" => Ok(Datatype::DoublePrecision),
+            "numeric" => Ok(Datatype::Numeric(None)),
+            "decimal" => Ok(Datatype::Numeric(None)),
+            "date" => Ok(Datatype::Date),
+            "timestamp" => Ok(Datatype::Timestamp),
             _ => Err(anyhow!("Undefined data type")),
         }
     }
+
+    fn syntax_error(&self, data_val: &str) -> anyhow::Error {
+        anyhow!("ERROR: invalid input syntax for type {}: '{}'", self.as_str(), data_val)
+    }
+
+    fn int_error(&self, data_val: &str, err: ParseIntError) -> anyhow::Error {
+        match err.kind() {
+            IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => {
+                anyhow!("ERROR: value '{}' is out of range for type {}", data_val, self.as_str())
+            }
+            _ => self.syntax_error(data_val),
+        }
+    }
 }
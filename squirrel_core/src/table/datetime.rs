@@ -0,0 +1,137 @@
+use anyhow::anyhow;
+
+pub const MICROS_PER_SECOND: i64 = 1_000_000;
+pub const MICROS_PER_DAY: i64 = 86_400 * MICROS_PER_SECOND;
+
+fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if is_leap_year(year) => 29,
+        _ => 28,
+    }
+}
+
+// Days since 1970-01-01 for a date in the proleptic Gregorian calendar.
+// See http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let month_from_march = (i64::from(month) + 9) % 12;
+    let day_of_year = (153 * month_from_march + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+// Inverse of days_from_civil, returns (year, month, day).
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = if days >= 0 { days } else { days - 146096 } / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_from_march + 2) / 5 + 1) as u32;
+    let month = if month_from_march < 10 { month_from_march + 3 } else { month_from_march - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn parse_field<T: std::str::FromStr>(field: Option<&str>, string: &str, type_name: &str) -> ::anyhow::Result<T> {
+    field
+        .filter(|field| !field.is_empty() && field.chars().all(|c| c.is_ascii_digit()))
+        .and_then(|field| field.parse::<T>().ok())
+        .ok_or_else(|| anyhow!("ERROR: invalid input syntax for type {}: '{}'", type_name, string))
+}
+
+// Parses YYYY-MM-DD into days since 1970-01-01.
+pub fn parse_date(string: &str) -> ::anyhow::Result<i32> {
+    let mut parts = string.trim().split('-');
+    let year: i64 = parse_field(parts.next(), string, "date")?;
+    let month: u32 = parse_field(parts.next(), string, "date")?;
+    let day: u32 = parse_field(parts.next(), string, "date")?;
+    if parts.next().is_some() {
+        return Err(anyhow!("ERROR: invalid input syntax for type date: '{}'", string));
+    }
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+        return Err(anyhow!("ERROR: date/time field value out of range: '{}'", string));
+    }
+    i32::try_from(days_from_civil(year, month, day))
+        .map_err(|_| anyhow!("ERROR: date out of range: '{}'", string))
+}
+
+pub fn format_date(days: i32) -> String {
+    let (year, month, day) = civil_from_days(days.into());
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// Parses 'YYYY-MM-DD[( |T)HH:MM[:SS[.ffffff]]]' into microseconds since
+// 1970-01-01 00:00:00.
+pub fn parse_timestamp(string: &str) -> ::anyhow::Result<i64> {
+    let trimmed = string.trim();
+    let (date_str, time_str) = match trimmed.find([' ', 'T']) {
+        Some(idx) => (&trimmed[..idx], trimmed[idx + 1..].trim()),
+        None => (trimmed, ""),
+    };
+    let days = i64::from(parse_date(date_str)?);
+
+    let mut micros: i64 = 0;
+    if !time_str.is_empty() {
+        let (hms, fraction) = match time_str.split_once('.') {
+            Some((hms, fraction)) => (hms, Some(fraction)),
+            None => (time_str, None),
+        };
+        let mut parts = hms.split(':');
+        let hour: i64 = parse_field(parts.next(), string, "timestamp")?;
+        let minute: i64 = parse_field(parts.next(), string, "timestamp")?;
+        let second: i64 = match parts.next() {
+            Some(second) => parse_field(Some(second), string, "timestamp")?,
+            None => 0,
+        };
+        if parts.next().is_some() {
+            return Err(anyhow!("ERROR: invalid input syntax for type timestamp: '{}'", string));
+        }
+        if hour > 23 || minute > 59 || second > 59 {
+            return Err(anyhow!("ERROR: date/time field value out of range: '{}'", string));
+        }
+        micros = ((hour * 60 + minute) * 60 + second) * MICROS_PER_SECOND;
+
+        if let Some(fraction) = fraction {
+            // Only microsecond precision is kept, anything finer is truncated
+            let digits: String = fraction.chars().take(6).collect();
+            let value: i64 = parse_field(Some(digits.as_str()), string, "timestamp")?;
+            micros += value * 10_i64.pow(6 - digits.len() as u32);
+        }
+    }
+
+    days.checked_mul(MICROS_PER_DAY)
+        .and_then(|day_micros| day_micros.checked_add(micros))
+        .ok_or_else(|| anyhow!("ERROR: timestamp out of range: '{}'", string))
+}
+
+pub fn format_timestamp(micros: i64) -> String {
+    let days = micros.div_euclid(MICROS_PER_DAY);
+    let time = micros.rem_euclid(MICROS_PER_DAY);
+    let (year, month, day) = civil_from_days(days);
+    let seconds = time / MICROS_PER_SECOND;
+    let fraction = time % MICROS_PER_SECOND;
+
+    let mut string = format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        (seconds / 60) % 60,
+        seconds % 60
+    );
+    if fraction > 0 {
+        string += format!(".{:06}", fraction).trim_end_matches('0');
+    }
+    string
+}
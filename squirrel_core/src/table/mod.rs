@@ -1,2 +1,5 @@
 pub mod datatypes;
+pub mod datetime;
+pub mod numeric;
+pub mod sequence_definition;
 pub mod table_definition;
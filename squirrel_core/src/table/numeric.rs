@@ -0,0 +1,102 @@
+use anyhow::anyhow;
+
+// Numerics are kept as an unscaled i128 and a scale, i.e. 123.45 is
+// (12345, 2). An i128 holds any 38 digit number.
+pub const MAX_PRECISION: u32 = 38;
+
+pub fn parse_numeric(string: &str) -> ::anyhow::Result<(i128, u32)> {
+    let trimmed = string.trim();
+    let (negative, unsigned) = match trimmed.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, trimmed.strip_prefix('+').unwrap_or(trimmed)),
+    };
+    let (whole, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+
+    if (whole.is_empty() && fraction.is_empty())
+        || !whole.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit())
+    {
+        return Err(anyhow!("ERROR: invalid input syntax for type numeric: '{}'", string));
+    }
+
+    let mut value: i128 = 0;
+    for digit in whole.chars().chain(fraction.chars()) {
+        value = value
+            .checked_mul(10)
+            .and_then(|value| value.checked_add(i128::from(digit.to_digit(10).unwrap())))
+            .ok_or_else(|| anyhow!("ERROR: value overflows numeric format: '{}'", string))?;
+    }
+
+    Ok((if negative { -value } else { value }, fraction.len() as u32))
+}
+
+pub fn format_numeric(value: i128, scale: u32) -> String {
+    let digits = value.unsigned_abs().to_string();
+    let sign = if value < 0 { "-" } else { "" };
+    if scale == 0 {
+        return format!("{}{}", sign, digits);
+    }
+    let scale = scale as usize;
+    let digits = format!("{:0>width$}", digits, width = scale + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - scale);
+    format!("{}{}.{}", sign, whole, fraction)
+}
+
+// Changes the scale of a value, rounding half away from zero when digits
+// have to be dropped. Returns None on overflow.
+pub fn rescale(value: i128, from: u32, to: u32) -> Option<i128> {
+    if to >= from {
+        return 10_i128.checked_pow(to - from).and_then(|factor| value.checked_mul(factor));
+    }
+    let factor = 10_i128.checked_pow(from - to)?;
+    let quotient = value / factor;
+    let remainder = (value % factor).abs();
+    if remainder * 2 >= factor {
+        Some(quotient + value.signum())
+    } else {
+        Some(quotient)
+    }
+}
+
+pub fn num_digits(value: i128) -> u32 {
+    value.unsigned_abs().checked_ilog10().unwrap_or(0) + 1
+}
+
+pub fn to_f64(value: i128, scale: u32) -> f64 {
+    value as f64 / 10_f64.powi(scale as i32)
+}
+
+// Number of fractional digits kept when dividing, on top of the scale of
+// the dividend.
+pub const DIVISION_SCALE: u32 = 16;
+
+// Arithmetic on (value, scale) pairs. All of these return None on overflow.
+
+pub fn add(left: (i128, u32), right: (i128, u32)) -> Option<(i128, u32)> {
+    let scale = left.1.max(right.1);
+    let value = rescale(left.0, left.1, scale)?.checked_add(rescale(right.0, right.1, scale)?)?;
+    Some((value, scale))
+}
+
+pub fn multiply(left: (i128, u32), right: (i128, u32)) -> Option<(i128, u32)> {
+    let value = left.0.checked_mul(right.0)?;
+    let scale = left.1 + right.1;
+    if scale > MAX_PRECISION {
+        return Some((rescale(value, scale, MAX_PRECISION)?, MAX_PRECISION));
+    }
+    Some((value, scale))
+}
+
+// The caller is responsible for checking for division by zero.
+pub fn divide(left: (i128, u32), right: (i128, u32)) -> Option<(i128, u32)> {
+    let scale = left.1.max(right.1).max(DIVISION_SCALE);
+    // Carry one extra digit through the division so the result can be rounded
+    let dividend = rescale(left.0, left.1, scale + right.1 + 1)?;
+    Some((rescale(dividend / right.0, scale + 1, scale)?, scale))
+}
+
+// The caller is responsible for checking for division by zero.
+pub fn modulo(left: (i128, u32), right: (i128, u32)) -> Option<(i128, u32)> {
+    let scale = left.1.max(right.1);
+    let value = rescale(left.0, left.1, scale)?.checked_rem(rescale(right.0, right.1, scale)?)?;
+    Some((value, scale))
+}
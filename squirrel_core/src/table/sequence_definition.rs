@@ -0,0 +1,60 @@
+use crate::table::datatypes::Datatype;
+use anyhow::anyhow;
+
+// A sequence made with CREATE SEQUENCE, or by a serial or identity column.
+// nextval starts at start and steps by increment, staying within
+// min_value..=max_value.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct SequenceDefinition {
+    pub name: String,
+    pub increment: i64,
+    pub min_value: i64,
+    pub max_value: i64,
+    pub start: i64,
+    pub cycle: bool,
+}
+
+// The options of CREATE SEQUENCE and GENERATED ... AS IDENTITY (...), None
+// where they're left out
+#[derive(Debug, Default)]
+pub struct SequenceOptions {
+    pub data_type: Option<Datatype>,
+    pub increment: Option<i64>,
+    pub min_value: Option<i64>,
+    pub max_value: Option<i64>,
+    pub start: Option<i64>,
+    pub cycle: bool,
+}
+
+impl SequenceDefinition {
+    // Fills in the defaults the way Postgres does. Ascending sequences go
+    // from 1 to the largest value of the type, descending ones from -1 to
+    // the smallest, and both start at the end they count from.
+    pub fn new(name: String, options: SequenceOptions) -> ::anyhow::Result<SequenceDefinition> {
+        let (type_min, type_max) = match options.data_type.unwrap_or(Datatype::BigInt) {
+            Datatype::SmallInt => (i16::MIN as i64, i16::MAX as i64),
+            Datatype::Integer => (i32::MIN as i64, i32::MAX as i64),
+            Datatype::BigInt => (i64::MIN, i64::MAX),
+            _ => return Err(anyhow!("ERROR: sequence type must be smallint, integer, or bigint")),
+        };
+
+        let increment = options.increment.unwrap_or(1);
+        if increment == 0 {
+            return Err(anyhow!("ERROR: INCREMENT must not be zero"));
+        }
+        let min_value = options.min_value.unwrap_or(if increment > 0 { 1 } else { type_min });
+        let max_value = options.max_value.unwrap_or(if increment > 0 { type_max } else { -1 });
+        if min_value < type_min || max_value > type_max {
+            return Err(anyhow!("ERROR: MINVALUE and MAXVALUE must be between {} and {}", type_min, type_max));
+        }
+        if min_value >= max_value {
+            return Err(anyhow!("ERROR: MINVALUE ({}) must be less than MAXVALUE ({})", min_value, max_value));
+        }
+        let start = options.start.unwrap_or(if increment > 0 { min_value } else { max_value });
+        if start < min_value || start > max_value {
+            return Err(anyhow!("ERROR: START value ({}) must be between MINVALUE ({}) and MAXVALUE ({})", start, min_value, max_value));
+        }
+
+        Ok(SequenceDefinition { name, increment, min_value, max_value, start, cycle: options.cycle })
+    }
+}
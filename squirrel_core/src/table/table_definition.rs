@@ -1,31 +1,188 @@
+use crate::parser::command::DataValue;
 use crate::table::datatypes::Datatype;
+use anyhow::anyhow;
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub struct ColumnDefinition {
     pub name: String,
     pub data_type: Datatype,
     pub length: usize, // used for char(n), varchar(n)
+    pub primary_key: bool,
+    pub not_null: bool,
+    // The SQL of the DEFAULT expression, used for columns an INSERT leaves
+    // out
+    pub default: Option<String>,
+    pub identity: Option<Identity>,
+    // The sequence a serial or identity column takes its values from,
+    // which goes with the column
+    pub sequence: Option<String>,
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub struct TableDefinition {
     pub name: String,
     pub column_defs: Vec<ColumnDefinition>,
+    pub foreign_keys: Vec<ForeignKey>,
+    pub unique_constraints: Vec<UniqueConstraint>,
+    pub check_constraints: Vec<CheckConstraint>,
 }
 
+// UNIQUE (columns), kept up to date as a unique index of the same name
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct UniqueConstraint {
+    pub name: String,
+    pub columns: Vec<String>,
+}
+
+// CHECK (expression). Rows where it's false are rejected, NULL passes.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct CheckConstraint {
+    pub name: String,
+    pub expression: String, // the SQL, see Command::stored_logic_expression
+}
+
+// FOREIGN KEY (columns) REFERENCES referenced_table (referenced_columns)
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct ForeignKey {
+    pub name: String,
+    pub columns: Vec<String>,
+    pub referenced_table: String,
+    // Empty when left out, which means the referenced table's primary key
+    pub referenced_columns: Vec<String>,
+    pub on_delete: ReferentialAction,
+}
+
+// GENERATED ALWAYS AS IDENTITY columns can't be given a value by INSERT or
+// UPDATE, GENERATED BY DEFAULT AS IDENTITY ones can
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Identity {
+    Always,
+    ByDefault,
+}
+
+impl Identity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Identity::Always => "identity_always",
+            Identity::ByDefault => "identity_by_default",
+        }
+    }
+}
+
+// What happens to referencing rows when the row they reference is deleted
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum ReferentialAction {
+    NoAction,
+    Restrict,
+    Cascade,
+    SetNull,
+}
+
+impl ReferentialAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReferentialAction::NoAction => "no_action",
+            ReferentialAction::Restrict => "restrict",
+            ReferentialAction::Cascade => "cascade",
+            ReferentialAction::SetNull => "set_null",
+        }
+    }
+
+    pub fn parse_from_str(action: &str) -> ::anyhow::Result<ReferentialAction> {
+        match action {
+            "no_action" => Ok(ReferentialAction::NoAction),
+            "restrict" => Ok(ReferentialAction::Restrict),
+            "cascade" => Ok(ReferentialAction::Cascade),
+            "set_null" => Ok(ReferentialAction::SetNull),
+            _ => Err(anyhow!("Unknown referential action '{}'", action)),
+        }
+    }
+}
+
+impl ColumnDefinition {
+    // A column without any constraints
+    pub fn new(name: String, data_type: Datatype, length: usize) -> ColumnDefinition {
+        ColumnDefinition {
+            name,
+            data_type,
+            length,
+            primary_key: false,
+            not_null: false,
+            default: None,
+            identity: None,
+            sequence: None,
+        }
+    }
+
+    pub fn get_byte_size(&self) -> usize {
+        self.data_type.get_byte_size().unwrap_or(self.length)
+    }
+
+    // Encodes a value into exactly get_byte_size() bytes. NULLs are
+    // zero-filled, they're tracked in the row's null bitmap instead.
+    pub fn encode_value(&self, value: &DataValue) -> ::anyhow::Result<Vec<u8>> {
+        if *value == DataValue::Null {
+            return Ok(vec![0; self.get_byte_size()]);
+        }
+        let mut bytes = self.data_type.to_bytes(value.to_string())?;
+        if bytes.len() > self.get_byte_size() {
+            return Err(anyhow!(
+                "ERROR: value too long for column '{}' ({} bytes, max {})",
+                self.name,
+                bytes.len(),
+                self.get_byte_size()
+            ));
+        }
+        bytes.resize(self.get_byte_size(), 0);
+        Ok(bytes)
+    }
+}
+
+// Rows are stored as a null bitmap (one bit per column, in column order)
+// followed by every column's fixed width value.
 impl TableDefinition {
+    pub fn get_null_bitmap_size(&self) -> usize {
+        self.column_defs.len().div_ceil(8)
+    }
+
     pub fn get_byte_size(&self) -> usize {
-        let mut sum: usize = 0;
+        let mut sum: usize = self.get_null_bitmap_size();
         for col_def in self.column_defs.iter() {
-            // TODO HACK FIXME
-            // We should keep track of length
-            // even for built-in datatypes.
-            sum += if col_def.length > 0 {
-                col_def.length
-            } else {
-                1
-            };
+            sum += col_def.get_byte_size();
         }
         sum
     }
+
+    pub fn get_column(&self, name: &str) -> Option<&ColumnDefinition> {
+        self.column_defs.iter().find(|col_def| col_def.name == name)
+    }
+
+    pub fn decode_row(&self, buf: &[u8]) -> ::anyhow::Result<Vec<DataValue>> {
+        let mut values: Vec<DataValue> = Vec::with_capacity(self.column_defs.len());
+        let mut idx: usize = self.get_null_bitmap_size();
+        for (col_idx, col_def) in self.column_defs.iter().enumerate() {
+            let len = col_def.get_byte_size();
+            if buf[col_idx / 8] & (1 << (col_idx % 8)) != 0 {
+                values.push(DataValue::Null);
+            } else {
+                values.push(col_def.data_type.value_from_bytes(&buf[idx..(idx + len)])?);
+            }
+            idx += len;
+        }
+        Ok(values)
+    }
+
+    pub fn encode_row(&self, values: &[DataValue]) -> ::anyhow::Result<Vec<u8>> {
+        if values.len() != self.column_defs.len() {
+            return Err(anyhow!("Expected {} values for table '{}', got {}", self.column_defs.len(), self.name, values.len()));
+        }
+        let mut row: Vec<u8> = vec![0; self.get_null_bitmap_size()];
+        for (col_idx, (col_def, value)) in self.column_defs.iter().zip(values).enumerate() {
+            if *value == DataValue::Null {
+                row[col_idx / 8] |= 1 << (col_idx % 8);
+            }
+            row.extend(col_def.encode_value(value)?);
+        }
+        Ok(row)
+    }
 }
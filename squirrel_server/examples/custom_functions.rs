@@ -0,0 +1,30 @@
+// Runs the server with a couple of extra functions registered, i.e.
+//
+//     cargo run --example custom_functions
+//     > SELECT fnv_hash(name), normalize_phone(phone) FROM users;
+use squirrel_core::functions::register_function;
+use squirrel_core::parser::command::DataValue;
+use squirrel_core::Datatype;
+
+// 64-bit FNV-1a, see http://www.isthe.com/chongo/tech/comp/fnv/
+fn fnv_hash(args: &[DataValue]) -> anyhow::Result<DataValue> {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in args[0].to_string().bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    Ok(DataValue::I64Value(hash as i64))
+}
+
+// Strips everything but the digits out of a phone number
+fn normalize_phone(args: &[DataValue]) -> anyhow::Result<DataValue> {
+    let digits: String = args[0].to_string().chars().filter(|c| c.is_ascii_digit()).collect();
+    Ok(DataValue::StringValue(digits))
+}
+
+fn main() -> anyhow::Result<()> {
+    register_function("FNV_HASH", vec![Datatype::CharacterVarying], Datatype::BigInt, fnv_hash)?;
+    register_function("NORMALIZE_PHONE", vec![Datatype::CharacterVarying], Datatype::CharacterVarying, normalize_phone)?;
+    squirrel_server::start("0.0.0.0:5433")?;
+    Ok(())
+}
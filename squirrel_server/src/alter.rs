@@ -0,0 +1,519 @@
+use anyhow::anyhow;
+use std::fs;
+use std::io::ErrorKind;
+
+use squirrel_core::parser::command::{AlterTableAction, AlterTableCommand, Command, DataValue};
+use squirrel_core::table::datatypes::Datatype;
+use squirrel_core::table::sequence_definition::SequenceDefinition;
+use squirrel_core::table::table_definition::{ColumnDefinition, TableDefinition};
+
+use crate::constraint::Constraints;
+use crate::foreign_key;
+use crate::index::{self, IndexDefinition};
+use crate::{read_tabledef, replace_blob, sequence, write_tabledef};
+
+// ALTER TABLE. Adding, dropping or retyping a column changes the layout of
+// every row, so the rows are read into memory, changed by each action in
+// turn and written out as a new blob, which is swapped in along with
+// rebuilt indexes. Renames only change definitions.
+pub fn alter_table(command: AlterTableCommand) -> ::anyhow::Result<()> {
+    let tabledef = match read_tabledef(command.table_name.clone()) {
+        Err(err) if err.downcast_ref::<std::io::Error>().is_some_and(|err| err.kind() == ErrorKind::NotFound) => {
+            return Err(anyhow!("ERROR: relation '{}' does not exist", command.table_name));
+        }
+        result => result?,
+    };
+    match command.actions.as_slice() {
+        [AlterTableAction::RenameTable(new_name)] => return rename_table(tabledef, new_name),
+        [AlterTableAction::RenameColumn(column_name, new_name)] => return rename_column(tabledef, column_name, new_name),
+        _ => {}
+    }
+
+    let rows = foreign_key::read_rows(&tabledef, &format!("./data/blobs/{}", tabledef.name))?;
+    let indexes = index::definitions(&tabledef.name)?;
+    let mut alteration = Alteration {
+        tabledef,
+        rows,
+        old_indexes: indexes.clone(),
+        indexes,
+        foreign_keys_added: false,
+        created_sequences: vec![],
+        dropped_sequences: vec![],
+        dropped_indexes: vec![],
+    };
+    let result = command.actions.into_iter().try_for_each(|action| alteration.apply(action)).and_then(|_| alteration.write());
+    // Sequences made for a new serial column go if the column doesn't
+    // make it
+    if result.is_err() {
+        for name in &alteration.created_sequences {
+            let _ = sequence::remove(name);
+        }
+    }
+    result
+}
+
+// A table part way through its ALTER TABLE actions
+struct Alteration {
+    tabledef: TableDefinition,
+    rows: Vec<Vec<DataValue>>,
+    indexes: Vec<IndexDefinition>,
+    old_indexes: Vec<IndexDefinition>,
+    foreign_keys_added: bool,
+    created_sequences: Vec<String>,
+    dropped_sequences: Vec<String>,
+    dropped_indexes: Vec<String>,
+}
+
+impl Alteration {
+    fn apply(&mut self, action: AlterTableAction) -> ::anyhow::Result<()> {
+        match action {
+            AlterTableAction::AddColumn(column, sequences) => self.add_column(column, sequences),
+            AlterTableAction::DropColumn(column_name) => self.drop_column(&column_name),
+            AlterTableAction::AlterColumnType(column_name, data_type, length) => self.alter_column_type(&column_name, data_type, length),
+            AlterTableAction::RenameColumn(..) | AlterTableAction::RenameTable(_) => {
+                Err(anyhow!("ERROR: RENAME can't be combined with other ALTER TABLE actions"))
+            }
+        }
+    }
+
+    // Existing rows get the column's DEFAULT, worked out for each row so
+    // a serial column numbers them
+    fn add_column(&mut self, column: TableDefinition, sequences: Vec<SequenceDefinition>) -> ::anyhow::Result<()> {
+        let tabledef = &mut self.tabledef;
+        let col_def = &column.column_defs[0];
+        if tabledef.get_column(&col_def.name).is_some() {
+            return Err(anyhow!("ERROR: column '{}' of relation '{}' already exists", col_def.name, tabledef.name));
+        }
+        if col_def.primary_key && tabledef.column_defs.iter().any(|col_def| col_def.primary_key) {
+            return Err(anyhow!("ERROR: multiple primary keys for table '{}' are not allowed", tabledef.name));
+        }
+        let new_names = column
+            .foreign_keys
+            .iter()
+            .map(|foreign_key| &foreign_key.name)
+            .chain(column.unique_constraints.iter().map(|unique| &unique.name))
+            .chain(column.check_constraints.iter().map(|check| &check.name));
+        for name in new_names {
+            let taken = tabledef.foreign_keys.iter().any(|foreign_key| foreign_key.name == *name)
+                || tabledef.unique_constraints.iter().any(|unique| unique.name == *name)
+                || tabledef.check_constraints.iter().any(|check| check.name == *name)
+                || self.indexes.iter().any(|index| index.name == *name);
+            if taken {
+                return Err(anyhow!("ERROR: constraint '{}' for relation '{}' already exists", name, tabledef.name));
+            }
+        }
+        // The new column's primary key and UNIQUE indexes share ./data/indexes
+        // with every other table's
+        let pkey_name = format!("{}_pkey", tabledef.name);
+        let index_names = column.unique_constraints.iter().map(|unique| &unique.name).chain(col_def.primary_key.then_some(&pkey_name));
+        for name in index_names {
+            if index::name_taken(name)? {
+                return Err(anyhow!("ERROR: relation '{}' already exists", name));
+            }
+        }
+
+        for definition in &sequences {
+            sequence::create(definition)?;
+            self.created_sequences.push(definition.name.clone());
+        }
+        let constraints = Constraints::new(&column)?;
+        for row in self.rows.iter_mut() {
+            row.push(constraints.default_value(0)?);
+        }
+
+        self.foreign_keys_added |= !column.foreign_keys.is_empty();
+        tabledef.column_defs.extend(column.column_defs);
+        tabledef.foreign_keys.extend(column.foreign_keys);
+        tabledef.unique_constraints.extend(column.unique_constraints);
+        tabledef.check_constraints.extend(column.check_constraints);
+        Ok(())
+    }
+
+    // Indexes and constraints on the column go with it, like in Postgres,
+    // unless another table's foreign key references it
+    fn drop_column(&mut self, column_name: &str) -> ::anyhow::Result<()> {
+        let tabledef = &mut self.tabledef;
+        let Some(position) = index::column_position(tabledef, column_name) else {
+            return Err(anyhow!("ERROR: column '{}' of relation '{}' does not exist", column_name, tabledef.name));
+        };
+        if tabledef.column_defs.len() == 1 {
+            return Err(anyhow!("ERROR: can't drop '{}', the last column of table '{}'", column_name, tabledef.name));
+        }
+        for (referencing, foreign_key) in foreign_key::referencing(&tabledef.name)? {
+            if referencing.name != tabledef.name && foreign_key.referenced_columns.iter().any(|column| column == column_name) {
+                return Err(anyhow!(
+                    "ERROR: cannot drop column '{}' of table '{}' because constraint '{}' on table '{}' depends on it",
+                    column_name,
+                    tabledef.name,
+                    foreign_key.name,
+                    referencing.name
+                ));
+            }
+        }
+
+        let col_def = tabledef.column_defs.remove(position);
+        if col_def.primary_key {
+            self.dropped_indexes.push(format!("{}_pkey", tabledef.name));
+        }
+        self.dropped_sequences.extend(col_def.sequence);
+        let table_name = tabledef.name.clone();
+        // Its own foreign keys, and self references to it
+        tabledef.foreign_keys.retain(|foreign_key| {
+            let uses_column = foreign_key.columns.iter().any(|column| column == column_name)
+                || (foreign_key.referenced_table == table_name && foreign_key.referenced_columns.iter().any(|column| column == column_name));
+            !uses_column
+        });
+        let dropped_indexes = &mut self.dropped_indexes;
+        tabledef.unique_constraints.retain(|unique| {
+            let kept = !unique.columns.iter().any(|column| column == column_name);
+            if !kept {
+                dropped_indexes.push(unique.name.clone());
+            }
+            kept
+        });
+        self.indexes.retain(|index| {
+            let kept = !index.columns.iter().any(|column| column == column_name);
+            if !kept {
+                dropped_indexes.push(index.name.clone());
+            }
+            kept
+        });
+        let mut checks = vec![];
+        for check in tabledef.check_constraints.drain(..) {
+            let expression = Command::stored_logic_expression(&check.expression)?;
+            let uses_column = expression.value_expressions().iter().flat_map(|value| value.column_names()).any(|column| {
+                column == column_name || column.split_once('.').is_some_and(|(_, column)| column == column_name)
+            });
+            if !uses_column {
+                checks.push(check);
+            }
+        }
+        tabledef.check_constraints = checks;
+
+        for row in self.rows.iter_mut() {
+            row.remove(position);
+        }
+        Ok(())
+    }
+
+    // Every value is cast to the new type, which fails the whole ALTER if
+    // one can't be
+    fn alter_column_type(&mut self, column_name: &str, data_type: Datatype, length: usize) -> ::anyhow::Result<()> {
+        let Some(position) = index::column_position(&self.tabledef, column_name) else {
+            return Err(anyhow!("ERROR: column '{}' of relation '{}' does not exist", column_name, self.tabledef.name));
+        };
+        self.check_foreign_key_types(column_name, &data_type)?;
+        let col_def = &mut self.tabledef.column_defs[position];
+        if col_def.identity.is_some() && !matches!(data_type, Datatype::SmallInt | Datatype::Integer | Datatype::BigInt) {
+            return Err(anyhow!("ERROR: identity column type must be smallint, integer, or bigint"));
+        }
+        col_def.data_type = data_type;
+        col_def.length = length;
+        for row in self.rows.iter_mut() {
+            let value = std::mem::replace(&mut row[position], DataValue::Null);
+            row[position] = col_def.data_type.cast_value(value)?;
+        }
+        Ok(())
+    }
+
+    // A column in a foreign key, on either end, can only change to a type
+    // whose values still match the other end's
+    fn check_foreign_key_types(&self, column_name: &str, data_type: &Datatype) -> ::anyhow::Result<()> {
+        let tabledef = &self.tabledef;
+        // The foreign key, this end's column and the other end's column
+        let mut pairs: Vec<(String, String, ColumnDefinition)> = vec![];
+        for foreign_key in &tabledef.foreign_keys {
+            let referenced = if foreign_key.referenced_table == tabledef.name {
+                tabledef.clone()
+            } else {
+                read_tabledef(foreign_key.referenced_table.clone())?
+            };
+            for (column, referenced_column) in foreign_key.columns.iter().zip(&foreign_key.referenced_columns) {
+                if column == column_name {
+                    pairs.extend(referenced.get_column(referenced_column).map(|other| (foreign_key.name.clone(), column.clone(), other.clone())));
+                }
+                if referenced.name == tabledef.name && referenced_column == column_name {
+                    pairs.extend(tabledef.get_column(column).map(|other| (foreign_key.name.clone(), referenced_column.clone(), other.clone())));
+                }
+            }
+        }
+        // A table's references to itself were dealt with above, as it is now
+        for (referencing_def, foreign_key) in foreign_key::referencing(&tabledef.name)? {
+            if referencing_def.name == tabledef.name {
+                continue;
+            }
+            for (column, referenced_column) in foreign_key.columns.iter().zip(&foreign_key.referenced_columns) {
+                if referenced_column == column_name {
+                    pairs.extend(referencing_def.get_column(column).map(|other| (foreign_key.name.clone(), referenced_column.clone(), other.clone())));
+                }
+            }
+        }
+
+        for (name, column, other) in pairs {
+            if !same_key_values(data_type, &other.data_type) {
+                return Err(anyhow!(
+                    "ERROR: foreign key constraint '{}' cannot be implemented, key columns '{}' and '{}' are of incompatible types: {} and {}",
+                    name,
+                    column,
+                    other.name,
+                    data_type,
+                    other.data_type
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // Checks the rows against the new definition and swaps everything in.
+    // The index list is written first since the indexes are rebuilt from
+    // it, and put back if anything fails.
+    fn write(&mut self) -> ::anyhow::Result<()> {
+        let tabledef = &mut self.tabledef;
+        let constraints = Constraints::new(tabledef)?;
+        let mut data: Vec<u8> = vec![];
+        for row in &self.rows {
+            constraints.check(tabledef, row)?;
+            data.extend(tabledef.encode_row(row)?);
+        }
+        let new_blob = format!("./data/blobs/{}_new", tabledef.name);
+        fs::write(&new_blob, data)?;
+
+        index::write_definitions(&tabledef.name, &self.indexes)?;
+        let result = (|| {
+            if self.foreign_keys_added {
+                foreign_key::resolve(tabledef)?;
+                // self.rows is the whole table
+                foreign_key::check_references(tabledef, &self.rows, &|| Ok(vec![]))?;
+            }
+            replace_blob(tabledef)
+        })();
+        if let Err(err) = result {
+            let _ = fs::remove_file(&new_blob);
+            index::write_definitions(&tabledef.name, &self.old_indexes)?;
+            return Err(err);
+        }
+        write_tabledef(tabledef)?;
+
+        for name in &self.dropped_indexes {
+            let _ = fs::remove_file(format!("./data/indexes/{}", name));
+        }
+        for name in &self.dropped_sequences {
+            let _ = sequence::remove(name);
+        }
+        Ok(())
+    }
+}
+
+// Renames the column everywhere it's named: the table's constraints and
+// indexes, and the foreign keys of tables that reference it. The rows
+// don't change.
+fn rename_column(mut tabledef: TableDefinition, column_name: &str, new_name: &str) -> ::anyhow::Result<()> {
+    if tabledef.get_column(new_name).is_some() {
+        return Err(anyhow!("ERROR: column '{}' of relation '{}' already exists", new_name, tabledef.name));
+    }
+    let Some(position) = index::column_position(&tabledef, column_name) else {
+        return Err(anyhow!("ERROR: column '{}' of relation '{}' does not exist", column_name, tabledef.name));
+    };
+    let rename = |columns: &mut Vec<String>| {
+        for column in columns.iter_mut().filter(|column| *column == column_name) {
+            *column = new_name.to_string();
+        }
+    };
+
+    tabledef.column_defs[position].name = new_name.to_string();
+    for foreign_key in tabledef.foreign_keys.iter_mut() {
+        rename(&mut foreign_key.columns);
+        if foreign_key.referenced_table == tabledef.name {
+            rename(&mut foreign_key.referenced_columns);
+        }
+    }
+    for unique in tabledef.unique_constraints.iter_mut() {
+        rename(&mut unique.columns);
+    }
+    for check in tabledef.check_constraints.iter_mut() {
+        check.expression = Command::rename_stored_column(&check.expression, column_name, new_name);
+    }
+    let mut indexes = index::definitions(&tabledef.name)?;
+    for index in indexes.iter_mut() {
+        rename(&mut index.columns);
+    }
+
+    for mut referencing in referencing_tables(&tabledef)?.into_iter().filter(|referencing| referencing.name != tabledef.name) {
+        for foreign_key in referencing.foreign_keys.iter_mut().filter(|foreign_key| foreign_key.referenced_table == tabledef.name) {
+            rename(&mut foreign_key.referenced_columns);
+        }
+        write_tabledef(&referencing)?;
+    }
+    index::write_definitions(&tabledef.name, &indexes)?;
+    write_tabledef(&tabledef)
+}
+
+// Moves the table's files to the new name. Index and sequence names stay
+// the same, except the primary key's index which is named after the table.
+fn rename_table(mut tabledef: TableDefinition, new_name: &str) -> ::anyhow::Result<()> {
+    if fs::metadata(format!("./data/tabledefs/{}", new_name)).is_ok() {
+        return Err(anyhow!("ERROR: relation '{}' already exists", new_name));
+    }
+    let old_name = tabledef.name.clone();
+    let referencing = referencing_tables(&tabledef)?;
+
+    tabledef.name = new_name.to_string();
+    for foreign_key in tabledef.foreign_keys.iter_mut().filter(|foreign_key| foreign_key.referenced_table == old_name) {
+        foreign_key.referenced_table = new_name.to_string();
+    }
+    write_tabledef(&tabledef)?;
+    let moves = [
+        (format!("./data/blobs/{}", old_name), format!("./data/blobs/{}", new_name)),
+        (index::definitions_path(&old_name), index::definitions_path(new_name)),
+        (format!("./data/indexes/{}_pkey", old_name), format!("./data/indexes/{}_pkey", new_name)),
+    ];
+    for (from, to) in moves {
+        match fs::rename(from, to) {
+            Err(err) if err.kind() != ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+    }
+    fs::remove_file(format!("./data/tabledefs/{}", old_name))?;
+
+    for mut referencing in referencing.into_iter().filter(|referencing| referencing.name != old_name) {
+        for foreign_key in referencing.foreign_keys.iter_mut().filter(|foreign_key| foreign_key.referenced_table == old_name) {
+            foreign_key.referenced_table = new_name.to_string();
+        }
+        write_tabledef(&referencing)?;
+    }
+    Ok(())
+}
+
+// Each table with a foreign key referencing this one, once
+fn referencing_tables(tabledef: &TableDefinition) -> ::anyhow::Result<Vec<TableDefinition>> {
+    let mut tables: Vec<TableDefinition> = vec![];
+    for (referencing, _) in foreign_key::referencing(&tabledef.name)? {
+        if !tables.iter().any(|table| table.name == referencing.name) {
+            tables.push(referencing);
+        }
+    }
+    Ok(tables)
+}
+
+// Whether values of the two types are kept as the same kind of value, so
+// a foreign key between them can find matching keys
+fn same_key_values(left: &Datatype, right: &Datatype) -> bool {
+    let integer = |data_type: &Datatype| matches!(data_type, Datatype::SmallInt | Datatype::Integer | Datatype::BigInt);
+    let float = |data_type: &Datatype| matches!(data_type, Datatype::Real | Datatype::DoublePrecision);
+    (integer(left) && integer(right))
+        || (float(left) && float(right))
+        || matches!((left, right), (Datatype::Numeric(_), Datatype::Numeric(_)))
+        || left == right
+}
+
+#[cfg(test)]
+use crate::{run_statements, select_rows, with_data_dir};
+
+#[cfg(test)]
+fn ok(statements: &[&str]) {
+    let responses = run_statements(statements);
+    assert!(responses.iter().all(|response| !response.starts_with("ERROR")), "{:?}", responses);
+}
+
+#[test]
+fn alter_columns_and_tables() {
+    with_data_dir(|| {
+        ok(&[
+            "CREATE TABLE users (id int PRIMARY KEY, name varchar 10, age int CHECK (age > 0));",
+            "INSERT INTO users (id, name, age) VALUES (1, 'ann', 30), (2, 'bob', 40), (3, 'cy', 50);",
+            "CREATE INDEX users_name ON users (name);",
+        ]);
+        let rows = |query: &str| -> Vec<String> { select_rows(query).into_iter().map(|row| row.join(" ")).collect() };
+        let error = |statement: &str, error: &str| {
+            let response = &run_statements(&[statement])[0];
+            assert!(response.starts_with("ERROR") && response.contains(error), "{}: {}", statement, response);
+        };
+
+        // Existing rows get the DEFAULT, and a serial numbers them
+        assert_eq!(run_statements(&["ALTER TABLE users ADD COLUMN email varchar 30 DEFAULT 'none', ADD COLUMN n serial;"]), vec!["Table Altered"]);
+        assert_eq!(rows("SELECT * FROM users ORDER BY id;"), vec!["1 ann 30 none 1", "2 bob 40 none 2", "3 cy 50 none 3"]);
+        ok(&["INSERT INTO users (id, name, age) VALUES (4, 'dee', 20);"]);
+        assert_eq!(rows("SELECT n FROM users WHERE id = 4;"), vec!["4"]);
+        assert_eq!(rows("SELECT id FROM users WHERE name = 'bob';"), vec!["2"]);
+
+        // A failed ALTER changes nothing
+        let before = rows("SELECT * FROM users ORDER BY id;");
+        error("ALTER TABLE users ADD COLUMN score int NOT NULL;", "violates not-null constraint");
+        error("ALTER TABLE users ADD COLUMN email int;", "column 'email' of relation 'users' already exists");
+        error("ALTER TABLE users ADD COLUMN tag varchar 5 UNIQUE DEFAULT 'x';", "violates unique constraint 'users_tag_key'");
+        error("ALTER TABLE users ALTER COLUMN name TYPE varchar 2;", "value too long");
+        error("ALTER TABLE users ALTER COLUMN name TYPE int;", "invalid input syntax for type integer");
+        // The serial's sequence goes with the column that didn't make it
+        error("ALTER TABLE users ADD COLUMN s serial, ADD COLUMN score int NOT NULL;", "violates not-null constraint");
+        assert!(std::fs::metadata(sequence::path("users_s_seq")).is_err());
+        assert_eq!(rows("SELECT * FROM users ORDER BY id;"), before);
+
+        // Retyped columns take the new type's values, and the indexes are
+        // rebuilt for the new layout
+        ok(&[
+            "ALTER TABLE users ALTER COLUMN name TYPE varchar 40, ALTER age TYPE bigint;",
+            "INSERT INTO users (id, name, age) VALUES (5, 'a much longer name here', 9000000000);",
+        ]);
+        assert_eq!(rows("SELECT id, age FROM users WHERE name = 'a much longer name here';"), vec!["5 9000000000"]);
+        assert_eq!(rows("SELECT name FROM users WHERE id = 3;"), vec!["cy"]);
+
+        ok(&["ALTER TABLE users RENAME COLUMN age TO years;"]);
+        error("INSERT INTO users (id, name, years) VALUES (6, 'neg', -1);", "violates check constraint");
+        assert_eq!(rows("SELECT id, years FROM users WHERE name = 'bob';"), vec!["2 40"]);
+
+        // Dropping a column drops its indexes and CHECKs
+        ok(&["ALTER TABLE users DROP COLUMN name, DROP years;"]);
+        assert_eq!(rows("SELECT * FROM users WHERE id = 5;"), vec!["5 none 5"]);
+        error("DROP INDEX users_name;", "index 'users_name' does not exist");
+        ok(&["INSERT INTO users (id) VALUES (6);"]);
+
+        ok(&["ALTER TABLE users RENAME TO people;"]);
+        error("SELECT * FROM users;", "relation 'users' does not exist");
+        assert_eq!(rows("SELECT id FROM people WHERE id = 2;"), vec!["2"]);
+        error("INSERT INTO people (id) VALUES (2);", "violates unique constraint 'people_pkey'");
+
+        // Foreign keys follow renames, and keep their columns
+        ok(&[
+            "CREATE TABLE orders (oid int PRIMARY KEY, uid int REFERENCES people ON DELETE CASCADE);",
+            "INSERT INTO orders (oid, uid) VALUES (1, 2), (2, 3);",
+        ]);
+        error("ALTER TABLE people DROP COLUMN id;", "constraint 'orders_uid_fkey' on table 'orders' depends on it");
+        ok(&["ALTER TABLE people RENAME COLUMN id TO pid;", "ALTER TABLE people RENAME TO persons;", "DELETE FROM persons WHERE pid = 2;"]);
+        assert_eq!(rows("SELECT * FROM orders;"), vec!["2 3"]);
+        error("ALTER TABLE orders ADD COLUMN bad int REFERENCES persons DEFAULT 99;", "key (bad)=(99) is not present in table 'persons'");
+        error("ALTER TABLE nope ADD COLUMN x int;", "relation 'nope' does not exist");
+    });
+}
+
+#[test]
+fn alter_type_of_foreign_key_columns() {
+    with_data_dir(|| {
+        ok(&[
+            "CREATE TABLE p (id integer PRIMARY KEY);",
+            "CREATE TABLE ch (x integer REFERENCES p(id) ON DELETE CASCADE);",
+            "CREATE TABLE tree (id int PRIMARY KEY, parent int REFERENCES tree);",
+            "INSERT INTO p (id) VALUES (1), (2);",
+            "INSERT INTO ch (x) VALUES (1), (2);",
+        ]);
+        let error = |statement: &str, error: &str| {
+            let response = &run_statements(&[statement])[0];
+            assert!(response.starts_with("ERROR") && response.contains(error), "{}: {}", statement, response);
+        };
+
+        // Neither end can change to a type the other end's values can't
+        // match
+        error(
+            "ALTER TABLE p ALTER COLUMN id TYPE varchar 5;",
+            "foreign key constraint 'ch_x_fkey' cannot be implemented, key columns 'id' and 'x' are of incompatible types",
+        );
+        error("ALTER TABLE ch ALTER COLUMN x TYPE numeric(5, 0);", "foreign key constraint 'ch_x_fkey' cannot be implemented");
+        error("ALTER TABLE tree ALTER COLUMN parent TYPE varchar 5;", "foreign key constraint 'tree_parent_fkey' cannot be implemented");
+        error("ALTER TABLE tree ALTER COLUMN id TYPE real;", "foreign key constraint 'tree_parent_fkey' cannot be implemented");
+
+        // Other integer types still match, and the foreign key still works
+        ok(&["ALTER TABLE p ALTER COLUMN id TYPE bigint;", "ALTER TABLE ch ALTER COLUMN x TYPE smallint;"]);
+        error("INSERT INTO ch (x) VALUES (3);", "key (x)=(3) is not present in table 'p'");
+        assert_eq!(run_statements(&["DELETE FROM p WHERE id = 1;"]), vec!["1 Rows Deleted"]);
+        assert_eq!(select_rows("SELECT x FROM ch;"), vec![vec!["2"]]);
+    });
+}
@@ -0,0 +1,455 @@
+use anyhow::anyhow;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ops::Bound;
+
+use squirrel_core::parser::command::DataValue;
+
+use crate::sort::{compare_rows, SortKey};
+use crate::TableDefinition;
+
+const PAGE_SIZE: usize = 4096;
+const MAGIC: &[u8; 4] = b"SQBT";
+const LEAF: u8 = 0;
+const INTERNAL: u8 = 1;
+// kind, entry count and the next leaf (or first child) page
+const NODE_HEADER_SIZE: usize = 1 + 2 + 8;
+
+// An index entry, the key's values and the row (its position in the blob)
+// they're from. Entries are ordered by key then row, so keys don't have to
+// be unique.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub key: Vec<DataValue>,
+    pub row_id: u64,
+}
+
+// A leaf holds entries and links to the next leaf. An internal node has one
+// more child than separators, children[i + 1] holds the entries from
+// separators[i] onwards.
+enum Node {
+    Leaf { entries: Vec<Entry>, next: u64 },
+    Internal { separators: Vec<Entry>, children: Vec<u64> },
+}
+
+// A B+ tree in a file of PAGE_SIZE pages. Page 0 is a header with the key
+// size and the root page, every other page is a node. Keys are encoded like
+// a row of the key's columns, so they're all the same size.
+//
+// Nodes that are read or written are kept in memory, changed ones are
+// written back by flush().
+pub struct BTree {
+    file: File,
+    key_def: TableDefinition,
+    key_size: usize,
+    root: u64,
+    page_count: u64,
+    nodes: HashMap<u64, Node>,
+    dirty: HashSet<u64>,
+}
+
+impl BTree {
+    // Makes an empty tree, replacing anything at the path
+    pub fn create(path: &str, key_def: TableDefinition) -> ::anyhow::Result<BTree> {
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path)?;
+        let key_size = key_def.get_byte_size();
+        let mut tree = BTree { file, key_def, key_size, root: 1, page_count: 2, nodes: HashMap::new(), dirty: HashSet::new() };
+        if tree.internal_capacity() < 3 {
+            return Err(anyhow!("ERROR: index row size {} exceeds the maximum for an index", key_size));
+        }
+        tree.put(1, Node::Leaf { entries: vec![], next: 0 });
+        tree.flush()?;
+        Ok(tree)
+    }
+
+    pub fn open(path: &str, key_def: TableDefinition) -> ::anyhow::Result<BTree> {
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+        let mut header = [0_u8; 24];
+        file.read_exact(&mut header)?;
+        if &header[0..4] != MAGIC {
+            return Err(anyhow!("ERROR: '{}' is not an index file", path));
+        }
+        let key_size = u32::from_le_bytes(header[4..8].try_into()?) as usize;
+        if key_size != key_def.get_byte_size() {
+            return Err(anyhow!("ERROR: index '{}' doesn't match its table, it has to be rebuilt", path));
+        }
+        let root = u64::from_le_bytes(header[8..16].try_into()?);
+        let page_count = u64::from_le_bytes(header[16..24].try_into()?);
+        Ok(BTree { file, key_def, key_size, root, page_count, nodes: HashMap::new(), dirty: HashSet::new() })
+    }
+
+    pub fn insert(&mut self, entry: Entry) -> ::anyhow::Result<()> {
+        if let Some((separator, right)) = self.insert_into(self.root, entry)? {
+            let root = self.allocate();
+            self.put(root, Node::Internal { separators: vec![separator], children: vec![self.root, right] });
+            self.root = root;
+        }
+        Ok(())
+    }
+
+    // Takes an entry out, false if it wasn't there. Nodes aren't merged when
+    // they empty out, a scan just passes over an empty leaf.
+    pub fn remove(&mut self, entry: &Entry) -> ::anyhow::Result<bool> {
+        let mut page = self.root;
+        while let Node::Internal { separators, children } = self.node(page)? {
+            page = children[separators.partition_point(|separator| compare_entries(separator, entry) != Ordering::Greater)];
+        }
+        let Node::Leaf { entries, .. } = self.node(page)? else { unreachable!() };
+        let Ok(idx) = entries.binary_search_by(|existing| compare_entries(existing, entry)) else {
+            return Ok(false);
+        };
+        let Node::Leaf { entries, .. } = self.node_mut(page)? else { unreachable!() };
+        entries.remove(idx);
+        Ok(true)
+    }
+
+    // Whether any entry has exactly this key
+    pub fn contains(&mut self, key: &[DataValue]) -> ::anyhow::Result<bool> {
+        Ok(!self.range(Bound::Included(key), Bound::Included(key), Some(1))?.is_empty())
+    }
+
+    // The rows whose keys are within the bounds, in key order. Bounds can
+    // be a prefix of the key.
+    pub fn range(&mut self, lower: Bound<&[DataValue]>, upper: Bound<&[DataValue]>, limit: Option<usize>) -> ::anyhow::Result<Vec<u64>> {
+        // Go down to the leftmost leaf that can have the lower bound
+        let mut page = self.root;
+        while let Node::Internal { separators, children } = self.node(page)? {
+            let idx = match lower {
+                Bound::Unbounded => 0,
+                Bound::Included(key) | Bound::Excluded(key) => {
+                    separators.partition_point(|separator| compare_keys(&separator.key, key) == Ordering::Less)
+                }
+            };
+            page = children[idx];
+        }
+
+        let mut row_ids: Vec<u64> = vec![];
+        while page != 0 {
+            let Node::Leaf { entries, next } = self.node(page)? else {
+                return Err(anyhow!("ERROR: index is corrupted, expected a leaf at page {}", page));
+            };
+            for entry in entries {
+                let above_lower = match lower {
+                    Bound::Unbounded => true,
+                    Bound::Included(key) => compare_keys(&entry.key, key) != Ordering::Less,
+                    Bound::Excluded(key) => compare_keys(&entry.key, key) == Ordering::Greater,
+                };
+                if !above_lower {
+                    continue;
+                }
+                let below_upper = match upper {
+                    Bound::Unbounded => true,
+                    Bound::Included(key) => compare_keys(&entry.key, key) != Ordering::Greater,
+                    Bound::Excluded(key) => compare_keys(&entry.key, key) == Ordering::Less,
+                };
+                if !below_upper || limit.is_some_and(|limit| row_ids.len() >= limit) {
+                    return Ok(row_ids);
+                }
+                row_ids.push(entry.row_id);
+            }
+            page = *next;
+        }
+        Ok(row_ids)
+    }
+
+    pub fn flush(&mut self) -> ::anyhow::Result<()> {
+        let mut dirty: Vec<u64> = self.dirty.drain().collect();
+        dirty.sort();
+        for page in dirty {
+            let bytes = self.encode_node(&self.nodes[&page])?;
+            self.file.seek(SeekFrom::Start(page * PAGE_SIZE as u64))?;
+            self.file.write_all(&bytes)?;
+        }
+        let mut header = vec![0_u8; PAGE_SIZE];
+        header[0..4].copy_from_slice(MAGIC);
+        header[4..8].copy_from_slice(&u32::try_from(self.key_size)?.to_le_bytes());
+        header[8..16].copy_from_slice(&self.root.to_le_bytes());
+        header[16..24].copy_from_slice(&self.page_count.to_le_bytes());
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&header)?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+
+    // Adds an entry under a page. If the page had to be split, returns the
+    // first entry of the new right half and its page.
+    fn insert_into(&mut self, page: u64, entry: Entry) -> ::anyhow::Result<Option<(Entry, u64)>> {
+        let leaf_capacity = self.leaf_capacity();
+        let internal_capacity = self.internal_capacity();
+        let child = match self.node_mut(page)? {
+            Node::Leaf { entries, .. } => {
+                let idx = entries.partition_point(|existing| compare_entries(existing, &entry) == Ordering::Less);
+                entries.insert(idx, entry);
+                if entries.len() <= leaf_capacity {
+                    return Ok(None);
+                }
+                let right_entries = entries.split_off(entries.len() / 2);
+                let separator = right_entries[0].clone();
+                let right = self.allocate();
+                let Node::Leaf { next, .. } = self.node_mut(page)? else { unreachable!() };
+                let right_next = std::mem::replace(next, right);
+                self.put(right, Node::Leaf { entries: right_entries, next: right_next });
+                return Ok(Some((separator, right)));
+            }
+            Node::Internal { separators, children } => {
+                let idx = separators.partition_point(|separator| compare_entries(separator, &entry) != Ordering::Greater);
+                (idx, children[idx])
+            }
+        };
+
+        let (idx, child_page) = child;
+        let Some((separator, right)) = self.insert_into(child_page, entry)? else {
+            return Ok(None);
+        };
+        let Node::Internal { separators, children } = self.node_mut(page)? else { unreachable!() };
+        separators.insert(idx, separator);
+        children.insert(idx + 1, right);
+        if separators.len() <= internal_capacity {
+            return Ok(None);
+        }
+        // The middle separator moves up, it's not kept in either half
+        let mid = separators.len() / 2;
+        let right_separators = separators.split_off(mid + 1);
+        let up = separators.pop().ok_or_else(|| anyhow!("ERROR: index is corrupted"))?;
+        let right_children = children.split_off(mid + 1);
+        let right_page = self.allocate();
+        self.put(right_page, Node::Internal { separators: right_separators, children: right_children });
+        Ok(Some((up, right_page)))
+    }
+
+    fn entry_size(&self) -> usize {
+        self.key_size + 8
+    }
+
+    fn leaf_capacity(&self) -> usize {
+        (PAGE_SIZE - NODE_HEADER_SIZE) / self.entry_size()
+    }
+
+    fn internal_capacity(&self) -> usize {
+        (PAGE_SIZE - NODE_HEADER_SIZE) / (self.entry_size() + 8)
+    }
+
+    fn allocate(&mut self) -> u64 {
+        self.page_count += 1;
+        self.page_count - 1
+    }
+
+    fn put(&mut self, page: u64, node: Node) {
+        self.nodes.insert(page, node);
+        self.dirty.insert(page);
+    }
+
+    fn node(&mut self, page: u64) -> ::anyhow::Result<&Node> {
+        if !self.nodes.contains_key(&page) {
+            let node = self.read_node(page)?;
+            self.nodes.insert(page, node);
+        }
+        Ok(&self.nodes[&page])
+    }
+
+    fn node_mut(&mut self, page: u64) -> ::anyhow::Result<&mut Node> {
+        self.node(page)?;
+        self.dirty.insert(page);
+        self.nodes.get_mut(&page).ok_or_else(|| anyhow!("ERROR: index page {} is missing", page))
+    }
+
+    fn read_node(&mut self, page: u64) -> ::anyhow::Result<Node> {
+        let mut bytes = vec![0_u8; PAGE_SIZE];
+        self.file.seek(SeekFrom::Start(page * PAGE_SIZE as u64))?;
+        self.file.read_exact(&mut bytes)?;
+        let count = usize::from(u16::from_le_bytes([bytes[1], bytes[2]]));
+        let link = u64::from_le_bytes(bytes[3..11].try_into()?);
+        let mut offset = NODE_HEADER_SIZE;
+        let mut entries: Vec<Entry> = Vec::with_capacity(count);
+        let mut children: Vec<u64> = vec![link];
+        for _ in 0..count {
+            let key = self.key_def.decode_row(&bytes[offset..offset + self.key_size])?;
+            offset += self.key_size;
+            let row_id = u64::from_le_bytes(bytes[offset..offset + 8].try_into()?);
+            offset += 8;
+            entries.push(Entry { key, row_id });
+            if bytes[0] == INTERNAL {
+                children.push(u64::from_le_bytes(bytes[offset..offset + 8].try_into()?));
+                offset += 8;
+            }
+        }
+        match bytes[0] {
+            LEAF => Ok(Node::Leaf { entries, next: link }),
+            INTERNAL => Ok(Node::Internal { separators: entries, children }),
+            kind => Err(anyhow!("ERROR: index is corrupted, unknown page kind {}", kind)),
+        }
+    }
+
+    fn encode_node(&self, node: &Node) -> ::anyhow::Result<Vec<u8>> {
+        let mut bytes: Vec<u8> = Vec::with_capacity(PAGE_SIZE);
+        let (kind, entries, link, children) = match node {
+            Node::Leaf { entries, next } => (LEAF, entries, *next, &[][..]),
+            Node::Internal { separators, children } => (INTERNAL, separators, children[0], &children[1..]),
+        };
+        bytes.push(kind);
+        bytes.extend(u16::try_from(entries.len())?.to_le_bytes());
+        bytes.extend(link.to_le_bytes());
+        for (idx, entry) in entries.iter().enumerate() {
+            bytes.extend(self.key_def.encode_row(&entry.key)?);
+            bytes.extend(entry.row_id.to_le_bytes());
+            if let Some(child) = children.get(idx) {
+                bytes.extend(child.to_le_bytes());
+            }
+        }
+        bytes.resize(PAGE_SIZE, 0);
+        Ok(bytes)
+    }
+}
+
+// Keys sort like ORDER BY key ASC, with NULLs last. A shorter key (a bound
+// on the first columns) is equal to every key it's a prefix of.
+pub fn compare_keys(left: &[DataValue], right: &[DataValue]) -> Ordering {
+    let columns = left.len().min(right.len());
+    let sort_keys: Vec<SortKey> = (0..columns).map(|column| SortKey { column, descending: false, nulls_first: false }).collect();
+    compare_rows(&sort_keys, left, right)
+}
+
+fn compare_entries(left: &Entry, right: &Entry) -> Ordering {
+    compare_keys(&left.key, &right.key).then(left.row_id.cmp(&right.row_id))
+}
+
+#[cfg(test)]
+use crate::with_data_dir;
+#[cfg(test)]
+use squirrel_core::table::{datatypes::Datatype, table_definition::ColumnDefinition};
+
+// A tree keyed on (n bigint, pad varchar(400)), so only about nine entries
+// fit in a page and a few hundred are enough for several levels
+#[cfg(test)]
+fn test_key_def() -> TableDefinition {
+    let column = |name: &str, data_type: Datatype, length: usize| ColumnDefinition {
+        name: name.to_string(),
+        data_type,
+        length,
+        primary_key: false,
+        not_null: false,
+        default: None,
+        identity: None,
+        sequence: None,
+    };
+    TableDefinition {
+        name: "test_index".to_string(),
+        column_defs: vec![column("n", Datatype::BigInt, 0), column("pad", Datatype::CharacterVarying, 400)],
+        foreign_keys: vec![],
+        unique_constraints: vec![],
+        check_constraints: vec![],
+    }
+}
+
+#[cfg(test)]
+fn test_entry(n: i64, row_id: u64) -> Entry {
+    Entry { key: vec![DataValue::I64Value(n), DataValue::StringValue(format!("pad {}", n))], row_id }
+}
+
+#[cfg(test)]
+fn test_key(n: i64) -> Vec<DataValue> {
+    vec![DataValue::I64Value(n)]
+}
+
+#[test]
+fn btree_splits_and_reopens() {
+    with_data_dir(|| {
+        let mut tree = BTree::create("./data/indexes/test_index", test_key_def()).unwrap();
+        assert_eq!(tree.leaf_capacity(), 9);
+        // Out of order, so splits happen all over the tree
+        for idx in 0..1000 {
+            let n = idx * 7919 % 1000;
+            tree.insert(test_entry(n, n as u64 + 10000)).unwrap();
+        }
+        let expected: Vec<u64> = (10000..11000).collect();
+        assert_eq!(tree.range(Bound::Unbounded, Bound::Unbounded, None).unwrap(), expected);
+
+        // The root's children are internal nodes too
+        let root = tree.root;
+        let Node::Internal { children, .. } = tree.node(root).unwrap() else { panic!("root is a leaf") };
+        let child = children[0];
+        assert!(matches!(tree.node(child).unwrap(), Node::Internal { .. }));
+
+        tree.flush().unwrap();
+        drop(tree);
+        let mut tree = BTree::open("./data/indexes/test_index", test_key_def()).unwrap();
+        assert_eq!(tree.range(Bound::Unbounded, Bound::Unbounded, None).unwrap(), expected);
+        assert_eq!(tree.range(Bound::Included(&test_key(500)), Bound::Excluded(&test_key(503)), None).unwrap(), vec![10500, 10501, 10502]);
+
+        // Changes that aren't flushed are lost
+        tree.insert(test_entry(1000, 11000)).unwrap();
+        drop(tree);
+        let mut tree = BTree::open("./data/indexes/test_index", test_key_def()).unwrap();
+        assert!(!tree.contains(&test_key(1000)).unwrap());
+
+        // A tree for a different key size can't be opened
+        let mut key_def = test_key_def();
+        key_def.column_defs.pop();
+        assert!(BTree::open("./data/indexes/test_index", key_def).is_err());
+    });
+}
+
+#[test]
+fn btree_duplicate_keys_and_bounds() {
+    with_data_dir(|| {
+        let mut tree = BTree::create("./data/indexes/test_index", test_key_def()).unwrap();
+        // Five entries per key, so every key's entries cross a page boundary
+        // somewhere. Row ids go down as they're added.
+        for copy in 0..5 {
+            for n in 0..200 {
+                tree.insert(test_entry(n, (n * 5 + 4 - copy) as u64)).unwrap();
+            }
+        }
+        let rows = |n: i64| -> Vec<u64> { (n as u64 * 5..n as u64 * 5 + 5).collect() };
+        for n in [0, 1, 17, 100, 199] {
+            assert_eq!(tree.range(Bound::Included(&test_key(n)), Bound::Included(&test_key(n)), None).unwrap(), rows(n));
+            // The whole key is a bound as well as its first column
+            let key = test_entry(n, 0).key;
+            assert_eq!(tree.range(Bound::Included(&key), Bound::Included(&key), None).unwrap(), rows(n));
+        }
+        assert_eq!(tree.range(Bound::Excluded(&test_key(17)), Bound::Excluded(&test_key(19)), None).unwrap(), rows(18));
+        assert_eq!(tree.range(Bound::Excluded(&test_key(197)), Bound::Unbounded, None).unwrap(), [rows(198), rows(199)].concat());
+        assert_eq!(tree.range(Bound::Unbounded, Bound::Excluded(&test_key(1)), None).unwrap(), rows(0));
+        assert_eq!(tree.range(Bound::Included(&test_key(50)), Bound::Unbounded, Some(7)).unwrap(), [rows(50), rows(51)].concat()[..7]);
+        assert!(tree.range(Bound::Excluded(&test_key(199)), Bound::Unbounded, None).unwrap().is_empty());
+        assert!(tree.range(Bound::Included(&test_key(-5)), Bound::Excluded(&test_key(0)), None).unwrap().is_empty());
+        assert!(tree.contains(&test_key(42)).unwrap());
+        assert!(!tree.contains(&test_key(200)).unwrap());
+
+        // NULLs sort after every value
+        tree.insert(Entry { key: vec![DataValue::Null, DataValue::Null], row_id: 5000 }).unwrap();
+        assert_eq!(tree.range(Bound::Excluded(&test_key(199)), Bound::Unbounded, None).unwrap(), vec![5000]);
+    });
+}
+
+#[test]
+fn btree_remove() {
+    with_data_dir(|| {
+        let mut tree = BTree::create("./data/indexes/test_index", test_key_def()).unwrap();
+        for n in 0..300 {
+            tree.insert(test_entry(n, n as u64)).unwrap();
+        }
+        // Everything from 100 to 199 empties a run of leaves, and every
+        // third entry elsewhere
+        for n in (0..300).filter(|n| (100..200).contains(n) || n % 3 == 0) {
+            assert!(tree.remove(&test_entry(n, n as u64)).unwrap());
+        }
+        assert!(!tree.remove(&test_entry(150, 150)).unwrap());
+        // The key has to match as well as the row
+        assert!(!tree.remove(&test_entry(301, 1)).unwrap());
+
+        let expected: Vec<u64> = (0..300).filter(|n| !(100..200).contains(n) && n % 3 != 0).collect();
+        assert_eq!(tree.range(Bound::Unbounded, Bound::Unbounded, None).unwrap(), expected);
+        assert_eq!(tree.range(Bound::Included(&test_key(90)), Bound::Included(&test_key(210)), None).unwrap(), vec![91, 92, 94, 95, 97, 98, 200, 202, 203, 205, 206, 208, 209]);
+        assert!(!tree.contains(&test_key(150)).unwrap());
+
+        // A removed entry can go back in, and it all survives a reopen
+        tree.insert(test_entry(150, 150)).unwrap();
+        tree.flush().unwrap();
+        drop(tree);
+        let mut tree = BTree::open("./data/indexes/test_index", test_key_def()).unwrap();
+        assert_eq!(tree.range(Bound::Included(&test_key(98)), Bound::Excluded(&test_key(201)), None).unwrap(), vec![98, 150, 200]);
+    });
+}
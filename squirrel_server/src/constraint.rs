@@ -0,0 +1,122 @@
+use anyhow::anyhow;
+use std::collections::HashMap;
+
+use squirrel_core::parser::command::{Command, DataValue, LogicExpression, ValueExpression};
+
+use crate::TableDefinition;
+
+// A table's NOT NULL, CHECK and DEFAULT constraints, with their expressions
+// parsed once for the whole statement. UNIQUE and foreign keys are checked
+// against indexes instead.
+pub struct Constraints {
+    defaults: Vec<Option<ValueExpression>>,
+    checks: Vec<(String, LogicExpression)>,
+}
+
+impl Constraints {
+    pub fn new(tabledef: &TableDefinition) -> ::anyhow::Result<Constraints> {
+        let defaults = tabledef
+            .column_defs
+            .iter()
+            .map(|col_def| col_def.default.as_deref().map(Command::stored_value_expression).transpose())
+            .collect::<::anyhow::Result<Vec<Option<ValueExpression>>>>()?;
+        let checks = tabledef
+            .check_constraints
+            .iter()
+            .map(|check| Ok((check.name.clone(), Command::stored_logic_expression(&check.expression)?)))
+            .collect::<::anyhow::Result<Vec<(String, LogicExpression)>>>()?;
+        Ok(Constraints { defaults, checks })
+    }
+
+    // The value of a column an INSERT leaves out, its DEFAULT or NULL
+    pub fn default_value(&self, column: usize) -> ::anyhow::Result<DataValue> {
+        match &self.defaults[column] {
+            Some(default) => default.evaluate(),
+            None => Ok(DataValue::Null),
+        }
+    }
+
+    // Errors naming the first constraint the row breaks. values are the
+    // row's values in column order.
+    pub fn check(&self, tabledef: &TableDefinition, values: &[DataValue]) -> ::anyhow::Result<()> {
+        for (col_def, value) in tabledef.column_defs.iter().zip(values) {
+            if (col_def.not_null || col_def.primary_key) && *value == DataValue::Null {
+                return Err(anyhow!(
+                    "ERROR: null value in column '{}' of relation '{}' violates not-null constraint",
+                    col_def.name,
+                    tabledef.name
+                ));
+            }
+        }
+
+        if self.checks.is_empty() {
+            return Ok(());
+        }
+        let row_data: HashMap<String, ValueExpression> = tabledef
+            .column_defs
+            .iter()
+            .zip(values)
+            .map(|(col_def, value)| (col_def.name.clone(), ValueExpression::column_value(value.clone(), &col_def.data_type)))
+            .collect();
+        for (name, check) in &self.checks {
+            let mut check = check.clone();
+            check.fill_values(row_data.clone())?;
+            // NULL passes, only false breaks the constraint
+            if check.evaluate_nullable()? == Some(false) {
+                return Err(anyhow!("ERROR: new row for relation '{}' violates check constraint '{}'", tabledef.name, name));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+use crate::{read_tabledef, run_statements, select_rows, with_data_dir};
+
+#[test]
+fn column_and_table_constraints() {
+    with_data_dir(|| {
+        let responses = run_statements(&[
+            "CREATE TABLE items (id int PRIMARY KEY, name varchar 20 NOT NULL, qty int DEFAULT 1 CHECK (qty > 0), sku varchar 10 UNIQUE, note varchar 20 DEFAULT 'none', price int, CONSTRAINT price_pos CHECK (price >= 0), UNIQUE (name, price));",
+            "INSERT INTO items (id, name) VALUES (1, 'apple');",
+            "INSERT INTO items (id, name, qty, sku, price) VALUES (2, 'pear', 5, 'P1', 10);",
+        ]);
+        assert!(responses.iter().all(|response| !response.starts_with("ERROR")), "{:?}", responses);
+        let rows = || -> Vec<String> { select_rows("SELECT * FROM items ORDER BY id;").into_iter().map(|row| row.join(" ")).collect() };
+        // Left out columns get their DEFAULT, or NULL without one
+        let expected = vec!["1 apple 1 NULL none NULL", "2 pear 5 P1 none 10"];
+        assert_eq!(rows(), expected);
+
+        let errors = [
+            ("INSERT INTO items (id, qty) VALUES (3, 2);", "null value in column 'name' of relation 'items' violates not-null constraint"),
+            ("INSERT INTO items (id, name, qty) VALUES (3, 'x', 0);", "violates check constraint 'items_qty_check'"),
+            ("INSERT INTO items (id, name, price) VALUES (3, 'x', -1);", "violates check constraint 'price_pos'"),
+            ("INSERT INTO items (id, name, sku) VALUES (3, 'x', 'P1');", "violates unique constraint 'items_sku_key', key (sku)=(P1) already exists"),
+            ("INSERT INTO items (id, name, price) VALUES (3, 'pear', 10);", "violates unique constraint 'items_name_price_key'"),
+            // The second row breaks the first's UNIQUE, so neither goes in
+            ("INSERT INTO items (id, name, sku) VALUES (3, 'x', 'S'), (4, 'y', 'S');", "violates unique constraint 'items_sku_key'"),
+            ("UPDATE items SET qty = -5 WHERE id = 1;", "violates check constraint 'items_qty_check'"),
+            ("UPDATE items SET name = NULL WHERE id = 1;", "violates not-null constraint"),
+            ("UPDATE items SET sku = 'P1' WHERE id = 1;", "violates unique constraint 'items_sku_key'"),
+            ("DROP INDEX items_sku_key;", "constraint 'items_sku_key' requires it"),
+            ("CREATE TABLE bad (a int DEFAULT b);", "cannot use column reference in DEFAULT expression"),
+            ("CREATE TABLE bad (a int CHECK (b > 0));", "column 'b' does not exist"),
+        ];
+        for (statement, error) in errors {
+            let response = &run_statements(&[statement])[0];
+            assert!(response.starts_with("ERROR") && response.contains(error), "{}: {}", statement, response);
+        }
+        assert_eq!(rows(), expected);
+
+        // A CHECK on NULL passes, and NULLs aren't duplicates of each other
+        assert_eq!(run_statements(&["INSERT INTO items (id, name, qty) VALUES (3, 'x', NULL), (4, 'y', NULL);"]), vec!["Data Inserted"]);
+        assert_eq!(run_statements(&["UPDATE items SET sku = 'P2' WHERE id = 1;"]), vec!["1 Rows Updated"]);
+
+        // The constraints are kept with the table
+        let tabledef = read_tabledef(String::from("items")).unwrap();
+        assert_eq!(tabledef.check_constraints.len(), 2);
+        assert_eq!(tabledef.unique_constraints.len(), 2);
+        assert_eq!(tabledef.get_column("note").unwrap().default.as_deref(), Some("'none'"));
+        assert!(tabledef.get_column("name").unwrap().not_null);
+    });
+}
@@ -0,0 +1,166 @@
+use anyhow::anyhow;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use squirrel_core::parser::command::{CommonTableExpression, DataValue, SelectCommand, SetOperator};
+
+use crate::join::QueryContext;
+use crate::set_operation::{cast_rows, row_key};
+use crate::{execute_select, ColumnDefinition, Datatype, QueryResult, TableDefinition};
+
+// The rows of a WITH query. They're worked out once, before the query that
+// uses them, and are read like a table from then on.
+pub struct CteTable {
+    pub tabledef: TableDefinition,
+    pub rows: Rc<Vec<Vec<DataValue>>>,
+}
+
+impl CteTable {
+    // Columns without a known type take the type of their first non-NULL value
+    fn new(name: &str, column_names: &[String], column_types: &[Option<Datatype>], rows: Vec<Vec<DataValue>>) -> CteTable {
+        let column_defs = column_names
+            .iter()
+            .zip(column_types)
+            .enumerate()
+            .map(|(idx, (column_name, column_type))| {
+                let data_type = column_type
+                    .clone()
+                    .or_else(|| rows.iter().find_map(|row| row[idx].datatype()))
+                    .unwrap_or(Datatype::CharacterVarying);
+                ColumnDefinition::new(column_name.clone(), data_type, 0)
+            })
+            .collect();
+        CteTable { tabledef: TableDefinition {
+            name: name.to_string(),
+            column_defs,
+            foreign_keys: vec![],
+            unique_constraints: vec![],
+            check_constraints: vec![],
+        }, rows: Rc::new(rows) }
+    }
+}
+
+// Runs a WITH query. A recursive one starts with the rows of everything
+// before its last UNION and runs the query after it against the rows the
+// last run added until it adds none. UNION (without ALL) skips rows that
+// were already added.
+pub fn materialize(cte: &CommonTableExpression, context: &QueryContext) -> ::anyhow::Result<CteTable> {
+    let recursive_operation = match cte.query.set_operations.last() {
+        Some(set_operation)
+            if cte.recursive && set_operation.operator == SetOperator::Union && reads_from(&set_operation.query, &cte.name) =>
+        {
+            set_operation
+        }
+        _ => {
+            let result = execute_select(&cte.query, context.clone())?;
+            let column_names = rename_columns(cte, &result)?;
+            return Ok(CteTable::new(&cte.name, &column_names, &result.column_types, result.rows));
+        }
+    };
+    if !cte.query.order_by.is_empty() {
+        return Err(anyhow!("ERROR: ORDER BY in a recursive query is not implemented"));
+    }
+    if cte.query.limit.is_some() || cte.query.offset > 0 {
+        return Err(anyhow!("ERROR: LIMIT and OFFSET in a recursive query are not implemented"));
+    }
+
+    let mut anchor = (*cte.query).clone();
+    anchor.set_operations.pop();
+    let result = execute_select(&anchor, context.clone())?;
+    let column_names = rename_columns(cte, &result)?;
+    let column_types = result.column_types;
+
+    // Rows of the recursive query are cast to the anchor's column types
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut new_rows = |rows: Vec<Vec<DataValue>>| -> ::anyhow::Result<Vec<Vec<DataValue>>> {
+        let rows = cast_rows(rows, &column_types)?;
+        Ok(rows.into_iter().filter(|row| recursive_operation.all || seen.insert(row_key(row))).collect())
+    };
+    let mut rows: Vec<Vec<DataValue>> = vec![];
+    let mut working_rows = new_rows(result.rows)?;
+    while !working_rows.is_empty() {
+        rows.extend(working_rows.iter().cloned());
+        let mut context = context.clone();
+        let working_table = CteTable::new(&cte.name, &column_names, &column_types, working_rows);
+        context.ctes.insert(cte.name.clone(), Rc::new(working_table));
+        let result = execute_select(&recursive_operation.query, context)?;
+        if result.column_names.len() != column_names.len() {
+            return Err(anyhow!("ERROR: each UNION query must have the same number of columns"));
+        }
+        working_rows = new_rows(result.rows)?;
+    }
+    Ok(CteTable::new(&cte.name, &column_names, &column_types, rows))
+}
+
+// Whether a query reads from a table, directly or in one of its set
+// operations
+fn reads_from(query: &SelectCommand, table_name: &str) -> bool {
+    query.table_name == table_name
+        || query.joins.iter().any(|join| join.table_name == table_name)
+        || query.set_operations.iter().any(|set_operation| reads_from(&set_operation.query, table_name))
+}
+
+// WITH name (a, b) renames the query's first columns
+fn rename_columns(cte: &CommonTableExpression, result: &QueryResult) -> ::anyhow::Result<Vec<String>> {
+    let mut column_names = result.column_names.clone();
+    if cte.column_names.len() > column_names.len() {
+        return Err(anyhow!(
+            "ERROR: WITH query '{}' has {} columns available but {} columns specified",
+            cte.name,
+            column_names.len(),
+            cte.column_names.len()
+        ));
+    }
+    for (column_name, new_name) in column_names.iter_mut().zip(&cte.column_names) {
+        *column_name = new_name.clone();
+    }
+    Ok(column_names)
+}
+
+#[cfg(test)]
+use crate::{run_statements, select_rows, with_data_dir};
+
+#[test]
+fn with_queries_over_stored_rows() {
+    with_data_dir(|| {
+        let responses = run_statements(&[
+            "CREATE TABLE edges (src int, dst int);",
+            "INSERT INTO edges (src, dst) VALUES (1, 2), (2, 3), (3, 1), (3, 4), (5, 5);",
+        ]);
+        assert!(responses.iter().all(|response| !response.starts_with("ERROR")), "{:?}", responses);
+        let values = |query: &str| -> Vec<String> { select_rows(query).into_iter().map(|row| row.join(" ")).collect() };
+
+        // UNION ALL keeps every row, and stops once the WHERE adds none
+        assert_eq!(
+            values("WITH RECURSIVE n (i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 5) SELECT i FROM n;"),
+            vec!["1", "2", "3", "4", "5"]
+        );
+        assert_eq!(
+            values(
+                "WITH RECURSIVE walk (node, steps) AS (SELECT 1, 0 UNION ALL SELECT e.dst, w.steps + 1 FROM edges e JOIN walk w ON e.src = w.node WHERE w.steps < 3) SELECT node, steps FROM walk ORDER BY steps, node;"
+            ),
+            vec!["1 0", "2 1", "3 2", "1 3", "4 3"]
+        );
+
+        // UNION drops rows already found, so cycles end: 5 only ever leads
+        // back to itself, and 1 to 2, 3, 1 and 4
+        assert_eq!(
+            values("WITH RECURSIVE reach (node) AS (SELECT 5 UNION SELECT e.dst FROM edges e JOIN reach r ON e.src = r.node) SELECT node FROM reach;"),
+            vec!["5"]
+        );
+        assert_eq!(
+            values("WITH RECURSIVE reach (node) AS (SELECT 1 UNION SELECT e.dst FROM edges e JOIN reach r ON e.src = r.node) SELECT node FROM reach ORDER BY node;"),
+            vec!["1", "2", "3", "4"]
+        );
+
+        // A plain WITH query is worked out once, and can be read more than
+        // once
+        assert_eq!(
+            values(
+                "WITH out_degree (node, n) AS (SELECT src, COUNT(*) FROM edges GROUP BY src) SELECT a.node, b.node FROM out_degree a JOIN out_degree b ON a.n = b.n AND a.node < b.node ORDER BY a.node, b.node;"
+            ),
+            vec!["1 2", "1 5", "2 5"]
+        );
+        assert!(run_statements(&["WITH t (a, b) AS (SELECT src FROM edges) SELECT * FROM t;"])[0].contains("has 1 columns available but 2 columns specified"));
+    });
+}
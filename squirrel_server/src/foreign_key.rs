@@ -0,0 +1,452 @@
+use anyhow::anyhow;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufReader, ErrorKind, Read};
+
+use squirrel_core::parser::command::DataValue;
+use squirrel_core::table::table_definition::{ForeignKey, ReferentialAction};
+
+use crate::index::{self, TableIndex};
+use crate::join::normalize_value;
+use crate::constraint::Constraints;
+use crate::row_changes::RowChanges;
+use crate::{read_tabledef, TableDefinition};
+
+// Fills in and checks a new table's foreign keys. The referenced columns
+// default to the referenced table's primary key, and have to be its
+// primary key or have a unique index.
+pub fn resolve(tabledef: &mut TableDefinition) -> ::anyhow::Result<()> {
+    let own = tabledef.clone();
+    for foreign_key in tabledef.foreign_keys.iter_mut() {
+        let referenced = if foreign_key.referenced_table == own.name {
+            own.clone()
+        } else {
+            match read_tabledef(foreign_key.referenced_table.clone()) {
+                Err(err) if err.downcast_ref::<std::io::Error>().is_some_and(|err| err.kind() == ErrorKind::NotFound) => {
+                    return Err(anyhow!("ERROR: relation '{}' does not exist", foreign_key.referenced_table));
+                }
+                result => result?,
+            }
+        };
+
+        if foreign_key.referenced_columns.is_empty() {
+            foreign_key.referenced_columns =
+                referenced.column_defs.iter().filter(|col_def| col_def.primary_key).map(|col_def| col_def.name.clone()).collect();
+            if foreign_key.referenced_columns.is_empty() {
+                return Err(anyhow!("ERROR: there is no primary key for referenced table '{}'", referenced.name));
+            }
+            if foreign_key.referenced_columns.len() != foreign_key.columns.len() {
+                return Err(anyhow!("ERROR: number of referencing and referenced columns for foreign key disagree"));
+            }
+        }
+
+        let mut columns = positions(&referenced, &foreign_key.referenced_columns)?;
+        columns.sort();
+        let unique = TableIndex::for_table(&referenced)?.into_iter().any(|index| {
+            let mut index_columns = index.columns.clone();
+            index_columns.sort();
+            index.unique && index_columns == columns
+        });
+        if !unique {
+            return Err(anyhow!(
+                "ERROR: there is no unique constraint matching given keys for referenced table '{}'",
+                referenced.name
+            ));
+        }
+    }
+    Ok(())
+}
+
+// The foreign keys that reference a table, with the table they're on.
+// Tables can reference themselves.
+pub fn referencing(table_name: &str) -> ::anyhow::Result<Vec<(TableDefinition, ForeignKey)>> {
+    let mut table_names: Vec<String> = vec![];
+    for entry in fs::read_dir("./data/tabledefs")? {
+        table_names.push(entry?.file_name().to_string_lossy().to_string());
+    }
+    table_names.sort();
+
+    let mut found: Vec<(TableDefinition, ForeignKey)> = vec![];
+    for name in table_names {
+        let tabledef = read_tabledef(name)?;
+        for foreign_key in &tabledef.foreign_keys {
+            if foreign_key.referenced_table == table_name {
+                found.push((tabledef.clone(), foreign_key.clone()));
+            }
+        }
+    }
+    Ok(found)
+}
+
+// Errors if a row references a key that isn't in the referenced table. Rows
+// with a NULL in a foreign key don't reference anything. A table that
+// references itself is checked against own_rows() along with the new rows.
+pub fn check_references(
+    tabledef: &TableDefinition,
+    rows: &[Vec<DataValue>],
+    own_rows: &dyn Fn() -> ::anyhow::Result<Vec<Vec<DataValue>>>,
+) -> ::anyhow::Result<()> {
+    for foreign_key in &tabledef.foreign_keys {
+        let columns = positions(tabledef, &foreign_key.columns)?;
+        let keys: Vec<Vec<DataValue>> = rows.iter().filter_map(|row| key_of(row, &columns)).collect();
+        if keys.is_empty() {
+            continue;
+        }
+
+        let missing = if foreign_key.referenced_table == tabledef.name {
+            let referenced = positions(tabledef, &foreign_key.referenced_columns)?;
+            let present: HashSet<String> = own_rows()?
+                .iter()
+                .chain(rows)
+                .filter_map(|row| key_of(row, &referenced))
+                .map(|key| key_string(&key))
+                .collect();
+            keys.into_iter().find(|key| !present.contains(&key_string(key)))
+        } else {
+            let referenced_def = read_tabledef(foreign_key.referenced_table.clone())?;
+            find_missing(&referenced_def, &foreign_key.referenced_columns, keys)?
+        };
+
+        if let Some(key) = missing {
+            return Err(anyhow!(
+                "ERROR: insert or update on table '{}' violates foreign key constraint '{}', key ({})=({}) is not present in table '{}'",
+                tabledef.name,
+                foreign_key.name,
+                foreign_key.columns.join(", "),
+                values_string(&key),
+                foreign_key.referenced_table
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Errors if an UPDATE changes keys that rows still reference. old_rows and
+// new_rows are the updated rows before and after, and own_rows() is the
+// table as it will be, for tables that reference themselves.
+pub fn check_referenced(
+    tabledef: &TableDefinition,
+    old_rows: &[Vec<DataValue>],
+    new_rows: &[Vec<DataValue>],
+    own_rows: &dyn Fn() -> ::anyhow::Result<Vec<Vec<DataValue>>>,
+) -> ::anyhow::Result<()> {
+    for (referencing_def, foreign_key) in referencing(&tabledef.name)? {
+        let referenced = positions(tabledef, &foreign_key.referenced_columns)?;
+        let new_keys: HashSet<String> = new_rows.iter().filter_map(|row| key_of(row, &referenced)).map(|key| key_string(&key)).collect();
+        // Keys no row has any more
+        let gone: HashMap<String, Vec<DataValue>> = old_rows
+            .iter()
+            .filter_map(|row| key_of(row, &referenced))
+            .map(|key| (key_string(&key), key))
+            .filter(|(key_string, _)| !new_keys.contains(key_string))
+            .collect();
+        if gone.is_empty() {
+            continue;
+        }
+
+        let rows = if referencing_def.name == tabledef.name {
+            own_rows()?
+        } else {
+            read_rows(&referencing_def, &format!("./data/blobs/{}", referencing_def.name))?
+        };
+        let columns = positions(&referencing_def, &foreign_key.columns)?;
+        for row in rows {
+            if let Some(key) = key_of(&row, &columns).and_then(|key| gone.get(&key_string(&key))) {
+                return Err(still_referenced(tabledef, &referencing_def.name, &foreign_key, key));
+            }
+        }
+    }
+    Ok(())
+}
+
+// The tables a DELETE changes through ON DELETE CASCADE and SET NULL. They're
+// read into memory and only written once every referencing row has been
+// dealt with, so a RESTRICT anywhere leaves every table untouched.
+#[derive(Default)]
+pub struct Cascade {
+    tables: Vec<CascadeTable>,
+    // Rows that have to be deleted by the end, and the error if they aren't
+    restricted: Vec<(usize, usize, ::anyhow::Error)>,
+}
+
+struct CascadeTable {
+    tabledef: TableDefinition,
+    rows: Vec<Vec<DataValue>>,
+    deleted: Vec<bool>,
+    // The rows SET NULL changed, as they were
+    updated: HashMap<usize, Vec<DataValue>>,
+    changed: bool,
+    referencing: Vec<(TableDefinition, ForeignKey)>,
+}
+
+impl Cascade {
+    fn load(&mut self, tabledef: &TableDefinition) -> ::anyhow::Result<usize> {
+        if let Some(idx) = self.tables.iter().position(|table| table.tabledef.name == tabledef.name) {
+            return Ok(idx);
+        }
+        let rows = read_rows(tabledef, &format!("./data/blobs/{}", tabledef.name))?;
+        self.tables.push(CascadeTable {
+            tabledef: tabledef.clone(),
+            deleted: vec![false; rows.len()],
+            rows,
+            updated: HashMap::new(),
+            changed: false,
+            referencing: referencing(&tabledef.name)?,
+        });
+        Ok(self.tables.len() - 1)
+    }
+
+    // Deletes rows (by row id) and does whatever the foreign keys that
+    // reference them say to the rows that do
+    pub fn delete(&mut self, tabledef: &TableDefinition, row_ids: &[usize]) -> ::anyhow::Result<()> {
+        let table = self.load(tabledef)?;
+        let deleted: Vec<usize> = row_ids.iter().copied().filter(|row_id| !self.tables[table].deleted[*row_id]).collect();
+        if deleted.is_empty() {
+            return Ok(());
+        }
+        for row_id in &deleted {
+            self.tables[table].deleted[*row_id] = true;
+        }
+        self.tables[table].changed = true;
+
+        for (referencing_def, foreign_key) in self.tables[table].referencing.clone() {
+            let referenced = positions(tabledef, &foreign_key.referenced_columns)?;
+            let keys: HashMap<String, Vec<DataValue>> = deleted
+                .iter()
+                .filter_map(|row_id| key_of(&self.tables[table].rows[*row_id], &referenced))
+                .map(|key| (key_string(&key), key))
+                .collect();
+            if keys.is_empty() {
+                continue;
+            }
+
+            let referencing_table = self.load(&referencing_def)?;
+            let columns = positions(&referencing_def, &foreign_key.columns)?;
+            let mut cascaded: Vec<usize> = vec![];
+            let CascadeTable { rows, deleted, updated, changed, .. } = &mut self.tables[referencing_table];
+            for (row_id, row) in rows.iter_mut().enumerate() {
+                if deleted[row_id] {
+                    continue;
+                }
+                let Some(key) = key_of(row, &columns).and_then(|key| keys.get(&key_string(&key))) else {
+                    continue;
+                };
+                match foreign_key.on_delete {
+                    ReferentialAction::Cascade => cascaded.push(row_id),
+                    ReferentialAction::SetNull => {
+                        updated.entry(row_id).or_insert_with(|| row.clone());
+                        for column in &columns {
+                            row[*column] = DataValue::Null;
+                        }
+                        *changed = true;
+                    }
+                    ReferentialAction::Restrict | ReferentialAction::NoAction => {
+                        self.restricted.push((
+                            referencing_table,
+                            row_id,
+                            still_referenced(tabledef, &referencing_def.name, &foreign_key, key),
+                        ));
+                    }
+                }
+            }
+            self.delete(&referencing_def, &cascaded)?;
+        }
+        Ok(())
+    }
+
+    // Writes every changed table, or none of them
+    pub fn write(self) -> ::anyhow::Result<()> {
+        let tables = self.tables;
+        if let Some((_, _, err)) = self.restricted.into_iter().find(|(table, row_id, _)| !tables[*table].deleted[*row_id]) {
+            return Err(err);
+        }
+
+        // Every table's changes are worked out before any are written
+        let mut changes: Vec<RowChanges> = vec![];
+        for table in tables.into_iter().filter(|table| table.changed) {
+            // SET NULL can break a NOT NULL or CHECK constraint
+            let constraints = Constraints::new(&table.tabledef)?;
+            let mut updated: Vec<(u64, Vec<DataValue>, Vec<DataValue>)> = vec![];
+            let mut deleted: Vec<(u64, Vec<DataValue>)> = vec![];
+            for (row_id, row) in table.rows.into_iter().enumerate() {
+                // The indexes still have a SET NULL row's old values
+                let old_row = table.updated.get(&row_id);
+                if table.deleted[row_id] {
+                    deleted.push((row_id as u64, old_row.cloned().unwrap_or(row)));
+                } else if let Some(old_row) = old_row {
+                    constraints.check(&table.tabledef, &row)?;
+                    updated.push((row_id as u64, old_row.clone(), row));
+                }
+            }
+            changes.push(RowChanges::new(&table.tabledef, updated, deleted)?);
+        }
+        for table_changes in changes {
+            table_changes.write()?;
+        }
+        Ok(())
+    }
+}
+
+// Whether any of the keys are missing from a table, using an index on the
+// columns if there is one
+fn find_missing(tabledef: &TableDefinition, column_names: &[String], keys: Vec<Vec<DataValue>>) -> ::anyhow::Result<Option<Vec<DataValue>>> {
+    let columns = positions(tabledef, column_names)?;
+    let mut sorted = columns.clone();
+    sorted.sort();
+    let index = TableIndex::for_table(tabledef)?.into_iter().find(|index| {
+        let mut index_columns = index.columns.clone();
+        index_columns.sort();
+        index_columns == sorted
+    });
+
+    if let Some(index) = index {
+        let mut tree = index.open(tabledef)?;
+        for key in keys {
+            // The index's columns can be in a different order
+            let index_key: Vec<DataValue> = index
+                .columns
+                .iter()
+                .map(|column| key[columns.iter().position(|key_column| key_column == column).unwrap_or_default()].clone())
+                .collect();
+            if !tree.contains(&index_key)? {
+                return Ok(Some(key));
+            }
+        }
+        return Ok(None);
+    }
+
+    let present: HashSet<String> = read_rows(tabledef, &format!("./data/blobs/{}", tabledef.name))?
+        .iter()
+        .filter_map(|row| key_of(row, &columns))
+        .map(|key| key_string(&key))
+        .collect();
+    Ok(keys.into_iter().find(|key| !present.contains(&key_string(key))))
+}
+
+fn still_referenced(tabledef: &TableDefinition, referencing_table: &str, foreign_key: &ForeignKey, key: &[DataValue]) -> ::anyhow::Error {
+    anyhow!(
+        "ERROR: update or delete on table '{}' violates foreign key constraint '{}' on table '{}', key ({})=({}) is still referenced",
+        tabledef.name,
+        foreign_key.name,
+        referencing_table,
+        foreign_key.referenced_columns.join(", "),
+        values_string(key)
+    )
+}
+
+fn positions(tabledef: &TableDefinition, column_names: &[String]) -> ::anyhow::Result<Vec<usize>> {
+    column_names
+        .iter()
+        .map(|column_name| {
+            index::column_position(tabledef, column_name)
+                .ok_or_else(|| anyhow!("ERROR: column '{}' referenced in foreign key constraint does not exist", column_name))
+        })
+        .collect()
+}
+
+// A row's values in the columns, None if any is NULL
+fn key_of(row: &[DataValue], columns: &[usize]) -> Option<Vec<DataValue>> {
+    let key: Vec<DataValue> = columns.iter().map(|column| row[*column].clone()).collect();
+    (!key.contains(&DataValue::Null)).then_some(key)
+}
+
+// Keys are matched the way hash joins match them, so 1.50 references 1.5
+fn key_string(key: &[DataValue]) -> String {
+    format!("{:?}", key.iter().map(normalize_value).collect::<Vec<DataValue>>())
+}
+
+fn values_string(key: &[DataValue]) -> String {
+    key.iter().map(|value| value.to_string()).collect::<Vec<String>>().join(", ")
+}
+
+// Every row of a blob, none if the table has no rows yet
+pub fn read_rows(tabledef: &TableDefinition, path: &str) -> ::anyhow::Result<Vec<Vec<DataValue>>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err.into()),
+    };
+    let mut reader = BufReader::new(file);
+    let mut buf: Vec<u8> = vec![0; tabledef.get_byte_size()];
+    let mut rows: Vec<Vec<DataValue>> = vec![];
+    while reader.read_exact(buf.as_mut_slice()).is_ok() {
+        rows.push(tabledef.decode_row(&buf)?);
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+use crate::{run_statements, select_rows, with_data_dir};
+
+#[cfg(test)]
+fn ok(statements: &[&str]) {
+    let responses = run_statements(statements);
+    assert!(responses.iter().all(|response| !response.starts_with("ERROR")), "{:?}", responses);
+}
+
+#[test]
+fn foreign_key_actions() {
+    with_data_dir(|| {
+        ok(&[
+            "CREATE TABLE users (id int PRIMARY KEY, name varchar 20);",
+            "CREATE TABLE orders (id int PRIMARY KEY, user_id int REFERENCES users ON DELETE CASCADE);",
+            "CREATE TABLE items (id int PRIMARY KEY, order_id int, FOREIGN KEY (order_id) REFERENCES orders (id) ON DELETE CASCADE);",
+            "CREATE TABLE reviews (id int PRIMARY KEY, user_id int REFERENCES users (id) ON DELETE SET NULL);",
+            "CREATE TABLE tickets (id int PRIMARY KEY, user_id int REFERENCES users ON DELETE RESTRICT);",
+            "INSERT INTO users (id, name) VALUES (1, 'ann'), (2, 'bob'), (3, 'cy');",
+            "INSERT INTO orders (id, user_id) VALUES (10, 1), (11, 1), (12, 2), (13, NULL);",
+            "INSERT INTO items (id, order_id) VALUES (100, 10), (101, 11), (102, 12);",
+            "INSERT INTO reviews (id, user_id) VALUES (1, 1), (2, 2);",
+            "INSERT INTO tickets (id, user_id) VALUES (1, 3);",
+        ]);
+        let ids = |query: &str| -> Vec<String> { select_rows(query).into_iter().map(|row| row.join(" ")).collect() };
+
+        assert!(run_statements(&["CREATE TABLE bad (x int REFERENCES nope);"])[0].contains("relation 'nope' does not exist"));
+        assert!(run_statements(&["CREATE TABLE bad (x int REFERENCES users (name));"])[0].contains("no unique constraint"));
+        assert!(run_statements(&["INSERT INTO orders (id, user_id) VALUES (14, 99);"])[0].contains("key (user_id)=(99) is not present in table 'users'"));
+        assert!(run_statements(&["UPDATE orders SET user_id = 42 WHERE id = 12;"])[0].contains("is not present"));
+        assert!(run_statements(&["UPDATE users SET id = 7 WHERE id = 2;"])[0].contains("key (id)=(2) is still referenced"));
+
+        // CASCADE goes through orders to items, SET NULL leaves the review
+        assert_eq!(run_statements(&["DELETE FROM users WHERE id = 1;"]), vec!["1 Rows Deleted"]);
+        assert_eq!(ids("SELECT id, user_id FROM orders ORDER BY id;"), vec!["12 2", "13 NULL"]);
+        assert_eq!(ids("SELECT id FROM items ORDER BY id;"), vec!["102"]);
+        assert_eq!(ids("SELECT id, user_id FROM reviews ORDER BY id;"), vec!["1 NULL", "2 2"]);
+        // The moved rows are still found through their indexes
+        assert_eq!(ids("SELECT id FROM orders WHERE id = 13;"), vec!["13"]);
+        assert_eq!(ids("SELECT id FROM items WHERE id = 102;"), vec!["102"]);
+
+        // RESTRICT stops the whole DELETE, the cascade along with it
+        let users = ids("SELECT id FROM users ORDER BY id;");
+        assert!(run_statements(&["DELETE FROM users;"])[0].contains("violates foreign key constraint"));
+        assert_eq!(ids("SELECT id FROM users ORDER BY id;"), users);
+        assert_eq!(ids("SELECT id FROM orders ORDER BY id;"), vec!["12", "13"]);
+
+        ok(&["DELETE FROM tickets;", "UPDATE users SET id = 8 WHERE id = 3;", "DELETE FROM users;"]);
+        assert!(ids("SELECT id FROM users;").is_empty());
+        assert_eq!(ids("SELECT id, user_id FROM orders;"), vec!["13 NULL"]);
+        assert!(ids("SELECT id FROM items;").is_empty());
+        assert_eq!(ids("SELECT id, user_id FROM reviews ORDER BY id;"), vec!["1 NULL", "2 NULL"]);
+
+        // SET NULL can't break NOT NULL, and nothing is deleted if it would
+        ok(&[
+            "CREATE TABLE owners (id int PRIMARY KEY);",
+            "CREATE TABLE pets (id int PRIMARY KEY, owner_id int NOT NULL REFERENCES owners ON DELETE SET NULL);",
+            "INSERT INTO owners (id) VALUES (1);",
+            "INSERT INTO pets (id, owner_id) VALUES (1, 1);",
+        ]);
+        assert!(run_statements(&["DELETE FROM owners WHERE id = 1;"])[0].contains("not-null"));
+        assert_eq!(ids("SELECT id, owner_id FROM pets;"), vec!["1 1"]);
+
+        // A table can reference itself, including rows of the same INSERT
+        ok(&[
+            "CREATE TABLE emp (id int PRIMARY KEY, boss int REFERENCES emp ON DELETE CASCADE);",
+            "INSERT INTO emp (id, boss) VALUES (1, NULL), (2, 1), (3, 2), (4, 5), (5, 1);",
+        ]);
+        assert!(run_statements(&["INSERT INTO emp (id, boss) VALUES (6, 60);"])[0].contains("is not present"));
+        assert!(run_statements(&["UPDATE emp SET id = 20 WHERE id = 2;"])[0].contains("still referenced"));
+        ok(&["UPDATE emp SET boss = 5 WHERE id = 3;"]);
+        assert_eq!(run_statements(&["DELETE FROM emp WHERE id = 5;"]), vec!["1 Rows Deleted"]);
+        assert_eq!(ids("SELECT id FROM emp ORDER BY id;"), vec!["1", "2"]);
+    });
+}
@@ -0,0 +1,545 @@
+use anyhow::anyhow;
+use std::cmp::Ordering;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::ops::Bound;
+
+use squirrel_core::parser::command::{DataValue, LogicExpression, LogicalOperator, ValueExpression};
+use squirrel_core::table::datatypes::Datatype;
+use squirrel_core::table::table_definition::ColumnDefinition;
+
+use crate::btree::{compare_keys, BTree, Entry};
+use crate::{read_tabledef, TableDefinition};
+
+// An index on some of a table's columns, kept in ./data/indexes/<name>.
+// A table's primary key has one named <table>_pkey and UNIQUE constraints
+// have one named after the constraint. The rest are made with CREATE INDEX
+// and listed in ./data/indexdefs/<table>.
+pub struct TableIndex {
+    pub name: String,
+    pub columns: Vec<usize>,
+    pub unique: bool,
+}
+
+// A CREATE INDEX index as it's listed in ./data/indexdefs/<table>, one
+// `name column,column flags` line each
+#[derive(Clone)]
+pub struct IndexDefinition {
+    pub name: String,
+    pub columns: Vec<String>,
+    pub unique: bool,
+}
+
+impl TableIndex {
+    // The primary key's index first, then the UNIQUE constraints', then the
+    // others in the order they were made
+    pub fn for_table(tabledef: &TableDefinition) -> ::anyhow::Result<Vec<TableIndex>> {
+        let mut indexes: Vec<TableIndex> = tabledef
+            .column_defs
+            .iter()
+            .enumerate()
+            .filter(|(_, col_def)| col_def.primary_key)
+            .map(|(idx, _)| TableIndex { name: format!("{}_pkey", tabledef.name), columns: vec![idx], unique: true })
+            .collect();
+        for unique in &tabledef.unique_constraints {
+            let columns = unique
+                .columns
+                .iter()
+                .map(|column| {
+                    column_position(tabledef, column)
+                        .ok_or_else(|| anyhow!("ERROR: column '{}' of constraint '{}' does not exist", column, unique.name))
+                })
+                .collect::<::anyhow::Result<Vec<usize>>>()?;
+            indexes.push(TableIndex { name: unique.name.clone(), columns, unique: true });
+        }
+
+        for definition in definitions(&tabledef.name)? {
+            let columns = definition
+                .columns
+                .iter()
+                .map(|column| {
+                    column_position(tabledef, column)
+                        .ok_or_else(|| anyhow!("ERROR: column '{}' of index '{}' does not exist", column, definition.name))
+                })
+                .collect::<::anyhow::Result<Vec<usize>>>()?;
+            indexes.push(TableIndex { name: definition.name, columns, unique: definition.unique });
+        }
+        Ok(indexes)
+    }
+
+    // Adds a CREATE INDEX index to its table's list
+    pub fn save(&self, tabledef: &TableDefinition) -> ::anyhow::Result<()> {
+        let columns: Vec<&str> = self.columns.iter().map(|column| tabledef.column_defs[*column].name.as_str()).collect();
+        let line = format!("{} {} {}\n", self.name, columns.join(","), if self.unique { "unique" } else { "" });
+        let mut file = fs::OpenOptions::new().create(true).append(true).open(definitions_path(&tabledef.name))?;
+        file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn path(&self) -> String {
+        format!("./data/indexes/{}", self.name)
+    }
+
+    // The index's columns of a row
+    pub fn key(&self, values: &[DataValue]) -> Vec<DataValue> {
+        self.columns.iter().map(|column| values[*column].clone()).collect()
+    }
+
+    fn key_def(&self, tabledef: &TableDefinition) -> TableDefinition {
+        TableDefinition {
+            name: self.name.clone(),
+            column_defs: self.columns.iter().map(|column| tabledef.column_defs[*column].clone()).collect(),
+            foreign_keys: vec![],
+            unique_constraints: vec![],
+            check_constraints: vec![],
+        }
+    }
+
+    // Opens the index, building it from the table if its file is missing
+    pub fn open(&self, tabledef: &TableDefinition) -> ::anyhow::Result<BTree> {
+        match BTree::open(&self.path(), self.key_def(tabledef)) {
+            Err(err) if err.downcast_ref::<std::io::Error>().is_some_and(|err| err.kind() == ErrorKind::NotFound) => {
+                self.build(tabledef, &format!("./data/blobs/{}", tabledef.name), &self.path())
+            }
+            result => result,
+        }
+    }
+
+    // Builds the index at index_path from every row of the blob at
+    // blob_path, checking a unique index's keys are
+    pub fn build(&self, tabledef: &TableDefinition, blob_path: &str, index_path: &str) -> ::anyhow::Result<BTree> {
+        let mut tree = BTree::create(index_path, self.key_def(tabledef))?;
+        let file = match File::open(blob_path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(tree),
+            Err(err) => return Err(err.into()),
+        };
+        let mut reader = BufReader::new(file);
+        let mut buf: Vec<u8> = vec![0; tabledef.get_byte_size()];
+        let mut row_id: u64 = 0;
+        while reader.read_exact(buf.as_mut_slice()).is_ok() {
+            let key = self.key(&tabledef.decode_row(&buf)?);
+            self.check_unique(tabledef, &mut tree, &key)?;
+            tree.insert(Entry { key, row_id })?;
+            row_id += 1;
+        }
+        tree.flush()?;
+        Ok(tree)
+    }
+
+    // Errors if a unique index already has the key. Keys with a NULL are
+    // never duplicates.
+    pub fn check_unique(&self, tabledef: &TableDefinition, tree: &mut BTree, key: &[DataValue]) -> ::anyhow::Result<()> {
+        if !self.unique || key.contains(&DataValue::Null) || !tree.contains(key)? {
+            return Ok(());
+        }
+        let columns: Vec<&str> = self.columns.iter().map(|column| tabledef.column_defs[*column].name.as_str()).collect();
+        let values: Vec<String> = key.iter().map(|value| value.to_string()).collect();
+        Err(anyhow!(
+            "ERROR: duplicate key value violates unique constraint '{}', key ({})=({}) already exists",
+            self.name,
+            columns.join(", "),
+            values.join(", ")
+        ))
+    }
+}
+
+// Drops a CREATE INDEX index, wherever it is. False if there's no such
+// index.
+pub fn remove(index_name: &str) -> ::anyhow::Result<bool> {
+    for entry in fs::read_dir("./data/indexdefs")? {
+        let path = entry?.path();
+        let contents = fs::read_to_string(&path)?;
+        let kept: Vec<&str> = contents.lines().filter(|line| line.split(' ').next() != Some(index_name)).collect();
+        if kept.len() == contents.lines().count() {
+            continue;
+        }
+        if kept.is_empty() {
+            fs::remove_file(&path)?;
+        } else {
+            fs::write(&path, kept.iter().map(|line| format!("{}\n", line)).collect::<String>())?;
+        }
+        match fs::remove_file(format!("./data/indexes/{}", index_name)) {
+            Err(err) if err.kind() != ErrorKind::NotFound => return Err(err.into()),
+            _ => return Ok(true),
+        }
+    }
+    Ok(false)
+}
+
+// Whether an index name is in use, by an index file, a CREATE INDEX listed
+// for any table, or a table's primary key or UNIQUE constraint. They all
+// share ./data/indexes, so a new one mustn't take an old one's file.
+pub fn name_taken(name: &str) -> ::anyhow::Result<bool> {
+    if fs::metadata(format!("./data/indexes/{}", name)).is_ok() {
+        return Ok(true);
+    }
+    for entry in fs::read_dir("./data/indexdefs")? {
+        let table_name = entry?.file_name().to_string_lossy().to_string();
+        if definitions(&table_name)?.iter().any(|definition| definition.name == name) {
+            return Ok(true);
+        }
+    }
+    for entry in fs::read_dir("./data/tabledefs")? {
+        let tabledef = read_tabledef(entry?.file_name().to_string_lossy().to_string())?;
+        if TableIndex::for_table(&tabledef)?.iter().any(|index| index.name == name) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+// ./data/reindex/<table> is there while a table's blob is being changed
+// and its indexes haven't been written to match yet. It's synced before the
+// blob is touched, so after a crash it says which indexes can't be trusted.
+pub fn mark_stale(table_name: &str) -> ::anyhow::Result<()> {
+    File::create(format!("./data/reindex/{}", table_name))?.sync_all()?;
+    Ok(())
+}
+
+pub fn mark_fresh(table_name: &str) -> ::anyhow::Result<()> {
+    fs::remove_file(format!("./data/reindex/{}", table_name))?;
+    Ok(())
+}
+
+// Rebuilds the indexes of every table marked stale, for when the server
+// starts after a crash. A blob that was never renamed into place is
+// dropped, the old one is still there.
+pub fn recover() -> ::anyhow::Result<()> {
+    for entry in fs::read_dir("./data/reindex")? {
+        let table_name = entry?.file_name().to_string_lossy().to_string();
+        let _ = fs::remove_file(format!("./data/blobs/{}_new", table_name));
+        match read_tabledef(table_name.clone()) {
+            Err(err) if err.downcast_ref::<std::io::Error>().is_some_and(|err| err.kind() == ErrorKind::NotFound) => {}
+            result => {
+                let tabledef = result?;
+                for index in TableIndex::for_table(&tabledef)? {
+                    index.build(&tabledef, &format!("./data/blobs/{}", table_name), &index.path())?;
+                }
+            }
+        }
+        mark_fresh(&table_name)?;
+    }
+    Ok(())
+}
+
+pub fn definitions_path(table_name: &str) -> String {
+    format!("./data/indexdefs/{}", table_name)
+}
+
+// A table's CREATE INDEX indexes, in the order they were made
+pub fn definitions(table_name: &str) -> ::anyhow::Result<Vec<IndexDefinition>> {
+    let file = match File::open(definitions_path(table_name)) {
+        Ok(file) => file,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err.into()),
+    };
+    let mut definitions: Vec<IndexDefinition> = vec![];
+    for line in BufReader::new(file).lines() {
+        let line_str = line?;
+        let parts: Vec<&str> = line_str.split(' ').collect();
+        definitions.push(IndexDefinition {
+            name: parts[0].to_string(),
+            columns: parts[1].split(',').map(String::from).collect(),
+            unique: parts.get(2).is_some_and(|flags| flags.split(',').any(|flag| flag == "unique")),
+        });
+    }
+    Ok(definitions)
+}
+
+// Replaces a table's list of CREATE INDEX indexes
+pub fn write_definitions(table_name: &str, definitions: &[IndexDefinition]) -> ::anyhow::Result<()> {
+    if definitions.is_empty() {
+        return match fs::remove_file(definitions_path(table_name)) {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        };
+    }
+    let lines: String = definitions
+        .iter()
+        .map(|definition| format!("{} {} {}\n", definition.name, definition.columns.join(","), if definition.unique { "unique" } else { "" }))
+        .collect();
+    fs::write(definitions_path(table_name), lines)?;
+    Ok(())
+}
+
+pub fn column_position(tabledef: &TableDefinition, column_name: &str) -> Option<usize> {
+    tabledef.column_defs.iter().position(|col_def| col_def.name == column_name)
+}
+
+// The rows of a table that can match a condition, in blob order, when the
+// condition pins down the first columns of one of the table's indexes: =
+// on some of them, then optionally a range on the next one. The index
+// matching the most columns is used, and the condition still has to be
+// checked against the rows. None means there's no index to use and every
+// row has to be read.
+pub fn lookup(tabledef: &TableDefinition, qualifier: &str, condition: &LogicExpression) -> ::anyhow::Result<Option<Vec<u64>>> {
+    let mut best: Option<(usize, IndexRange)> = None;
+    for index in TableIndex::for_table(tabledef)? {
+        let mut prefix: Vec<DataValue> = vec![];
+        let mut range: (Bound<DataValue>, Bound<DataValue>) = (Bound::Unbounded, Bound::Unbounded);
+        for column in &index.columns {
+            let (lower, upper) = column_bounds(condition, &tabledef.column_defs[*column], qualifier);
+            match (&lower, &upper) {
+                (Bound::Included(low), Bound::Included(high)) if compare_keys(std::slice::from_ref(low), std::slice::from_ref(high)) == Ordering::Equal => {
+                    prefix.push(low.clone());
+                }
+                _ => {
+                    range = (lower, upper);
+                    break;
+                }
+            }
+        }
+
+        // Equalities count for more than a range
+        let score = prefix.len() * 2 + usize::from(!matches!(range, (Bound::Unbounded, Bound::Unbounded)));
+        if score == 0 || best.as_ref().is_some_and(|(best_score, ..)| *best_score >= score) {
+            continue;
+        }
+        let with_prefix = |bound: Bound<DataValue>| match bound {
+            Bound::Included(value) => Bound::Included(prefix.iter().cloned().chain([value]).collect()),
+            Bound::Excluded(value) => Bound::Excluded(prefix.iter().cloned().chain([value]).collect()),
+            Bound::Unbounded if prefix.is_empty() => Bound::Unbounded,
+            Bound::Unbounded => Bound::Included(prefix.clone()),
+        };
+        let (lower, upper) = (with_prefix(range.0), with_prefix(range.1));
+        best = Some((score, IndexRange { index, lower, upper }));
+    }
+
+    let Some((_, IndexRange { index, lower, upper })) = best else {
+        return Ok(None);
+    };
+    let mut tree = index.open(tabledef)?;
+    let mut row_ids = tree.range(lower.as_ref().map(Vec::as_slice), upper.as_ref().map(Vec::as_slice), None)?;
+    row_ids.sort();
+    Ok(Some(row_ids))
+}
+
+// Keys of an index to read
+struct IndexRange {
+    index: TableIndex,
+    lower: Bound<Vec<DataValue>>,
+    upper: Bound<Vec<DataValue>>,
+}
+
+// The tightest bounds the condition puts on a column. A constant that can't
+// be compared with the column's values is left out, checking the rows one
+// by one gives the error (or the match) it would without the index.
+fn column_bounds(condition: &LogicExpression, col_def: &ColumnDefinition, qualifier: &str) -> (Bound<DataValue>, Bound<DataValue>) {
+    let names = [col_def.name.clone(), format!("{}.{}", qualifier, col_def.name)];
+    let mut lower: Bound<DataValue> = Bound::Unbounded;
+    let mut upper: Bound<DataValue> = Bound::Unbounded;
+    let comparisons = column_comparisons(condition, &names).into_iter().filter(|(_, value)| comparable(&col_def.data_type, value));
+    for (operator, value) in comparisons {
+        match operator {
+            LogicalOperator::Equal => {
+                lower = tighter(lower, Bound::Included(value.clone()), Ordering::Greater);
+                upper = tighter(upper, Bound::Included(value), Ordering::Less);
+            }
+            LogicalOperator::GreaterThan => lower = tighter(lower, Bound::Excluded(value), Ordering::Greater),
+            LogicalOperator::GreaterThanEqualTo => lower = tighter(lower, Bound::Included(value), Ordering::Greater),
+            LogicalOperator::LessThan => upper = tighter(upper, Bound::Excluded(value), Ordering::Less),
+            LogicalOperator::LessThanEqualTo => upper = tighter(upper, Bound::Included(value), Ordering::Less),
+            _ => {}
+        }
+    }
+    (lower, upper)
+}
+
+// column <op> constant comparisons ANDed together at the top of a condition,
+// flipped around if the constant comes first
+fn column_comparisons(condition: &LogicExpression, names: &[String]) -> Vec<(LogicalOperator, DataValue)> {
+    match condition {
+        LogicExpression::And(left, right) => {
+            let mut comparisons = column_comparisons(left, names);
+            comparisons.extend(column_comparisons(right, names));
+            comparisons
+        }
+        LogicExpression::Comparison(comparison) => {
+            let (operator, constant) = match (&comparison.left_hand, &comparison.right_hand) {
+                (ValueExpression::ColumnName(name), constant) if names.contains(name) => (comparison.operator.clone(), constant),
+                (constant, ValueExpression::ColumnName(name)) if names.contains(name) => {
+                    let operator = match comparison.operator {
+                        LogicalOperator::GreaterThan => LogicalOperator::LessThan,
+                        LogicalOperator::GreaterThanEqualTo => LogicalOperator::LessThanEqualTo,
+                        LogicalOperator::LessThan => LogicalOperator::GreaterThan,
+                        LogicalOperator::LessThanEqualTo => LogicalOperator::GreaterThanEqualTo,
+                        ref operator => operator.clone(),
+                    };
+                    (operator, constant)
+                }
+                _ => return vec![],
+            };
+            match constant.is_evaluatable().then(|| constant.evaluate()) {
+                Some(Ok(value)) if value != DataValue::Null => vec![(operator, value)],
+                _ => vec![],
+            }
+        }
+        _ => vec![],
+    }
+}
+
+// Whether values of the type can be compared with the value at all
+fn comparable(data_type: &Datatype, value: &DataValue) -> bool {
+    let sample = match data_type {
+        Datatype::Boolean => DataValue::BoolValue(false),
+        Datatype::SmallInt | Datatype::Integer | Datatype::BigInt => DataValue::I64Value(0),
+        Datatype::Real | Datatype::DoublePrecision => DataValue::F64Value(0.0),
+        Datatype::Numeric(_) => DataValue::NumericValue(0, 0),
+        Datatype::Date => DataValue::DateValue(0),
+        Datatype::Timestamp => DataValue::TimestampValue(0),
+        Datatype::CharacterVarying => DataValue::StringValue(String::new()),
+    };
+    DataValue::coerce(sample, value.clone()).is_ok_and(|(sample, value)| sample.compare(&value).is_some())
+}
+
+// The stricter of two bounds, the one further in the wanted direction
+fn tighter(current: Bound<DataValue>, new: Bound<DataValue>, wanted: Ordering) -> Bound<DataValue> {
+    let (current_value, new_value) = match (&current, &new) {
+        (Bound::Unbounded, _) => return new,
+        (_, Bound::Unbounded) => return current,
+        (Bound::Included(current_value) | Bound::Excluded(current_value), Bound::Included(new_value) | Bound::Excluded(new_value)) => {
+            (current_value, new_value)
+        }
+    };
+    match compare_keys(std::slice::from_ref(new_value), std::slice::from_ref(current_value)) {
+        ordering if ordering == wanted => new,
+        Ordering::Equal if matches!(new, Bound::Excluded(_)) => new,
+        _ => current,
+    }
+}
+
+#[cfg(test)]
+use crate::{run_statements, select_rows, with_data_dir, Command};
+
+#[test]
+fn create_and_drop_indexes() {
+    with_data_dir(|| {
+        let values: Vec<String> = (1..=60).map(|a| format!("({}, 'b{}', {})", a, a % 5, a * 2)).collect();
+        let insert = format!("INSERT INTO t (a, b, c) VALUES {};", values.join(", "));
+        let responses = run_statements(&["CREATE TABLE t (a int, b varchar 10, c int);", &insert]);
+        assert!(responses.iter().all(|response| !response.starts_with("ERROR")), "{:?}", responses);
+
+        // The rows an index picks out for a WHERE clause, None if no index
+        // can be used
+        let candidates = |condition: &str| -> Option<usize> {
+            let Command::Select(command) = Command::from_string(format!("SELECT a FROM t WHERE {};", condition)).unwrap() else {
+                unreachable!()
+            };
+            lookup(&read_tabledef(String::from("t")).unwrap(), "t", &command.logic_expression.unwrap()).unwrap().map(|row_ids| row_ids.len())
+        };
+        let selected = |condition: &str| -> Vec<String> {
+            let mut values: Vec<String> = select_rows(&format!("SELECT a FROM t WHERE {};", condition)).into_iter().map(|row| row[0].clone()).collect();
+            values.sort_by_key(|value| value.parse::<i64>().unwrap());
+            values
+        };
+
+        assert_eq!(candidates("b = 'b1'"), None);
+        assert_eq!(run_statements(&["CREATE INDEX t_b_c ON t (b, c);"]), vec!["Index Created"]);
+        assert!(fs::metadata("./data/indexes/t_b_c").is_ok());
+        assert!(run_statements(&["CREATE INDEX t_b_c ON t (a);"])[0].contains("already exists"));
+        assert!(run_statements(&["CREATE INDEX t_d ON t (d);"])[0].contains("does not exist"));
+
+        // = on the first column, then a range on the next
+        assert_eq!(candidates("b = 'b1'"), Some(12));
+        assert_eq!(candidates("b = 'b1' AND c > 100"), Some(2));
+        assert_eq!(selected("b = 'b1' AND c > 100"), vec!["51", "56"]);
+        // Not on the first column, so every row is read
+        assert_eq!(candidates("c = 4"), None);
+
+        // A unique index can't be made over duplicates, and isn't left
+        // behind
+        assert!(run_statements(&["CREATE UNIQUE INDEX t_b ON t (b);"])[0].contains("duplicate key"));
+        assert!(fs::metadata("./data/indexes/t_b").is_err());
+        assert_eq!(definitions("t").unwrap().len(), 1);
+
+        assert_eq!(run_statements(&["CREATE UNIQUE INDEX t_c ON t (c);"]), vec!["Index Created"]);
+        assert!(run_statements(&["INSERT INTO t (a, b, c) VALUES (61, 'x', 4);"])[0].contains("duplicate key value violates unique constraint 't_c'"));
+        assert!(run_statements(&["UPDATE t SET c = 6 WHERE a = 1;"])[0].contains("duplicate key"));
+        // NULLs are never duplicates
+        assert_eq!(run_statements(&["INSERT INTO t (a, b) VALUES (61, 'x'), (62, 'x');"]), vec!["Data Inserted"]);
+
+        // Both indexes follow INSERT, UPDATE and DELETE
+        let responses = run_statements(&[
+            "UPDATE t SET b = 'b1', c = 1000 WHERE a = 2;",
+            "DELETE FROM t WHERE b = 'b1' AND c < 50;",
+            "INSERT INTO t (a, b, c) VALUES (63, 'b1', 999);",
+        ]);
+        assert_eq!(responses, vec!["1 Rows Updated", "5 Rows Deleted", "Data Inserted"]);
+        assert_eq!(candidates("c = 1000"), Some(1));
+        assert_eq!(selected("c = 1000"), vec!["2"]);
+        assert_eq!(selected("b = 'b1'"), vec!["2", "26", "31", "36", "41", "46", "51", "56", "63"]);
+        assert_eq!(candidates("b = 'b2'"), Some(11));
+        assert!(selected("c = 4").is_empty());
+
+        assert_eq!(run_statements(&["DROP INDEX t_b_c;"]), vec!["Index Dropped"]);
+        assert!(fs::metadata("./data/indexes/t_b_c").is_err());
+        assert_eq!(candidates("b = 'b1'"), None);
+        assert!(run_statements(&["DROP INDEX t_b_c;"])[0].contains("does not exist"));
+        assert_eq!(definitions("t").unwrap().iter().map(|definition| definition.name.clone()).collect::<Vec<String>>(), vec!["t_c"]);
+
+        // A primary key's index goes with the table
+        run_statements(&["CREATE TABLE p (id int PRIMARY KEY);"]);
+        assert!(run_statements(&["DROP INDEX p_pkey;"])[0].contains("constraint 'p_pkey' requires it"));
+    });
+}
+
+#[test]
+fn index_names_are_shared() {
+    with_data_dir(|| {
+        let responses = run_statements(&["CREATE TABLE t (id int PRIMARY KEY, v int UNIQUE);", "INSERT INTO t (id, v) VALUES (1, 10), (2, 20);"]);
+        assert!(responses.iter().all(|response| !response.starts_with("ERROR")), "{:?}", responses);
+        let index_file = |name: &str| fs::read(format!("./data/indexes/{}", name)).unwrap();
+
+        // A CREATE INDEX first keeps its name from a later table's primary
+        // key, UNIQUE constraint or new column
+        assert_eq!(run_statements(&["CREATE INDEX t2_pkey ON t (v);", "CREATE INDEX t2_w_key ON t (id);"]), vec!["Index Created"; 2]);
+        let (pkey, w_key) = (index_file("t2_pkey"), index_file("t2_w_key"));
+        assert_eq!(run_statements(&["CREATE TABLE t2 (id int PRIMARY KEY);"]), vec!["ERROR: relation 't2_pkey' already exists"]);
+        assert_eq!(run_statements(&["CREATE TABLE t2 (id int, w int UNIQUE);"]), vec!["ERROR: relation 't2_w_key' already exists"]);
+        assert!(fs::metadata("./data/tabledefs/t2").is_err());
+        assert_eq!(run_statements(&["CREATE TABLE t2 (id int);"]), vec!["Table Created"]);
+        assert_eq!(run_statements(&["ALTER TABLE t2 ADD COLUMN w int UNIQUE;"]), vec!["ERROR: relation 't2_w_key' already exists"]);
+        assert_eq!(run_statements(&["ALTER TABLE t2 ADD COLUMN k int PRIMARY KEY;"]), vec!["ERROR: relation 't2_pkey' already exists"]);
+        assert_eq!((index_file("t2_pkey"), index_file("t2_w_key")), (pkey, w_key));
+        assert_eq!(select_rows("SELECT id FROM t WHERE v = 20;"), vec![vec!["2"]]);
+
+        // And the other way round, a table's indexes keep theirs from CREATE
+        // INDEX on any table
+        let (pkey, v_key) = (index_file("t_pkey"), index_file("t_v_key"));
+        assert_eq!(run_statements(&["CREATE INDEX t_pkey ON t2 (id);"]), vec!["ERROR: relation 't_pkey' already exists"]);
+        assert_eq!(run_statements(&["CREATE INDEX t_v_key ON t (id);"]), vec!["ERROR: relation 't_v_key' already exists"]);
+        assert_eq!((index_file("t_pkey"), index_file("t_v_key")), (pkey, v_key));
+        assert!(definitions("t2").unwrap().is_empty());
+        assert_eq!(select_rows("SELECT v FROM t WHERE id = 1;"), vec![vec!["10"]]);
+    });
+}
+
+#[test]
+fn constants_of_another_type() {
+    with_data_dir(|| {
+        let responses = run_statements(&[
+            "CREATE TABLE keyed (v varchar 10 PRIMARY KEY, n int);",
+            "CREATE TABLE plain (v varchar 10, n int);",
+            "CREATE INDEX keyed_n ON keyed (n);",
+            "INSERT INTO keyed (v, n) VALUES ('1', 1), ('7', 2), ('9', 3);",
+            "INSERT INTO plain (v, n) VALUES ('1', 1), ('7', 2), ('9', 3);",
+        ]);
+        assert!(responses.iter().all(|response| !response.starts_with("ERROR")), "{:?}", responses);
+
+        // The index isn't used for a constant the column can't be compared
+        // with, so the query fails the same way it does without one
+        for condition in ["v > 5", "v = 7", "n = 'x'"] {
+            let keyed = run_statements(&[&format!("SELECT v FROM keyed WHERE {};", condition)]);
+            let plain = run_statements(&[&format!("SELECT v FROM plain WHERE {};", condition)]);
+            assert_eq!(keyed, plain, "{}", condition);
+            assert!(keyed[0].contains("differing datatypes"), "{}: {:?}", condition, keyed);
+        }
+
+        // Other numbers are compared with an integer column's values
+        let keyed = |condition: &str| -> Vec<Vec<String>> { select_rows(&format!("SELECT v FROM keyed WHERE {};", condition)) };
+        assert_eq!(keyed("n = 2.0"), vec![vec!["7"]]);
+        assert_eq!(keyed("n > 1.5 AND n < 2.5"), vec![vec!["7"]]);
+        assert_eq!(keyed("v >= '7'").len(), 2);
+    });
+}
@@ -0,0 +1,554 @@
+use anyhow::anyhow;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+
+use squirrel_core::parser::command::{
+    Comparison, DataValue, Join, JoinType, LogicExpression, LogicalOperator, SelectCommand, SubqueryResult,
+    ValueExpression,
+};
+
+use crate::cte::CteTable;
+use crate::index;
+use crate::{execute_select, read_tabledef, Datatype, TableDefinition};
+
+// A row of the FROM clause, keyed by qualified column name (alias.column)
+pub type Row = HashMap<String, ValueExpression>;
+
+// A table in the FROM clause and the name its columns are qualified with,
+// which is its alias if it has one. WITH queries are read from memory.
+#[derive(Clone)]
+pub struct ScopeTable {
+    pub qualifier: String,
+    pub tabledef: TableDefinition,
+    rows: Option<Rc<Vec<Vec<DataValue>>>>,
+}
+
+// What a query can see besides its own tables: the WITH queries around it
+// and, for a subquery, the tables of each query around it (innermost first)
+// and the values of their current rows. Outer columns are swapped for
+// their values when the subquery's expressions are qualified.
+#[derive(Clone, Default)]
+pub struct QueryContext {
+    pub ctes: HashMap<String, Rc<CteTable>>,
+    outer_tables: Vec<Vec<ScopeTable>>,
+    outer_row: Row,
+    // Set when a column of an outer query is used, so the results can't be
+    // reused for the next outer row. The outer queries' flags are set too
+    // when a column comes from further out than them.
+    correlated: Rc<Cell<bool>>,
+    outer_correlated: Vec<Rc<Cell<bool>>>,
+}
+
+pub struct Scope {
+    pub tables: Vec<ScopeTable>,
+    context: QueryContext,
+    subquery_results: RefCell<HashMap<String, SubqueryResult>>,
+}
+
+impl Scope {
+    pub fn new(command: &SelectCommand, context: QueryContext) -> ::anyhow::Result<Scope> {
+        let mut scope = Scope { tables: vec![], context, subquery_results: RefCell::new(HashMap::new()) };
+        scope.add_table(&command.table_name, &command.table_alias)?;
+        for join in &command.joins {
+            scope.add_table(&join.table_name, &join.table_alias)?;
+        }
+
+        // The query's own tables hide outer ones with the same name
+        let tables = &scope.tables;
+        scope.context.outer_row.retain(|name, _| {
+            let qualifier = name.split_once('.').map(|(qualifier, _)| qualifier).unwrap_or_default();
+            !tables.iter().any(|table| table.qualifier == qualifier)
+        });
+        Ok(scope)
+    }
+
+    // The context of a subquery run for one of this query's rows
+    fn subquery_context(&self, row: &Row) -> QueryContext {
+        let mut outer_tables = vec![self.tables.clone()];
+        outer_tables.extend(self.context.outer_tables.iter().cloned());
+        let mut outer_correlated = vec![self.context.correlated.clone()];
+        outer_correlated.extend(self.context.outer_correlated.iter().cloned());
+        let mut outer_row = self.context.outer_row.clone();
+        outer_row.extend(row.iter().map(|(name, value)| (name.clone(), value.clone())));
+        QueryContext {
+            ctes: self.context.ctes.clone(),
+            outer_tables,
+            outer_row,
+            correlated: Rc::new(Cell::new(false)),
+            outer_correlated,
+        }
+    }
+
+    // WITH queries hide tables with the same name
+    fn add_table(&mut self, table_name: &str, alias: &Option<String>) -> ::anyhow::Result<()> {
+        let qualifier = alias.clone().unwrap_or_else(|| table_name.to_string());
+        if self.tables.iter().any(|table| table.qualifier == qualifier) {
+            return Err(anyhow!("ERROR: table name '{}' specified more than once", qualifier));
+        }
+        let table = match self.context.ctes.get(table_name) {
+            // A query without FROM, which has one row and no columns
+            None if table_name.is_empty() => ScopeTable {
+                qualifier,
+                tabledef: TableDefinition {
+                    name: String::new(),
+                    column_defs: vec![],
+                    foreign_keys: vec![],
+                    unique_constraints: vec![],
+                    check_constraints: vec![],
+                },
+                rows: Some(Rc::new(vec![vec![]])),
+            },
+            Some(cte) => ScopeTable { qualifier, tabledef: cte.tabledef.clone(), rows: Some(cte.rows.clone()) },
+            None => {
+                let tabledef = read_tabledef(table_name.to_string())
+                    .map_err(|_| anyhow!("ERROR: relation '{}' does not exist", table_name))?;
+                ScopeTable { qualifier, tabledef, rows: None }
+            }
+        };
+        self.tables.push(table);
+        Ok(())
+    }
+
+    pub fn find_table(&self, qualifier: &str) -> ::anyhow::Result<&ScopeTable> {
+        self.tables
+            .iter()
+            .find(|table| table.qualifier == qualifier)
+            .ok_or_else(|| anyhow!("ERROR: missing FROM-clause entry for table '{}'", qualifier))
+    }
+
+    // Qualifies a column reference with its table. Only the first `visible`
+    // tables can be referred to, i.e. a join's ON can't see later joins.
+    // Subqueries look through the queries around them if they need to.
+    pub fn qualify(&self, name: &str, visible: usize) -> ::anyhow::Result<String> {
+        if let Some(qualified) = find_column(&self.tables[..visible], name)? {
+            return Ok(qualified);
+        }
+        for (level, tables) in self.context.outer_tables.iter().enumerate() {
+            if let Some(qualified) = find_column(tables, name)? {
+                self.context.correlated.set(true);
+                for correlated in &self.context.outer_correlated[..level] {
+                    correlated.set(true);
+                }
+                return Ok(qualified);
+            }
+        }
+        match name.split_once('.') {
+            Some((qualifier, _)) if !self.tables.iter().any(|table| table.qualifier == qualifier) => {
+                Err(anyhow!("ERROR: missing FROM-clause entry for table '{}'", qualifier))
+            }
+            _ => Err(anyhow!("ERROR: column '{}' does not exist", name)),
+        }
+    }
+
+    pub fn qualify_expression(&self, expression: &mut ValueExpression) -> ::anyhow::Result<()> {
+        expression.rename_columns(&|name| self.qualify(name, self.tables.len()))?;
+        expression.fill_values(&self.context.outer_row);
+        Ok(())
+    }
+
+    pub fn qualify_logic_expression(&self, logic_expression: &mut LogicExpression, visible: usize) -> ::anyhow::Result<()> {
+        for expression in logic_expression.value_expressions_mut() {
+            expression.rename_columns(&|name| self.qualify(name, visible))?;
+            expression.fill_values(&self.context.outer_row);
+        }
+        Ok(())
+    }
+
+    // Evaluates an expression for a row, running any subqueries in it
+    pub fn evaluate(&self, expression: &ValueExpression, row: &Row) -> ::anyhow::Result<DataValue> {
+        let mut expression = expression.clone();
+        expression.fill_values(row);
+        if expression.has_subqueries() {
+            expression.resolve_subqueries(&mut |subquery| self.run_subquery(subquery, row))?;
+        }
+        expression.evaluate()
+    }
+
+    pub fn evaluate_condition(&self, condition: &LogicExpression, row: &Row) -> ::anyhow::Result<bool> {
+        let mut condition = condition.clone();
+        condition.fill_values(row.clone())?;
+        if condition.has_subqueries() {
+            condition.resolve_subqueries(&mut |subquery| self.run_subquery(subquery, row))?;
+        }
+        condition.evaluate()
+    }
+
+    // Subqueries that don't use the outer row are only run once
+    fn run_subquery(&self, subquery: &SelectCommand, row: &Row) -> ::anyhow::Result<SubqueryResult> {
+        let key = format!("{:?}", subquery);
+        if let Some(result) = self.subquery_results.borrow().get(&key) {
+            return Ok(result.clone());
+        }
+        let context = self.subquery_context(row);
+        let correlated = context.correlated.clone();
+        let result = execute_select(subquery, context)?;
+        let result = (result.column_names.len(), result.rows);
+        if !correlated.get() {
+            self.subquery_results.borrow_mut().insert(key, result.clone());
+        }
+        Ok(result)
+    }
+
+    // A WITH query's column only has a type if all its values are of it
+    pub fn column_type(&self, qualified_name: &str) -> Option<(usize, &Datatype)> {
+        let (qualifier, column) = qualified_name.split_once('.')?;
+        let idx = self.tables.iter().position(|table| table.qualifier == qualifier)?;
+        let table = &self.tables[idx];
+        let col_idx = table.tabledef.column_defs.iter().position(|col_def| col_def.name == column)?;
+        let data_type = &table.tabledef.column_defs[col_idx].data_type;
+        if let Some(rows) = &table.rows {
+            if rows.iter().any(|row| row[col_idx].datatype().is_some_and(|value_type| value_type != *data_type)) {
+                return None;
+            }
+        }
+        Some((idx, data_type))
+    }
+
+    // Reads a table's rows, only the ones at row_ids if they're given
+    fn scan(&self, idx: usize, row_ids: Option<Vec<u64>>) -> ::anyhow::Result<Box<dyn Iterator<Item = ::anyhow::Result<Row>> + '_>> {
+        let table = &self.tables[idx];
+        let keys: Vec<String> =
+            table.tabledef.column_defs.iter().map(|col_def| format!("{}.{}", table.qualifier, col_def.name)).collect();
+        if let Some(rows) = &table.rows {
+            return Ok(Box::new(rows.iter().map(move |values| {
+                Ok(keys.iter().cloned().zip(values.iter().cloned().map(ValueExpression::computed_value)).collect())
+            })));
+        }
+        // A table nothing's been inserted into yet has no blob
+        let file = match File::open(format!("./data/blobs/{}", table.tabledef.name)) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Box::new(std::iter::empty())),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(Box::new(TableScan {
+            tabledef: &table.tabledef,
+            keys,
+            reader: BufReader::new(file),
+            buf: vec![0; table.tabledef.get_byte_size()],
+            row_ids: row_ids.map(Vec::into_iter),
+        }))
+    }
+
+    // The side of an outer join without a match, all NULL
+    fn null_row(&self, tables: &[ScopeTable]) -> Row {
+        tables
+            .iter()
+            .flat_map(|table| {
+                table.tabledef.column_defs.iter().map(|col_def| {
+                    (format!("{}.{}", table.qualifier, col_def.name), ValueExpression::DataValue(DataValue::Null))
+                })
+            })
+            .collect()
+    }
+}
+
+struct TableScan<'a> {
+    tabledef: &'a TableDefinition,
+    keys: Vec<String>,
+    reader: BufReader<File>,
+    buf: Vec<u8>,
+    row_ids: Option<std::vec::IntoIter<u64>>,
+}
+
+impl Iterator for TableScan<'_> {
+    type Item = ::anyhow::Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(row_ids) = &mut self.row_ids {
+            let offset = row_ids.next()? * self.buf.len() as u64;
+            if let Err(err) = self.reader.seek(SeekFrom::Start(offset)) {
+                return Some(Err(err.into()));
+            }
+        }
+        self.reader.read_exact(self.buf.as_mut_slice()).ok()?;
+        Some(self.tabledef.decode_row(&self.buf).map(|values| {
+            let values = values
+                .into_iter()
+                .zip(&self.tabledef.column_defs)
+                .map(|(value, col_def)| ValueExpression::column_value(value, &col_def.data_type));
+            self.keys.iter().cloned().zip(values).collect()
+        }))
+    }
+}
+
+// Rows of the FROM clause. A single table is streamed, joins are done one
+// table at a time against the rows joined so far, using a hash join when
+// the ON condition has column = column equalities and a nested loop
+// otherwise. The first table is read through an index when the WHERE
+// clause pins down its primary key.
+pub fn from_rows<'a>(
+    scope: &'a Scope,
+    joins: &[Join],
+    where_clause: Option<&LogicExpression>,
+) -> ::anyhow::Result<Box<dyn Iterator<Item = ::anyhow::Result<Row>> + 'a>> {
+    let first = &scope.tables[0];
+    let first_rows = match (where_clause, &first.rows) {
+        (Some(condition), None) => index::lookup(&first.tabledef, &first.qualifier, condition)?,
+        _ => None,
+    };
+    let mut rows: Box<dyn Iterator<Item = ::anyhow::Result<Row>> + 'a> = scope.scan(0, first_rows)?;
+
+    for (idx, join) in joins.iter().enumerate() {
+        let right_idx = idx + 1;
+        let mut condition = join.condition.clone();
+        if let Some(condition) = &mut condition {
+            scope.qualify_logic_expression(condition, right_idx + 1)?;
+            if condition.value_expressions().iter().any(|expression| !expression.aggregates().is_empty()) {
+                return Err(anyhow!("ERROR: aggregate functions are not allowed in JOIN conditions"));
+            }
+        }
+
+        let right = scope.scan(right_idx, None)?.collect::<::anyhow::Result<Vec<Row>>>()?;
+        let left_nulls = scope.null_row(&scope.tables[..right_idx]);
+        let right_nulls = scope.null_row(&scope.tables[right_idx..=right_idx]);
+
+        let mut hash_keys: Vec<(String, String)> = vec![];
+        if let Some(condition) = &condition {
+            equi_join_keys(scope, condition, right_idx, &mut hash_keys);
+        }
+        let joined = if hash_keys.is_empty() {
+            let all_rows: Vec<usize> = (0..right.len()).collect();
+            join_rows(scope, rows, &right, join.join_type, &condition, left_nulls, right_nulls, |_| all_rows.clone())?
+        } else {
+            let (left_keys, right_keys): (Vec<String>, Vec<String>) = hash_keys.into_iter().unzip();
+            let mut table: HashMap<String, Vec<usize>> = HashMap::new();
+            for (row_idx, row) in right.iter().enumerate() {
+                if let Some(key) = hash_key(row, &right_keys) {
+                    table.entry(key).or_default().push(row_idx);
+                }
+            }
+            join_rows(scope, rows, &right, join.join_type, &condition, left_nulls, right_nulls, |row| {
+                hash_key(row, &left_keys).and_then(|key| table.get(&key).cloned()).unwrap_or_default()
+            })?
+        };
+        rows = Box::new(joined.into_iter().map(Ok));
+    }
+    Ok(rows)
+}
+
+// Joins the left rows with the right rows that `candidates` picks for each
+// of them and that pass the condition, adding NULL rows for outer joins.
+#[allow(clippy::too_many_arguments)]
+fn join_rows(
+    scope: &Scope,
+    left: impl Iterator<Item = ::anyhow::Result<Row>>,
+    right: &[Row],
+    join_type: JoinType,
+    condition: &Option<LogicExpression>,
+    left_nulls: Row,
+    right_nulls: Row,
+    candidates: impl Fn(&Row) -> Vec<usize>,
+) -> ::anyhow::Result<Vec<Row>> {
+    let mut rows: Vec<Row> = vec![];
+    let mut right_matched = vec![false; right.len()];
+
+    for left_row in left {
+        let left_row = left_row?;
+        let mut matched = false;
+        for right_idx in candidates(&left_row) {
+            let mut row = left_row.clone();
+            row.extend(right[right_idx].clone());
+            if let Some(condition) = condition {
+                if !scope.evaluate_condition(condition, &row)? {
+                    continue;
+                }
+            }
+            matched = true;
+            right_matched[right_idx] = true;
+            rows.push(row);
+        }
+        if !matched && matches!(join_type, JoinType::Left | JoinType::Full) {
+            let mut row = left_row;
+            row.extend(right_nulls.clone());
+            rows.push(row);
+        }
+    }
+
+    if matches!(join_type, JoinType::Right | JoinType::Full) {
+        for (right_row, matched) in right.iter().zip(right_matched) {
+            if !matched {
+                let mut row = left_nulls.clone();
+                row.extend(right_row.clone());
+                rows.push(row);
+            }
+        }
+    }
+    Ok(rows)
+}
+
+// The qualified name of a column in one of the tables, if it's there
+fn find_column(tables: &[ScopeTable], name: &str) -> ::anyhow::Result<Option<String>> {
+    if let Some((qualifier, column)) = name.split_once('.') {
+        let Some(table) = tables.iter().find(|table| table.qualifier == qualifier) else {
+            return Ok(None);
+        };
+        if table.tabledef.get_column(column).is_none() {
+            return Err(anyhow!("ERROR: column '{}' does not exist", name));
+        }
+        return Ok(Some(name.to_string()));
+    }
+
+    let mut matches = tables.iter().filter(|table| table.tabledef.get_column(name).is_some());
+    match (matches.next(), matches.next()) {
+        (Some(table), None) => Ok(Some(format!("{}.{}", table.qualifier, name))),
+        (Some(_), Some(_)) => Err(anyhow!("ERROR: column reference '{}' is ambiguous", name)),
+        (None, _) => Ok(None),
+    }
+}
+
+// Finds left.column = right.column equalities ANDed together in a join
+// condition. The whole condition is still checked for every match, so
+// anything else in it is fine.
+fn equi_join_keys(scope: &Scope, condition: &LogicExpression, right_idx: usize, keys: &mut Vec<(String, String)>) {
+    match condition {
+        LogicExpression::And(left, right) => {
+            equi_join_keys(scope, left, right_idx, keys);
+            equi_join_keys(scope, right, right_idx, keys);
+        }
+        LogicExpression::Comparison(Comparison {
+            left_hand: ValueExpression::ColumnName(left),
+            right_hand: ValueExpression::ColumnName(right),
+            operator: LogicalOperator::Equal,
+        }) => {
+            let (Some((left_table, left_type)), Some((right_table, right_type))) = (scope.column_type(left), scope.column_type(right)) else {
+                return;
+            };
+            if !hashable_together(left_type, right_type) {
+                return;
+            }
+            if left_table < right_idx && right_table == right_idx {
+                keys.push((left.clone(), right.clone()));
+            } else if right_table < right_idx && left_table == right_idx {
+                keys.push((right.clone(), left.clone()));
+            }
+        }
+        _ => {}
+    }
+}
+
+// Whether equal values of the two types always hash the same
+fn hashable_together(left: &Datatype, right: &Datatype) -> bool {
+    let integer = |datatype: &Datatype| matches!(datatype, Datatype::SmallInt | Datatype::Integer | Datatype::BigInt);
+    let float = |datatype: &Datatype| matches!(datatype, Datatype::Real | Datatype::DoublePrecision);
+    match (left, right) {
+        (Datatype::Numeric(_), Datatype::Numeric(_)) => true,
+        _ => (integer(left) && integer(right)) || (float(left) && float(right)) || left == right,
+    }
+}
+
+// NULL never equals anything, so rows with a NULL key have no hash key
+fn hash_key(row: &Row, columns: &[String]) -> Option<String> {
+    let mut values: Vec<DataValue> = vec![];
+    for column in columns {
+        let value = match row.get(column)? {
+            ValueExpression::DataValue(DataValue::Null) | ValueExpression::TypedValue(DataValue::Null, _) => return None,
+            ValueExpression::DataValue(value) | ValueExpression::TypedValue(value, _) => normalize_value(value),
+            _ => return None,
+        };
+        values.push(value);
+    }
+    Some(format!("{:?}", values))
+}
+
+// Makes equal values of the same type look the same, for hashing
+pub fn normalize_value(value: &DataValue) -> DataValue {
+    match value {
+        DataValue::NumericValue(val, scale) => {
+            // 1.50 and 1.5 are equal
+            let (mut val, mut scale) = (*val, *scale);
+            while scale > 0 && val % 10 == 0 {
+                val /= 10;
+                scale -= 1;
+            }
+            DataValue::NumericValue(val, scale)
+        }
+        DataValue::F64Value(val) if *val == 0.0 => DataValue::F64Value(0.0),
+        value => value.clone(),
+    }
+}
+
+#[cfg(test)]
+use crate::{run_statements, select_rows, with_data_dir};
+
+#[test]
+fn hash_and_nested_loop_joins() {
+    with_data_dir(|| {
+        let responses = run_statements(&[
+            "CREATE TABLE l (id int, k int);",
+            "INSERT INTO l (id, k) VALUES (1, 1), (2, 2), (3, NULL), (4, 2);",
+            "CREATE TABLE r (k int, v varchar 5);",
+            "INSERT INTO r (k, v) VALUES (2, 'b'), (NULL, 'n'), (5, 'e'), (2, 'c');",
+        ]);
+        assert!(responses.iter().all(|response| !response.starts_with("ERROR")), "{:?}", responses);
+
+        let rows = |rows: &[(&str, &str)]| -> Vec<Vec<String>> {
+            let mut rows: Vec<Vec<String>> = rows.iter().map(|(id, v)| vec![id.to_string(), v.to_string()]).collect();
+            rows.sort();
+            rows
+        };
+        let inner = [("2", "b"), ("2", "c"), ("4", "b"), ("4", "c")];
+        let left_only = [("1", "NULL"), ("3", "NULL")];
+        let right_only = [("NULL", "e"), ("NULL", "n")];
+        let expected = [
+            ("INNER", rows(&inner)),
+            ("LEFT", rows(&[&inner[..], &left_only[..]].concat())),
+            ("RIGHT", rows(&[&inner[..], &right_only[..]].concat())),
+            ("FULL", rows(&[&inner[..], &left_only[..], &right_only[..]].concat())),
+        ];
+
+        // An equality is hash joined, the same condition written as a range
+        // is joined with a nested loop. NULL keys match nothing either way.
+        for condition in ["l.k = r.k", "l.k >= r.k AND l.k <= r.k"] {
+            for (join_type, expected) in &expected {
+                let query = format!("SELECT l.id, r.v FROM l {} JOIN r ON {};", join_type, condition);
+                let mut joined = select_rows(&query);
+                joined.sort();
+                assert_eq!(&joined, expected, "{}", query);
+            }
+        }
+
+        assert_eq!(select_rows("SELECT l.id, r.v FROM l CROSS JOIN r;").len(), 16);
+        let row: Row = HashMap::from([(String::from("l.k"), ValueExpression::column_value(DataValue::Null, &Datatype::Integer))]);
+        assert_eq!(hash_key(&row, &[String::from("l.k")]), None);
+    });
+}
+
+#[test]
+fn subqueries() {
+    with_data_dir(|| {
+        let responses = run_statements(&[
+            "CREATE TABLE emp (id int, dept int, salary int);",
+            "CREATE TABLE depts (id int, name varchar 10);",
+            "INSERT INTO emp (id, dept, salary) VALUES (1, 10, 100), (2, 10, 300), (3, 20, 50), (4, 20, 70), (5, NULL, 80);",
+            "INSERT INTO depts (id, name) VALUES (10, 'eng'), (20, 'ops'), (30, 'hr');",
+        ]);
+        assert!(responses.iter().all(|response| !response.starts_with("ERROR")), "{:?}", responses);
+        let values = |query: &str| -> Vec<String> { select_rows(query).into_iter().map(|row| row.join(" ")).collect() };
+
+        // Correlated scalar subqueries are run again for each outer row. With
+        // no rows they're NULL, which no salary is greater than.
+        assert_eq!(
+            values("SELECT e.id FROM emp e WHERE e.salary > (SELECT AVG(x.salary) FROM emp x WHERE x.dept = e.dept) ORDER BY e.id;"),
+            vec!["2", "4"]
+        );
+        assert_eq!(
+            values("SELECT d.name, (SELECT MAX(e.salary) FROM emp e WHERE e.dept = d.id) FROM depts d ORDER BY d.name;"),
+            vec!["eng 300", "hr NULL", "ops 70"]
+        );
+        assert!(run_statements(&["SELECT name FROM depts WHERE id = (SELECT dept FROM emp);"])[0].contains("more than one row returned by a subquery"));
+
+        assert_eq!(
+            values("SELECT d.name FROM depts d WHERE EXISTS (SELECT e.id FROM emp e WHERE e.dept = d.id) ORDER BY d.name;"),
+            vec!["eng", "ops"]
+        );
+        assert_eq!(values("SELECT d.name FROM depts d WHERE NOT EXISTS (SELECT e.id FROM emp e WHERE e.dept = d.id);"), vec!["hr"]);
+
+        // A NULL in the list means NOT IN is never true, as 30 might be it
+        assert_eq!(values("SELECT name FROM depts WHERE id IN (SELECT dept FROM emp) ORDER BY name;"), vec!["eng", "ops"]);
+        assert!(values("SELECT name FROM depts WHERE id NOT IN (SELECT dept FROM emp);").is_empty());
+        assert_eq!(values("SELECT name FROM depts WHERE id NOT IN (SELECT dept FROM emp WHERE dept IS NOT NULL);"), vec!["hr"]);
+    });
+}
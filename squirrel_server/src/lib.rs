@@ -0,0 +1,1277 @@
+use anyhow::anyhow;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::thread;
+use std::collections::{HashMap, HashSet};
+use std::cmp;
+use std::rc::Rc;
+
+pub use squirrel_core::parser::command::Command;
+use squirrel_core::parser::command::{CreateCommand, CreateIndexCommand, DropIndexCommand, DropSequenceCommand, InsertCommand, InsertSource, SelectCommand, SelectColumn, Distinct, DeleteCommand, UpdateCommand, DataValue, ValueExpression, Aggregate, WindowFunction, LogicExpression};
+use squirrel_core::functions::aggregates::Accumulator;
+
+mod alter;
+mod btree;
+mod constraint;
+mod cte;
+mod foreign_key;
+mod index;
+mod join;
+mod returning;
+mod row_changes;
+mod sequence;
+mod set_operation;
+mod sort;
+mod window;
+use constraint::Constraints;
+use index::TableIndex;
+use join::{QueryContext, Scope};
+use returning::Returning;
+use row_changes::RowChanges;
+use sort::{SortKey, Sorter, SORT_MEMORY_LIMIT};
+pub use squirrel_core::table::datatypes::Datatype;
+pub use squirrel_core::table::table_definition::{
+    CheckConstraint, ColumnDefinition, ForeignKey, Identity, ReferentialAction, TableDefinition, UniqueConstraint,
+};
+
+const BUFFER_SIZE: usize = 500;
+
+fn handle_create(mut command: CreateCommand) -> ::anyhow::Result<TableDefinition> {
+    // The old table's rows and indexes would be read with the new layout
+    if fs::metadata(format!("./data/tabledefs/{}", command.table_definition.name)).is_ok() {
+        return Err(anyhow!("ERROR: relation '{}' already exists", command.table_definition.name));
+    }
+    foreign_key::resolve(&mut command.table_definition)?;
+    let indexes = TableIndex::for_table(&command.table_definition)?;
+    for (idx, index) in indexes.iter().enumerate() {
+        if index::name_taken(&index.name)? || indexes[..idx].iter().any(|other| other.name == index.name) {
+            return Err(anyhow!("ERROR: relation '{}' already exists", index.name));
+        }
+    }
+    for definition in &command.sequences {
+        if fs::metadata(sequence::path(&definition.name)).is_ok() {
+            return Err(anyhow!("ERROR: relation '{}' already exists", definition.name));
+        }
+    }
+
+    // None of these were there, so whatever was made before a failure is
+    // removed again, and doesn't block trying again
+    let tabledef = &command.table_definition;
+    let created = (|| -> ::anyhow::Result<()> {
+        write_tabledef(tabledef)?;
+        for index in &indexes {
+            index.build(tabledef, &format!("./data/blobs/{}", tabledef.name), &index.path())?;
+        }
+        for definition in &command.sequences {
+            sequence::create(definition)?;
+        }
+        Ok(())
+    })();
+    if let Err(err) = created {
+        let _ = fs::remove_file(format!("./data/tabledefs/{}", tabledef.name));
+        for index in &indexes {
+            let _ = fs::remove_file(index.path());
+        }
+        for definition in &command.sequences {
+            let _ = sequence::remove(&definition.name);
+        }
+        return Err(err);
+    }
+
+    Ok(command.table_definition)
+}
+
+// Writes a table's definition to ./data/tabledefs/<table>, to the side
+// first so it's never half written
+fn write_tabledef(tabledef: &TableDefinition) -> ::anyhow::Result<()> {
+    let mut contents = String::new();
+    for column in &tabledef.column_defs {
+        let mut flags: Vec<String> = [(column.primary_key, "primary_key"), (column.not_null, "not_null")]
+            .into_iter()
+            .filter(|(set, _)| *set)
+            .map(|(_, flag)| flag.to_string())
+            .collect();
+        flags.extend(column.identity.map(|identity| identity.as_str().to_string()));
+        flags.extend(column.sequence.as_ref().map(|name| format!("sequence={}", name)));
+        let line = format!(
+            "{} {} {} {}{}\n",
+            column.name,
+            column.data_type,
+            column.length,
+            flags.join(","),
+            column.default.as_ref().map(|default| format!(" DEFAULT {}", default)).unwrap_or_default()
+        );
+        contents.push_str(&line);
+    }
+    // Table constraints go after the columns, starting with a keyword no
+    // column can be named
+    for foreign_key in &tabledef.foreign_keys {
+        let line = format!(
+            "FOREIGN {} {} {} {} {}\n",
+            foreign_key.name,
+            foreign_key.columns.join(","),
+            foreign_key.referenced_table,
+            foreign_key.referenced_columns.join(","),
+            foreign_key.on_delete.as_str()
+        );
+        contents.push_str(&line);
+    }
+    for unique in &tabledef.unique_constraints {
+        contents.push_str(&format!("UNIQUE {} {}\n", unique.name, unique.columns.join(",")));
+    }
+    for check in &tabledef.check_constraints {
+        contents.push_str(&format!("CHECK {} {}\n", check.name, check.expression));
+    }
+    let tmp_path = format!("./data/tmp/tabledef_{}", tabledef.name);
+    fs::write(&tmp_path, contents)?;
+    fs::rename(&tmp_path, format!("./data/tabledefs/{}", tabledef.name))?;
+    Ok(())
+}
+
+fn handle_create_index(command: CreateIndexCommand) -> ::anyhow::Result<()> {
+    let tabledef = read_tabledef(command.table_name.clone())?;
+    let columns = command
+        .columns
+        .iter()
+        .map(|column_name| {
+            index::column_position(&tabledef, column_name)
+                .ok_or_else(|| anyhow!("ERROR: column '{}' does not exist", column_name))
+        })
+        .collect::<::anyhow::Result<Vec<usize>>>()?;
+    let index = TableIndex { name: command.index_name, columns, unique: command.unique };
+    if index::name_taken(&index.name)? {
+        return Err(anyhow!("ERROR: relation '{}' already exists", index.name));
+    }
+
+    // Built before it's listed, so a unique index over duplicate values
+    // is never used
+    if let Err(err) = index.build(&tabledef, &format!("./data/blobs/{}", tabledef.name), &index.path()) {
+        let _ = fs::remove_file(index.path());
+        return Err(err);
+    }
+    index.save(&tabledef)
+}
+
+fn handle_drop_sequence(command: DropSequenceCommand) -> ::anyhow::Result<()> {
+    if let Some((table_name, column_name)) = sequence::owner(&command.sequence_name)? {
+        return Err(anyhow!(
+            "ERROR: cannot drop sequence '{}' because column '{}' of table '{}' requires it",
+            command.sequence_name,
+            column_name,
+            table_name
+        ));
+    }
+    sequence::remove(&command.sequence_name)
+}
+
+fn handle_drop_index(command: DropIndexCommand) -> ::anyhow::Result<()> {
+    if index::remove(&command.index_name)? {
+        return Ok(());
+    }
+    // Primary keys and UNIQUE constraints have indexes of the same name
+    if fs::metadata(format!("./data/indexes/{}", command.index_name)).is_ok() {
+        return Err(anyhow!(
+            "ERROR: cannot drop index '{}' because constraint '{}' requires it",
+            command.index_name,
+            command.index_name
+        ));
+    }
+    Err(anyhow!("ERROR: index '{}' does not exist", command.index_name))
+}
+
+fn read_tabledef(table_name: String) -> ::anyhow::Result<TableDefinition> {
+    let file = fs::File::open(format!("./data/tabledefs/{}", table_name))?;
+
+    let mut column_defs = vec![];
+    let mut foreign_keys = vec![];
+    let mut unique_constraints = vec![];
+    let mut check_constraints = vec![];
+
+    for line in BufReader::new(file).lines() {
+        let line_str = line?;
+        let parts: Vec<&str> = line_str.split(' ').collect();
+        if parts[0] == "FOREIGN" {
+            foreign_keys.push(ForeignKey {
+                name: parts[1].to_string(),
+                columns: parts[2].split(',').map(String::from).collect(),
+                referenced_table: parts[3].to_string(),
+                referenced_columns: parts[4].split(',').map(String::from).collect(),
+                on_delete: ReferentialAction::parse_from_str(parts[5])?,
+            });
+            continue;
+        }
+        if parts[0] == "UNIQUE" {
+            unique_constraints.push(UniqueConstraint {
+                name: parts[1].to_string(),
+                columns: parts[2].split(',').map(String::from).collect(),
+            });
+            continue;
+        }
+        if parts[0] == "CHECK" {
+            check_constraints.push(CheckConstraint { name: parts[1].to_string(), expression: parts[2..].join(" ") });
+            continue;
+        }
+        // Flags after the length, comma separated
+        let has_flag = |name: &str| parts.get(3).is_some_and(|flags| flags.split(',').any(|flag| flag == name));
+        let col_def = ColumnDefinition {
+            name: parts[0].to_string(),
+            data_type: Datatype::parse_from_str(parts[1])?,
+            length: parts[2].parse::<u16>()?.into(),
+            primary_key: has_flag("primary_key"),
+            not_null: has_flag("not_null"),
+            identity: if has_flag("identity_always") {
+                Some(Identity::Always)
+            } else if has_flag("identity_by_default") {
+                Some(Identity::ByDefault)
+            } else {
+                None
+            },
+            sequence: parts
+                .get(3)
+                .and_then(|flags| flags.split(',').find_map(|flag| flag.strip_prefix("sequence=")))
+                .map(String::from),
+            // The rest of the line is the DEFAULT expression
+            default: (parts.get(4) == Some(&"DEFAULT")).then(|| parts[5..].join(" ")),
+        };
+        column_defs.push(col_def);
+    }
+
+    Ok(TableDefinition {
+        name: table_name,
+        column_defs,
+        foreign_keys,
+        unique_constraints,
+        check_constraints,
+    })
+}
+
+fn read_row(tabledef: &TableDefinition, buf: &[u8]) -> ::anyhow::Result<HashMap<String, ValueExpression>> {
+    let mut row_data: HashMap<String, ValueExpression> = HashMap::new();
+    for (col_def, value) in tabledef.column_defs.iter().zip(tabledef.decode_row(buf)?) {
+        row_data.insert(col_def.name.clone(), ValueExpression::column_value(value, &col_def.data_type));
+    }
+    Ok(row_data)
+}
+
+// Every row of the INSERT is encoded before the table's file is opened, so
+// the whole batch is written at once and nothing is if one row is bad
+fn handle_insert(command: InsertCommand) -> ::anyhow::Result<String> {
+    let tabledef = read_tabledef(command.table_name.clone())?;
+    let mut returning = Returning::new(&command.returning, &tabledef)?;
+    let constraints = Constraints::new(&tabledef)?;
+
+    let rows: Vec<HashMap<String, DataValue>> = match command.source {
+        InsertSource::Values(rows) => rows
+            .into_iter()
+            .map(|items| items.into_iter().map(|(column_name, item)| (column_name, item.column_value)).collect())
+            .collect(),
+        InsertSource::Select(column_names, query) => {
+            let result = execute_select(&query, QueryContext::default())?;
+            if column_names.len() != result.column_names.len() {
+                return Err(anyhow!(
+                    "ERROR: INSERT has {} target columns but {} expressions",
+                    column_names.len(),
+                    result.column_names.len()
+                ));
+            }
+            result.rows.into_iter().map(|row| column_names.iter().cloned().zip(row).collect()).collect()
+        }
+    };
+
+    // New rows go on the end of the blob, so their row ids follow on from
+    // the rows already there
+    let row_size = tabledef.get_byte_size() as u64;
+    let first_row_id = match fs::metadata(format!("./data/blobs/{}", command.table_name)) {
+        Ok(metadata) => metadata.len() / row_size,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => 0,
+        Err(err) => return Err(err.into()),
+    };
+    let indexes = TableIndex::for_table(&tabledef)?;
+    let mut trees = indexes.iter().map(|index| index.open(&tabledef)).collect::<::anyhow::Result<Vec<_>>>()?;
+
+    let mut data: Vec<u8> = vec![];
+    let mut new_rows: Vec<Vec<DataValue>> = vec![];
+    for (row_id, row) in (first_row_id..).zip(&rows) {
+        for column_name in row.keys() {
+            match tabledef.get_column(column_name) {
+                None => {
+                    return Err(anyhow!(
+                        "ERROR: column '{}' of relation '{}' does not exist",
+                        column_name,
+                        command.table_name
+                    ));
+                }
+                Some(col_def) if col_def.identity == Some(Identity::Always) => {
+                    return Err(anyhow!(
+                        "ERROR: cannot insert a non-DEFAULT value into column '{}', it is GENERATED ALWAYS AS IDENTITY",
+                        column_name
+                    ));
+                }
+                Some(_) => {}
+            }
+        }
+
+        // Columns left out of the INSERT get their DEFAULT, or NULL
+        let mut values: Vec<DataValue> = vec![];
+        for (idx, col_def) in tabledef.column_defs.iter().enumerate() {
+            match row.get(&col_def.name) {
+                Some(value) => values.push(value.clone()),
+                None => values.push(constraints.default_value(idx)?),
+            }
+        }
+        // RETURNING shows the values as they're stored
+        let row = tabledef.encode_row(&values)?;
+        let values = tabledef.decode_row(&row)?;
+        constraints.check(&tabledef, &values)?;
+        // Keys go into the trees as they're checked, so the batch can't
+        // duplicate itself either. The trees are only written out once the
+        // rows are.
+        for (index, tree) in indexes.iter().zip(trees.iter_mut()) {
+            let key = index.key(&values);
+            index.check_unique(&tabledef, tree, &key)?;
+            tree.insert(btree::Entry { key, row_id })?;
+        }
+        returning.push(&tabledef, &values)?;
+        data.extend(row);
+        new_rows.push(values);
+    }
+    foreign_key::check_references(&tabledef, &new_rows, &|| {
+        foreign_key::read_rows(&tabledef, &format!("./data/blobs/{}", command.table_name))
+    })?;
+
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(format!("./data/blobs/{}", command.table_name))?;
+    file.write_all(&data)?;
+    for tree in trees.iter_mut() {
+        tree.flush()?;
+    }
+
+    Ok(returning.finish(String::from("Data Inserted")))
+}
+
+// The row ids an index says can match a DELETE or UPDATE's WHERE clause, in
+// blob order. None when every row has to be checked.
+fn index_candidates(tabledef: &TableDefinition, condition: &Option<LogicExpression>) -> ::anyhow::Result<Option<Vec<u64>>> {
+    match condition {
+        Some(condition) => index::lookup(tabledef, &tabledef.name, condition),
+        None => Ok(None),
+    }
+}
+
+// The rows a DELETE or UPDATE's WHERE clause matches, encoded, with their
+// row ids. Only the index's candidates are read when there are any.
+fn matching_rows(tabledef: &TableDefinition, condition: &Option<LogicExpression>) -> ::anyhow::Result<Vec<(u64, Vec<u8>)>> {
+    let mut file = match fs::File::open(format!("./data/blobs/{}", tabledef.name)) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err.into()),
+    };
+    let row_size = tabledef.get_byte_size();
+    let mut buf: Vec<u8> = vec![0; row_size];
+    let mut rows: Vec<(u64, Vec<u8>)> = vec![];
+    match index_candidates(tabledef, condition)? {
+        Some(row_ids) => {
+            for row_id in row_ids {
+                file.seek(SeekFrom::Start(row_id * row_size as u64))?;
+                file.read_exact(&mut buf)?;
+                rows.push((row_id, buf.clone()));
+            }
+        }
+        None => {
+            let mut reader = BufReader::new(file);
+            for row_id in 0.. {
+                if reader.read_exact(&mut buf).is_err() {
+                    break;
+                }
+                rows.push((row_id, buf.clone()));
+            }
+        }
+    }
+
+    let mut matching = vec![];
+    for (row_id, buf) in rows {
+        if let Some(ref le) = condition {
+            let mut logic_expr = le.clone();
+            logic_expr.fill_values(read_row(tabledef, &buf)?)?;
+            if !logic_expr.evaluate()? {
+                continue;
+            }
+        }
+        matching.push((row_id, buf));
+    }
+    Ok(matching)
+}
+
+// Swaps the rewritten ./data/blobs/<table>_new in for the table's blob. The
+// table's indexes are rebuilt from the new blob first, and nothing is
+// swapped in if that fails.
+fn replace_blob(tabledef: &TableDefinition) -> ::anyhow::Result<()> {
+    let new_blob_path = format!("./data/blobs/{}_new", tabledef.name);
+    let mut built: Vec<TableIndex> = vec![];
+    for index in TableIndex::for_table(tabledef)? {
+        let result = index.build(tabledef, &new_blob_path, &format!("{}_new", index.path()));
+        built.push(index);
+        if let Err(err) = result {
+            for index in built {
+                let _ = fs::remove_file(format!("{}_new", index.path()));
+            }
+            let _ = fs::remove_file(&new_blob_path);
+            return Err(err);
+        }
+    }
+    for index in built {
+        fs::rename(format!("{}_new", index.path()), index.path())?;
+    }
+    fs::rename(new_blob_path, format!("./data/blobs/{}", tabledef.name))?;
+    Ok(())
+}
+
+fn handle_delete(command: DeleteCommand) -> ::anyhow::Result<String> {
+    let tabledef = read_tabledef(command.table_name.clone())?;
+    let mut returning = Returning::new(&command.returning, &tabledef)?;
+
+    let mut deleted: Vec<(u64, Vec<DataValue>)> = vec![];
+    for (row_id, buf) in matching_rows(&tabledef, &command.logic_expression)? {
+        let values = tabledef.decode_row(&buf)?;
+        returning.push(&tabledef, &values)?;
+        deleted.push((row_id, values));
+    }
+    let row_count = deleted.len();
+    if row_count == 0 {
+        return Ok(returning.finish(String::from("0 Rows Deleted")));
+    }
+
+    if foreign_key::referencing(&tabledef.name)?.is_empty() {
+        RowChanges::new(&tabledef, vec![], deleted)?.write()?;
+    } else {
+        // Rows other tables reference are deleted along with whatever the
+        // foreign keys say to do to the rows referencing them
+        let row_ids: Vec<usize> = deleted.iter().map(|(row_id, _)| *row_id as usize).collect();
+        let mut cascade = foreign_key::Cascade::default();
+        cascade.delete(&tabledef, &row_ids)?;
+        cascade.write()?;
+    }
+
+    Ok(returning.finish(format!("{} Rows Deleted", row_count)))
+}
+
+// Only the updated rows' index entries are touched. Nothing is written
+// until every row has been checked.
+fn handle_update(command: UpdateCommand) -> ::anyhow::Result<String> {
+    let tabledef = read_tabledef(command.table_name.clone())?;
+    let mut returning = Returning::new(&command.returning, &tabledef)?;
+
+    for column_name in command.items.keys() {
+        match tabledef.get_column(column_name) {
+            None => {
+                return Err(anyhow!(
+                    "ERROR: column '{}' of relation '{}' does not exist",
+                    column_name,
+                    command.table_name
+                ));
+            }
+            Some(col_def) if col_def.identity == Some(Identity::Always) => {
+                return Err(anyhow!(
+                    "ERROR: column '{}' can't be updated, it is GENERATED ALWAYS AS IDENTITY",
+                    column_name
+                ));
+            }
+            Some(_) => {}
+        }
+    }
+
+    let constraints = Constraints::new(&tabledef)?;
+
+    let mut updated: Vec<(u64, Vec<DataValue>, Vec<DataValue>)> = vec![];
+    for (row_id, buf) in matching_rows(&tabledef, &command.logic_expression)? {
+        let row_data = read_row(&tabledef, &buf)?;
+        let old_values = tabledef.decode_row(&buf)?;
+        let mut values = old_values.clone();
+        for (col_def, value) in tabledef.column_defs.iter().zip(values.iter_mut()) {
+            if let Some(update_item) = command.items.get(&col_def.name) {
+                let mut column_value = update_item.column_value.clone();
+                column_value.fill_values(&row_data);
+                *value = column_value.evaluate()?;
+            }
+        }
+        let row = tabledef.encode_row(&values)?;
+        let values = tabledef.decode_row(&row)?;
+        constraints.check(&tabledef, &values)?;
+        returning.push(&tabledef, &values)?;
+        updated.push((row_id, old_values, values));
+    }
+    let row_count = updated.len();
+    if row_count == 0 {
+        return Ok(returning.finish(String::from("0 Rows Updated")));
+    }
+
+    // The changed rows have to reference rows that exist, and rows that
+    // were referenced have to stay that way. A table that references itself
+    // is checked as it will be after the update.
+    let own_rows = || -> ::anyhow::Result<Vec<Vec<DataValue>>> {
+        let mut rows = foreign_key::read_rows(&tabledef, &format!("./data/blobs/{}", tabledef.name))?;
+        for (row_id, _, values) in &updated {
+            rows[*row_id as usize] = values.clone();
+        }
+        Ok(rows)
+    };
+    let (old_rows, new_rows): (Vec<Vec<DataValue>>, Vec<Vec<DataValue>>) =
+        updated.iter().map(|(_, old_values, values)| (old_values.clone(), values.clone())).unzip();
+    foreign_key::check_references(&tabledef, &new_rows, &own_rows)?;
+    foreign_key::check_referenced(&tabledef, &old_rows, &new_rows, &own_rows)?;
+
+    // Catches keys the update made duplicates
+    RowChanges::new(&tabledef, updated, vec![])?.write()?;
+
+    Ok(returning.finish(format!("{} Rows Updated", row_count)))
+}
+
+// A GROUP BY bucket, its key values and an accumulator per aggregate
+struct Group {
+    key: Vec<DataValue>,
+    accumulators: Vec<Accumulator>,
+}
+
+pub struct QueryResult {
+    pub column_names: Vec<String>,
+    // None when the type isn't known, i.e. for a column that's always NULL
+    pub column_types: Vec<Option<Datatype>>,
+    pub rows: Vec<Vec<DataValue>>,
+}
+
+fn handle_select(command: SelectCommand) -> ::anyhow::Result<String> {
+    let result = execute_select(&command, QueryContext::default())?;
+    let rows: Vec<Vec<String>> = result
+        .rows
+        .iter()
+        .map(|row| row.iter().map(|value| value.to_string()).collect())
+        .collect();
+    Ok(format_table(&result.column_names, &rows))
+}
+
+// Runs a select in the given context. Subqueries are run through here too,
+// with a context that can see the outer query's row.
+fn execute_select(command: &SelectCommand, mut context: QueryContext) -> ::anyhow::Result<QueryResult> {
+    for cte in &command.with {
+        let table = cte::materialize(cte, &context)?;
+        context.ctes.insert(cte.name.clone(), Rc::new(table));
+    }
+    if !command.set_operations.is_empty() {
+        return set_operation::execute(command, &context);
+    }
+    let scope = &Scope::new(command, context)?;
+    let mut column_names: Vec<String> = vec![];
+    let mut expressions: Vec<ValueExpression> = vec![];
+
+    // Every column reference is qualified with its table (alias.column)
+    // up front, so the same column is always written the same way
+    for column in &command.columns {
+        match column {
+            SelectColumn::Wildcard if command.table_name.is_empty() => {
+                return Err(anyhow!("ERROR: SELECT * with no tables specified is not valid"));
+            }
+            SelectColumn::Wildcard => {
+                for table in &scope.tables {
+                    for col_def in &table.tabledef.column_defs {
+                        column_names.push(col_def.name.clone());
+                        expressions.push(ValueExpression::ColumnName(format!("{}.{}", table.qualifier, col_def.name)));
+                    }
+                }
+            }
+            SelectColumn::TableWildcard(qualifier) => {
+                let table = scope.find_table(qualifier)?;
+                for col_def in &table.tabledef.column_defs {
+                    column_names.push(col_def.name.clone());
+                    expressions.push(ValueExpression::ColumnName(format!("{}.{}", table.qualifier, col_def.name)));
+                }
+            }
+            SelectColumn::Expression(expression, alias) => {
+                column_names.push(alias.clone().unwrap_or_else(|| expression.output_name()));
+                let mut expression = expression.clone();
+                scope.qualify_expression(&mut expression)?;
+                expressions.push(expression);
+            }
+        }
+    }
+
+    // GROUP BY 2 groups by the second column of the select list. A name
+    // that isn't a column of a table can be an alias from the select list.
+    let group_by = command
+        .group_by
+        .iter()
+        .map(|expression| match expression {
+            ValueExpression::DataValue(DataValue::I64Value(position)) => usize::try_from(*position - 1)
+                .ok()
+                .and_then(|idx| expressions.get(idx).cloned())
+                .ok_or_else(|| anyhow!("ERROR: GROUP BY position {} is not in select list", position)),
+            ValueExpression::ColumnName(name)
+                if scope.qualify(name, scope.tables.len()).is_err() && column_names.contains(name) =>
+            {
+                Ok(expressions[column_names.iter().position(|col_name| col_name == name).unwrap_or_default()].clone())
+            }
+            _ => {
+                let mut expression = expression.clone();
+                scope.qualify_expression(&mut expression)?;
+                Ok(expression)
+            }
+        })
+        .collect::<::anyhow::Result<Vec<ValueExpression>>>()?;
+
+    let visible_columns = expressions.len();
+    let mut sort_keys: Vec<SortKey> = vec![];
+    for item in &command.order_by {
+        let column = resolve_output_column(&item.expression, scope, &column_names, &mut expressions, "ORDER BY")?;
+        sort_keys.push(SortKey { column, descending: item.descending, nulls_first: item.nulls_first });
+    }
+
+    // The columns rows are deduplicated on
+    let distinct_columns: Option<Vec<usize>> = match &command.distinct {
+        None => None,
+        Some(Distinct::AllColumns) => {
+            if expressions.len() > visible_columns {
+                return Err(anyhow!("ERROR: for SELECT DISTINCT, ORDER BY expressions must appear in select list"));
+            }
+            Some((0..visible_columns).collect())
+        }
+        Some(Distinct::On(distinct_on)) => {
+            let columns = distinct_on
+                .iter()
+                .map(|expression| resolve_output_column(expression, scope, &column_names, &mut expressions, "DISTINCT ON"))
+                .collect::<::anyhow::Result<Vec<usize>>>()?;
+            // The ORDER BY has to start with the DISTINCT ON expressions, so
+            // the row that's kept for each of them is well defined
+            let leading_sort_columns: Vec<usize> = sort_keys.iter().take(columns.len()).map(|key| key.column).collect();
+            if !sort_keys.is_empty()
+                && (leading_sort_columns.len() < columns.len() || columns.iter().any(|column| !leading_sort_columns.contains(column)))
+            {
+                return Err(anyhow!("ERROR: SELECT DISTINCT ON expressions must match initial ORDER BY expressions"));
+            }
+            Some(columns)
+        }
+    };
+
+    let mut where_clause = command.logic_expression.clone();
+    let mut having = command.having.clone();
+    for logic_expression in where_clause.iter_mut().chain(having.iter_mut()) {
+        scope.qualify_logic_expression(logic_expression, scope.tables.len())?;
+    }
+    let having_values = having.iter().flat_map(|having| having.value_expressions());
+    let where_values = where_clause.iter().flat_map(|le| le.value_expressions());
+    if where_values.clone().any(|expression| !expression.aggregates().is_empty()) {
+        return Err(anyhow!("ERROR: aggregate functions are not allowed in WHERE"));
+    }
+    if group_by.iter().any(|expression| !expression.aggregates().is_empty()) {
+        return Err(anyhow!("ERROR: aggregate functions are not allowed in GROUP BY"));
+    }
+    if where_values.clone().any(|expression| !expression.windows().is_empty()) {
+        return Err(anyhow!("ERROR: window functions are not allowed in WHERE"));
+    }
+    if group_by.iter().any(|expression| !expression.windows().is_empty()) {
+        return Err(anyhow!("ERROR: window functions are not allowed in GROUP BY"));
+    }
+    if having_values.clone().any(|expression| !expression.windows().is_empty()) {
+        return Err(anyhow!("ERROR: window functions are not allowed in HAVING"));
+    }
+
+    let mut windows: Vec<WindowFunction> = vec![];
+    for expression in &expressions {
+        for window in expression.windows() {
+            if window.expressions().iter().any(|expression| !expression.windows().is_empty()) {
+                return Err(anyhow!("ERROR: window function calls cannot be nested"));
+            }
+            if !windows.contains(window) {
+                windows.push(window.clone());
+            }
+        }
+    }
+
+    let mut aggregates: Vec<Aggregate> = vec![];
+    for expression in expressions.iter().chain(having_values) {
+        for aggregate in expression.aggregates() {
+            if aggregate.argument.as_ref().is_some_and(|argument| !argument.aggregates().is_empty()) {
+                return Err(anyhow!("ERROR: aggregate function calls cannot be nested"));
+            }
+            if aggregate.argument.as_ref().is_some_and(|argument| !argument.windows().is_empty()) {
+                return Err(anyhow!("ERROR: aggregate function calls cannot contain window function calls"));
+            }
+            if !aggregates.contains(aggregate) {
+                aggregates.push(aggregate.clone());
+            }
+        }
+    }
+    let grouped = !group_by.is_empty() || having.is_some() || !aggregates.is_empty();
+
+    // Swaps the aggregates and GROUP BY expressions in an output expression
+    // for a group's values. Aggregates go first, as their arguments can
+    // contain the GROUP BY expressions.
+    let substitute_group = |expression: &mut ValueExpression, key: &[DataValue], results: &[DataValue]| {
+        for (aggregate, result) in aggregates.iter().zip(results) {
+            expression.substitute(&ValueExpression::Aggregate(aggregate.clone()), result);
+        }
+        for (group_expression, value) in group_by.iter().zip(key) {
+            expression.substitute(group_expression, value);
+        }
+    };
+    if grouped {
+        let key = vec![DataValue::Null; group_by.len()];
+        let results = vec![DataValue::Null; aggregates.len()];
+        let mut having = having.clone();
+        let mut outputs = expressions.clone();
+        let having_values = having.iter_mut().flat_map(|having| having.value_expressions_mut());
+        for expression in outputs.iter_mut().chain(having_values) {
+            substitute_group(expression, &key, &results);
+            if let Some(col_name) = expression.column_names().first() {
+                return Err(anyhow!(
+                    "ERROR: column '{}' must appear in the GROUP BY clause or be used in an aggregate function",
+                    col_name
+                ));
+            }
+        }
+    }
+
+    let mut rows: Vec<Vec<DataValue>> = vec![];
+    let mut sorter = (!sort_keys.is_empty()).then(|| Sorter::new(sort_keys, SORT_MEMORY_LIMIT));
+    // Without a sort, rows past OFFSET + LIMIT are never shown
+    let wanted_rows = command.limit.map(|limit| command.offset.saturating_add(limit));
+    let mut groups: Vec<Group> = vec![];
+    let mut group_indexes: HashMap<String, usize> = HashMap::new();
+    // Rows with window functions wait until every row is in
+    let mut pending: Vec<window::PendingRow> = vec![];
+
+    // Without a GROUP BY the whole table is one group, even when it's empty
+    if grouped && group_by.is_empty() {
+        group_indexes.insert(format!("{:?}", Vec::<DataValue>::new()), 0);
+        groups.push(Group { key: vec![], accumulators: aggregates.iter().map(Accumulator::new).collect() });
+    }
+
+    for row_data in join::from_rows(scope, &command.joins, where_clause.as_ref())? {
+        let row_data = row_data?;
+        if let Some(ref le) = where_clause {
+            if !scope.evaluate_condition(le, &row_data)? {
+                continue;
+            }
+        }
+
+        if !grouped && !windows.is_empty() {
+            pending.push(window::PendingRow { row: row_data, expressions: expressions.clone(), windows: windows.clone() });
+            continue;
+        }
+        if !grouped {
+            let row = expressions
+                .iter()
+                .map(|expression| scope.evaluate(expression, &row_data))
+                .collect::<::anyhow::Result<Vec<DataValue>>>()?;
+            match sorter {
+                Some(ref mut sorter) => sorter.push(row)?,
+                None => rows.push(row),
+            }
+            if sorter.is_none() && distinct_columns.is_none() && wanted_rows.is_some_and(|wanted_rows| rows.len() >= wanted_rows) {
+                break;
+            }
+            continue;
+        }
+
+        let key = group_by
+            .iter()
+            .map(|expression| scope.evaluate(expression, &row_data))
+            .collect::<::anyhow::Result<Vec<DataValue>>>()?;
+        let group_idx = *group_indexes.entry(format!("{:?}", key)).or_insert_with(|| {
+            groups.push(Group { key, accumulators: aggregates.iter().map(Accumulator::new).collect() });
+            groups.len() - 1
+        });
+        for (aggregate, accumulator) in aggregates.iter().zip(groups[group_idx].accumulators.iter_mut()) {
+            let value = match &aggregate.argument {
+                Some(argument) => scope.evaluate(argument, &row_data)?,
+                None => DataValue::BoolValue(true),
+            };
+            accumulator.update(value)?;
+        }
+    }
+
+    for group in groups {
+        let results = group
+            .accumulators
+            .iter()
+            .map(|accumulator| accumulator.finish())
+            .collect::<::anyhow::Result<Vec<DataValue>>>()?;
+        // Subqueries can only use the group's GROUP BY columns
+        let group_row: join::Row = group_by
+            .iter()
+            .zip(&group.key)
+            .filter_map(|(expression, value)| match expression {
+                ValueExpression::ColumnName(name) => Some((name.clone(), ValueExpression::computed_value(value.clone()))),
+                _ => None,
+            })
+            .collect();
+        if let Some(having) = &having {
+            let mut having = having.clone();
+            for expression in having.value_expressions_mut() {
+                substitute_group(expression, &group.key, &results);
+            }
+            if !scope.evaluate_condition(&having, &group_row)? {
+                continue;
+            }
+        }
+        if !windows.is_empty() {
+            let mut outputs = expressions.clone();
+            let mut group_windows = windows.clone();
+            let window_values = group_windows.iter_mut().flat_map(|window| window.expressions_mut());
+            for expression in outputs.iter_mut().chain(window_values) {
+                substitute_group(expression, &group.key, &results);
+            }
+            pending.push(window::PendingRow { row: group_row, expressions: outputs, windows: group_windows });
+            continue;
+        }
+        let mut row: Vec<DataValue> = vec![];
+        for expression in &expressions {
+            let mut expression = expression.clone();
+            substitute_group(&mut expression, &group.key, &results);
+            row.push(scope.evaluate(&expression, &group_row)?);
+        }
+        match sorter {
+            Some(ref mut sorter) => sorter.push(row)?,
+            None => rows.push(row),
+        }
+    }
+
+    if !pending.is_empty() {
+        for row in window::evaluate_rows(scope, pending)? {
+            match sorter {
+                Some(ref mut sorter) => sorter.push(row)?,
+                None => rows.push(row),
+            }
+        }
+    }
+
+    let rows: Box<dyn Iterator<Item = ::anyhow::Result<Vec<DataValue>>>> = match sorter {
+        Some(sorter) => Box::new(sorter.finish()?),
+        None => Box::new(rows.into_iter().map(Ok)),
+    };
+    let rows = match distinct_columns {
+        Some(distinct_columns) => {
+            let mut seen: HashSet<String> = HashSet::new();
+            Box::new(rows.filter(move |row| match row {
+                Ok(row) => {
+                    let key: Vec<&DataValue> = distinct_columns.iter().map(|column| &row[*column]).collect();
+                    seen.insert(format!("{:?}", key))
+                }
+                Err(_) => true,
+            }))
+        }
+        None => rows,
+    };
+    let rows = rows
+        .skip(command.offset)
+        .take(command.limit.unwrap_or(usize::MAX))
+        .map(|row| Ok(row?.into_iter().take(visible_columns).collect()))
+        .collect::<::anyhow::Result<Vec<Vec<DataValue>>>>()?;
+
+    // A column's type comes from its table, or else from its values
+    let column_types = expressions[..visible_columns]
+        .iter()
+        .enumerate()
+        .map(|(idx, expression)| match expression {
+            ValueExpression::ColumnName(name) => scope.column_type(name).map(|(_, data_type)| data_type.clone()),
+            _ => None,
+        }
+        .or_else(|| rows.iter().find_map(|row| row[idx].datatype())))
+        .collect();
+    Ok(QueryResult { column_names, column_types, rows })
+}
+
+// Finds the output column an ORDER BY or DISTINCT ON expression refers to.
+// It can name one by position, name or alias, or repeat its expression.
+// Anything else is added as a hidden column, which isn't shown.
+fn resolve_output_column(
+    expression: &ValueExpression,
+    scope: &Scope,
+    column_names: &[String],
+    expressions: &mut Vec<ValueExpression>,
+    clause: &str,
+) -> ::anyhow::Result<usize> {
+    match expression {
+        ValueExpression::DataValue(DataValue::I64Value(position)) => usize::try_from(*position - 1)
+            .ok()
+            .filter(|idx| *idx < column_names.len())
+            .ok_or_else(|| anyhow!("ERROR: {} position {} is not in select list", clause, position)),
+        ValueExpression::ColumnName(name) if column_names.contains(name) => {
+            if column_names.iter().filter(|col_name| *col_name == name).count() > 1 {
+                return Err(anyhow!("ERROR: {} '{}' is ambiguous", clause, name));
+            }
+            Ok(column_names.iter().position(|col_name| col_name == name).unwrap_or_default())
+        }
+        expression => {
+            let mut expression = expression.clone();
+            scope.qualify_expression(&mut expression)?;
+            match expressions.iter().position(|existing| *existing == expression) {
+                Some(idx) => Ok(idx),
+                None => {
+                    expressions.push(expression);
+                    Ok(expressions.len() - 1)
+                }
+            }
+        }
+    }
+}
+
+fn format_table(column_names: &[String], rows: &[Vec<String>]) -> String {
+    let mut response = String::new();
+    let mut longest_cols: Vec<usize> = column_names.iter().map(|col_name| col_name.len()).collect();
+    for row in rows {
+        for (longest, value) in longest_cols.iter_mut().zip(row) {
+            *longest = cmp::max(*longest, value.len());
+        }
+    }
+
+    response += "| ";
+    for (col_name, width) in column_names.iter().zip(&longest_cols) {
+        response += format!("{:0width$} | ", col_name, width = width).as_str();
+    }
+    let total_length: usize = 1 + longest_cols.iter().map(|width| width + 3).sum::<usize>();
+    response += "\n";
+    response += "-".repeat(total_length).as_str();
+    response += "\n";
+    for row in rows {
+        response += "| ";
+        for (value, width) in row.iter().zip(&longest_cols) {
+            response += format!("{:0width$} | ", value, width = width).as_str();
+        }
+        response += "\n";
+    }
+
+    response
+}
+
+fn run_command(query: String) -> ::anyhow::Result<String> {
+    if query.starts_with('\\') {
+        // handle PSQL's slash commands e.g.: \dt \d
+        return Err(anyhow!("Slash commands are not yet supported in SQUIRREL"));
+    }
+
+    let command: Command = Command::from_string(query)?;
+
+    match command {
+        Command::Create(create_command) => {
+            let result = handle_create(create_command);
+            if result.is_ok() {
+                Ok(String::from("Table Created"))
+            } else {
+                Ok(result.err().unwrap().to_string())
+            }
+        }
+        Command::CreateIndex(create_index_command) => {
+            let result = handle_create_index(create_index_command);
+            if result.is_ok() {
+                Ok(String::from("Index Created"))
+            } else {
+                Ok(result.err().unwrap().to_string())
+            }
+        }
+        Command::DropIndex(drop_index_command) => {
+            let result = handle_drop_index(drop_index_command);
+            if result.is_ok() {
+                Ok(String::from("Index Dropped"))
+            } else {
+                Ok(result.err().unwrap().to_string())
+            }
+        }
+        Command::CreateSequence(create_sequence_command) => {
+            let result = sequence::create(&create_sequence_command.sequence_definition);
+            if result.is_ok() {
+                Ok(String::from("Sequence Created"))
+            } else {
+                Ok(result.err().unwrap().to_string())
+            }
+        }
+        Command::DropSequence(drop_sequence_command) => {
+            let result = handle_drop_sequence(drop_sequence_command);
+            if result.is_ok() {
+                Ok(String::from("Sequence Dropped"))
+            } else {
+                Ok(result.err().unwrap().to_string())
+            }
+        }
+        Command::AlterTable(alter_table_command) => {
+            let result = alter::alter_table(alter_table_command);
+            if result.is_ok() {
+                Ok(String::from("Table Altered"))
+            } else {
+                Ok(result.err().unwrap().to_string())
+            }
+        }
+        Command::Insert(insert_command) => {
+            let result = handle_insert(insert_command);
+            if result.is_ok() {
+                Ok(result?)
+            } else {
+                Ok(result.err().unwrap().to_string())
+            }
+        }
+        Command::Select(select_command) => {
+            let result = handle_select(*select_command);
+            if result.is_ok() {
+                Ok(result?)
+            } else {
+                Ok(result.err().unwrap().to_string())
+            }
+        }
+        Command::Delete(delete_command) => {
+            let result = handle_delete(delete_command);
+            if result.is_ok() {
+                Ok(result?)
+            } else {
+                Ok(result.err().unwrap().to_string())
+            }
+        }
+        Command::Update(update_command) => {
+            let result = handle_update(update_command);
+            if result.is_ok() {
+                Ok(result?)
+            } else {
+                Ok(result.err().unwrap().to_string())
+            }
+        }
+    }
+}
+
+// Whether a query ends with a semicolon that isn't in a string, so a query
+// longer than one read isn't run until all of it has arrived
+fn is_complete_query(query: &str) -> bool {
+    let mut quote: Option<char> = None;
+    let mut last = None;
+    for character in query.chars() {
+        match quote {
+            Some(open) if character == open => quote = None,
+            Some(_) => {}
+            None if character == '\'' || character == '"' => quote = Some(character),
+            None => {}
+        }
+        if !character.is_whitespace() {
+            last = Some(character);
+        }
+    }
+    quote.is_none() && last == Some(';')
+}
+
+fn handle_client(mut stream: TcpStream) -> ::anyhow::Result<()> {
+    let mut data = [0_u8; BUFFER_SIZE];
+    let mut query_data: Vec<u8> = vec![];
+
+    while match stream.read(&mut data) {
+        Ok(0) => false,
+        Ok(size) => {
+            query_data.extend_from_slice(&data[..size]);
+            let query_string = String::from_utf8_lossy(&query_data).to_string();
+            // The client sends a line at a time, so a short read that ends a
+            // line is run too, i.e. to report a missing semicolon
+            if is_complete_query(&query_string) || (size < BUFFER_SIZE && query_string.ends_with('\n')) {
+                query_data.clear();
+                let response_res: ::anyhow::Result<String> = run_command(query_string);
+
+                let response = match response_res {
+                    Ok(result) => result,
+                    Err(err_msg) => format!("Error: {}", err_msg)
+                };
+
+                let response_data_size = response.len().to_le_bytes();
+                stream.write_all(&response_data_size)?; // send length of message
+                stream.write_all(response.as_bytes())?; // send message
+            }
+            true
+        }
+        Err(_) => {
+            println!(
+                "An error occurred, terminating connection with {}",
+                stream.peer_addr()?
+            );
+            stream.shutdown(Shutdown::Both)?;
+            false
+        }
+    } {}
+
+    Ok(())
+}
+
+// Starts accepting connections, blocking forever. Anything embedding the
+// server (i.e. to register its own functions with
+// squirrel_core::functions::register_function first) calls this instead of
+// running the squirrel_server binary.
+pub fn start(address: &str) -> std::io::Result<()> {
+    create_data_dirs();
+    index::recover().map_err(std::io::Error::other)?;
+    sequence::register_functions().map_err(std::io::Error::other)?;
+    let listener = TcpListener::bind(address)?;
+
+    for stream in listener.incoming() {
+        thread::spawn(|| -> ::anyhow::Result<()> {
+            handle_client(stream?)?;
+            Ok(())
+        });
+    }
+
+    Ok(())
+}
+
+fn create_data_dirs() {
+    //fs::remove_dir_all("./data")?;
+    let _ensure_data_exists = fs::create_dir("./data");
+    let _ensure_tabledefs_exists = fs::create_dir("./data/tabledefs");
+    let _ensure_blob_exists = fs::create_dir("./data/blobs");
+    let _ensure_tmp_exists = fs::create_dir("./data/tmp");
+    let _ensure_indexes_exists = fs::create_dir("./data/indexes");
+    let _ensure_indexdefs_exists = fs::create_dir("./data/indexdefs");
+    let _ensure_sequences_exists = fs::create_dir("./data/sequences");
+    let _ensure_reindex_exists = fs::create_dir("./data/reindex");
+}
+
+// Tests run against the ./data of a fresh directory. The working directory
+// belongs to the whole process, so they take turns.
+#[cfg(test)]
+static TEST_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+#[cfg(test)]
+fn with_data_dir(test: impl FnOnce()) {
+    static REGISTER_FUNCTIONS: std::sync::Once = std::sync::Once::new();
+    static NEXT_DIR_ID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+    // A failed test poisons the lock, which shouldn't fail the others
+    let _lock = TEST_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+    REGISTER_FUNCTIONS.call_once(|| sequence::register_functions().unwrap());
+    let dir = std::env::temp_dir().join(format!(
+        "squirrel_test_{}_{}",
+        std::process::id(),
+        NEXT_DIR_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+    ));
+    fs::create_dir_all(&dir).unwrap();
+    std::env::set_current_dir(&dir).unwrap();
+    create_data_dirs();
+    test();
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Runs each statement in turn, returning what the client would be sent
+#[cfg(test)]
+fn run_statements(statements: &[&str]) -> Vec<String> {
+    statements
+        .iter()
+        .map(|statement| match run_command(statement.to_string()) {
+            Ok(response) => response,
+            Err(err) => err.to_string(),
+        })
+        .collect()
+}
+
+// A SELECT's rows, with the values as the client would see them
+#[cfg(test)]
+fn select_rows(query: &str) -> Vec<Vec<String>> {
+    let Command::Select(command) = Command::from_string(query.to_string()).unwrap() else {
+        panic!("not a SELECT: {}", query);
+    };
+    let result = execute_select(&command, QueryContext::default()).unwrap();
+    result.rows.iter().map(|row| row.iter().map(|value| value.to_string()).collect()).collect()
+}
+
+#[test]
+fn grouped_and_aggregated_rows() {
+    with_data_dir(|| {
+        let responses = run_statements(&[
+            "CREATE TABLE users (id int, city varchar 10, age int);",
+            "INSERT INTO users (id, city, age) VALUES (1, 'oslo', 30), (2, 'oslo', 40), (3, 'rome', 25), (4, 'rome', NULL), (5, 'nice', 50);",
+        ]);
+        assert!(responses.iter().all(|response| !response.starts_with("ERROR")), "{:?}", responses);
+        let values = |query: &str| -> Vec<String> { select_rows(query).into_iter().map(|row| row.join(" ")).collect() };
+
+        // COUNT(*) counts rows, the others skip NULLs
+        assert_eq!(
+            values("SELECT city, COUNT(*), COUNT(age), SUM(age), AVG(age), MIN(age), MAX(age) FROM users GROUP BY city ORDER BY city;"),
+            vec!["nice 1 1 50 50.0000000000000000 50 50", "oslo 2 2 70 35.0000000000000000 30 40", "rome 2 1 25 25.0000000000000000 25 25"]
+        );
+        assert_eq!(values("SELECT city FROM users GROUP BY city HAVING COUNT(*) > 1 AND MAX(age) > 30;"), vec!["oslo"]);
+        assert_eq!(values("SELECT COUNT(*) FROM users WHERE age >= 30 GROUP BY city HAVING SUM(age) > 60;"), vec!["2"]);
+
+        // With no rows, a query without GROUP BY still has its one row
+        assert_eq!(
+            values("SELECT COUNT(*), COUNT(age), SUM(age), AVG(age), MIN(age), MAX(age) FROM users WHERE age > 100;"),
+            vec!["0 0 NULL NULL NULL NULL"]
+        );
+        assert!(values("SELECT city, COUNT(*) FROM users WHERE age > 100 GROUP BY city;").is_empty());
+
+        assert!(run_statements(&["SELECT city, age FROM users GROUP BY city;"])[0]
+            .contains("column 'users.age' must appear in the GROUP BY clause or be used in an aggregate function"));
+    });
+}
+
+#[test]
+fn distinct_rows_and_aliases() {
+    with_data_dir(|| {
+        let responses = run_statements(&[
+            "CREATE TABLE visits (id int, city varchar 10, days int);",
+            "INSERT INTO visits (id, city, days) VALUES (1, 'oslo', 3), (2, 'oslo', 3), (3, 'rome', 5), (4, 'rome', 2), (5, NULL, 1), (6, NULL, 1);",
+        ]);
+        assert!(responses.iter().all(|response| !response.starts_with("ERROR")), "{:?}", responses);
+        let values = |query: &str| -> Vec<String> { select_rows(query).into_iter().map(|row| row.join(" ")).collect() };
+
+        // NULLs count as the same value
+        assert_eq!(values("SELECT DISTINCT city, days FROM visits ORDER BY city, days;"), vec!["oslo 3", "rome 2", "rome 5", "NULL 1"]);
+        assert_eq!(values("SELECT DISTINCT city FROM visits ORDER BY city;"), vec!["oslo", "rome", "NULL"]);
+        // The first row of each city in ORDER BY order
+        assert_eq!(
+            values("SELECT DISTINCT ON (city) city, id FROM visits ORDER BY city, days DESC, id;"),
+            vec!["oslo 1", "rome 3", "NULL 5"]
+        );
+        assert!(run_statements(&["SELECT DISTINCT city FROM visits ORDER BY days;"])[0]
+            .contains("for SELECT DISTINCT, ORDER BY expressions must appear in select list"));
+        assert!(run_statements(&["SELECT DISTINCT ON (city) id FROM visits ORDER BY id;"])[0]
+            .contains("SELECT DISTINCT ON expressions must match initial ORDER BY expressions"));
+
+        // An alias is the column's header, and ORDER BY can use it
+        let header = |column_names: &[&str]| -> String {
+            let column_names: Vec<String> = column_names.iter().map(|name| name.to_string()).collect();
+            format_table(&column_names, &[])
+        };
+        assert_eq!(run_statements(&["SELECT id AS visit, days * 24 AS hours FROM visits WHERE id > 10;"]), vec![header(&["visit", "hours"])]);
+        assert_eq!(values("SELECT id AS visit, days * 24 AS hours FROM visits ORDER BY hours DESC, visit LIMIT 2;"), vec!["3 120", "1 72"]);
+        assert_eq!(values("SELECT DISTINCT days AS d FROM visits WHERE city = 'oslo';"), vec!["3"]);
+    });
+}
+
+#[test]
+fn batched_inserts() {
+    with_data_dir(|| {
+        let responses = run_statements(&[
+            "CREATE TABLE src (id int, name varchar 10);",
+            "CREATE TABLE dst (id int PRIMARY KEY, name varchar 10 NOT NULL, n int CHECK (n > 0) DEFAULT 1);",
+        ]);
+        assert!(responses.iter().all(|response| !response.starts_with("ERROR")), "{:?}", responses);
+        let values = |query: &str| -> Vec<String> { select_rows(query).into_iter().map(|row| row.join(" ")).collect() };
+        let blob_size = || fs::metadata("./data/blobs/dst").map_or(0, |metadata| metadata.len());
+
+        assert_eq!(run_statements(&["INSERT INTO src (id, name) VALUES (1, 'a'), (2, 'b'), (3, NULL), (4, 'd');"]), vec!["Data Inserted"]);
+        assert_eq!(values("SELECT id, name FROM src;"), vec!["1 a", "2 b", "3 NULL", "4 d"]);
+
+        // One bad row and none of them are written: a NOT NULL column, a key
+        // the batch repeats, a CHECK
+        assert!(run_statements(&["INSERT INTO dst (id, name) SELECT id, name FROM src;"])[0].contains("not-null constraint"));
+        assert!(run_statements(&["INSERT INTO dst (id, name) VALUES (1, 'a'), (2, 'b'), (1, 'c');"])[0].contains("duplicate key"));
+        assert!(run_statements(&["INSERT INTO dst (id, name, n) VALUES (1, 'a', 1), (2, 'b', 0);"])[0].contains("check constraint"));
+        assert!(run_statements(&["INSERT INTO dst (id, name) VALUES (1, 'a'), (2, 'b', 3);"])[0].contains("INSERT has 2 target columns but 3 expressions"));
+        assert_eq!(blob_size(), 0);
+        assert!(values("SELECT id FROM dst;").is_empty());
+
+        // Left out columns get their defaults in every row
+        assert_eq!(run_statements(&["INSERT INTO dst (id, name) SELECT id, name FROM src WHERE name IS NOT NULL;"]), vec!["Data Inserted"]);
+        assert_eq!(values("SELECT id, name, n FROM dst;"), vec!["1 a 1", "2 b 1", "4 d 1"]);
+        let written = blob_size();
+        // The rows are selected before any are written, so a table can be
+        // copied into itself, and a key already there fails the batch
+        assert_eq!(run_statements(&["INSERT INTO dst (id, name, n) SELECT id + 10, name, n + 1 FROM dst;"]), vec!["Data Inserted"]);
+        assert_eq!(values("SELECT id, n FROM dst WHERE id > 10;"), vec!["11 2", "12 2", "14 2"]);
+        assert!(run_statements(&["INSERT INTO dst (id, name) SELECT id + 2, name FROM src WHERE name IS NOT NULL;"])[0].contains("duplicate key"));
+        assert_eq!(blob_size(), 2 * written);
+
+        assert!(run_statements(&["INSERT INTO dst (id, name) SELECT id FROM src;"])[0].contains("INSERT has 2 target columns but 1 expressions"));
+    });
+}
@@ -0,0 +1,125 @@
+use anyhow::anyhow;
+use std::collections::HashMap;
+
+use squirrel_core::parser::command::{DataValue, SelectColumn, ValueExpression};
+
+use crate::{format_table, TableDefinition};
+
+// The RETURNING clause of an INSERT, UPDATE or DELETE. Each row the command
+// writes (or deletes) is run through the columns as it goes.
+pub struct Returning {
+    column_names: Vec<String>,
+    expressions: Vec<ValueExpression>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Returning {
+    pub fn new(columns: &[SelectColumn], tabledef: &TableDefinition) -> ::anyhow::Result<Returning> {
+        let mut column_names: Vec<String> = vec![];
+        let mut expressions: Vec<ValueExpression> = vec![];
+        for column in columns {
+            match column {
+                SelectColumn::TableWildcard(qualifier) if *qualifier != tabledef.name => {
+                    return Err(anyhow!("ERROR: missing FROM-clause entry for table '{}'", qualifier));
+                }
+                SelectColumn::Wildcard | SelectColumn::TableWildcard(_) => {
+                    for col_def in &tabledef.column_defs {
+                        column_names.push(col_def.name.clone());
+                        expressions.push(ValueExpression::ColumnName(col_def.name.clone()));
+                    }
+                }
+                SelectColumn::Expression(expression, alias) => {
+                    if !expression.aggregates().is_empty() {
+                        return Err(anyhow!("ERROR: aggregate functions are not allowed in RETURNING"));
+                    }
+                    if !expression.windows().is_empty() {
+                        return Err(anyhow!("ERROR: window functions are not allowed in RETURNING"));
+                    }
+                    if expression.has_subqueries() {
+                        return Err(anyhow!("ERROR: subqueries are not supported in RETURNING"));
+                    }
+                    // Checked up front, so a command that changes no rows
+                    // still fails on a bad column
+                    for col_name in expression.column_names() {
+                        let name = col_name.strip_prefix(&format!("{}.", tabledef.name)).unwrap_or(col_name);
+                        if tabledef.get_column(name).is_none() {
+                            return Err(anyhow!("ERROR: column '{}' does not exist", col_name));
+                        }
+                    }
+                    column_names.push(alias.clone().unwrap_or_else(|| expression.output_name()));
+                    expressions.push(expression.clone());
+                }
+            }
+        }
+        Ok(Returning { column_names, expressions, rows: vec![] })
+    }
+
+    // values are the row's values in column order
+    pub fn push(&mut self, tabledef: &TableDefinition, values: &[DataValue]) -> ::anyhow::Result<()> {
+        let mut row_data: HashMap<String, ValueExpression> = HashMap::new();
+        for (col_def, value) in tabledef.column_defs.iter().zip(values) {
+            let value = ValueExpression::column_value(value.clone(), &col_def.data_type);
+            row_data.insert(format!("{}.{}", tabledef.name, col_def.name), value.clone());
+            row_data.insert(col_def.name.clone(), value);
+        }
+        let mut row: Vec<String> = vec![];
+        for expression in &self.expressions {
+            let mut expression = expression.clone();
+            expression.fill_values(&row_data);
+            row.push(expression.evaluate()?.to_string());
+        }
+        self.rows.push(row);
+        Ok(())
+    }
+
+    // The rows as a table, or the command's usual message without a
+    // RETURNING clause
+    pub fn finish(self, message: String) -> String {
+        if self.column_names.is_empty() {
+            return message;
+        }
+        format_table(&self.column_names, &self.rows)
+    }
+}
+
+#[cfg(test)]
+use crate::{run_statements, select_rows, with_data_dir};
+
+#[test]
+fn returning_written_rows() {
+    with_data_dir(|| {
+        assert_eq!(run_statements(&["CREATE TABLE items (id serial PRIMARY KEY, name varchar 10, qty int DEFAULT 5);"]), vec!["Table Created"]);
+        // The table a statement's RETURNING rows would be sent as
+        let table = |column_names: &[&str], rows: &[&[&str]]| -> String {
+            let column_names: Vec<String> = column_names.iter().map(|name| name.to_string()).collect();
+            let rows: Vec<Vec<String>> = rows.iter().map(|row| row.iter().map(|value| value.to_string()).collect()).collect();
+            format_table(&column_names, &rows)
+        };
+
+        // Serial and defaulted values are returned as they were written
+        assert_eq!(
+            run_statements(&["INSERT INTO items (name) VALUES ('a'), ('b') RETURNING id, name, qty;"]),
+            vec![table(&["id", "name", "qty"], &[&["1", "a", "5"], &["2", "b", "5"]])]
+        );
+        assert_eq!(
+            run_statements(&["INSERT INTO items (name, qty) VALUES ('c', 1) RETURNING *, qty * 2 AS double;"]),
+            vec![table(&["id", "name", "qty", "double"], &[&["3", "c", "1", "2"]])]
+        );
+
+        // UPDATE returns the rows as they are after it
+        assert_eq!(
+            run_statements(&["UPDATE items SET qty = qty + 10 WHERE id <= 2 RETURNING id, items.qty;"]),
+            vec![table(&["id", "qty"], &[&["1", "15"], &["2", "15"]])]
+        );
+        // And DELETE as they were before it
+        assert_eq!(run_statements(&["DELETE FROM items WHERE qty < 10 RETURNING items.*;"]), vec![table(&["id", "name", "qty"], &[&["3", "c", "1"]])]);
+        assert_eq!(run_statements(&["DELETE FROM items WHERE id = 3 RETURNING id;"]), vec![table(&["id"], &[])]);
+
+        // A bad column fails before anything is written
+        assert!(run_statements(&["UPDATE items SET qty = 0 RETURNING nope;"])[0].contains("column 'nope' does not exist"));
+        assert!(run_statements(&["INSERT INTO items (name) VALUES ('d') RETURNING COUNT(*);"])[0].contains("aggregate functions are not allowed"));
+        assert_eq!(select_rows("SELECT id, name, qty FROM items;"), vec![vec!["1", "a", "15"], vec!["2", "b", "15"]]);
+        // Without RETURNING the usual message is sent
+        assert_eq!(run_statements(&["INSERT INTO items (name) VALUES ('d');"]), vec!["Data Inserted"]);
+    });
+}
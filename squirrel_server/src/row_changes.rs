@@ -0,0 +1,212 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+
+use squirrel_core::parser::command::DataValue;
+
+use crate::btree::{BTree, Entry};
+use crate::index::{self, TableIndex};
+use crate::TableDefinition;
+
+// Changes to a table's rows, with the index entries of just the rows that
+// changed or moved updated to match. new() works everything out with the
+// trees in memory, so nothing is written if it fails (e.g. on a duplicate
+// key), and write() puts it on disk.
+pub struct RowChanges {
+    table_name: String,
+    row_size: usize,
+    trees: Vec<BTree>,
+    // Encoded new rows by row id
+    updated: HashMap<u64, Vec<u8>>,
+    deleted: HashSet<u64>,
+}
+
+impl RowChanges {
+    // updated are (row id, old values, new values) and deleted are (row id,
+    // values). Rows after a deleted one move up, so the rest keep their
+    // order and row ids stay the row's position in the blob.
+    pub fn new(
+        tabledef: &TableDefinition,
+        updated: Vec<(u64, Vec<DataValue>, Vec<DataValue>)>,
+        deleted: Vec<(u64, Vec<DataValue>)>,
+    ) -> ::anyhow::Result<RowChanges> {
+        let indexes = TableIndex::for_table(tabledef)?;
+        let mut trees = indexes.iter().map(|index| index.open(tabledef)).collect::<::anyhow::Result<Vec<_>>>()?;
+
+        // Every old key is taken out before any new one goes in, so rows can
+        // trade keys (SET id = id + 1) without looking like duplicates
+        for (index, tree) in indexes.iter().zip(trees.iter_mut()) {
+            for (row_id, old_values, new_values) in &updated {
+                let key = index.key(old_values);
+                if key != index.key(new_values) {
+                    tree.remove(&Entry { key, row_id: *row_id })?;
+                }
+            }
+            for (row_id, old_values, new_values) in &updated {
+                let key = index.key(new_values);
+                if key != index.key(old_values) {
+                    index.check_unique(tabledef, tree, &key)?;
+                    tree.insert(Entry { key, row_id: *row_id })?;
+                }
+            }
+            for (row_id, values) in &deleted {
+                tree.remove(&Entry { key: index.key(values), row_id: *row_id })?;
+            }
+        }
+        let mut changes = RowChanges {
+            table_name: tabledef.name.clone(),
+            row_size: tabledef.get_byte_size(),
+            trees,
+            updated: HashMap::new(),
+            deleted: deleted.iter().map(|(row_id, _)| *row_id).collect(),
+        };
+        for (row_id, _, new_values) in &updated {
+            changes.updated.insert(*row_id, tabledef.encode_row(new_values)?);
+        }
+
+        // Each row after the first deleted one moves up by the number of
+        // deleted rows before it
+        let Some(first) = changes.deleted.iter().min().copied() else {
+            return Ok(changes);
+        };
+        let mut file = File::open(changes.blob_path())?;
+        file.seek(SeekFrom::Start(first * changes.row_size as u64))?;
+        let mut reader = BufReader::new(file);
+        let mut buf: Vec<u8> = vec![0; changes.row_size];
+        let mut moved_by: u64 = 0;
+        for row_id in first.. {
+            if reader.read_exact(&mut buf).is_err() {
+                break;
+            }
+            if changes.deleted.contains(&row_id) {
+                moved_by += 1;
+                continue;
+            }
+            let values = tabledef.decode_row(changes.updated.get(&row_id).unwrap_or(&buf))?;
+            for (index, tree) in indexes.iter().zip(changes.trees.iter_mut()) {
+                let key = index.key(&values);
+                tree.remove(&Entry { key: key.clone(), row_id })?;
+                tree.insert(Entry { key, row_id: row_id - moved_by })?;
+            }
+        }
+        Ok(changes)
+    }
+
+    // The changed blob is written to the side and renamed over the old one
+    // once it's on disk, then the indexes are written. The table is marked
+    // as having stale indexes in between, so a crash leaves them to be
+    // rebuilt rather than disagreeing with the blob.
+    pub fn write(mut self) -> ::anyhow::Result<()> {
+        index::mark_stale(&self.table_name)?;
+        let new_blob_path = format!("{}_new", self.blob_path());
+        let written = (|| -> ::anyhow::Result<()> {
+            let mut reader = BufReader::new(File::open(self.blob_path())?);
+            let mut writer = BufWriter::new(File::create(&new_blob_path)?);
+            let mut buf: Vec<u8> = vec![0; self.row_size];
+            for row_id in 0.. {
+                match reader.read_exact(&mut buf) {
+                    Err(err) if err.kind() == ErrorKind::UnexpectedEof => break,
+                    result => result?,
+                }
+                if self.deleted.contains(&row_id) {
+                    continue;
+                }
+                writer.write_all(self.updated.get(&row_id).unwrap_or(&buf))?;
+            }
+            writer.into_inner().map_err(|err| err.into_error())?.sync_all()?;
+            Ok(())
+        })();
+        if let Err(err) = written {
+            let _ = fs::remove_file(&new_blob_path);
+            index::mark_fresh(&self.table_name)?;
+            return Err(err);
+        }
+        fs::rename(&new_blob_path, self.blob_path())?;
+
+        for tree in self.trees.iter_mut() {
+            tree.flush()?;
+        }
+        index::mark_fresh(&self.table_name)
+    }
+
+    fn blob_path(&self) -> String {
+        format!("./data/blobs/{}", self.table_name)
+    }
+}
+
+#[cfg(test)]
+use crate::{run_statements, select_rows, with_data_dir};
+
+#[test]
+fn point_updates_and_deletes() {
+    with_data_dir(|| {
+        let values: Vec<String> = (1..=300).map(|id| format!("({}, 'v{}')", id, id)).collect();
+        let insert = format!("INSERT INTO t (id, v) VALUES {};", values.join(", "));
+        let responses = run_statements(&["CREATE TABLE t (id int PRIMARY KEY, v varchar 10);", &insert]);
+        assert!(responses.iter().all(|response| !response.starts_with("ERROR")), "{:?}", responses);
+        let find = |id: i64| select_rows(&format!("SELECT id, v FROM t WHERE id = {};", id));
+        let row = |id: i64, v: &str| vec![vec![id.to_string(), v.to_string()]];
+
+        // The table is already there, with rows and an index
+        assert!(run_statements(&["CREATE TABLE t (v varchar 10);"])[0].contains("already exists"));
+        assert!(run_statements(&["INSERT INTO t (id, v) VALUES (5, 'again');"])[0].contains("duplicate key"));
+
+        assert_eq!(run_statements(&["UPDATE t SET v = 'seven' WHERE id = 7;"]), vec!["1 Rows Updated"]);
+        assert_eq!(find(7), row(7, "seven"));
+        // Keys can move onto keys other rows are leaving
+        assert_eq!(run_statements(&["UPDATE t SET id = id + 1 WHERE id >= 296;"]), vec!["5 Rows Updated"]);
+        assert_eq!(find(296), vec![] as Vec<Vec<String>>);
+        assert_eq!(find(301), row(301, "v300"));
+        // A duplicate changes nothing
+        assert!(run_statements(&["UPDATE t SET id = 1 WHERE id = 2;"])[0].contains("duplicate key"));
+        assert_eq!(find(2), row(2, "v2"));
+
+        // Deleted rows are gone from the blob, and the rows after them move
+        // up in the same order
+        let blob_size = || std::fs::metadata("./data/blobs/t").unwrap().len();
+        let row_size = blob_size() / 300;
+        assert_eq!(run_statements(&["DELETE FROM t WHERE id = 10;"]), vec!["1 Rows Deleted"]);
+        assert_eq!(blob_size(), 299 * row_size);
+        assert_eq!(find(10), vec![] as Vec<Vec<String>>);
+        assert_eq!(find(301), row(301, "v300"));
+        assert_eq!(run_statements(&["DELETE FROM t WHERE id > 150 AND id <= 160 OR id = 301;"]), vec!["11 Rows Deleted"]);
+        assert_eq!(run_statements(&["DELETE FROM t WHERE id = 10;"]), vec!["0 Rows Deleted"]);
+        assert_eq!(blob_size(), 288 * row_size);
+
+        // Every row left is found through the index, and only those
+        let ids: Vec<i64> = select_rows("SELECT id FROM t;").iter().map(|row| row[0].parse().unwrap()).collect();
+        let expected: Vec<i64> = (1..=300).filter(|id| ![10, 296].contains(id) && !(151..=160).contains(id)).collect();
+        assert_eq!(ids, expected);
+        for id in 1..=301 {
+            assert_eq!(find(id).len(), usize::from(ids.contains(&id)), "{}", id);
+        }
+        assert_eq!(find(7), row(7, "seven"));
+    });
+}
+
+#[test]
+fn stale_indexes_are_rebuilt() {
+    with_data_dir(|| {
+        let responses = run_statements(&[
+            "CREATE TABLE t (id int PRIMARY KEY, v varchar 10);",
+            "INSERT INTO t (id, v) VALUES (1, 'a'), (2, 'b'), (3, 'c');",
+        ]);
+        assert!(responses.iter().all(|response| !response.starts_with("ERROR")), "{:?}", responses);
+
+        // A crash after the blob was renamed into place but before the index
+        // was written leaves the old index and the marker
+        let old_index = std::fs::read("./data/indexes/t_pkey").unwrap();
+        assert_eq!(run_statements(&["DELETE FROM t WHERE id = 1;"]), vec!["1 Rows Deleted"]);
+        assert!(std::fs::read_dir("./data/reindex").unwrap().next().is_none());
+        std::fs::write("./data/indexes/t_pkey", old_index).unwrap();
+        std::fs::write("./data/blobs/t_new", b"partial").unwrap();
+        index::mark_stale("t").unwrap();
+        assert_eq!(select_rows("SELECT v FROM t WHERE id = 3;"), vec![] as Vec<Vec<String>>);
+
+        index::recover().unwrap();
+        assert_eq!(select_rows("SELECT v FROM t WHERE id = 3;"), vec![vec!["c"]]);
+        assert_eq!(select_rows("SELECT v FROM t WHERE id = 1;"), vec![] as Vec<Vec<String>>);
+        assert!(std::fs::read_dir("./data/reindex").unwrap().next().is_none());
+        assert!(std::fs::metadata("./data/blobs/t_new").is_err());
+    });
+}
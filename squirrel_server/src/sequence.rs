@@ -0,0 +1,271 @@
+use anyhow::anyhow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Mutex;
+
+use squirrel_core::functions::register_function;
+use squirrel_core::parser::command::DataValue;
+use squirrel_core::table::datatypes::Datatype;
+use squirrel_core::table::sequence_definition::SequenceDefinition;
+
+use crate::read_tabledef;
+
+// Sequences are kept in ./data/sequences/<name> as one line,
+//
+//     increment min_value max_value start cycle last_value is_called
+//
+// where last_value is the value nextval last handed out, or the one it
+// hands out next if is_called is false.
+struct Sequence {
+    definition: SequenceDefinition,
+    last_value: i64,
+    is_called: bool,
+}
+
+// nextval reads and rewrites the file, which can't interleave between
+// clients
+static SEQUENCE_LOCK: Mutex<()> = Mutex::new(());
+
+thread_local! {
+    // The value nextval or setval last gave each sequence in this session,
+    // for currval. Every client has its own thread.
+    static CURRENT_VALUES: RefCell<HashMap<String, i64>> = RefCell::new(HashMap::new());
+}
+
+pub fn path(name: &str) -> String {
+    format!("./data/sequences/{}", name)
+}
+
+pub fn create(definition: &SequenceDefinition) -> ::anyhow::Result<()> {
+    let _lock = SEQUENCE_LOCK.lock().map_err(|_| anyhow!("Sequence lock is poisoned"))?;
+    if fs::metadata(path(&definition.name)).is_ok() {
+        return Err(anyhow!("ERROR: relation '{}' already exists", definition.name));
+    }
+    Sequence { definition: definition.clone(), last_value: definition.start, is_called: false }.write()
+}
+
+pub fn remove(name: &str) -> ::anyhow::Result<()> {
+    let _lock = SEQUENCE_LOCK.lock().map_err(|_| anyhow!("Sequence lock is poisoned"))?;
+    match fs::remove_file(path(name)) {
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Err(anyhow!("ERROR: sequence '{}' does not exist", name)),
+        result => Ok(result?),
+    }
+}
+
+// The table and column a serial or identity sequence belongs to
+pub fn owner(name: &str) -> ::anyhow::Result<Option<(String, String)>> {
+    for entry in fs::read_dir("./data/tabledefs")? {
+        let tabledef = read_tabledef(entry?.file_name().to_string_lossy().to_string())?;
+        if let Some(col_def) = tabledef.column_defs.iter().find(|col_def| col_def.sequence.as_deref() == Some(name)) {
+            return Ok(Some((tabledef.name.clone(), col_def.name.clone())));
+        }
+    }
+    Ok(None)
+}
+
+impl Sequence {
+    fn read(name: &str) -> ::anyhow::Result<Sequence> {
+        let line = match fs::read_to_string(path(name)) {
+            Ok(line) => line,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Err(anyhow!("relation '{}' does not exist", name));
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let parts: Vec<&str> = line.trim().split(' ').collect();
+        if parts.len() != 7 {
+            return Err(anyhow!("Sequence '{}' is corrupt", name));
+        }
+        Ok(Sequence {
+            definition: SequenceDefinition {
+                name: name.to_string(),
+                increment: parts[0].parse()?,
+                min_value: parts[1].parse()?,
+                max_value: parts[2].parse()?,
+                start: parts[3].parse()?,
+                cycle: parts[4] == "cycle",
+            },
+            last_value: parts[5].parse()?,
+            is_called: parts[6] == "called",
+        })
+    }
+
+    // Written to the side and renamed over the old file, so a crash never
+    // leaves half a sequence behind
+    fn write(&self) -> ::anyhow::Result<()> {
+        let definition = &self.definition;
+        let line = format!(
+            "{} {} {} {} {} {} {}\n",
+            definition.increment,
+            definition.min_value,
+            definition.max_value,
+            definition.start,
+            if definition.cycle { "cycle" } else { "no_cycle" },
+            self.last_value,
+            if self.is_called { "called" } else { "not_called" }
+        );
+        let new_path = format!("{}_new", path(&definition.name));
+        fs::write(&new_path, line)?;
+        fs::rename(&new_path, path(&definition.name))?;
+        Ok(())
+    }
+}
+
+fn sequence_name(args: &[DataValue]) -> String {
+    args[0].to_string()
+}
+
+fn nextval(args: &[DataValue]) -> ::anyhow::Result<DataValue> {
+    let name = sequence_name(args);
+    let _lock = SEQUENCE_LOCK.lock().map_err(|_| anyhow!("Sequence lock is poisoned"))?;
+    let mut sequence = Sequence::read(&name)?;
+    let definition = &sequence.definition;
+
+    let value = if !sequence.is_called {
+        sequence.last_value
+    } else {
+        match sequence.last_value.checked_add(definition.increment) {
+            Some(value) if value >= definition.min_value && value <= definition.max_value => value,
+            // Past the end, which wraps around to the other end with CYCLE
+            _ if definition.cycle && definition.increment > 0 => definition.min_value,
+            _ if definition.cycle => definition.max_value,
+            _ if definition.increment > 0 => {
+                return Err(anyhow!("reached maximum value of sequence '{}' ({})", name, definition.max_value));
+            }
+            _ => return Err(anyhow!("reached minimum value of sequence '{}' ({})", name, definition.min_value)),
+        }
+    };
+    sequence.last_value = value;
+    sequence.is_called = true;
+    sequence.write()?;
+
+    CURRENT_VALUES.with(|values| values.borrow_mut().insert(name, value));
+    Ok(DataValue::I64Value(value))
+}
+
+fn currval(args: &[DataValue]) -> ::anyhow::Result<DataValue> {
+    let name = sequence_name(args);
+    // Errors for a sequence that's been dropped since
+    Sequence::read(&name)?;
+    match CURRENT_VALUES.with(|values| values.borrow().get(&name).copied()) {
+        Some(value) => Ok(DataValue::I64Value(value)),
+        None => Err(anyhow!("currval of sequence '{}' is not yet defined in this session", name)),
+    }
+}
+
+// setval(sequence, value), the next nextval returns value + increment
+fn setval(args: &[DataValue]) -> ::anyhow::Result<DataValue> {
+    let name = sequence_name(args);
+    let DataValue::I64Value(value) = args[1] else {
+        return Err(anyhow!("value must be an integer"));
+    };
+    let _lock = SEQUENCE_LOCK.lock().map_err(|_| anyhow!("Sequence lock is poisoned"))?;
+    let mut sequence = Sequence::read(&name)?;
+    let definition = &sequence.definition;
+    if value < definition.min_value || value > definition.max_value {
+        return Err(anyhow!(
+            "value {} is out of bounds for sequence '{}' ({}..{})",
+            value,
+            name,
+            definition.min_value,
+            definition.max_value
+        ));
+    }
+    sequence.last_value = value;
+    sequence.is_called = true;
+    sequence.write()?;
+
+    CURRENT_VALUES.with(|values| values.borrow_mut().insert(name, value));
+    Ok(DataValue::I64Value(value))
+}
+
+// Makes nextval, currval and setval callable from queries, including the
+// DEFAULT of serial and identity columns
+pub fn register_functions() -> ::anyhow::Result<()> {
+    register_function("NEXTVAL", vec![Datatype::CharacterVarying], Datatype::BigInt, nextval)?;
+    register_function("CURRVAL", vec![Datatype::CharacterVarying], Datatype::BigInt, currval)?;
+    register_function("SETVAL", vec![Datatype::CharacterVarying, Datatype::BigInt], Datatype::BigInt, setval)?;
+    Ok(())
+}
+
+#[cfg(test)]
+use crate::{run_statements, select_rows, with_data_dir};
+
+#[test]
+fn sequences_and_identity_columns() {
+    with_data_dir(|| {
+        let responses = run_statements(&[
+            "CREATE TABLE orders (id serial PRIMARY KEY, note varchar 20);",
+            "INSERT INTO orders (note) VALUES ('a'), ('b');",
+            "CREATE SEQUENCE ticket START 100 INCREMENT BY 5 MAXVALUE 110;",
+            "CREATE SEQUENCE ring MINVALUE 1 MAXVALUE 2 CYCLE;",
+            "CREATE TABLE gen (id int GENERATED ALWAYS AS IDENTITY (START WITH 1000 INCREMENT BY 10), b bigint GENERATED BY DEFAULT AS IDENTITY, name varchar 10);",
+            "INSERT INTO gen (name) VALUES ('x'), ('y');",
+            "INSERT INTO gen (b, name) VALUES (77, 'z');",
+        ]);
+        assert!(responses.iter().all(|response| !response.starts_with("ERROR")), "{:?}", responses);
+        let value = |query: &str| -> String { select_rows(query)[0].join(" ") };
+
+        assert_eq!(run_statements(&["INSERT INTO orders (note) VALUES ('c');"]), vec!["Data Inserted"]);
+        assert_eq!(value("SELECT id FROM orders WHERE note = 'c';"), "3");
+        assert_eq!(value("SELECT currval('orders_id_seq');"), "3");
+        assert!(run_statements(&["SELECT currval('ticket');"])[0].contains("not yet defined in this session"));
+        assert_eq!(value("SELECT nextval('ticket'), currval('ticket');"), "100 100");
+        assert_eq!(value("SELECT nextval('ticket');"), "105");
+        assert_eq!(value("SELECT nextval('ticket');"), "110");
+        assert!(run_statements(&["SELECT nextval('ticket');"])[0].contains("reached maximum value of sequence 'ticket' (110)"));
+        assert_eq!(value("SELECT setval('ticket', 101);"), "101");
+        assert_eq!(value("SELECT nextval('ticket');"), "106");
+        assert!(run_statements(&["CREATE SEQUENCE ticket;"])[0].contains("already exists"));
+        let ring: Vec<String> = (0..3).map(|_| value("SELECT nextval('ring');")).collect();
+        assert_eq!(ring, vec!["1", "2", "1"]);
+        assert!(run_statements(&["SELECT nextval('nope');"])[0].starts_with("ERROR"));
+
+        assert!(run_statements(&["INSERT INTO gen (id, name) VALUES (5, 'z');"])[0].contains("GENERATED ALWAYS AS IDENTITY"));
+        assert!(run_statements(&["UPDATE gen SET id = 3;"])[0].contains("GENERATED ALWAYS AS IDENTITY"));
+        assert_eq!(
+            select_rows("SELECT id, b, name FROM gen ORDER BY name;"),
+            vec![vec!["1000", "1", "x"], vec!["1010", "2", "y"], vec!["1020", "77", "z"]]
+        );
+        assert!(run_statements(&["DROP SEQUENCE gen_id_seq;"])[0].contains("column 'id' of table 'gen' requires it"));
+        assert_eq!(run_statements(&["DROP SEQUENCE ring;"]), vec!["Sequence Dropped"]);
+        assert!(run_statements(&["SELECT nextval('ring');"])[0].starts_with("ERROR"));
+
+        // A restart, i.e. a new session in a new thread, carries on from
+        // the sequence files but has no currval yet
+        std::thread::spawn(move || {
+            assert!(run_statements(&["SELECT currval('ticket');"])[0].contains("not yet defined in this session"));
+            assert!(run_statements(&["SELECT nextval('ticket');"])[0].contains("reached maximum value"));
+            assert_eq!(run_statements(&["INSERT INTO orders (note) VALUES ('d');", "INSERT INTO gen (name) VALUES ('w');"]), vec!["Data Inserted"; 2]);
+            assert_eq!(value("SELECT id FROM orders WHERE note = 'd';"), "4");
+            assert_eq!(value("SELECT id, b FROM gen WHERE name = 'w';"), "1030 3");
+        })
+        .join()
+        .unwrap();
+    });
+}
+
+#[test]
+fn failed_create_table_leaves_no_sequences() {
+    with_data_dir(|| {
+        assert_eq!(run_statements(&["CREATE SEQUENCE t_b_seq;"]), vec!["Sequence Created"]);
+
+        // The second serial's sequence is taken, so the first isn't made
+        // either, nor the table
+        assert_eq!(run_statements(&["CREATE TABLE t (a serial, b serial);"]), vec!["ERROR: relation 't_b_seq' already exists"]);
+        assert!(fs::metadata(path("t_a_seq")).is_err());
+        assert!(fs::metadata("./data/tabledefs/t").is_err());
+
+        // Nor when the primary key's index name is taken
+        let responses = run_statements(&["CREATE TABLE other (id int);", "CREATE INDEX u_pkey ON other (id);"]);
+        assert_eq!(responses, vec!["Table Created", "Index Created"]);
+        assert!(run_statements(&["CREATE TABLE u (id serial PRIMARY KEY);"])[0].contains("relation 'u_pkey' already exists"));
+        assert!(fs::metadata(path("u_id_seq")).is_err());
+
+        // So trying again once the name is free works
+        assert_eq!(run_statements(&["DROP SEQUENCE t_b_seq;", "CREATE TABLE t (a serial, b serial);"]), vec!["Sequence Dropped", "Table Created"]);
+        assert_eq!(run_statements(&["INSERT INTO t (a) VALUES (10);", "INSERT INTO t (a) VALUES (20);"]), vec!["Data Inserted"; 2]);
+        assert_eq!(select_rows("SELECT a, b FROM t;"), vec![vec!["10", "1"], vec!["20", "2"]]);
+    });
+}
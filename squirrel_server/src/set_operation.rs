@@ -0,0 +1,192 @@
+use anyhow::anyhow;
+use std::collections::{HashMap, HashSet};
+
+use squirrel_core::parser::command::{DataValue, SelectCommand, SetOperator, ValueExpression};
+
+use crate::join::{normalize_value, QueryContext};
+use crate::sort::{SortKey, Sorter, SORT_MEMORY_LIMIT};
+use crate::{execute_select, Datatype, QueryResult};
+
+// Runs a query with UNION, INTERSECT or EXCEPT. Each query is run on its
+// own and the results are combined left to right, then the combined rows
+// are sorted and limited.
+pub fn execute(command: &SelectCommand, context: &QueryContext) -> ::anyhow::Result<QueryResult> {
+    let first = SelectCommand {
+        with: vec![],
+        order_by: vec![],
+        limit: None,
+        offset: 0,
+        set_operations: vec![],
+        ..command.clone()
+    };
+    let mut result = execute_select(&first, context.clone())?;
+    for set_operation in &command.set_operations {
+        let right = execute_select(&set_operation.query, context.clone())?;
+        result = combine(result, right, set_operation.operator, set_operation.all)?;
+    }
+
+    let mut sort_keys: Vec<SortKey> = vec![];
+    for item in &command.order_by {
+        let column = output_column(&item.expression, &result.column_names)?;
+        sort_keys.push(SortKey { column, descending: item.descending, nulls_first: item.nulls_first });
+    }
+    if !sort_keys.is_empty() {
+        let mut sorter = Sorter::new(sort_keys, SORT_MEMORY_LIMIT);
+        for row in result.rows {
+            sorter.push(row)?;
+        }
+        result.rows = sorter.finish()?.collect::<::anyhow::Result<Vec<Vec<DataValue>>>>()?;
+    }
+    result.rows = result
+        .rows
+        .into_iter()
+        .skip(command.offset)
+        .take(command.limit.unwrap_or(usize::MAX))
+        .collect();
+    Ok(result)
+}
+
+// The combined rows take the column names of the left query. Both sides are
+// cast to a type that fits either column, so i.e. integers and numerics
+// compare equal.
+fn combine(left: QueryResult, right: QueryResult, operator: SetOperator, all: bool) -> ::anyhow::Result<QueryResult> {
+    let operator_name = match operator {
+        SetOperator::Union => "UNION",
+        SetOperator::Intersect => "INTERSECT",
+        SetOperator::Except => "EXCEPT",
+    };
+    if left.column_names.len() != right.column_names.len() {
+        return Err(anyhow!("ERROR: each {} query must have the same number of columns", operator_name));
+    }
+    let column_types = left
+        .column_types
+        .iter()
+        .zip(&right.column_types)
+        .map(|(left_type, right_type)| match (left_type, right_type) {
+            (Some(left_type), Some(right_type)) => left_type.common_type(right_type).map(Some).ok_or_else(|| {
+                anyhow!("ERROR: {} types {} and {} cannot be matched", operator_name, left_type, right_type)
+            }),
+            _ => Ok(left_type.clone().or_else(|| right_type.clone())),
+        })
+        .collect::<::anyhow::Result<Vec<Option<Datatype>>>>()?;
+    let left_rows = cast_rows(left.rows, &column_types)?;
+    let right_rows = cast_rows(right.rows, &column_types)?;
+
+    let mut seen: HashSet<String> = HashSet::new();
+    let rows: Vec<Vec<DataValue>> = match operator {
+        SetOperator::Union if all => left_rows.into_iter().chain(right_rows).collect(),
+        SetOperator::Union => left_rows
+            .into_iter()
+            .chain(right_rows)
+            .filter(|row| seen.insert(row_key(row)))
+            .collect(),
+        SetOperator::Intersect | SetOperator::Except => {
+            let mut right_counts: HashMap<String, usize> = HashMap::new();
+            for row in &right_rows {
+                *right_counts.entry(row_key(row)).or_default() += 1;
+            }
+            // With ALL, each right row cancels out one matching left row
+            left_rows
+                .into_iter()
+                .filter(|row| {
+                    let key = row_key(row);
+                    let matched = match right_counts.get_mut(&key) {
+                        Some(count) if *count > 0 => {
+                            if all {
+                                *count -= 1;
+                            }
+                            true
+                        }
+                        _ => false,
+                    };
+                    matched == (operator == SetOperator::Intersect) && (all || seen.insert(key))
+                })
+                .collect()
+        }
+    };
+    Ok(QueryResult { column_names: left.column_names, column_types, rows })
+}
+
+pub fn cast_rows(rows: Vec<Vec<DataValue>>, column_types: &[Option<Datatype>]) -> ::anyhow::Result<Vec<Vec<DataValue>>> {
+    rows.into_iter()
+        .map(|row| {
+            row.into_iter()
+                .zip(column_types)
+                .map(|(value, column_type)| match column_type {
+                    Some(column_type) => column_type.cast_value(value),
+                    None => Ok(value),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Rows are the same if all their values are, NULLs included
+pub fn row_key(row: &[DataValue]) -> String {
+    format!("{:?}", row.iter().map(normalize_value).collect::<Vec<DataValue>>())
+}
+
+// ORDER BY on a UNION, INTERSECT or EXCEPT can only name an output column
+// or give its position
+fn output_column(expression: &ValueExpression, column_names: &[String]) -> ::anyhow::Result<usize> {
+    match expression {
+        ValueExpression::DataValue(DataValue::I64Value(position)) => usize::try_from(*position - 1)
+            .ok()
+            .filter(|idx| *idx < column_names.len())
+            .ok_or_else(|| anyhow!("ERROR: ORDER BY position {} is not in select list", position)),
+        ValueExpression::ColumnName(name) => {
+            let mut matches = column_names.iter().enumerate().filter(|(_, col_name)| *col_name == name);
+            match (matches.next(), matches.next()) {
+                (Some((idx, _)), None) => Ok(idx),
+                (Some(_), Some(_)) => Err(anyhow!("ERROR: ORDER BY '{}' is ambiguous", name)),
+                (None, _) => Err(anyhow!("ERROR: column '{}' does not exist", name)),
+            }
+        }
+        _ => Err(anyhow!("ERROR: invalid UNION/INTERSECT/EXCEPT ORDER BY clause")),
+    }
+}
+
+#[cfg(test)]
+use crate::{run_statements, select_rows, with_data_dir};
+
+#[test]
+fn set_operations_over_stored_rows() {
+    with_data_dir(|| {
+        let responses = run_statements(&[
+            "CREATE TABLE a (x int, name varchar 10);",
+            "CREATE TABLE b (x int);",
+            "INSERT INTO a (x, name) VALUES (1, 'one'), (1, 'one'), (2, 'two'), (3, 'three'), (3, 'three'), (NULL, 'none'), (NULL, 'none');",
+            "INSERT INTO b (x) VALUES (1), (3), (3), (3), (NULL), (4);",
+        ]);
+        assert!(responses.iter().all(|response| !response.starts_with("ERROR")), "{:?}", responses);
+        let values = |query: &str| -> Vec<String> { select_rows(&format!("{} ORDER BY 1;", query)).into_iter().map(|row| row.join(" ")).collect() };
+
+        assert_eq!(values("SELECT x FROM a UNION SELECT x FROM b"), vec!["1", "2", "3", "4", "NULL"]);
+        assert_eq!(values("SELECT x FROM a UNION ALL SELECT x FROM b").len(), 13);
+        assert_eq!(values("SELECT x FROM a INTERSECT SELECT x FROM b"), vec!["1", "3", "NULL"]);
+        assert_eq!(values("SELECT x FROM b EXCEPT SELECT x FROM a"), vec!["4"]);
+
+        // NULLs match each other, so a NULL on the right takes out those on
+        // the left
+        assert_eq!(values("SELECT x FROM a EXCEPT SELECT x FROM b"), vec!["2"]);
+        assert_eq!(values("SELECT x FROM a EXCEPT SELECT x FROM b WHERE x IS NOT NULL"), vec!["2", "NULL"]);
+
+        // With ALL, each right row matches one left row: 1 is in both once,
+        // 3 twice, NULL once
+        assert_eq!(values("SELECT x FROM a INTERSECT ALL SELECT x FROM b"), vec!["1", "3", "3", "NULL"]);
+        assert_eq!(values("SELECT x FROM b INTERSECT ALL SELECT x FROM a"), vec!["1", "3", "3", "NULL"]);
+        assert_eq!(values("SELECT x FROM a EXCEPT ALL SELECT x FROM b"), vec!["1", "2", "NULL"]);
+        assert_eq!(values("SELECT x FROM b EXCEPT ALL SELECT x FROM a"), vec!["3", "4"]);
+
+        // Left to right, with the left query's column names
+        assert_eq!(values("SELECT x FROM a EXCEPT SELECT x FROM b UNION SELECT x FROM b WHERE x > 3"), vec!["2", "4"]);
+        assert_eq!(
+            select_rows("SELECT x AS n, name FROM a WHERE x < 3 UNION SELECT x, 'b' FROM b ORDER BY n, name LIMIT 3;"),
+            vec![vec!["1", "b"], vec!["1", "one"], vec!["2", "two"]]
+        );
+
+        assert!(run_statements(&["SELECT x FROM a UNION SELECT x, x FROM b;"])[0].contains("each UNION query must have the same number of columns"));
+        assert!(run_statements(&["SELECT name FROM a INTERSECT SELECT x FROM b;"])[0].contains("INTERSECT types"));
+        assert!(run_statements(&["SELECT x FROM a UNION SELECT x FROM b ORDER BY name;"])[0].contains("column 'name' does not exist"));
+    });
+}
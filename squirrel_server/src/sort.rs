@@ -0,0 +1,354 @@
+use anyhow::anyhow;
+use std::cmp::Ordering;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::mem;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::vec;
+
+use squirrel_core::parser::command::DataValue;
+
+// How much row data a query's sort holds in memory before it writes a sorted
+// run out to ./data/tmp. Runs are merged back together when the rows are read.
+pub const SORT_MEMORY_LIMIT: usize = 64 * 1024 * 1024;
+
+static NEXT_RUN_ID: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Clone)]
+pub struct SortKey {
+    pub column: usize,
+    pub descending: bool,
+    pub nulls_first: bool,
+}
+
+pub fn compare_rows(keys: &[SortKey], left: &[DataValue], right: &[DataValue]) -> Ordering {
+    for key in keys {
+        let ordering = match (&left[key.column], &right[key.column]) {
+            (DataValue::Null, DataValue::Null) => Ordering::Equal,
+            (DataValue::Null, _) if key.nulls_first => Ordering::Less,
+            (DataValue::Null, _) => Ordering::Greater,
+            (_, DataValue::Null) if key.nulls_first => Ordering::Greater,
+            (_, DataValue::Null) => Ordering::Less,
+            (left, right) => {
+                let ordering = match DataValue::coerce(left.clone(), right.clone()) {
+                    Ok((left, right)) => left.compare(&right).unwrap_or_else(|| incomparable_order(&left, &right)),
+                    Err(_) => incomparable_order(left, right),
+                };
+                if key.descending { ordering.reverse() } else { ordering }
+            }
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+// Values that can't be compared still need a consistent place, not to be
+// taken as equal: NaN goes after every other number, and values of different
+// types are ordered by type
+fn incomparable_order(left: &DataValue, right: &DataValue) -> Ordering {
+    let type_order = |value: &DataValue| match value {
+        DataValue::BoolValue(_) => 0,
+        DataValue::I64Value(_) | DataValue::F64Value(_) | DataValue::NumericValue(..) => 1,
+        DataValue::DateValue(_) | DataValue::TimestampValue(_) => 2,
+        DataValue::StringValue(_) => 3,
+        DataValue::Null => 4,
+    };
+    match (left, right) {
+        (DataValue::F64Value(left), DataValue::F64Value(right)) => left.total_cmp(right),
+        _ => type_order(left).cmp(&type_order(right)),
+    }
+}
+
+// An external merge sort. Rows are buffered until they pass memory_limit
+// bytes, then sorted and spilled to a run file.
+pub struct Sorter {
+    keys: Vec<SortKey>,
+    memory_limit: usize,
+    rows: Vec<Vec<DataValue>>,
+    rows_size: usize,
+    runs: Vec<Run>,
+}
+
+impl Sorter {
+    pub fn new(keys: Vec<SortKey>, memory_limit: usize) -> Sorter {
+        Sorter { keys, memory_limit, rows: vec![], rows_size: 0, runs: vec![] }
+    }
+
+    pub fn push(&mut self, row: Vec<DataValue>) -> ::anyhow::Result<()> {
+        self.rows_size += row.iter().map(value_size).sum::<usize>();
+        self.rows.push(row);
+        if self.rows_size > self.memory_limit {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    fn spill(&mut self) -> ::anyhow::Result<()> {
+        let keys = &self.keys;
+        self.rows.sort_by(|left, right| compare_rows(keys, left, right));
+
+        let path = PathBuf::from(format!(
+            "./data/tmp/sort_{}_{}",
+            process::id(),
+            NEXT_RUN_ID.fetch_add(1, AtomicOrdering::Relaxed)
+        ));
+        let mut writer = BufWriter::new(File::create(&path)?);
+        for row in self.rows.drain(..) {
+            write_row(&mut writer, &row)?;
+        }
+        writer.flush()?;
+        self.rows_size = 0;
+
+        let reader = BufReader::new(File::open(&path)?);
+        self.runs.push(Run { path, reader, next: None });
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> ::anyhow::Result<SortedRows> {
+        if self.runs.is_empty() {
+            let keys = &self.keys;
+            self.rows.sort_by(|left, right| compare_rows(keys, left, right));
+            return Ok(SortedRows::Memory(self.rows.into_iter()));
+        }
+
+        if !self.rows.is_empty() {
+            self.spill()?;
+        }
+        for run in self.runs.iter_mut() {
+            run.next = read_row(&mut run.reader)?;
+        }
+        Ok(SortedRows::Merge { keys: self.keys, runs: self.runs })
+    }
+}
+
+pub enum SortedRows {
+    Memory(vec::IntoIter<Vec<DataValue>>),
+    Merge { keys: Vec<SortKey>, runs: Vec<Run> },
+}
+
+impl Iterator for SortedRows {
+    type Item = ::anyhow::Result<Vec<DataValue>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            SortedRows::Memory(rows) => rows.next().map(Ok),
+            SortedRows::Merge { keys, runs } => {
+                // There are only ever a handful of runs, so finding the
+                // smallest head with a scan is fine
+                let mut smallest: Option<usize> = None;
+                for (idx, run) in runs.iter().enumerate() {
+                    let Some(row) = &run.next else { continue };
+                    let is_smaller = match smallest {
+                        Some(current) => compare_rows(keys, row, runs[current].next.as_ref()?) == Ordering::Less,
+                        None => true,
+                    };
+                    if is_smaller {
+                        smallest = Some(idx);
+                    }
+                }
+                let run = &mut runs[smallest?];
+                let next = match read_row(&mut run.reader) {
+                    Ok(next) => next,
+                    Err(err) => return Some(Err(err)),
+                };
+                mem::replace(&mut run.next, next).map(Ok)
+            }
+        }
+    }
+}
+
+pub struct Run {
+    path: PathBuf,
+    reader: BufReader<File>,
+    next: Option<Vec<DataValue>>,
+}
+
+impl Drop for Run {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn value_size(value: &DataValue) -> usize {
+    match value {
+        DataValue::StringValue(val) => mem::size_of::<DataValue>() + val.capacity(),
+        _ => mem::size_of::<DataValue>(),
+    }
+}
+
+// Rows in a run are a value count followed by tagged values
+fn write_row(writer: &mut impl Write, row: &[DataValue]) -> ::anyhow::Result<()> {
+    writer.write_all(&u32::try_from(row.len())?.to_le_bytes())?;
+    for value in row {
+        match value {
+            DataValue::Null => writer.write_all(&[0])?,
+            DataValue::StringValue(val) => {
+                writer.write_all(&[1])?;
+                writer.write_all(&u32::try_from(val.len())?.to_le_bytes())?;
+                writer.write_all(val.as_bytes())?;
+            }
+            DataValue::I64Value(val) => {
+                writer.write_all(&[2])?;
+                writer.write_all(&val.to_le_bytes())?;
+            }
+            DataValue::F64Value(val) => {
+                writer.write_all(&[3])?;
+                writer.write_all(&val.to_le_bytes())?;
+            }
+            DataValue::NumericValue(val, scale) => {
+                writer.write_all(&[4])?;
+                writer.write_all(&val.to_le_bytes())?;
+                writer.write_all(&scale.to_le_bytes())?;
+            }
+            DataValue::BoolValue(val) => writer.write_all(&[5, u8::from(*val)])?,
+            DataValue::DateValue(val) => {
+                writer.write_all(&[6])?;
+                writer.write_all(&val.to_le_bytes())?;
+            }
+            DataValue::TimestampValue(val) => {
+                writer.write_all(&[7])?;
+                writer.write_all(&val.to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_bytes<const N: usize>(reader: &mut impl Read) -> ::anyhow::Result<[u8; N]> {
+    let mut buf = [0; N];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn read_row(reader: &mut impl Read) -> ::anyhow::Result<Option<Vec<DataValue>>> {
+    let length = match read_bytes::<4>(reader) {
+        Ok(length) => u32::from_le_bytes(length),
+        Err(err) if err.downcast_ref::<std::io::Error>().is_some_and(|err| err.kind() == ErrorKind::UnexpectedEof) => {
+            return Ok(None);
+        }
+        Err(err) => return Err(err),
+    };
+
+    let mut row: Vec<DataValue> = vec![];
+    for _ in 0..length {
+        let [tag] = read_bytes::<1>(reader)?;
+        row.push(match tag {
+            0 => DataValue::Null,
+            1 => {
+                let length = u32::from_le_bytes(read_bytes::<4>(reader)?) as usize;
+                let mut buf = vec![0; length];
+                reader.read_exact(&mut buf)?;
+                DataValue::StringValue(String::from_utf8(buf)?)
+            }
+            2 => DataValue::I64Value(i64::from_le_bytes(read_bytes::<8>(reader)?)),
+            3 => DataValue::F64Value(f64::from_le_bytes(read_bytes::<8>(reader)?)),
+            4 => {
+                let val = i128::from_le_bytes(read_bytes::<16>(reader)?);
+                DataValue::NumericValue(val, u32::from_le_bytes(read_bytes::<4>(reader)?))
+            }
+            5 => DataValue::BoolValue(read_bytes::<1>(reader)? == [1]),
+            6 => DataValue::DateValue(i32::from_le_bytes(read_bytes::<4>(reader)?)),
+            7 => DataValue::TimestampValue(i64::from_le_bytes(read_bytes::<8>(reader)?)),
+            _ => return Err(anyhow!("Corrupt sort run, unknown value tag {}", tag)),
+        });
+    }
+    Ok(Some(row))
+}
+
+#[cfg(test)]
+use crate::with_data_dir;
+
+// Sorts with room for about three rows of two integers, so a run is spilled
+// every few rows
+#[cfg(test)]
+fn sort_spilled(keys: Vec<SortKey>, rows: Vec<Vec<DataValue>>) -> ::anyhow::Result<(usize, Vec<Vec<DataValue>>)> {
+    let mut sorter = Sorter::new(keys, 6 * mem::size_of::<DataValue>());
+    for row in rows {
+        sorter.push(row)?;
+    }
+    let sorted = sorter.finish()?;
+    let run_count = match &sorted {
+        SortedRows::Memory(_) => 0,
+        SortedRows::Merge { runs, .. } => runs.len(),
+    };
+    assert_eq!(fs::read_dir("./data/tmp")?.count(), run_count);
+    let rows = sorted.collect::<::anyhow::Result<Vec<Vec<DataValue>>>>()?;
+    // Runs are deleted once they've been read
+    assert_eq!(fs::read_dir("./data/tmp")?.count(), 0);
+    Ok((run_count, rows))
+}
+
+#[test]
+fn external_merge_sort() {
+    with_data_dir(|| {
+        let ascending = SortKey { column: 0, descending: false, nulls_first: false };
+
+        // Every key four times, the second value is the row's position so
+        // the merged order can be checked for stability
+        let rows: Vec<Vec<DataValue>> =
+            (0..100).map(|idx| vec![DataValue::I64Value((idx * 37 % 100) / 4), DataValue::I64Value(idx)]).collect();
+        let mut expected = rows.clone();
+        expected.sort_by_key(|row| match row[0] {
+            DataValue::I64Value(key) => key,
+            _ => unreachable!(),
+        });
+        let (run_count, sorted) = sort_spilled(vec![ascending.clone()], rows).unwrap();
+        assert!(run_count > 10);
+        assert_eq!(sorted, expected);
+
+        // DESC puts NULLs first by default, NULLS LAST after everything
+        let values = [Some(3), None, Some(7), Some(1), None, Some(7), Some(5), Some(2), None, Some(9)];
+        let rows: Vec<Vec<DataValue>> = values
+            .iter()
+            .enumerate()
+            .map(|(idx, value)| vec![value.map_or(DataValue::Null, DataValue::I64Value), DataValue::I64Value(idx as i64)])
+            .collect();
+        let order = |sorted: Vec<Vec<DataValue>>| -> Vec<DataValue> { sorted.into_iter().map(|row| row[1].clone()).collect() };
+        let positions = |positions: &[i64]| -> Vec<DataValue> { positions.iter().map(|idx| DataValue::I64Value(*idx)).collect() };
+
+        let descending = SortKey { column: 0, descending: true, nulls_first: true };
+        let (run_count, sorted) = sort_spilled(vec![descending], rows.clone()).unwrap();
+        assert!(run_count > 1);
+        assert_eq!(order(sorted), positions(&[1, 4, 8, 9, 2, 5, 6, 0, 7, 3]));
+
+        let descending_nulls_last = SortKey { column: 0, descending: true, nulls_first: false };
+        let (_, sorted) = sort_spilled(vec![descending_nulls_last], rows.clone()).unwrap();
+        assert_eq!(order(sorted), positions(&[9, 2, 5, 6, 0, 7, 3, 1, 4, 8]));
+
+        let (_, sorted) = sort_spilled(vec![ascending.clone()], rows.clone()).unwrap();
+        assert_eq!(order(sorted), positions(&[3, 7, 0, 6, 2, 5, 9, 1, 4, 8]));
+
+        // Ties on the first key fall to the second, which sorts descending
+        let by_parity = rows
+            .iter()
+            .map(|row| vec![DataValue::BoolValue(matches!(row[1], DataValue::I64Value(idx) if idx % 2 == 0)), row[1].clone()])
+            .collect();
+        let keys = vec![
+            SortKey { column: 0, descending: false, nulls_first: false },
+            SortKey { column: 1, descending: true, nulls_first: false },
+        ];
+        let (_, sorted) = sort_spilled(keys, by_parity).unwrap();
+        assert_eq!(order(sorted), positions(&[9, 7, 5, 3, 1, 8, 6, 4, 2, 0]));
+
+        // Small enough to never leave memory
+        let rows = vec![vec![DataValue::I64Value(2)], vec![DataValue::I64Value(1)]];
+        let (run_count, sorted) = sort_spilled(vec![ascending], rows).unwrap();
+        assert_eq!(run_count, 0);
+        assert_eq!(sorted, vec![vec![DataValue::I64Value(1)], vec![DataValue::I64Value(2)]]);
+    });
+}
+
+#[test]
+fn incomparable_values_are_ordered() {
+    let key = [SortKey { column: 0, descending: false, nulls_first: false }];
+    let compare = |left: DataValue, right: DataValue| compare_rows(&key, &[left], &[right]);
+    assert_eq!(compare(DataValue::I64Value(5), DataValue::StringValue(String::from("5"))), Ordering::Less);
+    assert_eq!(compare(DataValue::StringValue(String::from("5")), DataValue::I64Value(5)), Ordering::Greater);
+    assert_eq!(compare(DataValue::F64Value(f64::NAN), DataValue::F64Value(f64::INFINITY)), Ordering::Greater);
+    assert_eq!(compare(DataValue::I64Value(1), DataValue::F64Value(f64::NAN)), Ordering::Less);
+    assert_eq!(compare(DataValue::F64Value(f64::NAN), DataValue::F64Value(f64::NAN)), Ordering::Equal);
+}
@@ -0,0 +1,252 @@
+use anyhow::anyhow;
+use std::cmp::Ordering;
+
+use squirrel_core::functions::aggregates::Accumulator;
+use squirrel_core::parser::command::{
+    DataValue, FrameBound, FrameUnits, ValueExpression, WindowFrame, WindowFunction, WindowFunctionType,
+};
+
+use crate::join::{Row, Scope};
+use crate::sort::{compare_rows, SortKey};
+
+// An output row that's waiting on its window functions. The expressions and
+// windows already have the row's group values swapped in, if it's grouped,
+// and are evaluated against `row`.
+pub struct PendingRow {
+    pub row: Row,
+    pub expressions: Vec<ValueExpression>,
+    pub windows: Vec<WindowFunction>,
+}
+
+// The values a window function needs from a row
+struct WindowInput {
+    key: Vec<DataValue>, // the PARTITION BY values, then the ORDER BY ones
+    arguments: Vec<DataValue>,
+    aggregate_argument: DataValue,
+}
+
+// Works out each window function over all the rows, swaps the results into
+// the rows' expressions and evaluates them
+pub fn evaluate_rows(scope: &Scope, mut pending: Vec<PendingRow>) -> ::anyhow::Result<Vec<Vec<DataValue>>> {
+    let window_count = pending.first().map_or(0, |pending_row| pending_row.windows.len());
+    for idx in 0..window_count {
+        let values = evaluate_window(scope, &pending, idx)?;
+        for (pending_row, value) in pending.iter_mut().zip(values) {
+            let window = ValueExpression::Window(Box::new(pending_row.windows[idx].clone()));
+            for expression in pending_row.expressions.iter_mut() {
+                expression.substitute(&window, &value);
+            }
+        }
+    }
+    pending
+        .iter()
+        .map(|pending_row| {
+            pending_row
+                .expressions
+                .iter()
+                .map(|expression| scope.evaluate(expression, &pending_row.row))
+                .collect()
+        })
+        .collect()
+}
+
+// The value of the idx-th window function for every row. Rows are sorted by
+// partition then by the window's ORDER BY, and each partition is walked in
+// that order.
+fn evaluate_window(scope: &Scope, pending: &[PendingRow], idx: usize) -> ::anyhow::Result<Vec<DataValue>> {
+    let window = &pending[0].windows[idx];
+    let inputs = pending
+        .iter()
+        .map(|pending_row| {
+            let window = &pending_row.windows[idx];
+            let evaluate = |expression: &ValueExpression| scope.evaluate(expression, &pending_row.row);
+            let mut key = window.partition_by.iter().map(evaluate).collect::<::anyhow::Result<Vec<DataValue>>>()?;
+            for item in &window.order_by {
+                key.push(evaluate(&item.expression)?);
+            }
+            let aggregate_argument = match &window.function {
+                WindowFunctionType::Aggregate(aggregate) => match &aggregate.argument {
+                    Some(argument) => evaluate(argument)?,
+                    None => DataValue::BoolValue(true),
+                },
+                _ => DataValue::Null,
+            };
+            let arguments = window.arguments.iter().map(evaluate).collect::<::anyhow::Result<Vec<DataValue>>>()?;
+            Ok(WindowInput { key, arguments, aggregate_argument })
+        })
+        .collect::<::anyhow::Result<Vec<WindowInput>>>()?;
+
+    let partition_count = window.partition_by.len();
+    let partition_keys: Vec<SortKey> =
+        (0..partition_count).map(|column| SortKey { column, descending: false, nulls_first: false }).collect();
+    let order_keys: Vec<SortKey> = window
+        .order_by
+        .iter()
+        .enumerate()
+        .map(|(column, item)| SortKey { column: partition_count + column, descending: item.descending, nulls_first: item.nulls_first })
+        .collect();
+    let sort_keys: Vec<SortKey> = partition_keys.iter().chain(&order_keys).cloned().collect();
+    let mut sorted: Vec<usize> = (0..inputs.len()).collect();
+    sorted.sort_by(|left, right| compare_rows(&sort_keys, &inputs[*left].key, &inputs[*right].key));
+
+    let mut values = vec![DataValue::Null; inputs.len()];
+    let mut start = 0;
+    while start < sorted.len() {
+        let first = &inputs[sorted[start]].key;
+        let end = start
+            + sorted[start..]
+                .iter()
+                .position(|row_idx| compare_rows(&partition_keys, first, &inputs[*row_idx].key) != Ordering::Equal)
+                .unwrap_or(sorted.len() - start);
+        let partition: Vec<&WindowInput> = sorted[start..end].iter().map(|row_idx| &inputs[*row_idx]).collect();
+        let partition_values = evaluate_partition(window, &partition, &order_keys)?;
+        for (row_idx, value) in sorted[start..end].iter().zip(partition_values) {
+            values[*row_idx] = value;
+        }
+        start = end;
+    }
+    Ok(values)
+}
+
+fn evaluate_partition(window: &WindowFunction, rows: &[&WindowInput], order_keys: &[SortKey]) -> ::anyhow::Result<Vec<DataValue>> {
+    // Rows with the same ORDER BY values are peers. peer_ranges[i] is the
+    // range of row i's peers, and rows are all peers without an ORDER BY.
+    let mut peer_ranges: Vec<(usize, usize)> = Vec::with_capacity(rows.len());
+    let mut peer_groups: Vec<usize> = Vec::with_capacity(rows.len());
+    let mut peer_start = 0;
+    for idx in 0..rows.len() {
+        if idx > 0 && compare_rows(order_keys, &rows[idx - 1].key, &rows[idx].key) != Ordering::Equal {
+            for range in peer_ranges[peer_start..].iter_mut() {
+                range.1 = idx;
+            }
+            peer_start = idx;
+        }
+        peer_ranges.push((peer_start, rows.len()));
+        peer_groups.push(if idx == peer_start { peer_groups.last().map_or(1, |group| group + 1) } else { peer_groups[idx - 1] });
+    }
+
+    let mut values: Vec<DataValue> = Vec::with_capacity(rows.len());
+    match &window.function {
+        WindowFunctionType::RowNumber => values.extend((1..=rows.len()).map(|row_number| DataValue::I64Value(row_number as i64))),
+        WindowFunctionType::Rank => values.extend(peer_ranges.iter().map(|(peer_start, _)| DataValue::I64Value(*peer_start as i64 + 1))),
+        WindowFunctionType::DenseRank => values.extend(peer_groups.iter().map(|group| DataValue::I64Value(*group as i64))),
+        WindowFunctionType::Lag | WindowFunctionType::Lead => {
+            for (idx, row) in rows.iter().enumerate() {
+                // LAG(value, offset, default) is the value offset rows back,
+                // or the default when that's outside the partition
+                let offset = match row.arguments.get(1) {
+                    None => 1,
+                    Some(DataValue::I64Value(offset)) => *offset,
+                    Some(DataValue::Null) => {
+                        values.push(DataValue::Null);
+                        continue;
+                    }
+                    Some(_) => return Err(anyhow!("ERROR: {} offset must be an integer", window.function.as_str())),
+                };
+                let offset = if window.function == WindowFunctionType::Lag { -offset } else { offset };
+                let target = i64::try_from(idx).ok().and_then(|idx| idx.checked_add(offset));
+                values.push(match target.and_then(|target| usize::try_from(target).ok()).and_then(|target| rows.get(target)) {
+                    Some(target_row) => target_row.arguments[0].clone(),
+                    None => row.arguments.get(2).cloned().unwrap_or(DataValue::Null),
+                });
+            }
+        }
+        WindowFunctionType::Aggregate(aggregate) => {
+            let frame = window.frame.unwrap_or(WindowFrame {
+                units: FrameUnits::Range,
+                start: FrameBound::UnboundedPreceding,
+                end: FrameBound::CurrentRow,
+            });
+            // Frames that start at the top of the partition only ever grow,
+            // so one accumulator is fed as they do. Others are worked out
+            // from scratch for every row.
+            let mut running = Accumulator::new(aggregate);
+            let mut fed = 0;
+            for (idx, peer_range) in peer_ranges.iter().enumerate() {
+                let (frame_start, frame_end) = frame_range(&frame, idx, *peer_range, rows.len());
+                if frame.start == FrameBound::UnboundedPreceding {
+                    while fed < frame_end {
+                        running.update(rows[fed].aggregate_argument.clone())?;
+                        fed += 1;
+                    }
+                    values.push(running.finish()?);
+                    continue;
+                }
+                let mut accumulator = Accumulator::new(aggregate);
+                for row in rows.iter().take(frame_end).skip(frame_start) {
+                    accumulator.update(row.aggregate_argument.clone())?;
+                }
+                values.push(accumulator.finish()?);
+            }
+        }
+    }
+    Ok(values)
+}
+
+// The rows of the partition in row idx's frame, as a start..end range
+fn frame_range(frame: &WindowFrame, idx: usize, peer_range: (usize, usize), row_count: usize) -> (usize, usize) {
+    let start = match (frame.start, frame.units) {
+        (FrameBound::UnboundedPreceding, _) => 0,
+        (FrameBound::CurrentRow, FrameUnits::Range) => peer_range.0,
+        (FrameBound::CurrentRow, FrameUnits::Rows) => idx,
+        (FrameBound::Preceding(offset), _) => idx.saturating_sub(offset),
+        (FrameBound::Following(offset), _) => idx.saturating_add(offset),
+        (FrameBound::UnboundedFollowing, _) => row_count,
+    };
+    let end = match (frame.end, frame.units) {
+        (FrameBound::UnboundedFollowing, _) => row_count,
+        (FrameBound::CurrentRow, FrameUnits::Range) => peer_range.1,
+        (FrameBound::CurrentRow, FrameUnits::Rows) => idx + 1,
+        (FrameBound::Preceding(offset), _) => (idx + 1).saturating_sub(offset),
+        (FrameBound::Following(offset), _) => idx.saturating_add(offset).saturating_add(1),
+        (FrameBound::UnboundedPreceding, _) => 0,
+    };
+    (start.min(row_count), end.min(row_count))
+}
+
+#[cfg(test)]
+use crate::{run_statements, select_rows, with_data_dir};
+
+#[test]
+fn window_functions_over_stored_rows() {
+    with_data_dir(|| {
+        let responses = run_statements(&[
+            "CREATE TABLE sales (id int, region varchar 10, amount int);",
+            "INSERT INTO sales (id, region, amount) VALUES (1, 'east', 10), (2, 'east', 20), (3, 'east', 20), (4, 'east', 40), (5, 'west', 5), (6, 'west', 5);",
+        ]);
+        assert!(responses.iter().all(|response| !response.starts_with("ERROR")), "{:?}", responses);
+        // One column per window function, rows in id order
+        let columns = |windows: &str| -> Vec<String> {
+            select_rows(&format!("SELECT {} FROM sales ORDER BY id;", windows)).into_iter().map(|row| row.join(" ")).collect()
+        };
+
+        // The row before and this one, never reaching into the other region
+        assert_eq!(
+            columns("SUM(amount) OVER (PARTITION BY region ORDER BY id ROWS BETWEEN 1 PRECEDING AND CURRENT ROW)"),
+            vec!["10", "30", "40", "60", "5", "10"]
+        );
+        assert_eq!(
+            columns("SUM(amount) OVER (ORDER BY id ROWS BETWEEN 1 FOLLOWING AND UNBOUNDED FOLLOWING)"),
+            vec!["90", "70", "50", "10", "5", "NULL"]
+        );
+
+        // The default frame runs to the last peer of the current row, so
+        // rows with the same amount get the same running total
+        assert_eq!(columns("SUM(amount) OVER (PARTITION BY region ORDER BY amount)"), vec!["10", "50", "50", "90", "10", "10"]);
+        // And without an ORDER BY every row is a peer
+        assert_eq!(columns("COUNT(*) OVER (PARTITION BY region)"), vec!["4", "4", "4", "4", "2", "2"]);
+
+        // Ties share a rank, RANK skips past them and DENSE_RANK doesn't
+        assert_eq!(
+            columns("RANK() OVER (ORDER BY amount DESC), DENSE_RANK() OVER (ORDER BY amount DESC)"),
+            vec!["4 3", "2 2", "2 2", "1 1", "5 4", "5 4"]
+        );
+
+        // Off the edge of a partition is NULL, or the default given
+        assert_eq!(
+            columns("LAG(amount) OVER (PARTITION BY region ORDER BY id), LEAD(amount, 1, 0) OVER (PARTITION BY region ORDER BY id)"),
+            vec!["NULL 20", "10 20", "20 40", "20 0", "NULL 5", "5 0"]
+        );
+        assert_eq!(columns("LAG(id, 2) OVER (PARTITION BY region ORDER BY id)"), vec!["NULL", "NULL", "1", "2", "NULL", "NULL"]);
+    });
+}